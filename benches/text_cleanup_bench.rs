@@ -5,7 +5,7 @@ use std::thread;
 use std::time::Duration;
 
 use VoxAurora::whisper_integration::{
-    burt_correct_text, clean_whisper_text, merge_separated_words_dawg_regex,
+    MergeOptions, burt_correct_text, clean_whisper_text, merge_separated_words_dawg_regex,
     start_languagetool_server,
 };
 
@@ -62,7 +62,7 @@ fn bench_burt_correct_text(c: &mut Criterion) {
     // Simple text with common errors
     let sample_text = "La voiture roule tres vite sur l'autoroute.";
     group.bench_function("burt_correct_text", |b| {
-        b.iter(|| burt_correct_text(black_box(sample_text)))
+        b.iter(|| burt_correct_text(black_box(sample_text), "fr"))
     });
 
     group.finish();
@@ -83,7 +83,11 @@ fn bench_merge_separated_words(c: &mut Criterion) {
             BenchmarkId::new("merge_separated_words", i),
             &(text, max_merge),
             |b, &(text, max_merge)| {
-                b.iter(|| merge_separated_words_dawg_regex(black_box(text), black_box(*max_merge)))
+                let options = MergeOptions {
+                    max_merge: *max_merge,
+                    ..Default::default()
+                };
+                b.iter(|| merge_separated_words_dawg_regex(black_box(text), black_box(&options)))
             },
         );
     }
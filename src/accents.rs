@@ -0,0 +1,174 @@
+use crate::dawg_loader;
+use crate::whisper_integration::DAWGS;
+use once_cell::sync::OnceCell;
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+
+/// Accent restoration settings, read from the `cleanup.accents` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct AccentSettings {
+    /// Restores diacritics on words that are only valid dictionary words once accented.
+    pub enabled: bool,
+    /// Optional word frequency list ("word count" per line) used to pick between
+    /// several accented candidates, e.g. "peche" -> "pêche" rather than "péché".
+    pub frequency_file: Option<String>,
+}
+
+impl Default for AccentSettings {
+    fn default() -> Self {
+        AccentSettings {
+            enabled: true,
+            frequency_file: None,
+        }
+    }
+}
+
+/// Maps the unaccented form of a word to every accented dictionary word folding to it.
+#[derive(Default)]
+pub struct AccentIndex {
+    candidates: HashMap<String, Vec<String>>,
+}
+
+impl AccentIndex {
+    /// Indexes all words of a dictionary that contain at least one diacritic.
+    pub fn new(words: &[String]) -> Self {
+        let mut candidates: HashMap<String, Vec<String>> = HashMap::new();
+        for word in words {
+            let folded = strip_diacritics(word);
+            if folded != *word {
+                candidates.entry(folded).or_default().push(word.clone());
+            }
+        }
+        AccentIndex { candidates }
+    }
+
+    /// Returns the accented candidates for an unaccented, lowercased word.
+    pub fn lookup(&self, folded: &str) -> &[String] {
+        self.candidates.get(folded).map(Vec::as_slice).unwrap_or(&[])
+    }
+}
+
+/// Removes combining marks after canonical decomposition ("écran" -> "ecran").
+pub fn strip_diacritics(word: &str) -> String {
+    word.nfd().filter(|c| !is_combining_mark(*c)).nfc().collect()
+}
+
+/// Word counts loaded from `AccentSettings::frequency_file`, if any.
+static FREQUENCIES: OnceCell<HashMap<String, u64>> = OnceCell::new();
+
+fn frequencies(settings: &AccentSettings) -> &'static HashMap<String, u64> {
+    FREQUENCIES.get_or_init(|| {
+        let Some(path) = settings.frequency_file.as_deref() else {
+            return HashMap::new();
+        };
+        match fs::read_to_string(path) {
+            Ok(content) => {
+                let table: HashMap<String, u64> = content
+                    .lines()
+                    .filter_map(|line| {
+                        let mut parts = line.split_whitespace();
+                        let word = parts.next()?.to_lowercase().nfc().collect::<String>();
+                        let count = parts.next()?.parse::<u64>().ok()?;
+                        Some((word, count))
+                    })
+                    .collect();
+                log::info!("Loaded {} word frequencies from {}", table.len(), path);
+                table
+            }
+            Err(e) => {
+                log::error!("Error reading frequency file {}: {}", path, e);
+                HashMap::new()
+            }
+        }
+    })
+}
+
+/// Picks the most frequent candidate, or the one with the fewest diacritics
+/// when no frequency information is available.
+fn select_candidate<'a>(
+    candidates: &'a [String],
+    settings: &AccentSettings,
+) -> Option<&'a String> {
+    let frequencies = frequencies(settings);
+    candidates.iter().max_by_key(|candidate| {
+        let count = frequencies.get(candidate.as_str()).copied().unwrap_or(0);
+        let marks = candidate.nfd().filter(|c| is_combining_mark(*c)).count();
+        (count, std::cmp::Reverse(marks))
+    })
+}
+
+/// Re-applies the casing of `original` ("Ecran" -> "Écran", "ECRAN" -> "ÉCRAN").
+fn match_casing(original: &str, replacement: &str) -> String {
+    let letters: Vec<char> = original.chars().filter(|c| c.is_alphabetic()).collect();
+    if letters.len() > 1 && letters.iter().all(|c| c.is_uppercase()) {
+        return replacement.to_uppercase();
+    }
+    if original.chars().next().is_some_and(|c| c.is_uppercase()) {
+        let mut chars = replacement.chars();
+        return match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => String::new(),
+        };
+    }
+    replacement.to_string()
+}
+
+/// Restores missing accents on words that are not in any dictionary as written
+/// but whose accented form is ("deja" -> "déjà", "ecran" -> "écran").
+pub fn restore_accents(text: &str, settings: &AccentSettings) -> String {
+    restore_accents_in(text, settings, None)
+}
+
+/// `restore_accents` with the dictionary of `lang` only, or all of them when `None`.
+pub fn restore_accents_in(text: &str, settings: &AccentSettings, lang: Option<&str>) -> String {
+    if !settings.enabled {
+        return text.to_string();
+    }
+
+    let re = Regex::new(r"\p{L}+(?:[’']\p{L}+)*").unwrap();
+    let mut result = String::with_capacity(text.len());
+    let mut last_end = 0;
+
+    for token in re.find_iter(text) {
+        result.push_str(&text[last_end..token.start()]);
+        last_end = token.end();
+
+        let word = token.as_str();
+        let lower = word.nfkc().collect::<String>().to_lowercase();
+
+        // Only words written without any accent and unknown as-is are candidates
+        let known = DAWGS
+            .0
+            .iter()
+            .filter(|(code, _)| dawg_loader::in_language(code, lang))
+            .any(|(_, dawg)| dawg_loader::contains_exact(dawg, &lower));
+        if known || strip_diacritics(&lower) != lower {
+            result.push_str(word);
+            continue;
+        }
+
+        let candidates: Vec<String> = DAWGS
+            .2
+            .iter()
+            .filter(|(code, _)| dawg_loader::in_language(code, lang))
+            .flat_map(|(_, index)| index.lookup(&lower).iter().cloned())
+            .collect();
+
+        match select_candidate(&candidates, settings) {
+            Some(accented) => {
+                log::info!("Restored accents: '{}' -> '{}'", word, accented);
+                result.push_str(&match_casing(word, accented));
+            }
+            None => result.push_str(word),
+        }
+    }
+
+    result.push_str(&text[last_end..]);
+    result
+}
@@ -1,28 +1,254 @@
+use crate::apps;
+use crate::browser::{self, BrowserSettings};
+use crate::capture::{self, CaptureSettings};
+use crate::clipboard;
+use crate::macros::{self, MacroSettings};
+use crate::music::{self, MusicSettings};
+use crate::platform;
+use crate::playback;
+use crate::secrets::{self, SecretsSettings};
+use crate::services::{self, ServiceSpec};
+use crate::ssh::{self, SshHost};
+use chrono::{DateTime, Local};
 use enigo::*;
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
-use std::process::Command;
+use std::fmt::Write;
 
-pub fn execute_action(input: &str) -> Result<(), Box<dyn Error>> {
-    let action = input.to_string();
+/// `{{date}}`, `{{time}}`, `{{clipboard}}` and `{{transcription}}` in text actions; dates
+/// and times may give a strftime format, e.g. `{{date:%A %d %B}}`.
+static PLACEHOLDER_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"\{\{(date|time|clipboard|transcription)(?::([^}]*))?\}\}").unwrap()
+});
 
-    if action.starts_with("cmd:") {
-        let tmp = action.strip_prefix("cmd:").unwrap_or("");
-        match execute_shell_command(tmp) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(format!("{}", e).into()),
+/// What to type after dictated text.
+#[derive(Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Trailing {
+    #[default]
+    Space,
+    Newline,
+    Nothing,
+}
+
+/// Where dictated text goes.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TextSink {
+    /// Typed with simulated key presses.
+    #[default]
+    Keyboard,
+    /// Copied to the clipboard with a notification, for apps where key injection is unreliable.
+    Clipboard,
+    /// Copied to the clipboard, then pasted with Ctrl+V (Cmd+V on macOS).
+    Paste,
+    /// Dropped, e.g. while a password manager has the focus.
+    Suppress,
+}
+
+/// Formatting applied to text right before it is typed.
+#[derive(Deserialize, JsonSchema, Clone, Debug, Default)]
+#[serde(default)]
+pub struct OutputFormat {
+    pub sink: TextSink,
+    pub trailing: Trailing,
+    /// Removes sentence-final punctuation Whisper adds ("git status." -> "git status").
+    pub strip_final_punctuation: bool,
+}
+
+impl OutputFormat {
+    /// Applies the trailing and punctuation rules to `text`.
+    pub fn apply(&self, text: &str) -> String {
+        let mut formatted = text.trim_end().to_string();
+        if self.strip_final_punctuation {
+            let stripped = formatted
+                .trim_end_matches(|c: char| matches!(c, '.' | '!' | '?' | ',' | ';' | ':' | '…'))
+                .trim_end()
+                .len();
+            formatted.truncate(stripped);
         }
-    } else {
-        match execute_enigo_text(action) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(e),
+        match self.trailing {
+            Trailing::Space => formatted.push(' '),
+            Trailing::Newline => formatted.push('\n'),
+            Trailing::Nothing => {}
+        }
+        formatted
+    }
+}
+
+/// Where and as whom `cmd:` actions run, from the object form of a command's action.
+/// They also apply to the command's follow-up `cmd:` actions.
+#[derive(Deserialize, JsonSchema, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct ShellOptions {
+    /// Working directory; a leading `~` is the home directory.
+    pub cwd: Option<String>,
+    /// Variables set on top of VoxAurora's environment.
+    pub env: BTreeMap<String, String>,
+    /// Runs the command as this user through `sudo -n`, which fails rather than asking for
+    /// a password (not on Windows).
+    pub user: Option<String>,
+    /// The command speaks, e.g. `espeak`: with `playback.half_duplex`, the microphones
+    /// are ignored while it runs.
+    pub speaks: bool,
+}
+
+/// Everything an action may need at execution time besides its own definition.
+#[derive(Clone, Debug, Default)]
+pub struct ActionContext {
+    pub output: OutputFormat,
+    pub secrets: SecretsSettings,
+    pub capture: CaptureSettings,
+    pub music: MusicSettings,
+    pub browser: BrowserSettings,
+    pub macros: MacroSettings,
+    pub services: HashMap<String, ServiceSpec>,
+    pub ssh: HashMap<String, SshHost>,
+    /// Options of the command being run.
+    pub shell: ShellOptions,
+    /// What was said to run the command, for `{{transcription}}`.
+    pub transcription: String,
+    /// How sure Whisper was of `transcription`, from 0 to 1, when known.
+    pub confidence: Option<f32>,
+    /// Similarity between `transcription` and the matched trigger.
+    pub score: f32,
+}
+
+impl ActionContext {
+    /// Variables telling `cmd:` actions what was said and how reliably, so a script can
+    /// e.g. ask for confirmation on shaky input. Commands can override them in `env`.
+    pub fn utterance_env(&self) -> BTreeMap<String, String> {
+        let mut env = BTreeMap::from([
+            ("VOXAURORA_TRANSCRIPTION".to_string(), self.transcription.clone()),
+            ("VOXAURORA_SCORE".to_string(), format!("{:.3}", self.score)),
+        ]);
+        if let Some(confidence) = self.confidence {
+            env.insert("VOXAURORA_CONFIDENCE".to_string(), format!("{:.3}", confidence));
         }
+        env
+    }
+}
+
+/// Executes one action. Returns the path of the file it produced, if any
+/// (e.g. a screenshot), so follow-up actions can use it.
+pub fn execute_action(input: &str, ctx: &ActionContext) -> Result<Option<String>, Box<dyn Error>> {
+    if let Some(script) = input.strip_prefix("cmd:") {
+        // Secrets reach the shell as variables, never as part of the script it parses
+        let (script, secrets) = secrets::export_secrets(script, &ctx.secrets)?;
+        let mut options = ctx.shell.clone();
+        options.env = ctx.utterance_env().into_iter().chain(options.env).collect();
+        return match run_shell_command(&script, &options, &secrets) {
+            Ok(_) => Ok(None),
+            Err(e) => Err(format!("{}", e).into()),
+        };
+    }
+
+    // Secrets are resolved as late as possible so they never sit in the parsed config
+    let action = secrets::resolve_secrets(input, &ctx.secrets)?;
+
+    if let Some(name) = action.strip_prefix("app:") {
+        apps::launch_app(name)?;
+        Ok(None)
+    } else if let Some(spec) = action.strip_prefix("screenshot:") {
+        let path = capture::take_screenshot(spec, &ctx.capture)?;
+        Ok(Some(path.to_string_lossy().into_owned()))
+    } else if let Some(spec) = action.strip_prefix("record-screen:") {
+        let path = capture::record_screen(spec, &ctx.capture)?;
+        Ok(Some(path.to_string_lossy().into_owned()))
+    } else if let Some(spec) = action.strip_prefix("music:") {
+        music::execute_music(spec, &ctx.music, &ctx.secrets)?;
+        Ok(None)
+    } else if let Some(spec) = action.strip_prefix("browser:") {
+        browser::execute_browser(spec, &ctx.browser)?;
+        Ok(None)
+    } else if let Some(spec) = action.strip_prefix("service:") {
+        services::execute_service(spec, &ctx.services)?;
+        Ok(None)
+    } else if let Some(name) = action.strip_prefix("macro:") {
+        macros::play(name.trim(), &ctx.macros)?;
+        Ok(None)
+    } else if let Some(spec) = action.strip_prefix("ssh:") {
+        ssh::execute_ssh(spec, &ctx.ssh, &ctx.secrets)?;
+        Ok(None)
+    } else {
+        let text = expand_placeholders(&action, ctx, Local::now())?;
+        execute_enigo_text(text, &ctx.output).map(|_| None)
     }
 }
 
-pub fn execute_enigo_text(action: String) -> Result<(), Box<dyn Error>> {
+/// Replaces the placeholders of a text action with their value at `now`. The clipboard is
+/// only read when the text uses it.
+pub fn expand_placeholders(
+    text: &str,
+    ctx: &ActionContext,
+    now: DateTime<Local>,
+) -> Result<String, Box<dyn Error>> {
+    let mut failure = None;
+    let expanded = PLACEHOLDER_RE.replace_all(text, |caps: &Captures| {
+        let value = match &caps[1] {
+            "date" => format_time(now, caps.get(2).map_or("%d/%m/%Y", |f| f.as_str())),
+            "time" => format_time(now, caps.get(2).map_or("%H:%M", |f| f.as_str())),
+            "clipboard" => clipboard::get_text(),
+            _ => Ok(ctx.transcription.clone()),
+        };
+        value.unwrap_or_else(|e| {
+            failure.get_or_insert(e);
+            String::new()
+        })
+    });
+    match failure {
+        Some(e) => Err(e),
+        None => Ok(expanded.into_owned()),
+    }
+}
+
+/// `now` in the strftime `format`, failing rather than panicking on an invalid one.
+fn format_time(now: DateTime<Local>, format: &str) -> Result<String, Box<dyn Error>> {
+    let mut formatted = String::new();
+    write!(formatted, "{}", now.format(format))
+        .map_err(|_| format!("Invalid date format '{}'", format))?;
+    Ok(formatted)
+}
+
+/// Executes `action`, then each follow-up with `{{file}}` replaced by the file it produced.
+pub fn execute_chain(
+    action: &str,
+    follow_ups: &[String],
+    ctx: &ActionContext,
+) -> Result<(), Box<dyn Error>> {
+    let produced = execute_action(action, ctx)?;
+    for follow_up in follow_ups {
+        let follow_up = match &produced {
+            Some(file) => follow_up.replace("{{file}}", file),
+            None => follow_up.clone(),
+        };
+        execute_action(&follow_up, ctx)?;
+    }
+    Ok(())
+}
+
+pub fn execute_enigo_text(action: String, format: &OutputFormat) -> Result<(), Box<dyn Error>> {
+    let text = format.apply(&action);
+    match format.sink {
+        TextSink::Keyboard => {}
+        TextSink::Clipboard => return clipboard::copy_text(&text),
+        TextSink::Paste => {
+            clipboard::set_text(&text)?;
+            return paste();
+        }
+        TextSink::Suppress => {
+            log::info!("🔇 Text output suppressed");
+            return Ok(());
+        }
+    }
+
     let enigo_result = Enigo::new(&enigo::Settings::default());
     match enigo_result {
-        Ok(mut enigo) => match enigo.text(&(action.clone() + " ")) {
+        Ok(mut enigo) => match enigo.text(&text) {
             Ok(_) => Ok(()),
             Err(e) => Err(format!("Failed to execute key sequence: {}", e).into()),
         },
@@ -30,8 +256,57 @@ pub fn execute_enigo_text(action: String) -> Result<(), Box<dyn Error>> {
     }
 }
 
-pub fn execute_shell_command(action: &str) -> Result<(), Box<dyn Error>> {
-    let status = Command::new("sh").arg("-c").arg(action).status()?;
+/// Sends the paste shortcut to the focused window.
+fn paste() -> Result<(), Box<dyn Error>> {
+    send_shortcut(&[primary_modifier()], Key::Unicode('v'))
+}
+
+/// The modifier of standard shortcuts: Cmd on macOS, Ctrl elsewhere.
+pub fn primary_modifier() -> Key {
+    if cfg!(target_os = "macos") {
+        Key::Meta
+    } else {
+        Key::Control
+    }
+}
+
+/// Presses `key` while holding `modifiers` in the focused window.
+pub fn send_shortcut(modifiers: &[Key], key: Key) -> Result<(), Box<dyn Error>> {
+    let mut enigo = Enigo::new(&enigo::Settings::default())
+        .map_err(|e| format!("Failed to create Enigo instance: {}", e))?;
+    let result = modifiers
+        .iter()
+        .try_for_each(|modifier| enigo.key(*modifier, Direction::Press))
+        .and_then(|_| enigo.key(key, Direction::Click));
+    // Release the modifiers even if a key failed, so none stays stuck
+    for modifier in modifiers.iter().rev() {
+        let _ = enigo.key(*modifier, Direction::Release);
+    }
+    result.map_err(|e| format!("Failed to send shortcut: {}", e).into())
+}
+
+pub fn execute_shell_command(action: &str, options: &ShellOptions) -> Result<(), Box<dyn Error>> {
+    run_shell_command(action, options, &BTreeMap::new())
+}
+
+/// Runs `action` with `secrets` set in its environment, but never on its command line.
+fn run_shell_command(
+    action: &str,
+    options: &ShellOptions,
+    secrets: &BTreeMap<String, String>,
+) -> Result<(), Box<dyn Error>> {
+    let mut command = match &options.user {
+        Some(user) => {
+            let preserved: Vec<&str> = secrets.keys().map(String::as_str).collect();
+            platform::shell_as(user, action, &options.env, &preserved)?
+        }
+        None => platform::shell(action),
+    };
+    if let Some(cwd) = &options.cwd {
+        command.current_dir(platform::expand_home(cwd));
+    }
+    let _playback = options.speaks.then(playback::start);
+    let status = command.envs(&options.env).envs(secrets).status()?;
 
     if status.success() {
         Ok(())
@@ -0,0 +1,212 @@
+use crate::accents::strip_diacritics;
+use crate::platform;
+use once_cell::sync::Lazy;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use strsim::normalized_levenshtein;
+
+/// Minimum similarity between a spoken name and an application name to launch it.
+const APP_SIMILARITY_THRESHOLD: f64 = 0.6;
+
+/// An installed application.
+#[derive(Clone, Debug)]
+pub struct AppEntry {
+    /// Display name ("Text Editor").
+    pub name: String,
+    /// Localized names, generic names and keywords ("Éditeur de texte", "gedit").
+    pub aliases: Vec<String>,
+    launch: Launch,
+}
+
+#[derive(Clone, Debug)]
+enum Launch {
+    /// A desktop entry `Exec` line, field codes removed.
+    Exec(String),
+    /// A file opened by the OS (macOS bundle, Windows shortcut).
+    Open(PathBuf),
+}
+
+/// Installed applications, discovered once on first use.
+static APPS: Lazy<Vec<AppEntry>> = Lazy::new(|| {
+    let apps = discover();
+    log::info!("Found {} installed applications", apps.len());
+    apps
+});
+
+/// Launches the installed application whose name best matches `name`.
+pub fn launch_app(name: &str) -> Result<(), Box<dyn Error>> {
+    let app = resolve(name, &APPS).ok_or_else(|| format!("No application matches '{}'", name))?;
+    log::info!("🚀 Launching {} for '{}'", app.name, name);
+
+    match &app.launch {
+        Launch::Exec(exec) => platform::shell(exec).spawn()?,
+        Launch::Open(path) if cfg!(target_os = "windows") => Command::new("cmd")
+            .args(["/C", "start", ""])
+            .arg(path)
+            .spawn()?,
+        Launch::Open(path) => Command::new("open").arg(path).spawn()?,
+    };
+    Ok(())
+}
+
+/// Finds the app whose name or alias is closest to `name`, ignoring case and accents.
+pub fn resolve<'a>(name: &str, apps: &'a [AppEntry]) -> Option<&'a AppEntry> {
+    let spoken = fold(name);
+    apps.iter()
+        .map(|app| {
+            let score = std::iter::once(&app.name)
+                .chain(&app.aliases)
+                .map(|candidate| similarity(&spoken, &fold(candidate)))
+                .fold(0.0, f64::max);
+            (app, score)
+        })
+        .filter(|(_, score)| *score >= APP_SIMILARITY_THRESHOLD)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(app, _)| app)
+}
+
+fn similarity(spoken: &str, candidate: &str) -> f64 {
+    if spoken == candidate {
+        return 1.0;
+    }
+    normalized_levenshtein(spoken, candidate)
+}
+
+/// Lowercases and strips accents and leading articles ("l'éditeur" -> "editeur").
+fn fold(name: &str) -> String {
+    let folded = strip_diacritics(&name.trim().to_lowercase()).replace('’', "'");
+    ["l'", "le ", "la ", "les ", "the "]
+        .iter()
+        .find_map(|article| folded.strip_prefix(article))
+        .unwrap_or(&folded)
+        .to_string()
+}
+
+/// Parses a freedesktop `.desktop` file, skipping hidden and non-application entries.
+pub fn parse_desktop_entry(content: &str) -> Option<AppEntry> {
+    let mut in_entry = false;
+    let mut name = None;
+    let mut exec = None;
+    let mut aliases = Vec::new();
+
+    for line in content.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_entry = line == "[Desktop Entry]";
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        if !in_entry {
+            continue;
+        }
+        let (key, value) = (key.trim(), value.trim());
+        match key.split('[').next().unwrap_or(key) {
+            "Type" if value != "Application" => return None,
+            "NoDisplay" | "Hidden" if value == "true" => return None,
+            "Name" if key == "Name" => name = Some(value.to_string()),
+            "Name" | "GenericName" => aliases.push(value.to_string()),
+            "Keywords" => aliases.extend(
+                value
+                    .split(';')
+                    .filter(|k| !k.is_empty())
+                    .map(str::to_string),
+            ),
+            "Exec" => exec = Some(strip_field_codes(value)),
+            _ => {}
+        }
+    }
+
+    Some(AppEntry {
+        name: name?,
+        aliases,
+        launch: Launch::Exec(exec?),
+    })
+}
+
+/// Removes `%f`, `%U`, ... placeholders from an `Exec` line.
+fn strip_field_codes(exec: &str) -> String {
+    exec.split_whitespace()
+        .filter(|part| !(part.len() == 2 && part.starts_with('%')))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn discover() -> Vec<AppEntry> {
+    if cfg!(target_os = "macos") {
+        let home = std::env::var("HOME").unwrap_or_default();
+        let dirs = [
+            PathBuf::from("/Applications"),
+            PathBuf::from("/System/Applications"),
+            Path::new(&home).join("Applications"),
+        ];
+        files_with_extension(&dirs, "app", false)
+            .into_iter()
+            .map(open_entry)
+            .collect()
+    } else if cfg!(target_os = "windows") {
+        let dirs: Vec<PathBuf> = ["ProgramData", "AppData"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .map(|base| Path::new(&base).join(r"Microsoft\Windows\Start Menu\Programs"))
+            .collect();
+        files_with_extension(&dirs, "lnk", true)
+            .into_iter()
+            .map(open_entry)
+            .collect()
+    } else {
+        files_with_extension(&desktop_entry_dirs(), "desktop", true)
+            .iter()
+            .filter_map(|path| parse_desktop_entry(&fs::read_to_string(path).ok()?))
+            .collect()
+    }
+}
+
+/// `applications` directories from the XDG base directories, plus Flatpak and Snap exports.
+fn desktop_entry_dirs() -> Vec<PathBuf> {
+    let home = std::env::var("HOME").unwrap_or_default();
+    let data_home = std::env::var("XDG_DATA_HOME")
+        .unwrap_or_else(|_| format!("{}/.local/share", home));
+    let data_dirs = std::env::var("XDG_DATA_DIRS")
+        .unwrap_or_else(|_| "/usr/local/share:/usr/share".to_string());
+
+    std::iter::once(data_home.as_str())
+        .chain(data_dirs.split(':'))
+        .chain(["/var/lib/flatpak/exports/share", "/var/lib/snapd/desktop"])
+        .map(|dir| Path::new(dir).join("applications"))
+        .collect()
+}
+
+/// An app known only by its file, named after the file stem.
+fn open_entry(path: PathBuf) -> AppEntry {
+    let name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    AppEntry {
+        name,
+        aliases: Vec::new(),
+        launch: Launch::Open(path),
+    }
+}
+
+/// Lists files (or bundles) with `extension` in `dirs`, optionally recursing.
+fn files_with_extension(dirs: &[PathBuf], extension: &str, recursive: bool) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    let mut pending: Vec<PathBuf> = dirs.to_vec();
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if path.extension().is_some_and(|ext| ext == extension) {
+                found.push(path);
+            } else if recursive && path.is_dir() {
+                pending.push(path);
+            }
+        }
+    }
+    found
+}
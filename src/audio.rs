@@ -1,149 +1,1431 @@
-use cpal::traits::{DeviceTrait, HostTrait};
-use cpal::Device;
+use crate::codec;
+use crate::events::{self, Event};
+use crate::flashback;
+use crate::health;
+use crate::hotkey::{self, PushToTalkSettings};
+use crate::pipeline::{AudioPipeline, PipelineStep};
+use crate::playback;
+use crate::vad::{self, Calibration, VadBackend, VadSettings, VoiceActivityDetector};
+use crate::wake_stream::WakeFeed;
+use crate::whisper_integration::SpeechFeed;
+use crate::watchdog::{self, InputWatchdog, WatchdogSettings};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{Device, FromSample, SampleFormat, SizedSample, Stream, StreamConfig};
 use rubato::Resampler;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::collections::BTreeSet;
 use std::error::Error;
+use std::fs;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
 
-const SILENCE_THRESHOLD: f32 = 0.01;
-const MAX_SPEECH_DURATION: Duration = Duration::from_secs(10);
-const SILENCE_DURATION_TO_FINALIZE: Duration = Duration::from_millis(1000);
+/// Rate assumed for a device that doesn't report its input configuration.
+const FALLBACK_SAMPLE_RATE: u32 = 44_100;
+
+/// Rate of the segments handed to Whisper.
+pub const WHISPER_SAMPLE_RATE: u32 = 16_000;
+
+/// Longest push-to-talk segment, in case the key release is missed.
+const MAX_GATED_DURATION: Duration = Duration::from_secs(60);
+
+/// How often the input watchdog checks for stalled devices while waiting for audio.
+const WATCHDOG_POLL: Duration = Duration::from_secs(1);
+
+/// How fast a device's noise floor follows the energy of its silent chunks.
+const NOISE_FLOOR_SMOOTHING: f32 = 0.05;
+
+/// Input device selection, read from the `input` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug, Default)]
+#[serde(default)]
+pub struct InputSettings {
+    /// Microphones to capture from at the same time, e.g. a headset and a webcam.
+    /// Each utterance is taken from the one that hears it best.
+    pub devices: Vec<DeviceSpec>,
+    /// Input channels used on devices that don't set their own (all of them when unset).
+    pub channels: Option<Vec<usize>>,
+    pub watchdog: WatchdogSettings,
+    pub vad: VadSettings,
+    /// Processing applied to captured chunks, in order, before speech detection.
+    pub pipeline: Vec<PipelineStep>,
+    pub timing: AudioSettings,
+    pub push_to_talk: PushToTalkSettings,
+    /// Format of the audio received with `--listen-audio`.
+    pub network: NetworkSettings,
+    /// Stops capturing while a command runs, so long actions (music, recordings, spoken
+    /// replies) aren't heard as speech. What is said meanwhile is lost.
+    pub pause_during_actions: bool,
+}
+
+/// How speech is cut into segments, read from the `input.timing` section of the config.
+/// Dictation wants long segments and a patient end of speech, commands short ones.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct AudioSettings {
+    /// Longest segment, cut even if the speaker goes on; the next segment then carries
+    /// on from it.
+    pub max_speech_secs: f32,
+    /// Silence ending a segment.
+    pub silence_secs: f32,
+    /// Segments with less speech than this, e.g. a cough or a door, are dropped.
+    pub min_speech_secs: f32,
+    /// Samples, counting every channel, gathered before a chunk is checked for speech.
+    /// Smaller chunks end segments closer to the last word, for more work.
+    pub chunk_samples: usize,
+    /// End of a segment cut at `max_speech_secs` repeated at the start of the next one,
+    /// so the word it was cut on is heard whole once. At most half of `max_speech_secs`.
+    pub overlap_secs: f32,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        AudioSettings {
+            max_speech_secs: 10.0,
+            silence_secs: 1.0,
+            min_speech_secs: 0.0,
+            chunk_samples: 4096,
+            overlap_secs: 1.0,
+        }
+    }
+}
+
+impl AudioSettings {
+    fn max_speech(&self) -> Duration {
+        Duration::from_secs_f32(self.max_speech_secs.max(0.0))
+    }
+
+    fn silence(&self) -> Duration {
+        Duration::from_secs_f32(self.silence_secs.max(0.0))
+    }
+
+    fn min_speech(&self) -> Duration {
+        Duration::from_secs_f32(self.min_speech_secs.max(0.0))
+    }
+
+    /// Samples at 16 kHz kept from the end of a segment cut mid-speech.
+    fn overlap_samples(&self) -> usize {
+        let overlap = self.overlap_secs.clamp(0.0, self.max_speech_secs.max(0.0) / 2.0);
+        (overlap * WHISPER_SAMPLE_RATE as f32) as usize
+    }
+}
+
+/// A segment of speech, at 16 kHz.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpeechSegment {
+    pub samples: Vec<f32>,
+    /// Cut at `max_speech_secs` while the speaker went on: the next segment carries on
+    /// from this one, starting with its last `overlap_secs`.
+    pub continues: bool,
+}
+
+impl SpeechSegment {
+    fn new(samples: Vec<f32>, continues: bool) -> Self {
+        SpeechSegment { samples, continues }
+    }
+}
+
+/// A configured microphone: a name (or part of it), optionally with the channels to use.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(untagged)]
+pub enum DeviceSpec {
+    Name(String),
+    Detailed {
+        name: String,
+        /// Zero-based input channels mixed into the mono signal, e.g. `[1]` for an
+        /// interface that has the mic on its second input only.
+        channels: Option<Vec<usize>>,
+    },
+}
+
+impl DeviceSpec {
+    pub fn name(&self) -> &str {
+        match self {
+            DeviceSpec::Name(name) | DeviceSpec::Detailed { name, .. } => name,
+        }
+    }
+
+    pub fn channels(&self) -> Option<&[usize]> {
+        match self {
+            DeviceSpec::Name(_) => None,
+            DeviceSpec::Detailed { channels, .. } => channels.as_deref(),
+        }
+    }
+}
+
+/// Mixes interleaved `channels`-channel audio down to mono, averaging only the
+/// `selection` channels (all of them when `None`). Out-of-range channels are ignored.
+pub fn downmix(interleaved: &[f32], channels: usize, selection: Option<&[usize]>) -> Vec<f32> {
+    let channels = channels.max(1);
+    let selected: Vec<usize> = match selection {
+        Some(selection) => selection.iter().copied().filter(|c| *c < channels).collect(),
+        None => (0..channels).collect(),
+    };
+    if selected.is_empty() {
+        return vec![0.0; interleaved.len() / channels];
+    }
+    interleaved
+        .chunks_exact(channels)
+        .map(|frame| selected.iter().map(|c| frame[*c]).sum::<f32>() / selected.len() as f32)
+        .collect()
+}
+
+/// Converts integer or float samples to f32 between -1 and 1.
+pub fn to_f32<T: SizedSample>(samples: &[T]) -> Vec<f32>
+where
+    f32: FromSample<T>,
+{
+    samples.iter().map(|&sample| f32::from_sample_(sample)).collect()
+}
+
+fn build_typed_stream<T: SizedSample>(
+    device: &Device,
+    config: &StreamConfig,
+    mut on_data: impl FnMut(&[f32]) + Send + 'static,
+    on_error: impl FnMut(cpal::StreamError) + Send + 'static,
+) -> Result<Stream, Box<dyn Error>>
+where
+    f32: FromSample<T>,
+{
+    let stream = device.build_input_stream(
+        config,
+        move |data: &[T], _: &cpal::InputCallbackInfo| on_data(&to_f32(data)),
+        on_error,
+        None,
+    )?;
+    Ok(stream)
+}
+
+/// Opens an input stream in the device's sample format, f32, i16, u16 or i32, handing
+/// `on_data` the interleaved samples converted to f32.
+pub fn build_input_stream(
+    device: &Device,
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+    on_data: impl FnMut(&[f32]) + Send + 'static,
+    on_error: impl FnMut(cpal::StreamError) + Send + 'static,
+) -> Result<Stream, Box<dyn Error>> {
+    match sample_format {
+        SampleFormat::F32 => build_typed_stream::<f32>(device, config, on_data, on_error),
+        SampleFormat::I16 => build_typed_stream::<i16>(device, config, on_data, on_error),
+        SampleFormat::U16 => build_typed_stream::<u16>(device, config, on_data, on_error),
+        SampleFormat::I32 => build_typed_stream::<i32>(device, config, on_data, on_error),
+        format => Err(format!("Unsupported sample format {:?}", format).into()),
+    }
+}
+
+/// Loudness of the latest block a device delivered, for level meters.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AudioLevel {
+    pub rms: f32,
+    /// Largest absolute sample.
+    pub peak: f32,
+}
+
+impl AudioLevel {
+    pub fn measure(samples: &[f32]) -> Self {
+        if samples.is_empty() {
+            return AudioLevel::default();
+        }
+        let energy = samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32;
+        AudioLevel {
+            rms: energy.sqrt(),
+            peak: samples.iter().fold(0.0, |peak, s| s.abs().max(peak)),
+        }
+    }
+
+    /// The RMS level in dBFS, from -100 for silence to 0 for a full-scale square wave.
+    pub fn rms_dbfs(&self) -> f32 {
+        (20.0 * self.rms.max(1e-5).log10()).min(0.0)
+    }
+}
 
 pub struct AudioProcessor {
-    pub device: Device,
-    sender: mpsc::Sender<Vec<f32>>,
-    receiver: mpsc::Receiver<Vec<f32>>,
-    // Storage for the stop signal
-    keep_alive_tx: Arc<Mutex<Option<tokio::sync::oneshot::Sender<()>>>>,
+    pub devices: Vec<Device>,
+    /// Rate each device captures at, from its default input configuration.
+    sample_rates: Vec<u32>,
+    /// Channels mixed into the mono signal of each device, all of them when `None`.
+    channel_maps: Vec<Option<Vec<usize>>>,
+    /// Chunks tagged with the index of the device they come from.
+    sender: mpsc::Sender<(usize, Vec<f32>)>,
+    receiver: mpsc::Receiver<(usize, Vec<f32>)>,
+    watchdog_settings: WatchdogSettings,
+    watchdogs: Vec<InputWatchdog>,
+    /// One detector per device, as detectors may keep state between chunks.
+    vads: Vec<Box<dyn VoiceActivityDetector>>,
+    vad_settings: VadSettings,
+    /// One pipeline per device, as its steps learn each device's noise.
+    pipelines: Vec<AudioPipeline>,
+    pipeline_steps: Vec<PipelineStep>,
+    timing: AudioSettings,
+    /// Bounds segments instead of silence detection when set, e.g. by push-to-talk.
+    gate: Option<watch::Receiver<bool>>,
+    /// Input stream of each device, capturing until dropped; `None` for a device that
+    /// couldn't be opened.
+    streams: Vec<Option<Stream>>,
+    capturing: bool,
+    paused: bool,
+    /// Indexes of the devices whose stream failed, reported by the stream callbacks.
+    failure_sender: mpsc::UnboundedSender<usize>,
+    failure_receiver: mpsc::UnboundedReceiver<usize>,
+    /// Devices whose stream failed and couldn't be reopened yet.
+    lost: BTreeSet<usize>,
+    last_recovery: Option<Instant>,
+    /// Level of each device, updated by the stream callbacks whatever reads the segments.
+    levels: Arc<watch::Sender<Vec<AudioLevel>>>,
+    /// End of the last segment when it was cut mid-speech, to start the next one with.
+    overlap: Option<Vec<f32>>,
+    /// Ambient noise being measured on each device, until its threshold is set.
+    calibrations: Vec<Option<Calibration>>,
+    /// Windows of the first device checked for the wake word while speech goes on.
+    wake_feed: Option<WakeFeed>,
+    /// Speech of the first device transcribed while the segment is recorded.
+    speech_feed: Option<SpeechFeed>,
+}
+
+/// Speech and noise statistics of one device while an utterance is being captured.
+struct DeviceBuffer {
+    noise_floor: Option<f32>,
+    speech: Vec<f32>,
+    speech_energy: f32,
+    speech_chunks: usize,
+}
+
+impl DeviceBuffer {
+    /// Ratio between the mean energy of the captured speech and the noise floor.
+    fn snr(&self) -> f32 {
+        if self.speech_chunks == 0 {
+            return 0.0;
+        }
+        let speech = self.speech_energy / self.speech_chunks as f32;
+        speech / self.noise_floor.unwrap_or(vad::DEFAULT_ENERGY_THRESHOLD).max(1e-4)
+    }
 }
 
 impl AudioProcessor {
     pub fn new(device: Device) -> Self {
-        let (sender, receiver) = mpsc::channel(100);
+        Self::with_devices(vec![(device, None)])
+    }
+
+    /// Captures from every device at once, each with its optional channel selection.
+    pub fn with_devices(devices: Vec<(Device, Option<Vec<usize>>)>) -> Self {
+        let (sender, receiver) = mpsc::channel(100 * devices.len().max(1));
+        let (failure_sender, failure_receiver) = mpsc::unbounded_channel();
+        let (devices, channel_maps): (Vec<Device>, _) = devices.into_iter().unzip();
+        let (levels, _) = watch::channel(vec![AudioLevel::default(); devices.len()]);
+        let sample_rates: Vec<u32> = devices.iter().map(device_sample_rate).collect();
+        let watchdog_settings = WatchdogSettings::default();
+        let watchdogs = sample_rates
+            .iter()
+            .map(|rate| InputWatchdog::new(watchdog_settings.clone(), *rate))
+            .collect();
+        let vads = sample_rates
+            .iter()
+            .map(|rate| vad::create(&VadSettings::default(), *rate))
+            .collect();
+        let pipelines = sample_rates
+            .iter()
+            .map(|rate| AudioPipeline::new(&[], *rate))
+            .collect();
         AudioProcessor {
-            device,
+            devices,
+            sample_rates,
+            channel_maps,
             sender,
             receiver,
-            keep_alive_tx: Arc::new(Mutex::new(None)),
+            watchdog_settings,
+            watchdogs,
+            vads,
+            vad_settings: VadSettings::default(),
+            pipelines,
+            pipeline_steps: Vec::new(),
+            timing: AudioSettings::default(),
+            gate: None,
+            streams: Vec::new(),
+            capturing: false,
+            paused: false,
+            failure_sender,
+            failure_receiver,
+            lost: BTreeSet::new(),
+            last_recovery: None,
+            levels: Arc::new(levels),
+            overlap: None,
+            calibrations: Vec::new(),
+            wake_feed: None,
+            speech_feed: None,
         }
     }
 
+    /// Replaces the default input watchdog settings.
+    pub fn set_watchdog(&mut self, settings: WatchdogSettings) {
+        self.watchdogs = self
+            .sample_rates
+            .iter()
+            .map(|rate| InputWatchdog::new(settings.clone(), *rate))
+            .collect();
+        self.watchdog_settings = settings;
+    }
+
+    /// Replaces the default energy detector of every device.
+    pub fn set_vad(&mut self, settings: &VadSettings) {
+        self.vads = self
+            .sample_rates
+            .iter()
+            .map(|rate| vad::create(settings, *rate))
+            .collect();
+        self.vad_settings = settings.clone();
+    }
+
+    /// Runs captured chunks through `steps` before speech detection, e.g. to denoise them.
+    pub fn set_pipeline(&mut self, steps: &[PipelineStep]) {
+        self.pipelines = self
+            .sample_rates
+            .iter()
+            .map(|rate| AudioPipeline::new(steps, *rate))
+            .collect();
+        self.pipeline_steps = steps.to_vec();
+    }
+
+    /// Replaces the default segment timing. The chunk size applies from the next
+    /// `start_capture`.
+    pub fn set_timing(&mut self, timing: AudioSettings) {
+        self.timing = timing;
+    }
+
+    /// Switches to the speech detection and timing of `settings`, e.g. those of a profile
+    /// switched to. The new detector's threshold is calibrated again if it can be.
+    pub fn apply_settings(&mut self, settings: &InputSettings) {
+        self.set_vad(&settings.vad);
+        self.set_timing(settings.timing.clone());
+        if self.capturing && settings.vad.calibration.enabled {
+            self.recalibrate();
+        }
+    }
+
+    /// Records only while `gate` is open: a segment starts when it opens and ends when it
+    /// closes, whatever the voice activity detector hears.
+    pub fn set_gate(&mut self, gate: watch::Receiver<bool>) {
+        self.gate = Some(gate);
+    }
+
+    /// Sends the speech of the first device to `feed` as it is heard, for the wake word
+    /// to be checked before segments are complete.
+    pub fn set_wake_feed(&mut self, feed: WakeFeed) {
+        self.wake_feed = Some(feed);
+    }
+
+    /// Sends the speech of the first device to `feed` while a segment is recorded, for it
+    /// to be transcribed before the segment is complete.
+    pub fn set_speech_feed(&mut self, feed: SpeechFeed) {
+        self.speech_feed = Some(feed);
+    }
+
+    /// Measures the ambient noise of every device again over the next
+    /// `calibration.duration_secs`, then sets the energy threshold from it. Capture goes
+    /// on meanwhile, with the previous threshold.
+    pub fn recalibrate(&mut self) {
+        if self.vad_settings.backend != VadBackend::Energy {
+            log::info!("🎚️ Only the energy detector is calibrated");
+            return;
+        }
+        log::info!("🎚️ Calibrating on the ambient noise, stay quiet for a moment");
+        self.calibrations = (0..self.devices.len()).map(|index| self.calibration(index)).collect();
+    }
+
+    /// A new calibration of the device at `index`, if its detector uses a threshold.
+    fn calibration(&self, index: usize) -> Option<Calibration> {
+        (self.vad_settings.backend == VadBackend::Energy)
+            .then(|| Calibration::new(&self.vad_settings.calibration, self.sample_rate(index)))
+    }
+
+    /// Sets the threshold of the device at `index` once its calibration is over.
+    fn calibrate(&mut self, index: usize, chunk: &[f32]) {
+        let Some(calibration) = self.calibrations.get_mut(index).and_then(Option::as_mut) else {
+            return;
+        };
+        let Some(noise_floor) = calibration.add(chunk) else {
+            return;
+        };
+        self.calibrations[index] = None;
+        let threshold = self.vad_settings.calibration.threshold(noise_floor);
+        self.vads[index].set_energy_threshold(threshold);
+        log::info!(
+            "🎚️ {}: noise floor {:.4}, speech threshold {:.4}",
+            self.devices[index].name().unwrap_or_default(),
+            noise_floor,
+            threshold
+        );
+    }
+
+    /// The current level of every device, by index, e.g. to show whether the microphone
+    /// picks anything up. Updated on every block captured, even while nothing reads the
+    /// segments.
+    pub fn level_monitor(&self) -> watch::Receiver<Vec<AudioLevel>> {
+        self.levels.subscribe()
+    }
+
+    /// Rate the device at `index` captures at.
+    pub fn sample_rate(&self, index: usize) -> u32 {
+        self.sample_rates
+            .get(index)
+            .copied()
+            .unwrap_or(FALLBACK_SAMPLE_RATE)
+    }
+
     /// Starts audio capture in a non-blocking manner.
     /// Chunks of samples are gathered and sent via a channel.
-    pub async fn start_capture(&self) -> Result<(), Box<dyn Error>> {
-        let config = self.device.default_input_config()?;
+    pub async fn start_capture(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.capturing {
+            return Err("Audio capture is already active".into());
+        }
+        let mut streams = Vec::with_capacity(self.devices.len());
+        for (index, device) in self.devices.iter().enumerate() {
+            let result = self.capture_device(index, device).and_then(|stream| {
+                stream.play()?;
+                Ok(stream)
+            });
+            match result {
+                Ok(stream) => streams.push(Some(stream)),
+                // A missing extra microphone shouldn't prevent listening on the others
+                Err(e) if self.devices.len() > 1 => {
+                    log::warn!(
+                        "Failed to capture from {}: {}",
+                        device.name().unwrap_or_default(),
+                        e
+                    );
+                    streams.push(None);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        if streams.iter().all(Option::is_none) {
+            return Err("No input device could be captured from".into());
+        }
+        self.streams = streams;
+        self.capturing = true;
+        self.paused = false;
+        if self.vad_settings.calibration.enabled {
+            self.recalibrate();
+        }
+
+        Ok(())
+    }
+
+    /// Stops capturing without closing the devices, e.g. while a long action runs.
+    pub fn pause(&mut self) -> Result<(), Box<dyn Error>> {
+        if !self.capturing {
+            return Err("Audio capture is not active".into());
+        }
+        for stream in self.streams.iter().flatten() {
+            stream.pause()?;
+        }
+        self.paused = true;
+        log::info!("⏸️ Audio capture paused");
+        Ok(())
+    }
+
+    /// Captures again after `pause`. Chunks queued before the pause are dropped, so the
+    /// next segment only holds what is said from now on.
+    pub fn resume(&mut self) -> Result<(), Box<dyn Error>> {
+        if !self.capturing {
+            return Err("Audio capture is not active".into());
+        }
+        while self.receiver.try_recv().is_ok() {}
+        for detector in &mut self.vads {
+            detector.reset();
+        }
+        for pipeline in &mut self.pipelines {
+            pipeline.reset();
+        }
+        for watchdog in &mut self.watchdogs {
+            watchdog.resume(Instant::now());
+        }
+        for stream in self.streams.iter().flatten() {
+            stream.play()?;
+        }
+        self.paused = false;
+        log::info!("▶️ Audio capture resumed");
+        Ok(())
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Closes the input streams; `start_capture` opens them again.
+    pub fn stop_capture(&mut self) -> Result<(), Box<dyn Error>> {
+        if !self.capturing {
+            return Err("Audio capture is not active or already stopped".into());
+        }
+        self.streams.clear();
+        self.lost.clear();
+        self.overlap = None;
+        self.capturing = false;
+        self.paused = false;
+        log::info!("Stopping audio capture");
+        Ok(())
+    }
+
+    fn capture_device(&self, index: usize, device: &Device) -> Result<Stream, Box<dyn Error>> {
+        let name = device.name().unwrap_or_default();
+        let config = device.default_input_config()?;
         let sample_format = config.sample_format();
+        let channels = config.channels() as usize;
         let config = config.into();
         let sender = self.sender.clone();
+        let selection = self.channel_maps.get(index).cloned().flatten();
+        if let Some(selection) = &selection {
+            log::info!("Using input channels {:?} of {}", selection, channels);
+        }
 
         // Buffer to accumulate audio samples
         let audio_data = Arc::new(Mutex::new(Vec::new()));
         let audio_data_clone = audio_data.clone();
+        let failures = self.failure_sender.clone();
+        let failed = AtomicBool::new(false);
+        let levels = self.levels.clone();
+        let chunk_samples = self.timing.chunk_samples.max(1);
+
+        let stream = build_input_stream(
+            device,
+            &config,
+            sample_format,
+            move |data: &[f32]| {
+                health::audio_heartbeat();
+                // Channels are selected here so VAD and resampling only see the mic
+                let mono = downmix(data, channels, selection.as_deref());
+                let level = AudioLevel::measure(&mono);
+                levels.send_modify(|levels| {
+                    if let Some(slot) = levels.get_mut(index) {
+                        *slot = level;
+                    }
+                });
+                if let Ok(mut buffer) = audio_data_clone.lock() {
+                    buffer.extend(mono);
 
-        // Channel to signal stream stop
-        let (keep_alive_tx, _keep_alive_rx) = tokio::sync::oneshot::channel::<()>();
-        {
-            let mut tx_lock = self.keep_alive_tx.lock().unwrap();
-            *tx_lock = Some(keep_alive_tx);
-        }
-
-        let _stream = match sample_format {
-            cpal::SampleFormat::F32 => self.device.build_input_stream(
-                &config,
-                move |data: &[f32], _| {
-                    if let Ok(mut buffer) = audio_data_clone.lock() {
-                        buffer.extend_from_slice(data);
-
-                        // Once enough samples are accumulated, send a chunk for processing
-                        if buffer.len() > 4096 {
-                            let chunk = buffer.clone();
-                            buffer.clear();
-
-                            // Use try_send instead of spawning a task
-                            if let Err(e) = sender.try_send(chunk) {
-                                match e {
-                                    tokio::sync::mpsc::error::TrySendError::Full(_) => {
-                                        log::warn!(
-                                            "Audio processing channel is full, dropping samples"
-                                        );
-                                    }
-                                    _ => {
-                                        log::error!("Audio channel closed: {}", e);
-                                    }
+                    // Once enough samples are accumulated, send a chunk for processing
+                    if buffer.len() > chunk_samples / channels.max(1) {
+                        let chunk = buffer.clone();
+                        buffer.clear();
+
+                        // Use try_send instead of spawning a task
+                        if let Err(e) = sender.try_send((index, chunk)) {
+                            match e {
+                                tokio::sync::mpsc::error::TrySendError::Full(_) => {
+                                    log::warn!(
+                                        "Audio processing channel is full, dropping samples"
+                                    );
+                                }
+                                _ => {
+                                    log::error!("Audio channel closed: {}", e);
                                 }
                             }
                         }
                     }
-                },
-                |err| log::error!("Stream error: {}", err),
-                None,
-            )?,
-            _ => return Err("Unsupported sample format".into()),
-        };
+                }
+            },
+            move |err| {
+                // An unplugged device fails on every callback; one report is enough
+                if !failed.swap(true, Ordering::Relaxed) {
+                    log::error!("Stream error on {}: {}", name, err);
+                    let _ = failures.send(index);
+                }
+            },
+        )?;
 
-        Ok(())
+        Ok(stream)
+    }
+
+    /// Reopens the devices whose stream failed, e.g. an unplugged microphone: the same
+    /// device if it is back, the default input device otherwise. Those that can't be
+    /// reopened yet are tried again on the next call.
+    fn recover(&mut self) {
+        while let Ok(index) = self.failure_receiver.try_recv() {
+            self.lost.insert(index);
+        }
+        let recent = self
+            .last_recovery
+            .is_some_and(|last| last.elapsed() < WATCHDOG_POLL);
+        if self.lost.is_empty() || !self.capturing || recent {
+            return;
+        }
+        self.last_recovery = Some(Instant::now());
+        for index in std::mem::take(&mut self.lost) {
+            if let Err(e) = self.reopen(index) {
+                log::debug!("Input device {} can't be reopened yet: {}", index, e);
+                self.lost.insert(index);
+            }
+        }
     }
 
-    /*
-    /// Optional function to stop capturing if you ever need it.
-    pub async fn stop_capture(&self) -> Result<(), Box<dyn Error>> {
-        if let Some(tx) = self.keep_alive_tx.lock().unwrap().take() {
-            let _ = tx.send(());
-            log::info!("Stopping audio capture");
-            Ok(())
+    fn reopen(&mut self, index: usize) -> Result<(), Box<dyn Error>> {
+        let lost = self.devices[index].name().unwrap_or_default();
+        // Dropping the failed stream releases the device
+        self.streams[index] = None;
+        let host = cpal::default_host();
+        let same = host
+            .input_devices()?
+            .find(|device| device.name().is_ok_and(|name| name == lost));
+        let device = match same {
+            Some(device) => device,
+            None => host
+                .default_input_device()
+                .ok_or("No input device is available")?,
+        };
+        let name = device.name().unwrap_or_default();
+        if name != lost {
+            // The channels chosen for the lost microphone mean nothing on another one
+            self.channel_maps[index] = None;
+        }
+
+        let stream = self.capture_device(index, &device)?;
+        if !self.paused {
+            stream.play()?;
+        }
+        let rate = device_sample_rate(&device);
+        if rate != self.sample_rates[index] {
+            self.sample_rates[index] = rate;
+            self.watchdogs[index] = InputWatchdog::new(self.watchdog_settings.clone(), rate);
+            self.vads[index] = vad::create(&self.vad_settings, rate);
+            self.pipelines[index] = AudioPipeline::new(&self.pipeline_steps, rate);
+            if index == 0 {
+                flashback::set_sample_rate(rate);
+            }
+        }
+        // Another microphone, or the same one elsewhere, hears another noise
+        if self.vad_settings.calibration.enabled && index < self.calibrations.len() {
+            self.calibrations[index] = self.calibration(index);
+        }
+        self.watchdogs[index].resume(Instant::now());
+        self.devices[index] = device;
+        self.streams[index] = Some(stream);
+
+        if name == lost {
+            log::info!("🎙️ {} is back, capturing from it again", name);
         } else {
-            Err("Audio capture is not active or already stopped".into())
+            log::warn!("🎙️ {} is gone, falling back to {}", lost, name);
         }
+        events::emit(Event::InputChange {
+            lost: &lost,
+            device: &name,
+        });
+        if self.watchdog_settings.notify {
+            if let Err(e) = notify_rust::Notification::new()
+                .summary(&format!("VoxAurora: {} disconnected", lost))
+                .body(&format!("Now listening on {}", name))
+                .show()
+            {
+                log::warn!("Failed to show input notification: {}", e);
+            }
+        }
+        Ok(())
     }
-    */
 
     /// Continuously listens for speech segments and returns them once they are complete.
-    /// - If silence is detected for `SILENCE_DURATION_TO_FINALIZE`, the segment is considered done.
-    /// - If the segment exceeds `MAX_SPEECH_DURATION`, it's finalized automatically.
-    pub async fn get_next_speech_segment(&mut self) -> Result<Vec<f32>, Box<dyn Error>> {
-        let channels = self.device.default_input_config()?.channels() as usize;
-        let mut is_speech_active = false;
-        let mut speech_buffer = Vec::new();
+    /// Speech starts above the detector's threshold and lasts while it stays above
+    /// `vad.offset_ratio` of it, plus `vad.hangover_secs`; only then does silence count.
+    /// - If silence is detected for `timing.silence_secs`, the segment is considered done,
+    ///   or dropped when it holds less than `timing.min_speech_secs` of speech.
+    /// - If the segment exceeds `timing.max_speech_secs`, it's finalized automatically and
+    ///   marked as continuing: the next segment starts at once, with its overlap, and is
+    ///   never dropped as too short.
+    ///
+    /// With several devices, speech on any of them starts a segment, and the segment is
+    /// taken from the device with the best signal-to-noise ratio.
+    pub async fn get_next_speech_segment(&mut self) -> Result<SpeechSegment, Box<dyn Error>> {
+        if !self.capturing {
+            return Err("Audio capture is not active".into());
+        }
+        if let Some(gate) = self.gate.clone() {
+            return self.get_next_gated_segment(gate).await;
+        }
+        let mut buffers = self.new_buffers();
+        // The speaker was still talking when the last segment was cut
+        let continuing = self.overlap.is_some();
+        let mut is_speech_active = continuing;
         let mut silence_start = Instant::now();
         let mut speech_start = Instant::now();
+        for watchdog in &mut self.watchdogs {
+            watchdog.resume(Instant::now());
+        }
+        for detector in &mut self.vads {
+            detector.reset();
+        }
 
-        while let Some(chunk) = self.receiver.recv().await {
-            let energy = chunk.iter().map(|sample| sample.abs()).sum::<f32>() / chunk.len() as f32;
+        loop {
+            // Waits at most `WATCHDOG_POLL` so a device that stopped entirely is noticed
+            let received = tokio::time::timeout(WATCHDOG_POLL, self.receiver.recv()).await;
+            self.recover();
+            let now = Instant::now();
+            for index in 0..self.watchdogs.len() {
+                if let Some(problem) = self.watchdogs[index].check_stall(now) {
+                    self.alert(index, problem);
+                }
+            }
+            if vad::take_recalibration_request() {
+                self.recalibrate();
+            }
+            let (index, chunk) = match received {
+                Ok(Some(received)) => received,
+                Ok(None) => break,
+                Err(_) => continue,
+            };
+            let Some(buffer) = buffers.get_mut(index) else {
+                continue;
+            };
+            // The watchdog checks the raw signal, as denoising could hide a broken mic
+            if let Some(problem) = self.watchdogs[index].chunk(&chunk, now) {
+                self.alert(index, problem);
+            }
+            let chunk = self.pipelines[index].process(chunk);
+            if chunk.is_empty() {
+                continue;
+            }
+            // Half-duplex: the assistant's own voice is neither speech nor room noise
+            if playback::mutes_input() {
+                if is_speech_active {
+                    log::info!("🔈 Speech heard while the assistant spoke, ignored");
+                    if let Some(feed) = &mut self.wake_feed {
+                        feed.discard();
+                    }
+                    if let Some(feed) = &mut self.speech_feed {
+                        feed.discard();
+                    }
+                    is_speech_active = false;
+                    buffers = self.new_buffers();
+                    self.overlap = None;
+                }
+                continue;
+            }
+            self.calibrate(index, &chunk);
+            let energy = vad::energy(&chunk);
 
-            if energy > SILENCE_THRESHOLD {
+            if self.vads[index].is_speech(&chunk) {
                 if !is_speech_active {
                     is_speech_active = true;
                     speech_start = Instant::now();
+                    flashback::mark_speech();
                     log::info!("🔊 Speech detected");
                 }
                 silence_start = Instant::now();
-                speech_buffer.extend_from_slice(&chunk);
-            } else if is_speech_active {
+            } else if !is_speech_active {
+                let floor = buffer.noise_floor.get_or_insert(energy);
+                *floor += (energy - *floor) * NOISE_FLOOR_SMOOTHING;
+            }
+
+            // The flashback records the first microphone, speech or not
+            if index == 0 {
+                flashback::record(&chunk);
+                if let Some(feed) = &mut self.wake_feed {
+                    feed.push(&chunk, self.sample_rates[0], is_speech_active);
+                }
+                if let Some(feed) = self.speech_feed.as_mut().filter(|_| is_speech_active) {
+                    feed.push(&chunk, self.sample_rates[0]);
+                }
+            }
+
+            if is_speech_active {
                 // We continue to accumulate samples just in case it's a brief silence
-                speech_buffer.extend_from_slice(&chunk);
+                buffer.speech.extend_from_slice(&chunk);
+                buffer.speech_energy += energy;
+                buffer.speech_chunks += 1;
 
-                if silence_start.elapsed() > SILENCE_DURATION_TO_FINALIZE {
-                    log::info!("🔇 Speech segment complete");
-                    let resampled = resample_to_16k(&speech_buffer, channels);
-                    return Ok(resampled);
+                if silence_start.elapsed() > self.timing.silence() {
+                    if continuing
+                        || silence_start.duration_since(speech_start) >= self.timing.min_speech()
+                    {
+                        log::info!("🔇 Speech segment complete");
+                        return Ok(self.finish_segment(&buffers, false));
+                    }
+                    log::info!("🔇 Speech too short, ignored");
+                    if let Some(feed) = &mut self.wake_feed {
+                        feed.discard();
+                    }
+                    if let Some(feed) = &mut self.speech_feed {
+                        feed.discard();
+                    }
+                    is_speech_active = false;
+                    buffers = self.new_buffers();
+                    for detector in &mut self.vads {
+                        detector.reset();
+                    }
+                    continue;
                 }
             }
 
-            if is_speech_active && speech_start.elapsed() > MAX_SPEECH_DURATION {
-                log::info!("⏱️ Maximum speech duration reached");
-                let resampled = resample_to_16k(&speech_buffer, channels);
-                return Ok(resampled);
+            if is_speech_active && speech_start.elapsed() > self.timing.max_speech() {
+                log::info!("⏱️ Maximum speech duration reached, the next segment carries on");
+                return Ok(self.finish_segment(&buffers, true));
             }
         }
 
         Err("Audio stream ended unexpectedly".into())
     }
+
+    /// Records from the moment `gate` opens until it closes. Audio captured while it is
+    /// closed only goes to the flashback.
+    async fn get_next_gated_segment(
+        &mut self,
+        mut gate: watch::Receiver<bool>,
+    ) -> Result<SpeechSegment, Box<dyn Error>> {
+        while !*gate.borrow_and_update() {
+            let received = tokio::select! {
+                changed = gate.changed() => {
+                    changed.map_err(|_| "Push-to-talk listener stopped")?;
+                    continue;
+                }
+                received = self.receiver.recv() => received,
+                _ = tokio::time::sleep(WATCHDOG_POLL) => {
+                    self.recover();
+                    continue;
+                }
+            };
+            let (index, chunk) = received.ok_or("Audio stream ended unexpectedly")?;
+            let Some(pipeline) = self.pipelines.get_mut(index) else {
+                continue;
+            };
+            let chunk = pipeline.process(chunk);
+            if index == 0 {
+                flashback::record(&chunk);
+            }
+        }
+
+        log::info!("🔊 Recording until the key is released");
+        flashback::mark_speech();
+        for watchdog in &mut self.watchdogs {
+            watchdog.resume(Instant::now());
+        }
+        let mut buffers = self.new_buffers();
+        let started = Instant::now();
+        loop {
+            let received = tokio::select! {
+                changed = gate.changed() => {
+                    changed.map_err(|_| "Push-to-talk listener stopped")?;
+                    None
+                }
+                received = self.receiver.recv() => Some(received),
+            };
+            match received {
+                None if !*gate.borrow_and_update() => break,
+                None => {}
+                Some(None) => return Err("Audio stream ended unexpectedly".into()),
+                Some(Some((index, chunk))) => self.record_gated(&mut buffers, index, &chunk),
+            }
+            if started.elapsed() > MAX_GATED_DURATION {
+                log::info!("⏱️ Maximum push-to-talk duration reached");
+                break;
+            }
+        }
+        // Chunks captured just before the release are still queued
+        while let Ok((index, chunk)) = self.receiver.try_recv() {
+            self.record_gated(&mut buffers, index, &chunk);
+        }
+
+        log::info!("🔇 Speech segment complete");
+        if let Some(feed) = &mut self.speech_feed {
+            feed.finish();
+        }
+        Ok(SpeechSegment::new(self.best_source(&buffers), false))
+    }
+
+    /// Adds a chunk captured while the gate is open; all of it counts as speech.
+    fn record_gated(&mut self, buffers: &mut [DeviceBuffer], index: usize, chunk: &[f32]) {
+        let Some(buffer) = buffers.get_mut(index) else {
+            return;
+        };
+        if let Some(problem) = self.watchdogs[index].chunk(chunk, Instant::now()) {
+            self.alert(index, problem);
+        }
+        let chunk = self.pipelines[index].process(chunk.to_vec());
+        if index == 0 {
+            flashback::record(&chunk);
+            if let Some(feed) = &mut self.speech_feed {
+                feed.push(&chunk, self.sample_rates[0]);
+            }
+        }
+        buffer.speech.extend_from_slice(&chunk);
+        buffer.speech_energy += vad::energy(&chunk);
+        buffer.speech_chunks += 1;
+    }
+
+    fn new_buffers(&self) -> Vec<DeviceBuffer> {
+        (0..self.devices.len())
+            .map(|_| DeviceBuffer {
+                noise_floor: None,
+                speech: Vec::new(),
+                speech_energy: 0.0,
+                speech_chunks: 0,
+            })
+            .collect()
+    }
+
+    fn alert(&self, index: usize, problem: watchdog::Problem) {
+        let device = self.devices[index].name().unwrap_or_default();
+        watchdog::alert(&device, problem, &self.watchdog_settings);
+    }
+
+    /// The segment made of the overlap of the previous one and the speech of the device
+    /// that heard it best. When it `continues`, its end is kept for the next one. The
+    /// wake stream is sent what it hasn't checked yet, and the transcription stream told
+    /// the segment ended.
+    fn finish_segment(&mut self, buffers: &[DeviceBuffer], continues: bool) -> SpeechSegment {
+        if let Some(feed) = &mut self.wake_feed {
+            feed.finish();
+        }
+        if let Some(feed) = &mut self.speech_feed {
+            feed.finish();
+        }
+        let mut samples = self.overlap.take().unwrap_or_default();
+        samples.extend(self.best_source(buffers));
+        if continues {
+            let start = samples.len().saturating_sub(self.timing.overlap_samples());
+            self.overlap = Some(samples[start..].to_vec());
+        }
+        SpeechSegment::new(samples, continues)
+    }
+
+    /// Resamples the speech of the device that heard it best.
+    fn best_source(&self, buffers: &[DeviceBuffer]) -> Vec<f32> {
+        let Some((index, best)) = buffers
+            .iter()
+            .enumerate()
+            .filter(|(_, buffer)| !buffer.speech.is_empty())
+            .max_by(|a, b| a.1.snr().total_cmp(&b.1.snr()))
+        else {
+            return Vec::new();
+        };
+        if self.devices.len() > 1 {
+            log::info!(
+                "🎙️ Using {} (SNR {:.1})",
+                self.devices[index].name().unwrap_or_default(),
+                best.snr()
+            );
+        }
+        // Chunks are already mono
+        resample_from_rate(&best.speech, 1, self.sample_rate(index))
+    }
+}
+
+/// Rate of the default input configuration of `device`, which is what gets captured.
+fn device_sample_rate(device: &Device) -> u32 {
+    match device.default_input_config() {
+        Ok(config) => {
+            let rate = config.sample_rate().0;
+            log::info!("{} captures at {} Hz", device.name().unwrap_or_default(), rate);
+            rate
+        }
+        Err(e) => {
+            log::warn!(
+                "No input configuration for {}, assuming {} Hz: {}",
+                device.name().unwrap_or_default(),
+                FALLBACK_SAMPLE_RATE,
+                e
+            );
+            FALLBACK_SAMPLE_RATE
+        }
+    }
+}
+
+/// Samples per chunk given to the detector, 64ms at 16kHz, about what a device sends.
+const FILE_CHUNK: usize = 1024;
+
+/// Cuts a stream of 16kHz mono chunks into speech segments like `AudioProcessor` does,
+/// with durations counted in samples rather than on the clock. Used for audio that
+/// doesn't come from a local device: files and network streams.
+pub struct Segmenter {
+    vad: Box<dyn VoiceActivityDetector>,
+    timing: AudioSettings,
+    /// Speech of the segment in progress, starting with the overlap of the previous
+    /// segment when it was cut mid-speech.
+    speech: Vec<f32>,
+    /// Samples of silence at the end of `speech`.
+    silence: usize,
+    /// The segment in progress carries on from one cut at the maximum duration.
+    continuing: bool,
+}
+
+impl Segmenter {
+    pub fn new(settings: &VadSettings, timing: AudioSettings) -> Self {
+        Segmenter {
+            vad: vad::create(settings, WHISPER_SAMPLE_RATE),
+            timing,
+            speech: Vec::new(),
+            silence: 0,
+            continuing: false,
+        }
+    }
+
+    pub fn set_timing(&mut self, timing: AudioSettings) {
+        self.timing = timing;
+    }
+
+    /// Switches to the speech detection and timing of `settings`, keeping the segment in
+    /// progress.
+    pub fn apply_settings(&mut self, settings: &InputSettings) {
+        self.vad = vad::create(&settings.vad, WHISPER_SAMPLE_RATE);
+        self.timing = settings.timing.clone();
+    }
+
+    /// Adds a chunk; returns the segment it completes. A segment ends after
+    /// `timing.silence_secs` of silence or after `timing.max_speech_secs`. Segments
+    /// shorter than `timing.min_speech_secs` are dropped, unless they carry on from a
+    /// segment cut at the maximum duration, which they start with the overlap of.
+    pub fn push(&mut self, chunk: &[f32]) -> Option<SpeechSegment> {
+        let rate = WHISPER_SAMPLE_RATE as f32;
+        let silence_limit = (self.timing.silence().as_secs_f32() * rate) as usize;
+        let speech_limit = (self.timing.max_speech().as_secs_f32() * rate) as usize;
+
+        if self.vad.is_speech(chunk) {
+            self.silence = 0;
+        } else if self.speech.is_empty() {
+            return None;
+        } else {
+            self.silence += chunk.len();
+        }
+        self.speech.extend_from_slice(chunk);
+        if self.silence > silence_limit {
+            if self.long_enough() {
+                return Some(self.cut(false));
+            }
+            self.speech.clear();
+            self.silence = 0;
+            self.vad.reset();
+        } else if self.speech.len() > speech_limit {
+            return Some(self.cut(true));
+        }
+        None
+    }
+
+    /// The segment in progress when the audio ends, if long enough.
+    pub fn finish(&mut self) -> Option<SpeechSegment> {
+        let segment = (!self.speech.is_empty() && self.long_enough())
+            .then(|| SpeechSegment::new(std::mem::take(&mut self.speech), false));
+        self.speech.clear();
+        self.silence = 0;
+        self.continuing = false;
+        self.vad.reset();
+        segment
+    }
+
+    fn long_enough(&self) -> bool {
+        let min_speech =
+            (self.timing.min_speech().as_secs_f32() * WHISPER_SAMPLE_RATE as f32) as usize;
+        self.continuing || self.speech.len() - self.silence >= min_speech
+    }
+
+    /// Ends the segment in progress. When it `continues`, the next one starts with its
+    /// last `timing.overlap_secs`.
+    fn cut(&mut self, continues: bool) -> SpeechSegment {
+        let speech = std::mem::take(&mut self.speech);
+        if continues {
+            let start = speech.len().saturating_sub(self.timing.overlap_samples());
+            self.speech = speech[start..].to_vec();
+        }
+        self.continuing = continues;
+        self.silence = 0;
+        self.vad.reset();
+        SpeechSegment::new(speech, continues)
+    }
+}
+
+/// Speech segments read from a recorded WAV or FLAC file instead of a microphone, for
+/// batch-testing recognition. Segments are cut like `AudioProcessor` cuts them, with
+/// durations counted in file time, so a file is processed as fast as it transcribes.
+pub struct FileSource {
+    /// The whole file, 16kHz mono.
+    samples: Vec<f32>,
+    position: usize,
+    segmenter: Segmenter,
+}
+
+impl FileSource {
+    /// Decodes the file at `path`, whatever its rate, keeping the channels of
+    /// `input.channels` like a live device does.
+    pub fn open(path: &str, settings: &InputSettings) -> Result<Self, Box<dyn Error>> {
+        let bytes = fs::read(path).map_err(|e| format!("Can't read {}: {}", path, e))?;
+        let samples = codec::decode_channels(&bytes, settings.channels.as_deref())
+            .map_err(|e| format!("Can't decode {}: {}", path, e))?;
+        log::info!(
+            "📁 Reading {} ({:.1} s)",
+            path,
+            samples.len() as f32 / WHISPER_SAMPLE_RATE as f32
+        );
+        let mut source = Self::from_samples(samples, &settings.vad);
+        source.set_timing(settings.timing.clone());
+        Ok(source)
+    }
+
+    /// Reads already decoded 16kHz mono samples.
+    pub fn from_samples(samples: Vec<f32>, settings: &VadSettings) -> Self {
+        FileSource {
+            samples,
+            position: 0,
+            segmenter: Segmenter::new(settings, AudioSettings::default()),
+        }
+    }
+
+    /// Replaces the default segment timing; the chunk size doesn't apply to files.
+    pub fn set_timing(&mut self, timing: AudioSettings) {
+        self.segmenter.set_timing(timing);
+    }
+
+    /// Switches to the speech detection and timing of `settings`.
+    pub fn apply_settings(&mut self, settings: &InputSettings) {
+        self.segmenter.apply_settings(settings);
+    }
+
+    /// Whether the whole file has been read.
+    pub fn finished(&self) -> bool {
+        self.position >= self.samples.len()
+    }
+
+    /// How far the file has been read, e.g. up to the end of the last segment.
+    pub fn position(&self) -> Duration {
+        Duration::from_secs_f32(self.position as f32 / WHISPER_SAMPLE_RATE as f32)
+    }
+
+    /// The next speech segment of the file; an error once it has all been read.
+    pub async fn get_next_speech_segment(&mut self) -> Result<SpeechSegment, Box<dyn Error>> {
+        self.next_segment().ok_or_else(|| "End of the input file".into())
+    }
+
+    /// Cuts the next segment, see `Segmenter::push`; the last one ends with the file.
+    pub fn next_segment(&mut self) -> Option<SpeechSegment> {
+        while !self.finished() {
+            let end = (self.position + FILE_CHUNK).min(self.samples.len());
+            let chunk = &self.samples[self.position..end];
+            self.position = end;
+            if let Some(segment) = self.segmenter.push(chunk) {
+                return Some(segment);
+            }
+        }
+        self.segmenter.finish()
+    }
+}
+
+/// Raw audio streamed by satellites, read from the `input.network` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct NetworkSettings {
+    /// Rate of the received audio; anything but 16kHz is resampled.
+    pub sample_rate: u32,
+    /// Interleaved channels of the received audio, averaged into one.
+    pub channels: usize,
+}
+
+impl Default for NetworkSettings {
+    fn default() -> Self {
+        NetworkSettings {
+            sample_rate: WHISPER_SAMPLE_RATE,
+            channels: 1,
+        }
+    }
+}
+
+/// Speech segments cut from audio streamed over the network instead of a microphone,
+/// e.g. by a Raspberry Pi satellite, with the same detector and timing as live audio.
+///
+/// The stream is little-endian 16-bit PCM at `input.network.sample_rate`, interleaved
+/// when it has several channels, with no header:
+/// - `udp://HOST:PORT`: each datagram holds the next samples
+/// - `ws://HOST:PORT`: each binary message holds the next samples, one client at a time
+///
+/// Unlike `--serve`, the satellite sends everything it hears and the speech is found
+/// here, so it needs no model nor any speech detection of its own.
+pub struct NetworkSource {
+    receiver: mpsc::Receiver<Vec<f32>>,
+    segmenter: Segmenter,
+}
+
+impl NetworkSource {
+    /// Starts receiving audio on `address` in the background.
+    pub fn bind(address: &str, settings: &InputSettings) -> Result<Self, Box<dyn Error>> {
+        let (sender, receiver) = mpsc::channel(100);
+        let converter = PcmConverter::new(&settings.network);
+        if let Some(address) = address.strip_prefix("udp://") {
+            let socket = std::net::UdpSocket::bind(address)?;
+            log::info!("📡 Receiving PCM audio over UDP on {}", address);
+            std::thread::spawn(move || receive_udp(socket, converter, sender));
+        } else if let Some(address) = address.strip_prefix("ws://") {
+            let listener = std::net::TcpListener::bind(address)?;
+            log::info!("📡 Receiving PCM audio over WebSocket on {}", address);
+            std::thread::spawn(move || receive_websocket(listener, converter, sender));
+        } else {
+            return Err(format!(
+                "Unsupported audio address {}, expected udp://HOST:PORT or ws://HOST:PORT",
+                address
+            )
+            .into());
+        }
+        Ok(NetworkSource {
+            receiver,
+            segmenter: Segmenter::new(&settings.vad, settings.timing.clone()),
+        })
+    }
+
+    /// Switches to the speech detection and timing of `settings`.
+    pub fn apply_settings(&mut self, settings: &InputSettings) {
+        self.segmenter.apply_settings(settings);
+    }
+
+    /// Waits until the received audio holds a complete speech segment.
+    pub async fn get_next_speech_segment(&mut self) -> Result<SpeechSegment, Box<dyn Error>> {
+        loop {
+            let chunk = self
+                .receiver
+                .recv()
+                .await
+                .ok_or("Network audio receiver stopped")?;
+            health::audio_heartbeat();
+            if let Some(segment) = self.segmenter.push(&chunk) {
+                log::info!("🔇 Speech segment complete");
+                return Ok(segment);
+            }
+        }
+    }
+}
+
+/// Turns received bytes into 16kHz mono chunks of about `FILE_CHUNK` samples, whatever
+/// the size of the datagrams or messages they came in.
+pub struct PcmConverter {
+    sample_rate: u32,
+    channels: usize,
+    /// Byte of a sample split between two datagrams.
+    odd_byte: Option<u8>,
+    /// Samples at the received rate, waiting for a full chunk.
+    pending: Vec<f32>,
+}
+
+impl PcmConverter {
+    pub fn new(settings: &NetworkSettings) -> Self {
+        PcmConverter {
+            sample_rate: settings.sample_rate.max(1),
+            channels: settings.channels.max(1),
+            odd_byte: None,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Adds received bytes; returns the chunks they complete.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Vec<f32>> {
+        let mut bytes = bytes;
+        if let (Some(low), Some((&high, rest))) = (self.odd_byte, bytes.split_first()) {
+            self.pending.extend(to_f32(&[i16::from_le_bytes([low, high])]));
+            self.odd_byte = None;
+            bytes = rest;
+        }
+        let pairs = bytes.chunks_exact(2);
+        self.odd_byte = pairs.remainder().first().copied().or(self.odd_byte);
+        let samples: Vec<i16> = pairs
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        self.pending.extend(to_f32(&samples));
+
+        // Whole frames, about 64ms of them at the received rate
+        let frames = (FILE_CHUNK * self.sample_rate as usize / WHISPER_SAMPLE_RATE as usize).max(1);
+        let chunk = frames * self.channels;
+        let mut chunks = Vec::new();
+        while self.pending.len() >= chunk {
+            let samples: Vec<f32> = self.pending.drain(..chunk).collect();
+            chunks.push(resample_from_rate(&samples, self.channels, self.sample_rate));
+        }
+        chunks
+    }
+}
+
+fn forward(sender: &mpsc::Sender<Vec<f32>>, chunks: Vec<Vec<f32>>) -> bool {
+    for chunk in chunks {
+        match sender.try_send(chunk) {
+            Ok(()) => {}
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                log::warn!("Audio processing channel is full, dropping samples");
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => return false,
+        }
+    }
+    true
+}
+
+fn receive_udp(
+    socket: std::net::UdpSocket,
+    mut converter: PcmConverter,
+    sender: mpsc::Sender<Vec<f32>>,
+) {
+    let mut buffer = vec![0; 65_536];
+    let mut last_peer = None;
+    loop {
+        let (len, peer) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(e) => {
+                log::warn!("Failed to receive network audio: {}", e);
+                continue;
+            }
+        };
+        if last_peer.replace(peer) != Some(peer) {
+            log::info!("🔌 Receiving audio from {}", peer);
+        }
+        if !forward(&sender, converter.push(&buffer[..len])) {
+            return;
+        }
+    }
+}
+
+fn receive_websocket(
+    listener: std::net::TcpListener,
+    mut converter: PcmConverter,
+    sender: mpsc::Sender<Vec<f32>>,
+) {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                log::error!("Failed to accept audio client: {}", e);
+                continue;
+            }
+        };
+        let peer = stream
+            .peer_addr()
+            .map(|peer| peer.to_string())
+            .unwrap_or_default();
+        let mut socket = match tungstenite::accept(stream) {
+            Ok(socket) => socket,
+            Err(e) => {
+                log::warn!("WebSocket handshake with {} failed: {}", peer, e);
+                continue;
+            }
+        };
+        log::info!("🔌 Audio client connected: {}", peer);
+        loop {
+            match socket.read() {
+                Ok(tungstenite::Message::Binary(bytes)) => {
+                    if !forward(&sender, converter.push(&bytes)) {
+                        return;
+                    }
+                }
+                Ok(tungstenite::Message::Close(_)) => break,
+                Ok(_) => {}
+                Err(e) => {
+                    log::warn!("Audio client {} failed: {}", peer, e);
+                    break;
+                }
+            }
+        }
+        log::info!("Audio client disconnected: {}", peer);
+    }
 }
 
 /// Lets the user pick a device interactively, or defaults to the system's default device.
+/// Without a terminal on stdin, e.g. when run as a service, the default device is used
+/// without asking.
 pub fn get_device() -> Result<Device, Box<dyn Error>> {
     let host = cpal::default_host();
+    if !std::io::stdin().is_terminal() {
+        let device = host.default_input_device().ok_or("No input device found")?;
+        log::info!("🎙️ No terminal to ask on, using the default device: {}", device.name()?);
+        return Ok(device);
+    }
     let devices = host.input_devices()?;
 
     println!("Available input devices:");
@@ -170,34 +1452,268 @@ pub fn get_device() -> Result<Device, Box<dyn Error>> {
     Ok(device)
 }
 
-/// Resamples the given audio data to 16kHz mono.
-/// Uses rubato for chunked FFT-based resampling.
-fn resample_to_16k(input: &[f32], channels: usize) -> Vec<f32> {
+/// Input devices whose name contains the name of one of `specs` (ignoring case), in the
+/// order of `specs`, with the channels to use on each.
+pub fn find_devices(
+    specs: &[DeviceSpec],
+    default_channels: Option<&[usize]>,
+) -> Result<Vec<(Device, Option<Vec<usize>>)>, Box<dyn Error>> {
+    let host = cpal::default_host();
+    let mut found: Vec<(String, Device, Option<Vec<usize>>)> = Vec::new();
+    for spec in specs {
+        let name = spec.name();
+        let wanted = name.to_lowercase();
+        let device = host.input_devices()?.find_map(|device| {
+            let device_name = device.name().ok()?;
+            device_name
+                .to_lowercase()
+                .contains(&wanted)
+                .then_some((device_name, device))
+        });
+        let channels = spec.channels().or(default_channels).map(<[usize]>::to_vec);
+        match device {
+            Some((device_name, _)) if found.iter().any(|(n, _, _)| *n == device_name) => {}
+            Some((device_name, device)) => found.push((device_name, device, channels)),
+            None => log::warn!("No input device matches '{}'", name),
+        }
+    }
+    Ok(found
+        .into_iter()
+        .map(|(_, device, channels)| (device, channels))
+        .collect())
+}
+
+/// Opens the devices listed in `settings`, or asks for one when none is listed or found.
+pub fn open_input(settings: &InputSettings) -> Result<AudioProcessor, Box<dyn Error>> {
+    let mut devices = find_devices(&settings.devices, settings.channels.as_deref())?;
+    if devices.is_empty() {
+        devices.push((get_device()?, settings.channels.clone()));
+    } else {
+        for (device, _) in &devices {
+            println!("Using device: {}", device.name()?);
+        }
+    }
+    let mut processor = AudioProcessor::with_devices(devices);
+    processor.set_watchdog(settings.watchdog.clone());
+    processor.set_vad(&settings.vad);
+    processor.set_pipeline(&settings.pipeline);
+    processor.set_timing(settings.timing.clone());
+    if settings.push_to_talk.enabled {
+        processor.set_gate(hotkey::listen(&settings.push_to_talk));
+    }
+    Ok(processor)
+}
+
+/// Resamples interleaved audio recorded at `sample_rate` to 16kHz mono.
+/// Uses rubato for chunked FFT-based resampling. Any rate works: the last chunk is
+/// processed partially, and the resampler's delay is trimmed so the output is exactly
+/// as long as the input.
+pub fn resample_from_rate(input: &[f32], channels: usize, sample_rate: u32) -> Vec<f32> {
     // Downmix to mono by averaging channels
+    let channels = channels.max(1);
     let mono_input: Vec<f32> = input
         .chunks(channels)
         .map(|frame| frame.iter().sum::<f32>() / channels as f32)
         .collect();
 
-    // Create a resampler for mono (1 channel), chunk size 1323
-    let mut resampler = rubato::FftFixedInOut::<f32>::new(44100, 16000, 1323, 1)
-        .expect("Error creating resampler");
+    if sample_rate == WHISPER_SAMPLE_RATE || mono_input.is_empty() {
+        return mono_input;
+    }
 
-    let mut output = Vec::new();
+    // Create a resampler for mono (1 channel), with about 30ms chunks; rubato rounds the
+    // size to a multiple the rate ratio allows
+    let chunk_size = (sample_rate as usize * 3 / 100).max(1);
+    let mut resampler = rubato::FftFixedInOut::<f32>::new(
+        sample_rate as usize,
+        WHISPER_SAMPLE_RATE as usize,
+        chunk_size,
+        1,
+    )
+    .expect("Error creating resampler");
+    let chunk_size = resampler.input_frames_next();
+    let delay = resampler.output_delay();
+    let expected =
+        (mono_input.len() as u64 * WHISPER_SAMPLE_RATE as u64 / sample_rate as u64) as usize;
 
-    // Process each 1323-sample chunk
-    for chunk in mono_input.chunks(1323) {
-        let mut frame = chunk.to_vec();
-        if frame.len() < 1323 {
-            // Pad with zeros if not enough samples
-            frame.resize(1323, 0.0);
-        }
-        // The resampler expects a slice of length 1323 for each channel
+    let mut output = Vec::with_capacity(expected + delay + resampler.output_frames_max());
+    let mut chunks = mono_input.chunks_exact(chunk_size);
+    for chunk in &mut chunks {
+        let res = resampler.process(&[chunk], None).expect("Resampling failed");
+        // The output is a Vec of one Vec<f32>, so we take channel 0
+        output.extend_from_slice(&res[0]);
+    }
+    let remainder = chunks.remainder();
+    if !remainder.is_empty() {
         let res = resampler
-            .process(&[&frame[..]], None)
+            .process_partial(Some(&[remainder]), None)
+            .expect("Resampling failed");
+        output.extend_from_slice(&res[0]);
+    }
+    // Flush the samples still held back by the resampler's delay
+    while output.len() < expected + delay {
+        let res = resampler
+            .process_partial::<&[f32]>(None, None)
             .expect("Resampling failed");
-        // The output is a Vec of one Vec<f32>, so we take channel 0
         output.extend_from_slice(&res[0]);
     }
+
+    output.drain(..delay);
+    output.truncate(expected);
     output
 }
+
+/// Resamples mono audio captured at `sample_rate` to 16kHz as it arrives, with one
+/// resampler kept across chunks so their boundaries don't leave artifacts. The
+/// resampler's delay is trimmed once, at the start of the stream.
+pub struct StreamResampler {
+    sample_rate: u32,
+    resampler: Option<rubato::FftFixedInOut<f32>>,
+    /// Input waiting for a full resampler chunk.
+    pending: Vec<f32>,
+    /// Output samples of the delay still to drop.
+    skip: usize,
+}
+
+impl StreamResampler {
+    pub fn new(sample_rate: u32) -> Self {
+        let resampler = (sample_rate != WHISPER_SAMPLE_RATE).then(|| {
+            let chunk_size = (sample_rate as usize * 3 / 100).max(1);
+            rubato::FftFixedInOut::<f32>::new(
+                sample_rate as usize,
+                WHISPER_SAMPLE_RATE as usize,
+                chunk_size,
+                1,
+            )
+            .expect("Error creating resampler")
+        });
+        let skip = resampler.as_ref().map_or(0, |r| r.output_delay());
+        StreamResampler {
+            sample_rate,
+            resampler,
+            pending: Vec::new(),
+            skip,
+        }
+    }
+
+    /// The rate the input is captured at.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Resamples `chunk`, returning the 16kHz samples available so far.
+    pub fn process(&mut self, chunk: &[f32]) -> Vec<f32> {
+        let Some(resampler) = self.resampler.as_mut() else {
+            return chunk.to_vec();
+        };
+        self.pending.extend_from_slice(chunk);
+        let chunk_size = resampler.input_frames_next();
+        let mut output = Vec::new();
+        let mut consumed = 0;
+        for input in self.pending.chunks_exact(chunk_size) {
+            let res = resampler.process(&[input], None).expect("Resampling failed");
+            output.extend_from_slice(&res[0]);
+            consumed += chunk_size;
+        }
+        self.pending.drain(..consumed);
+        let skipped = self.skip.min(output.len());
+        self.skip -= skipped;
+        output.drain(..skipped);
+        output
+    }
+
+    /// Forgets the audio of the stream so far, for a new one to start.
+    pub fn reset(&mut self) {
+        if let Some(resampler) = self.resampler.as_mut() {
+            resampler.reset();
+            self.skip = resampler.output_delay();
+        }
+        self.pending.clear();
+    }
+}
+
+/// Decodes a WAV file (16-bit PCM or 32-bit float) to 16kHz mono.
+pub fn decode_wav(bytes: &[u8]) -> Result<Vec<f32>, Box<dyn Error>> {
+    decode_wav_channels(bytes, None)
+}
+
+/// Like `decode_wav`, averaging only the `selection` channels (all of them when `None`).
+pub fn decode_wav_channels(
+    bytes: &[u8],
+    selection: Option<&[usize]>,
+) -> Result<Vec<f32>, Box<dyn Error>> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err("Not a WAV file".into());
+    }
+
+    let mut format: Option<(u16, usize, u32, u16)> = None;
+    let mut offset = 12;
+    while offset + 8 <= bytes.len() {
+        let id = &bytes[offset..offset + 4];
+        let size = u32::from_le_bytes(bytes[offset + 4..offset + 8].try_into()?) as usize;
+        let body = &bytes[offset + 8..(offset + 8 + size).min(bytes.len())];
+
+        match id {
+            b"fmt " if body.len() >= 16 => {
+                let tag = u16::from_le_bytes([body[0], body[1]]);
+                let channels = u16::from_le_bytes([body[2], body[3]]) as usize;
+                let sample_rate = u32::from_le_bytes(body[4..8].try_into()?);
+                let bits = u16::from_le_bytes([body[14], body[15]]);
+                format = Some((tag, channels, sample_rate, bits));
+            }
+            b"data" => {
+                let (tag, channels, sample_rate, bits) = format.ok_or("WAV data before format")?;
+                if channels == 0 {
+                    return Err("WAV file has no channels".into());
+                }
+                let samples: Vec<f32> = match (tag, bits) {
+                    (1, 16) => body
+                        .chunks_exact(2)
+                        .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / i16::MAX as f32)
+                        .collect(),
+                    (3, 32) => body
+                        .chunks_exact(4)
+                        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                        .collect(),
+                    _ => {
+                        return Err(
+                            format!("Unsupported WAV encoding ({} bits, format {})", bits, tag)
+                                .into(),
+                        );
+                    }
+                };
+                let mono = downmix(&samples, channels, selection);
+                return Ok(resample_from_rate(&mono, 1, sample_rate));
+            }
+            _ => {}
+        }
+        // Chunks are padded to an even size
+        offset += 8 + size + size % 2;
+    }
+
+    Err("WAV file has no data".into())
+}
+
+/// Encodes 16kHz mono samples as a 16-bit PCM WAV file.
+pub fn encode_wav(samples: &[f32]) -> Vec<u8> {
+    const SAMPLE_RATE: u32 = 16000;
+    let data_len = (samples.len() * 2) as u32;
+
+    let mut wav = Vec::with_capacity(44 + samples.len() * 2);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+    wav.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes()); // byte rate
+    wav.extend_from_slice(&2u16.to_le_bytes()); // block align
+    wav.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        let pcm = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        wav.extend_from_slice(&pcm.to_le_bytes());
+    }
+    wav
+}
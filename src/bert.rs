@@ -1,40 +1,338 @@
+use once_cell::sync::{Lazy, OnceCell};
 use rust_bert::pipelines::sentence_embeddings::{
     SentenceEmbeddingsBuilder, SentenceEmbeddingsModel, SentenceEmbeddingsModelType,
 };
-use std::cell::RefCell;
-use std::thread_local;
-
-thread_local! {
-    /// Thread-local storage for the sentence embeddings model.
-    static SENTENCE_EMBEDDINGS_MODEL: RefCell<Option<SentenceEmbeddingsModel>> = const { RefCell::new(None) };
-}
-
-/// Retrieves or initializes the global Sentence Embeddings model.
-/// Once initialized, it will stay in memory for the remainder of the program.
-pub fn get_model() -> &'static SentenceEmbeddingsModel {
-    SENTENCE_EMBEDDINGS_MODEL.with(|model_cell| {
-        let mut model_ref = model_cell.borrow_mut();
-        if model_ref.is_none() {
-            *model_ref = Some(
-                SentenceEmbeddingsBuilder::remote(SentenceEmbeddingsModelType::AllMiniLmL6V2)
-                    .create_model()
-                    .expect("Failed to initialize Sentence Embeddings model"),
-            );
+use crate::embedding_store::{self, EmbeddingStore, MemoryStore, StoreBackend};
+use crate::supervisor::{self, Subsystem};
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use tch::Device;
+
+/// Identifies the embeddings model in the on-disk cache, so switching models invalidates it.
+pub const MODEL_ID: &str = "rust-bert/AllMiniLmL6V2";
+
+/// Minimum similarity for `find_best_match` to accept a candidate.
+pub const MATCH_THRESHOLD: f32 = 0.75;
+
+/// Recent sentences kept with their embeddings, so the wake check and the matcher
+/// encoding the same utterance only run the model once.
+const RECENT_CAPACITY: usize = 32;
+
+/// Where the embeddings model runs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BertDevice {
+    /// The first GPU when CUDA is available, the CPU otherwise.
+    #[default]
+    Auto,
+    Cpu,
+    /// The CUDA GPU of this index.
+    Cuda(usize),
+}
+
+impl BertDevice {
+    /// Reads `"auto"`, `"cpu"`, `"cuda"` (the first GPU) or `"cuda:N"`.
+    pub fn parse(spec: &str) -> Result<BertDevice, String> {
+        match spec.trim().to_lowercase().as_str() {
+            "auto" => Ok(BertDevice::Auto),
+            "cpu" => Ok(BertDevice::Cpu),
+            "cuda" => Ok(BertDevice::Cuda(0)),
+            spec => spec
+                .strip_prefix("cuda:")
+                .and_then(|index| index.parse().ok())
+                .map(BertDevice::Cuda)
+                .ok_or_else(|| {
+                    format!("Unknown BERT device '{}' (auto, cpu, cuda or cuda:N)", spec)
+                }),
         }
-        // This is safe because the RefCell lives for the entire program.
-        unsafe { &*(model_ref.as_ref().unwrap() as *const SentenceEmbeddingsModel) }
-    })
+    }
+}
+
+fn device_from_str<'de, D>(deserializer: D) -> Result<BertDevice, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    BertDevice::parse(&String::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+}
+
+/// Embeddings model settings, read from the `bert` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug, Default)]
+#[serde(default)]
+pub struct BertSettings {
+    /// `"auto"` (default), `"cpu"`, `"cuda"` or `"cuda:N"`; the CPU is used when the
+    /// GPU fails.
+    #[serde(deserialize_with = "device_from_str")]
+    #[schemars(with = "String")]
+    pub device: BertDevice,
+}
+
+/// Process-wide model settings, set once after the config is loaded.
+static BERT_SETTINGS: OnceCell<BertSettings> = OnceCell::new();
+
+/// Set once a model failed to load on the GPU.
+static GPU_FAILED: AtomicBool = AtomicBool::new(false);
+
+/// Installs the model settings. Must be called before the first model is loaded.
+pub fn init(settings: &BertSettings) {
+    if BERT_SETTINGS.set(settings.clone()).is_err() {
+        log::warn!("BERT settings were already initialized, ignoring new values");
+    }
+}
+
+fn settings() -> &'static BertSettings {
+    BERT_SETTINGS.get_or_init(BertSettings::default)
+}
+
+/// The CUDA device of `index`, if this machine has it.
+fn cuda_device(index: usize) -> Result<Device, String> {
+    let count = tch::Cuda::device_count();
+    if count == 0 {
+        return Err("no CUDA device available".to_string());
+    }
+    if index as i64 >= count {
+        return Err(format!("only {} CUDA devices available", count));
+    }
+    Ok(Device::Cuda(index))
 }
 
-/// Encodes a single sentence into a vector of floats.
+/// Loads the model on the configured device, or on the CPU if the GPU is missing or
+/// runs out of memory.
+fn create_model() -> SentenceEmbeddingsModel {
+    let builder = || SentenceEmbeddingsBuilder::remote(SentenceEmbeddingsModelType::AllMiniLmL6V2);
+    let gpu = match settings().device {
+        BertDevice::Auto => tch::Cuda::is_available().then_some(0),
+        BertDevice::Cpu => None,
+        BertDevice::Cuda(index) => Some(index),
+    };
+    // Once the GPU failed, reloads after parking go straight to the CPU
+    if let Some(index) = gpu.filter(|_| !GPU_FAILED.load(Ordering::Relaxed)) {
+        let model = cuda_device(index).and_then(|device| {
+            builder()
+                .with_device(device)
+                .create_model()
+                .map_err(|e| e.to_string())
+        });
+        match model {
+            Ok(model) => {
+                log::info!("🧠 Embeddings model loaded on cuda:{}", index);
+                return model;
+            }
+            Err(e) => {
+                log::warn!(
+                    "Couldn't load the embeddings model on cuda:{}, using the CPU: {}",
+                    index,
+                    e
+                );
+                GPU_FAILED.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+    let model = builder()
+        .with_device(Device::Cpu)
+        .create_model()
+        .expect("Failed to initialize Sentence Embeddings model");
+    log::info!("🧠 Embeddings model loaded on the CPU");
+    model
+}
+
+/// A request to the thread owning the model.
+enum Request {
+    /// Loads the model if needed, answering once it is loaded.
+    Load(mpsc::Sender<()>),
+    Unload,
+    Encode(String, mpsc::Sender<Result<Vec<f32>, String>>),
+}
+
+/// The one embeddings model of the process, owned by a thread of its own that serves
+/// requests in order, whatever thread of the runtime or the blocking pool sends them.
+static MODEL_REQUESTS: Lazy<mpsc::Sender<Request>> = Lazy::new(|| {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::Builder::new()
+        .name("bert".to_string())
+        .spawn(move || serve(receiver))
+        .expect("Failed to start the embeddings model thread");
+    sender
+});
+
+/// Runs the requests sent to `MODEL_REQUESTS` until the process exits.
+fn serve(requests: mpsc::Receiver<Request>) {
+    let mut model: Option<SentenceEmbeddingsModel> = None;
+    for request in requests {
+        match request {
+            Request::Load(done) => {
+                let _ = supervisor::run(Subsystem::Bert, || {
+                    model.get_or_insert_with(create_model);
+                });
+                let _ = done.send(());
+            }
+            Request::Unload => model = None,
+            Request::Encode(sentence, reply) => {
+                let output = supervisor::run(Subsystem::Bert, || {
+                    model.get_or_insert_with(create_model).encode(&[sentence])
+                })
+                .and_then(|output| output.map_err(|e| e.to_string()))
+                .map(|mut output| output.swap_remove(0));
+                let _ = reply.send(output);
+            }
+        }
+    }
+}
+
+fn send(request: Request) -> Result<(), String> {
+    MODEL_REQUESTS
+        .send(request)
+        .map_err(|_| "The embeddings model thread stopped".to_string())
+}
+
+/// Loads the Sentence Embeddings model, if it isn't loaded yet.
+pub fn load_model() {
+    let (done, loaded) = mpsc::channel();
+    if send(Request::Load(done)).is_ok() {
+        let _ = loaded.recv();
+    }
+}
+
+/// Frees the model; it is reloaded on next use. Requests already queued run first.
+pub fn unload_model() {
+    if let Err(e) = send(Request::Unload) {
+        log::error!("{}", e);
+    }
+}
+
+static RECENT: Lazy<Mutex<VecDeque<(String, Vec<f32>)>>> = Lazy::new(Mutex::default);
+
+fn recent(sentence: &str) -> Option<Vec<f32>> {
+    let recent = RECENT.lock().unwrap_or_else(|e| e.into_inner());
+    recent
+        .iter()
+        .find(|(text, _)| text == sentence)
+        .map(|(_, embedding)| embedding.clone())
+}
+
+fn remember(sentence: &str, embedding: &[f32]) {
+    let mut recent = RECENT.lock().unwrap_or_else(|e| e.into_inner());
+    if recent.len() >= RECENT_CAPACITY {
+        recent.pop_front();
+    }
+    recent.push_back((sentence.to_string(), embedding.to_vec()));
+}
+
+/// Encodes a single sentence into a vector of floats, queued behind the other requests
+/// to the shared model.
 pub fn encode_sentence(
     sentence: &str,
 ) -> Result<Vec<f32>, Box<dyn std::error::Error + Send + Sync>> {
-    let model = get_model();
-    let output = model
-        .encode(&[sentence])
-        .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
-    Ok(output[0].clone())
+    if let Some(embedding) = recent(sentence) {
+        return Ok(embedding);
+    }
+    let (reply, answer) = mpsc::channel();
+    send(Request::Encode(sentence.to_string(), reply))?;
+    let embedding = answer
+        .recv()
+        .map_err(|_| "The embeddings model thread stopped".to_string())??;
+    remember(sentence, &embedding);
+    Ok(embedding)
+}
+
+/// Embedding cache settings, read from the `embedding_cache` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct EmbeddingCacheSettings {
+    /// Persist embeddings between runs; when disabled they are kept in memory only.
+    pub enabled: bool,
+    pub path: String,
+    pub backend: StoreBackend,
+    /// Look up similar phrases through an HNSW index instead of comparing them all.
+    pub hnsw: bool,
+}
+
+impl Default for EmbeddingCacheSettings {
+    fn default() -> Self {
+        EmbeddingCacheSettings {
+            enabled: true,
+            path: "./cache/embeddings.json".to_string(),
+            backend: StoreBackend::Json,
+            hnsw: false,
+        }
+    }
+}
+
+/// Embeddings of fixed phrases (triggers, wake variants), shared by every similarity lookup.
+static EMBEDDING_STORE: Lazy<Mutex<Box<dyn EmbeddingStore>>> =
+    Lazy::new(|| Mutex::new(Box::new(MemoryStore::default())));
+
+/// A panic while the store is locked must not break every later lookup.
+fn lock_store() -> std::sync::MutexGuard<'static, Box<dyn EmbeddingStore>> {
+    EMBEDDING_STORE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Opens the configured embedding store, discarding entries built with another model.
+pub fn init_embedding_cache(settings: &EmbeddingCacheSettings) {
+    let backend = if settings.enabled {
+        settings.backend
+    } else {
+        StoreBackend::Memory
+    };
+    let store = embedding_store::open(backend, &settings.path, MODEL_ID, settings.hnsw);
+    if backend != StoreBackend::Memory {
+        log::info!("Loaded {} cached embeddings from {}", store.len(), settings.path);
+    }
+    *lock_store() = store;
+}
+
+/// Encodes a fixed phrase, reusing its cached embedding when available.
+pub fn encode_cached(
+    sentence: &str,
+) -> Result<Vec<f32>, Box<dyn std::error::Error + Send + Sync>> {
+    if let Some(embedding) = lock_store().get(sentence) {
+        return Ok(embedding);
+    }
+
+    let embedding = encode_sentence(sentence)?;
+    lock_store().insert(sentence, embedding.clone());
+    Ok(embedding)
+}
+
+/// The `k` of `phrases` most similar to `input_embedding`, best first, encoding those not
+/// stored yet. Approximate with `embedding_cache.hnsw`.
+pub fn nearest<'a>(
+    input_embedding: &[f32],
+    phrases: impl IntoIterator<Item = &'a str>,
+    k: usize,
+) -> Result<Vec<(String, f32)>, Box<dyn std::error::Error + Send + Sync>> {
+    let phrases: HashSet<&str> = phrases.into_iter().collect();
+    let missing: Vec<&str> = {
+        let store = lock_store();
+        phrases.iter().copied().filter(|phrase| !store.contains(phrase)).collect()
+    };
+    for phrase in missing {
+        encode_cached(phrase)?;
+    }
+    Ok(lock_store().nearest(input_embedding, k, &|text| phrases.contains(text)))
+}
+
+/// Pre-encodes every fixed phrase at startup and persists the store.
+/// Entries for phrases no longer in the config are dropped.
+pub fn warm_up(phrases: &[&str]) {
+    for phrase in phrases {
+        if let Err(e) = encode_cached(phrase) {
+            log::error!("Failed to encode '{}': {}", phrase, e);
+        }
+    }
+
+    let keep: HashSet<&str> = phrases.iter().copied().collect();
+    let mut store = lock_store();
+    let before = store.len();
+    store.retain(&|text| keep.contains(text));
+    if store.len() != before {
+        log::info!("Config changed, embedding cache refreshed");
+    }
+    if let Err(e) = store.flush() {
+        log::error!("Failed to save embedding cache: {}", e);
+    }
 }
 
 /// Computes the cosine similarity between two float slices.
@@ -51,27 +349,41 @@ pub fn find_best_match<T: AsRef<str> + Clone>(
     input: &str,
     candidates: &[T],
 ) -> Result<Option<(T, f32)>, Box<dyn std::error::Error + Send + Sync>> {
-    let input_embedding = encode_sentence(input)?;
-    let threshold = 0.75;
-    let mut best_score = 0.0;
-    let mut best_candidate: Option<T> = None;
+    best_match_for(&encode_sentence(input)?, candidates)
+}
+
+/// `find_best_match` for an input already encoded.
+pub fn best_match_for<T: AsRef<str> + Clone>(
+    input_embedding: &[f32],
+    candidates: &[T],
+) -> Result<Option<(T, f32)>, Box<dyn std::error::Error + Send + Sync>> {
+    best_match_with(input_embedding, candidates, |_| MATCH_THRESHOLD)
+}
 
+/// `best_match_for` with a threshold of its own for each candidate.
+pub fn best_match_with<T: AsRef<str> + Clone>(
+    input_embedding: &[f32],
+    candidates: &[T],
+    threshold_of: impl Fn(&T) -> f32,
+) -> Result<Option<(T, f32)>, Box<dyn std::error::Error + Send + Sync>> {
+    // The first candidate of a phrase wins, as when they were compared in order
+    let mut by_phrase: HashMap<&str, &T> = HashMap::new();
     for candidate in candidates {
-        let candidate_str = candidate.as_ref();
-        let candidate_embedding = encode_sentence(candidate_str)?;
-        let similarity = cosine_similarity(&input_embedding, &candidate_embedding);
+        by_phrase.entry(candidate.as_ref()).or_insert(candidate);
+    }
 
+    let scored = nearest(input_embedding, by_phrase.keys().copied(), by_phrase.len())?;
+    for (phrase, similarity) in scored {
         log::info!(
             "Comparing input with candidate '{}': similarity = {:.3}",
-            candidate_str,
+            phrase,
             similarity
         );
 
-        if similarity > threshold && similarity > best_score {
-            best_score = similarity;
-            best_candidate = Some(candidate.clone());
+        let candidate = by_phrase[phrase.as_str()];
+        if similarity > 0.0 && similarity > threshold_of(candidate) {
+            return Ok(Some((candidate.clone(), similarity)));
         }
     }
-
-    Ok(best_candidate.map(|c| (c, best_score)))
+    Ok(None)
 }
@@ -0,0 +1,131 @@
+use crate::actions::{primary_modifier, send_shortcut};
+use enigo::Key;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::error::Error;
+use std::process::Command;
+
+/// Web browser settings, read from the `browser` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct BrowserSettings {
+    /// Search URL, with `{query}` replaced by the URL-encoded query.
+    pub search_url: String,
+    /// Browser executable to open pages with; the system default browser when unset.
+    pub command: Option<String>,
+}
+
+impl Default for BrowserSettings {
+    fn default() -> Self {
+        BrowserSettings {
+            search_url: "https://duckduckgo.com/?q={query}".to_string(),
+            command: None,
+        }
+    }
+}
+
+/// A browser action.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BrowserCommand {
+    /// Opens a URL; `https://` is added when no scheme is given.
+    Open(String),
+    /// Searches the web for a query.
+    Search(String),
+    /// Opens a new tab, with a URL if given.
+    NewTab(Option<String>),
+    CloseTab,
+    NextTab,
+    PreviousTab,
+    /// Switches to the n-th tab (1 to 9, 9 being the last one).
+    Tab(u8),
+}
+
+impl BrowserCommand {
+    /// Parses the part of a `browser:` action after the prefix ("open github.com", "tab 2").
+    pub fn parse(spec: &str) -> Result<Self, Box<dyn Error>> {
+        let spec = spec.trim();
+        let (verb, rest) = spec.split_once(' ').unwrap_or((spec, ""));
+        let rest = rest.trim();
+        match verb {
+            "open" if !rest.is_empty() => Ok(BrowserCommand::Open(rest.to_string())),
+            "search" if !rest.is_empty() => Ok(BrowserCommand::Search(rest.to_string())),
+            "new-tab" => Ok(BrowserCommand::NewTab(
+                Some(rest.to_string()).filter(|url| !url.is_empty()),
+            )),
+            "close-tab" => Ok(BrowserCommand::CloseTab),
+            "next-tab" => Ok(BrowserCommand::NextTab),
+            "previous-tab" => Ok(BrowserCommand::PreviousTab),
+            "tab" => match rest.parse::<u8>() {
+                Ok(n @ 1..=9) => Ok(BrowserCommand::Tab(n)),
+                _ => Err(format!("Tab number must be between 1 and 9, got '{}'", rest).into()),
+            },
+            _ => Err(format!("Unknown browser command: '{}'", spec).into()),
+        }
+    }
+}
+
+/// The search URL for `query`.
+pub fn search_url(query: &str, settings: &BrowserSettings) -> String {
+    settings
+        .search_url
+        .replace("{query}", &urlencoding::encode(query))
+}
+
+/// Adds `https://` to URLs spoken without a scheme ("github.com").
+pub fn normalize_url(url: &str) -> String {
+    if url.contains("://") || url.starts_with("about:") || url.starts_with("file:") {
+        url.to_string()
+    } else {
+        format!("https://{}", url)
+    }
+}
+
+/// Runs a `browser:` action.
+pub fn execute_browser(spec: &str, settings: &BrowserSettings) -> Result<(), Box<dyn Error>> {
+    let command = BrowserCommand::parse(spec)?;
+    log::info!("🌐 Browser: {:?}", command);
+
+    // Tab handling has no portable API; the focused browser gets its standard shortcuts
+    let modifier = primary_modifier();
+    match command {
+        BrowserCommand::Open(url) | BrowserCommand::NewTab(Some(url)) => {
+            open_url(&normalize_url(&url), settings)
+        }
+        BrowserCommand::Search(query) => open_url(&search_url(&query, settings), settings),
+        BrowserCommand::NewTab(None) => send_shortcut(&[modifier], Key::Unicode('t')),
+        BrowserCommand::CloseTab => send_shortcut(&[modifier], Key::Unicode('w')),
+        BrowserCommand::NextTab => send_shortcut(&[Key::Control], Key::Tab),
+        BrowserCommand::PreviousTab => send_shortcut(&[Key::Control, Key::Shift], Key::Tab),
+        BrowserCommand::Tab(n) => {
+            let digit = char::from_digit(n as u32, 10).unwrap_or('1');
+            send_shortcut(&[modifier], Key::Unicode(digit))
+        }
+    }
+}
+
+/// Opens `url` in the configured browser, or the system default one.
+fn open_url(url: &str, settings: &BrowserSettings) -> Result<(), Box<dyn Error>> {
+    match &settings.command {
+        Some(browser) => {
+            Command::new(browser).arg(url).spawn()?;
+            Ok(())
+        }
+        None => open_default(url),
+    }
+}
+
+/// Opens `url` with the handler the system associates with its scheme (browser, mail client).
+pub fn open_default(url: &str) -> Result<(), Box<dyn Error>> {
+    let mut command = if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(target_os = "windows") {
+        // `cmd /C start` would split URLs on `&`
+        let mut handler = Command::new("rundll32");
+        handler.arg("url.dll,FileProtocolHandler");
+        handler
+    } else {
+        Command::new("xdg-open")
+    };
+    command.arg(url).spawn()?;
+    Ok(())
+}
@@ -0,0 +1,673 @@
+use crate::actions::{self, OutputFormat, TextSink};
+use crate::clock;
+use crate::command_state::{CommandState, SharedCommandState};
+use crate::config::{Command, Config};
+use crate::dialog;
+use crate::dictation;
+use crate::events;
+use crate::flashback;
+use crate::grid::GridDialog;
+use crate::groups;
+use crate::macros::{self, NameDialog};
+use crate::messaging::MessageDialog;
+use crate::music;
+use crate::routing;
+use crate::services;
+use crate::vad;
+use crate::weather;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::time::Duration;
+use strsim::normalized_levenshtein;
+
+/// Minimum similarity between a spoken name and a trigger/profile to accept it.
+const NAME_SIMILARITY_THRESHOLD: f64 = 0.6;
+
+/// "désactive la commande lance chrome", "active le profil rust", "désactive le groupe média",
+/// "désactive les commandes shell"
+static TOGGLE_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(concat!(
+        r"^(?P<verb>désactive|active|réactive)\s+",
+        r"(?P<what>la commande|le profil|le groupe|les commandes)\s+",
+        r"(?P<target>.+)$"
+    ))
+    .unwrap()
+});
+
+/// "joue daft punk", "play bohemian rhapsody"
+static MUSIC_SEARCH_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(?:joue|play)\s+(?P<query>.+)$").unwrap());
+
+/// "transcris les 30 dernières secondes", "transcris la dernière minute",
+/// "transcribe the last 30 seconds"
+static FLASHBACK_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(concat!(
+        r"^(?:transcris|transcribe) (?:les |la |the )?(?:dernières |dernière |last )?",
+        r"(?:(?P<count>\d+) )?(?:dernières )?(?P<unit>secondes?|seconds?|minutes?)$"
+    ))
+    .unwrap()
+});
+
+/// "envoie un message à marie", "send a message"
+static MESSAGE_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(concat!(
+        r"^(?:envoie|envoyer|send) (?:un|a) (?:message|mail|e-mail|email|sms)",
+        r"(?:\s+(?:à|a|to)\s+(?P<recipient>.+))?$"
+    ))
+    .unwrap()
+});
+
+/// "enregistre une macro", "enregistre une macro ouvre mes outils", "record a macro"
+static RECORD_MACRO_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(?:enregistre|record) (?:une |la |a )?macro(?:\s+(?P<name>.+))?$").unwrap()
+});
+
+/// "lance la macro ouvre mes outils", "play the macro …"
+static PLAY_MACRO_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(?:lance|exécute|joue|run|play) (?:la |the )?macro (?P<name>.+)$").unwrap()
+});
+
+/// "est-ce que le serveur tourne", "is the dev server running"
+static SERVICE_STATUS_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(concat!(
+        r"^(?:(?:est-ce que|est ce que) (?:le |la |les |l')?(?P<fr>.+?) (?:tourne|tournent|marche)",
+        r"(?: encore| toujours)?|is (?:the )?(?P<en>.+?) (?:still )?running)$"
+    ))
+    .unwrap()
+});
+
+/// "insère le snippet signature", "insert the snippet …"
+static SNIPPET_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(?:insère|insérer|tape|insert|type) (?:le |the )?snippet (?P<name>.+)$").unwrap()
+});
+
+/// "passe au profil salon", "utilise le profil casque", "switch to profile desk"
+static PROFILE_SWITCH_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(concat!(
+        r"^(?:passe (?:au|en|sur le) profil|utilise le profil|switch to (?:the )?profile)",
+        r"\s+(?P<name>.+)$"
+    ))
+    .unwrap()
+});
+
+/// Names going back to the profile of the config after "passe au profil".
+const DEFAULT_PROFILE_NAMES: &[&str] = &["par défaut", "default"];
+
+/// Phrases asking which services are running.
+const SERVICES_PHRASES: &[&str] = &[
+    "quels services tournent",
+    "qu'est-ce qui tourne",
+    "which services are running",
+];
+
+/// Phrases ending the recording of a macro.
+const END_MACRO_PHRASES: &[&str] = &[
+    "fin de macro",
+    "fin de la macro",
+    "termine la macro",
+    "end of macro",
+    "stop recording",
+];
+
+/// Phrases showing the mouse grid.
+const GRID_PHRASES: &[&str] = &[
+    "grille",
+    "grille souris",
+    "affiche la grille",
+    "mouse grid",
+    "show the grid",
+];
+
+/// Phrases asking which commands are available.
+const HELP_PHRASES: &[&str] = &[
+    "qu'est-ce que je peux dire",
+    "que puis-je dire",
+    "quelles sont les commandes",
+    "liste des commandes",
+    "what can i say",
+    "aide",
+];
+
+/// Phrases asking for the time.
+const TIME_PHRASES: &[&str] = &[
+    "quelle heure est-il",
+    "quelle heure il est",
+    "il est quelle heure",
+    "what time is it",
+];
+
+/// Phrases asking for the date.
+const DATE_PHRASES: &[&str] = &[
+    "quel jour sommes-nous",
+    "quel jour on est",
+    "on est quel jour",
+    "quelle est la date",
+    "what day is it",
+];
+
+/// Phrases asking for the weather.
+const WEATHER_PHRASES: &[&str] = &[
+    "quel temps fait-il",
+    "quel temps il fait",
+    "quelle est la météo",
+    "la météo",
+    "what's the weather",
+];
+
+/// Phrases measuring the ambient noise again, e.g. after moving to a noisier room.
+const RECALIBRATE_PHRASES: &[&str] = &[
+    "recalibre le micro",
+    "recalibre le microphone",
+    "calibre le micro",
+    "recalibrate microphone",
+    "recalibrate the microphone",
+];
+
+/// Phrases switching dictated text to the clipboard.
+const CLIPBOARD_PHRASES: &[&str] = &[
+    "mode presse-papier",
+    "mode presse papier",
+    "colle dans le presse-papier",
+    "clipboard mode",
+];
+
+/// Phrases switching dictated text back to simulated typing.
+const KEYBOARD_PHRASES: &[&str] = &["mode clavier", "tape au clavier", "keyboard mode"];
+
+/// Phrases sending the dictation buffer.
+const SEND_PHRASES: &[&str] = &["envoie", "insère", "envoie le texte", "send", "insert"];
+
+/// Phrases removing the last utterance of the dictation buffer.
+const UNDO_PHRASES: &[&str] = &[
+    "efface la dernière phrase",
+    "supprime la dernière phrase",
+    "delete the last sentence",
+];
+
+/// Phrases emptying the dictation buffer without sending it.
+const CLEAR_PHRASES: &[&str] = &["efface tout", "vide le texte", "clear the buffer"];
+
+/// The built-in phrases worth listing on a cheat sheet, with what they do. Only the
+/// first phrasing of each is given; the dictation ones need `dictation.buffer`.
+pub fn examples() -> Vec<(&'static str, &'static str)> {
+    vec![
+        (HELP_PHRASES[0], "lists the available commands"),
+        (TIME_PHRASES[0], "tells the time"),
+        (DATE_PHRASES[0], "tells the date"),
+        (WEATHER_PHRASES[0], "tells the weather"),
+        (RECALIBRATE_PHRASES[0], "measures the ambient noise again"),
+        (CLIPBOARD_PHRASES[0], "sends dictated text to the clipboard"),
+        (KEYBOARD_PHRASES[0], "types dictated text again"),
+        ("désactive la commande …", "disables a command (or `active`)"),
+        ("active le profil …", "enables every command of a profile"),
+        ("passe au profil …", "switches to a profile and its audio settings"),
+        ("joue …", "plays an artist or a title"),
+        ("transcris les 30 dernières secondes", "types what was just said"),
+        ("envoie un message à …", "dictates a message"),
+        ("est-ce que … tourne", "tells whether a service is running"),
+        ("enregistre une macro …", "records keyboard and mouse until “fin de macro”"),
+        ("lance la macro …", "plays a recorded macro (or just say its name)"),
+        ("insère le snippet …", "types a snippet from the config"),
+        (GRID_PHRASES[1], "moves the mouse through a numbered grid, then “clic”"),
+        (SEND_PHRASES[0], "sends the dictation buffer"),
+        (UNDO_PHRASES[0], "removes the last dictated sentence"),
+        (CLEAR_PHRASES[0], "empties the dictation buffer"),
+    ]
+}
+
+/// How many triggers are announced by the help command; the full list is printed.
+const HELP_SUMMARY_LEN: usize = 5;
+
+/// Lowercases and strips the punctuation Whisper puts around short utterances.
+pub fn normalize(text: &str) -> String {
+    text.trim()
+        .trim_matches(|c: char| c.is_ascii_punctuation() || c.is_whitespace())
+        .to_lowercase()
+        .replace('’', "'")
+}
+
+/// Handles built-in voice commands that act on VoxAurora itself.
+/// Returns a short response when `text` was a built-in, `None` otherwise.
+pub fn handle_builtin(text: &str, config: &Config, state: &SharedCommandState) -> Option<String> {
+    // A dialog waiting for an answer gets the utterance before anything else
+    if let Some(response) = dialog::handle(text) {
+        return Some(response);
+    }
+
+    let normalized = normalize(text);
+
+    if let Some(caps) = MESSAGE_RE.captures(&normalized) {
+        let recipient = caps.name("recipient").map(|m| m.as_str());
+        let (dialog, question) =
+            MessageDialog::new(&config.messaging, &config.secrets, recipient);
+        return Some(dialog::start(Box::new(dialog), question));
+    }
+
+    if GRID_PHRASES.contains(&normalized.as_str()) {
+        return Some(match GridDialog::new(&config.grid) {
+            Ok((dialog, question)) => dialog::start(Box::new(dialog), question),
+            Err(e) => format!("Couldn't show the mouse grid: {}", e),
+        });
+    }
+
+    if HELP_PHRASES.iter().any(|phrase| normalized == *phrase) {
+        let state = state.lock().unwrap_or_else(|e| e.into_inner());
+        return Some(help(config, &state));
+    }
+
+    // Simple questions answered without any command
+    if TIME_PHRASES.contains(&normalized.as_str()) {
+        return Some(clock::current_time());
+    }
+    if DATE_PHRASES.contains(&normalized.as_str()) {
+        return Some(clock::current_date());
+    }
+    if WEATHER_PHRASES.contains(&normalized.as_str()) {
+        return Some(weather::answer(&config.weather));
+    }
+    if RECALIBRATE_PHRASES.contains(&normalized.as_str()) {
+        vad::request_recalibration();
+        return Some("Calibrating the microphone, stay quiet for a moment".to_string());
+    }
+
+    let sink = if CLIPBOARD_PHRASES.contains(&normalized.as_str()) {
+        Some((TextSink::Clipboard, "clipboard"))
+    } else if KEYBOARD_PHRASES.contains(&normalized.as_str()) {
+        Some((TextSink::Keyboard, "keyboard"))
+    } else {
+        None
+    };
+    if let Some((sink, target)) = sink {
+        let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+        state.set_output_sink(Some(sink));
+        return Some(format!("Dictated text now goes to the {}", target));
+    }
+
+    if let Some(response) = handle_service_status(&normalized, config) {
+        return Some(response);
+    }
+
+    if let Some(response) = handle_macro(&normalized, config, state) {
+        return Some(response);
+    }
+
+    if let Some(response) = handle_snippet(&normalized, config, state) {
+        return Some(response);
+    }
+
+    if let Some(response) = handle_profile_switch(&normalized, config, state) {
+        return Some(response);
+    }
+
+    if let Some(response) = handle_dictation(&normalized, config, state) {
+        return Some(response);
+    }
+
+    if let Some(response) = handle_flashback(&normalized, config, state) {
+        return Some(response);
+    }
+
+    if let Some(response) = handle_music_search(&normalized, config, state) {
+        return Some(response);
+    }
+
+    handle_toggle(&normalized, config, state)
+}
+
+/// "joue <artiste / titre>": searches the music player for the spoken text.
+/// Commands whose trigger is exactly the spoken text keep precedence, and disabling
+/// the "music" kind disables the search too.
+fn handle_music_search(
+    normalized: &str,
+    config: &Config,
+    state: &SharedCommandState,
+) -> Option<String> {
+    let disabled = {
+        let state = state.lock().unwrap_or_else(|e| e.into_inner());
+        state.disabled_kinds.contains("music")
+    };
+    if !config.music.voice_search || disabled {
+        return None;
+    }
+    let caps = MUSIC_SEARCH_RE.captures(normalized)?;
+    if config
+        .commands
+        .iter()
+        .flat_map(|command| &command.triggers)
+        .any(|trigger| trigger.to_lowercase() == normalized)
+    {
+        return None;
+    }
+
+    let query = caps["query"].trim();
+    let search = music::MusicCommand::Search(query.to_string());
+    let result = music::controller(&config.music, &config.secrets)
+        .and_then(|controller| controller.send(&search));
+    Some(match result {
+        Ok(()) => format!("Playing {}", query),
+        Err(e) => format!("Couldn't play {}: {}", query, e),
+    })
+}
+
+/// "transcris les 30 dernières secondes": types what was said before the request, even
+/// while VoxAurora was sleeping.
+fn handle_flashback(
+    normalized: &str,
+    config: &Config,
+    state: &SharedCommandState,
+) -> Option<String> {
+    let caps = FLASHBACK_RE.captures(normalized)?;
+    let count = caps
+        .name("count")
+        .and_then(|count| count.as_str().parse::<u64>().ok());
+    let seconds = if caps["unit"].starts_with("minute") {
+        count.unwrap_or(1) * 60
+    } else {
+        count.unwrap_or(30)
+    };
+    let seconds = seconds.min(config.flashback.seconds);
+
+    let text = match flashback::transcribe_recent(Duration::from_secs(seconds), "fr") {
+        Ok(text) if !text.is_empty() => text,
+        Ok(_) => return Some(format!("Nothing was said in the last {} seconds", seconds)),
+        Err(e) => return Some(format!("Couldn't transcribe the last {} seconds: {}", seconds, e)),
+    };
+
+    // Sent like dictated text, following the runtime sink and routing rules
+    if let Err(e) = actions::execute_enigo_text(text.clone(), &dictation_output(config, state)) {
+        log::error!("Failed to output the flashback: {}", e);
+    }
+    Some(format!("Last {} seconds: {}", seconds, text))
+}
+
+/// "est-ce que le serveur tourne ?", "quels services tournent ?": answered from the
+/// services `service:start` launched.
+fn handle_service_status(normalized: &str, config: &Config) -> Option<String> {
+    if config.services.is_empty() {
+        return None;
+    }
+    if SERVICES_PHRASES.contains(&normalized) {
+        let running: Vec<String> = services::statuses()
+            .into_iter()
+            .filter(|(_, status)| status.is_running())
+            .map(|(name, _)| name)
+            .collect();
+        return Some(if running.is_empty() {
+            "No service is running".to_string()
+        } else {
+            format!("Running: {}", running.join(", "))
+        });
+    }
+    let caps = SERVICE_STATUS_RE.captures(normalized)?;
+    let spoken = caps.name("fr").or(caps.name("en"))?.as_str().trim();
+    let name = closest_name(spoken, config.services.keys().map(String::as_str))?;
+    Some(services::status(&name).describe(&name))
+}
+
+/// "enregistre une macro …" to "fin de macro": records keyboard and mouse events, then
+/// plays them back when the macro's name is said.
+fn handle_macro(normalized: &str, config: &Config, state: &SharedCommandState) -> Option<String> {
+    if let Some(caps) = RECORD_MACRO_RE.captures(normalized) {
+        let name = caps.name("name").map(|name| name.as_str().trim().to_string());
+        return Some(match macros::start(name) {
+            Ok(()) => "Recording a macro, say “fin de macro” when done".to_string(),
+            Err(e) => e.to_string(),
+        });
+    }
+    if END_MACRO_PHRASES.contains(&normalized) {
+        let Some((name, mut steps)) = macros::finish() else {
+            return Some("No macro is being recorded".to_string());
+        };
+        // The hotkey pressed to say "fin de macro" isn't part of what was demonstrated
+        if config.input.push_to_talk.enabled {
+            steps = macros::without_key(steps, &config.input.push_to_talk.key);
+        }
+        if steps.is_empty() {
+            return Some("Nothing was recorded".to_string());
+        }
+        return Some(match name {
+            Some(name) => macros::saved(&config.macros, &name, steps),
+            None => {
+                let (dialog, question) = NameDialog::new(&config.macros, steps);
+                dialog::start(Box::new(dialog), question)
+            }
+        });
+    }
+    // Saying a macro's own name while recording would replay it into the recording
+    let disabled = {
+        let state = state.lock().unwrap_or_else(|e| e.into_inner());
+        state.disabled_kinds.contains("macro")
+    };
+    if disabled || macros::is_recording() {
+        return None;
+    }
+
+    let recorded = match macros::load(&config.macros) {
+        Ok(recorded) => recorded,
+        Err(e) => {
+            log::warn!("Failed to load the macros from {}: {}", config.macros.file, e);
+            return None;
+        }
+    };
+    let name = match PLAY_MACRO_RE.captures(normalized) {
+        Some(caps) => closest_name(caps["name"].trim(), recorded.keys().map(String::as_str))?,
+        None if recorded.contains_key(normalized) => normalized.to_string(),
+        None => return None,
+    };
+    Some(match macros::play(&name, &config.macros) {
+        Ok(()) => format!("Played macro '{}'", name),
+        Err(e) => format!("Couldn't play the macro '{}': {}", name, e),
+    })
+}
+
+/// "insère le snippet <nom>": types the snippet whose name is closest to the spoken one,
+/// like dictated text, placeholders expanded.
+fn handle_snippet(normalized: &str, config: &Config, state: &SharedCommandState) -> Option<String> {
+    if config.snippets.is_empty() {
+        return None;
+    }
+    let caps = SNIPPET_RE.captures(normalized)?;
+    let spoken = caps["name"].trim();
+    let Some(name) = closest_name(spoken, config.snippets.keys().map(String::as_str)) else {
+        return Some(format!("No snippet named '{}'", spoken));
+    };
+    let text = match actions::expand_placeholders(
+        &config.snippets[&name],
+        &config.action_context(),
+        chrono::Local::now(),
+    ) {
+        Ok(text) => text,
+        Err(e) => return Some(format!("Couldn't insert the snippet '{}': {}", name, e)),
+    };
+    if let Err(e) = actions::execute_enigo_text(text, &dictation_output(config, state)) {
+        log::error!("Failed to output the snippet: {}", e);
+    }
+    Some(format!("Inserted snippet '{}'", name))
+}
+
+/// "passe au profil <nom>": makes the profile closest to the spoken name the active one,
+/// its output and audio settings included. "par défaut" goes back to the config's.
+fn handle_profile_switch(
+    normalized: &str,
+    config: &Config,
+    state: &SharedCommandState,
+) -> Option<String> {
+    let caps = PROFILE_SWITCH_RE.captures(normalized)?;
+    let spoken = caps["name"].trim();
+    let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+    if DEFAULT_PROFILE_NAMES.contains(&spoken) {
+        state.set_active_profile(None);
+        return Some("Back to the default profile".to_string());
+    }
+    let Some(name) = closest_name(spoken, config.profiles.keys().map(String::as_str)) else {
+        return Some(format!("No profile named '{}'", spoken));
+    };
+    state.set_active_profile(Some(name.clone()));
+    Some(format!("Switched to profile '{}'", name))
+}
+
+/// "envoie", "efface la dernière phrase", "efface tout": edits and sends the dictation
+/// buffer, when `dictation.buffer` is on.
+fn handle_dictation(
+    normalized: &str,
+    config: &Config,
+    state: &SharedCommandState,
+) -> Option<String> {
+    if !config.dictation.buffer {
+        return None;
+    }
+    if SEND_PHRASES.contains(&normalized) {
+        let text = dictation::take();
+        if text.is_empty() {
+            return Some("Nothing to send".to_string());
+        }
+        let output = dictation_output(config, state);
+        if let Err(e) = actions::execute_enigo_text(text.clone(), &output) {
+            log::error!("Failed to output the dictation buffer: {}", e);
+        }
+        return Some(format!("Sent: {}", text));
+    }
+    if UNDO_PHRASES.contains(&normalized) {
+        return Some(match dictation::undo(&config.dictation) {
+            Some(removed) => format!("Removed: {}", removed),
+            None => "The dictation buffer is empty".to_string(),
+        });
+    }
+    if CLEAR_PHRASES.contains(&normalized) {
+        dictation::clear(&config.dictation);
+        return Some("Dictation buffer cleared".to_string());
+    }
+    None
+}
+
+/// Where dictated text goes: the runtime sink and routing rules override the config.
+fn dictation_output(config: &Config, state: &SharedCommandState) -> OutputFormat {
+    let mut output = config.output_format().clone();
+    {
+        let state = state.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(profile) = &state.active_profile {
+            output = config.output_format_of(Some(profile)).clone();
+        }
+        if let Some(sink) = state.output_sink {
+            output.sink = sink;
+        }
+    }
+    if let Some(sink) = routing::route_focused(&config.routing) {
+        output.sink = sink;
+    }
+    output
+}
+
+/// Commands that can currently be matched: enabled, and allowed in the focused app.
+pub fn available_commands<'a>(config: &'a Config, state: &CommandState) -> Vec<&'a Command> {
+    let mut available: Vec<&Command> = config
+        .commands
+        .iter()
+        .filter(|command| state.is_enabled(command))
+        .collect();
+    groups::retain_in_context(&mut available);
+    available
+}
+
+/// Prints every available trigger and returns a summary of the first few.
+fn help(config: &Config, state: &CommandState) -> String {
+    let available = available_commands(config, state);
+
+    // The full list would break `--output json`; the summary is still emitted as an event
+    if !events::json_output() {
+        println!("Available commands ({}):", available.len());
+        for command in &available {
+            println!("  - {}", command.triggers.join(" / "));
+        }
+    }
+
+    let summary: Vec<&str> = available
+        .iter()
+        .take(HELP_SUMMARY_LEN)
+        .map(|command| command.trigger())
+        .collect();
+    format!(
+        "{} commands available, for example: {}",
+        available.len(),
+        summary.join(", ")
+    )
+}
+
+/// "désactive la commande …", "active le profil …", "désactive le groupe …",
+/// "désactive les commandes shell"
+fn handle_toggle(normalized: &str, config: &Config, state: &SharedCommandState) -> Option<String> {
+    let caps = TOGGLE_RE.captures(normalized)?;
+
+    let enabled = &caps["verb"] != "désactive";
+    let target = caps["target"].trim();
+    let verb = if enabled { "enabled" } else { "disabled" };
+    let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+
+    match &caps["what"] {
+        "la commande" => {
+            let triggers = config.commands.iter().map(|c| c.trigger());
+            let trigger = closest_name(target, triggers)?;
+            state.set_command(&trigger, enabled);
+            Some(format!("Command '{}' {}", trigger, verb))
+        }
+        "le profil" => {
+            let profiles = config
+                .profiles
+                .keys()
+                .map(String::as_str)
+                .chain(config.commands.iter().filter_map(|c| c.profile.as_deref()));
+            let profile = closest_name(target, profiles)?;
+            state.set_profile(&profile, enabled);
+            Some(format!("Profile '{}' {}", profile, verb))
+        }
+        "le groupe" => {
+            let groups = config
+                .groups
+                .keys()
+                .map(String::as_str)
+                .chain(config.commands.iter().filter_map(|c| c.group.as_deref()));
+            let group = closest_name(target, groups)?;
+            state.set_group(&group, enabled);
+            Some(format!("Group '{}' {}", group, verb))
+        }
+        _ => {
+            let kind = spoken_kind(target)?;
+            state.set_kind(kind, enabled);
+            Some(format!("{} commands {}", kind, verb))
+        }
+    }
+}
+
+/// Maps a spoken action kind ("shell", "de texte") to its identifier.
+fn spoken_kind(spoken: &str) -> Option<&'static str> {
+    if spoken.contains("shell") || spoken.contains("terminal") {
+        Some("shell")
+    } else if spoken.contains("texte") || spoken.contains("text") {
+        Some("text")
+    } else if spoken.contains("application") || spoken.contains("app") {
+        Some("app")
+    } else if spoken.contains("capture") || spoken.contains("écran") {
+        Some("capture")
+    } else if spoken.contains("musique") || spoken.contains("music") {
+        Some("music")
+    } else if spoken.contains("navigateur") || spoken.contains("browser") {
+        Some("browser")
+    } else if spoken.contains("service") {
+        Some("service")
+    } else if spoken.contains("macro") {
+        Some("macro")
+    } else if spoken.contains("ssh") || spoken.contains("distant") {
+        Some("ssh")
+    } else {
+        None
+    }
+}
+
+/// Finds the name closest to `spoken`, if it is similar enough.
+fn closest_name<'a>(spoken: &str, names: impl Iterator<Item = &'a str>) -> Option<String> {
+    names
+        .map(|name| (name, normalized_levenshtein(spoken, &name.to_lowercase())))
+        .filter(|(_, score)| *score >= NAME_SIMILARITY_THRESHOLD)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(name, _)| name.to_string())
+}
@@ -0,0 +1,277 @@
+use crate::audio::{AudioProcessor, FileSource};
+use crate::codec::Codec;
+use crate::events::{self, Event};
+use crate::performance;
+use crate::session::SessionRecorder;
+use crate::speaker::SpeakerTracker;
+use crate::whisper_integration;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use whisper_rs::WhisperContext;
+
+/// Segments of a file read ahead for each worker, so it isn't all held in memory.
+const QUEUED_PER_WORKER: usize = 2;
+
+/// Captioning mode settings, read from the `captioning` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct CaptionSettings {
+    pub language: String,
+    /// Label speaker turns ("Speaker 1", "Speaker 2", ...).
+    pub label_speakers: bool,
+    /// Voiceprint similarity (0 to 1) above which a segment is given to a known speaker.
+    /// Lower it if one person is split into several speakers.
+    pub speaker_threshold: f32,
+    pub max_speakers: usize,
+    /// Also keep the audio of every segment next to the transcript, in this codec.
+    pub save_audio: Option<Codec>,
+    /// Segments of a recorded file (`--input-file`) transcribed at the same time, each
+    /// by a worker with its own Whisper state. As many as the cores allow with
+    /// `performance.whisper_threads` each when unset.
+    pub workers: Option<usize>,
+}
+
+impl Default for CaptionSettings {
+    fn default() -> Self {
+        CaptionSettings {
+            language: "fr".to_string(),
+            label_speakers: true,
+            speaker_threshold: 0.9,
+            max_speakers: 8,
+            save_audio: None,
+            workers: None,
+        }
+    }
+}
+
+impl CaptionSettings {
+    /// `workers`, at least one.
+    pub fn worker_count(&self) -> usize {
+        self.workers
+            .unwrap_or_else(|| {
+                let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+                cores / performance::whisper_thread_count()
+            })
+            .max(1)
+    }
+}
+
+/// Writes the transcribed segments to the transcript, in the order they were said.
+struct Captioner<'a> {
+    recorder: SessionRecorder,
+    speakers: SpeakerTracker,
+    settings: &'a CaptionSettings,
+}
+
+impl<'a> Captioner<'a> {
+    fn new(settings: &'a CaptionSettings, path: &str) -> Self {
+        Captioner {
+            recorder: SessionRecorder::new(path),
+            speakers: SpeakerTracker::new(settings.speaker_threshold, settings.max_speakers),
+            settings,
+        }
+    }
+
+    /// Adds the text of the segment that just ended.
+    fn add(&mut self, segment: &[f32], text: &str) {
+        let speaker = if self.settings.label_speakers {
+            self.speakers.identify(segment)
+        } else {
+            0
+        };
+
+        log::info!("🗣️ [{}] {}", speaker, text);
+        events::emit(Event::Transcript { text });
+        let duration = Duration::from_secs_f32(segment.len() as f32 / 16_000.0);
+        if let Some(codec) = self.settings.save_audio {
+            if let Err(e) = self.recorder.save_audio(segment, duration, codec) {
+                log::error!("Failed to save segment audio: {}", e);
+            }
+        }
+        if let Err(e) = self.recorder.record(speaker, text, duration) {
+            log::error!(
+                "Failed to write transcript {}: {}",
+                self.recorder.path().display(),
+                e
+            );
+        }
+    }
+}
+
+/// Transcribes everything said into the transcript at `path` until Ctrl+C, without
+/// wake word or command matching.
+pub async fn run(
+    processor: &mut AudioProcessor,
+    model: Arc<WhisperContext>,
+    settings: &CaptionSettings,
+    path: &str,
+) -> Result<SessionRecorder, Box<dyn Error>> {
+    let mut captioner = Captioner::new(settings, path);
+    log::info!("🗒️ Captioning to {}, press Ctrl+C to stop", path);
+    events::emit(Event::StateChange { state: "captioning" });
+
+    // Created once so a Ctrl+C pressed during a transcription is not missed
+    let interrupted = tokio::signal::ctrl_c();
+    tokio::pin!(interrupted);
+
+    loop {
+        let segment = tokio::select! {
+            segment = processor.get_next_speech_segment() => segment?.samples,
+            _ = &mut interrupted => break,
+        };
+        if segment.len() < 1000 {
+            continue;
+        }
+
+        let transcription =
+            whisper_integration::transcribe(&model, &segment, &settings.language).await;
+        let stage = whisper_integration::cleanup_settings().consumers.captions;
+        let text = match transcription {
+            Ok(transcription) if !transcription.is_empty() => {
+                transcription.text(stage).to_string()
+            }
+            Ok(_) => continue,
+            Err(e) => {
+                log::error!("Error during audio transcription: {}", e);
+                continue;
+            }
+        };
+        captioner.add(&segment, &text);
+    }
+
+    events::emit(Event::StateChange { state: "stopped" });
+    Ok(captioner.recorder)
+}
+
+/// A segment of a file with its text, `None` when nothing was said.
+struct Transcribed {
+    index: usize,
+    /// Where the segment ends in the file.
+    end: Duration,
+    samples: Vec<f32>,
+    text: Result<Option<String>, String>,
+}
+
+/// Transcribes the speech of a recorded file into the transcript at `path`. Segments
+/// are cut like live audio and transcribed `captioning.workers` at a time, then written
+/// in the file's order, with offsets in file time.
+pub fn run_file(
+    mut source: FileSource,
+    model: Arc<WhisperContext>,
+    settings: &CaptionSettings,
+    path: &str,
+) -> SessionRecorder {
+    let mut captioner = Captioner::new(settings, path);
+    let workers = settings.worker_count();
+    log::info!("🗒️ Captioning to {} with {} workers", path, workers);
+    events::emit(Event::StateChange { state: "captioning" });
+
+    let (jobs, queue) = mpsc::sync_channel(workers * QUEUED_PER_WORKER);
+    // Dropped with the last worker, so segments aren't queued for nobody
+    let queue = Arc::new(Mutex::new(queue));
+    let (results, transcribed) = mpsc::channel();
+    // Transcripts finished ahead of an earlier segment wait for it
+    let mut finished = BTreeMap::new();
+    let mut next = 0;
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            let (queue, results, model) = (queue.clone(), results.clone(), &model);
+            scope.spawn(move || transcribe_queue(model, &queue, results, &settings.language));
+        }
+        drop((queue, results));
+
+        let mut index = 0;
+        while let Some(segment) = source.next_segment() {
+            if segment.samples.len() < 1000 {
+                continue;
+            }
+            if jobs.send((index, source.position(), segment.samples)).is_err() {
+                log::error!("Every transcription worker stopped");
+                break;
+            }
+            index += 1;
+            finished.extend(transcribed.try_iter().map(|done| (done.index, done)));
+            write_in_order(&mut captioner, &mut finished, &mut next);
+        }
+        drop(jobs);
+        for done in transcribed {
+            finished.insert(done.index, done);
+            write_in_order(&mut captioner, &mut finished, &mut next);
+        }
+    });
+    // The segments following one a stopped worker never returned
+    for done in finished.into_values() {
+        write(&mut captioner, done);
+    }
+
+    log::info!("📁 End of the input file");
+    events::emit(Event::StateChange { state: "stopped" });
+    captioner.recorder
+}
+
+/// A worker: transcribes the segments of `queue` with a state of its own until the file
+/// has been read.
+fn transcribe_queue(
+    model: &WhisperContext,
+    queue: &Mutex<Receiver<(usize, Duration, Vec<f32>)>>,
+    results: Sender<Transcribed>,
+    language: &str,
+) {
+    let mut state = match model.create_state() {
+        Ok(state) => state,
+        Err(e) => {
+            log::error!("Failed to create a Whisper state for a worker: {}", e);
+            return;
+        }
+    };
+    let stage = whisper_integration::cleanup_settings().consumers.captions;
+    loop {
+        let job = queue.lock().unwrap_or_else(|e| e.into_inner()).recv();
+        let Ok((index, end, samples)) = job else {
+            return;
+        };
+        let text = whisper_integration::transcribe_with_state(&mut state, &samples, language)
+            .map(|transcription| {
+                (!transcription.is_empty()).then(|| transcription.text(stage).to_string())
+            })
+            .map_err(|e| e.to_string());
+        let done = Transcribed {
+            index,
+            end,
+            samples,
+            text,
+        };
+        if results.send(done).is_err() {
+            return;
+        }
+    }
+}
+
+/// Writes the segments of `finished` following the last one written, up to the first
+/// one still being transcribed.
+fn write_in_order(
+    captioner: &mut Captioner,
+    finished: &mut BTreeMap<usize, Transcribed>,
+    next: &mut usize,
+) {
+    while let Some(done) = finished.remove(next) {
+        write(captioner, done);
+        *next += 1;
+    }
+}
+
+fn write(captioner: &mut Captioner, done: Transcribed) {
+    match done.text {
+        Ok(Some(text)) => {
+            captioner.recorder.set_position(done.end);
+            captioner.add(&done.samples, &text);
+        }
+        Ok(None) => {}
+        Err(e) => log::error!("Error during audio transcription: {}", e),
+    }
+}
@@ -0,0 +1,137 @@
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Screen capture settings, read from the `capture` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct CaptureSettings {
+    /// Where screenshots and recordings are saved.
+    pub directory: String,
+    /// Length of `record-screen:` recordings when the action doesn't give one.
+    pub default_record_secs: u64,
+}
+
+impl Default for CaptureSettings {
+    fn default() -> Self {
+        CaptureSettings {
+            directory: "./captures".to_string(),
+            default_record_secs: 10,
+        }
+    }
+}
+
+/// `screenshot:` captures the whole screen, `screenshot:region` lets the user select an area.
+/// Returns the path of the saved image.
+pub fn take_screenshot(spec: &str, settings: &CaptureSettings) -> Result<PathBuf, Box<dyn Error>> {
+    let region = match spec.trim() {
+        "" | "full" => false,
+        "region" => true,
+        other => return Err(format!("Unknown screenshot mode: '{}'", other).into()),
+    };
+    let path = output_path(settings, "screenshot", "png")?;
+    let file = path.to_string_lossy().into_owned();
+
+    let status = if cfg!(target_os = "macos") {
+        let mode = if region { "-i" } else { "-x" };
+        Command::new("screencapture").args([mode, &file]).status()?
+    } else if cfg!(target_os = "windows") {
+        if region {
+            return Err("Region screenshots are not supported on Windows".into());
+        }
+        let script = format!(
+            "Add-Type -AssemblyName System.Windows.Forms,System.Drawing; \
+             $b = [System.Windows.Forms.SystemInformation]::VirtualScreen; \
+             $bmp = New-Object System.Drawing.Bitmap $b.Width, $b.Height; \
+             $g = [System.Drawing.Graphics]::FromImage($bmp); \
+             $g.CopyFromScreen($b.Left, $b.Top, 0, 0, $bmp.Size); \
+             $bmp.Save('{}')",
+            file.replace('\'', "''")
+        );
+        Command::new("powershell")
+            .args(["-NoProfile", "-Command", &script])
+            .status()?
+    } else if is_wayland() {
+        let script = if region {
+            "grim -g \"$(slurp)\" \"$0\""
+        } else {
+            "grim \"$0\""
+        };
+        Command::new("sh").args(["-c", script, &file]).status()?
+    } else {
+        let mut command = Command::new("scrot");
+        if region {
+            command.arg("-s");
+        }
+        command.args(["--overwrite", &file]).status()?
+    };
+
+    saved(&path, status.success(), "Screenshot")
+}
+
+/// `record-screen:` records the screen for the configured length, `record-screen:30` for 30s.
+/// Returns the path of the saved video.
+pub fn record_screen(spec: &str, settings: &CaptureSettings) -> Result<PathBuf, Box<dyn Error>> {
+    let secs = match spec.trim() {
+        "" => settings.default_record_secs,
+        secs => secs
+            .trim_end_matches('s')
+            .parse::<u64>()
+            .map_err(|_| format!("Invalid recording length: '{}'", secs))?,
+    };
+    let extension = if cfg!(target_os = "macos") { "mov" } else { "mp4" };
+    let path = output_path(settings, "recording", extension)?;
+    let file = path.to_string_lossy().into_owned();
+    let duration = secs.to_string();
+    log::info!("🎥 Recording the screen for {}s", secs);
+
+    // Recorders stopped by a timeout exit with an error status, so only the file is checked
+    if cfg!(target_os = "macos") {
+        Command::new("screencapture")
+            .args(["-v", "-V", &duration, &file])
+            .status()?;
+    } else if cfg!(target_os = "windows") {
+        Command::new("ffmpeg")
+            .args(["-y", "-f", "gdigrab", "-i", "desktop", "-t", &duration, &file])
+            .status()?;
+    } else if is_wayland() {
+        Command::new("timeout")
+            .args(["-s", "INT", &duration, "wf-recorder", "-f", &file])
+            .status()?;
+    } else {
+        let display = std::env::var("DISPLAY").unwrap_or_else(|_| ":0".to_string());
+        Command::new("ffmpeg")
+            .args(["-y", "-f", "x11grab", "-i", &display, "-t", &duration, &file])
+            .status()?;
+    }
+
+    saved(&path, true, "Recording")
+}
+
+fn is_wayland() -> bool {
+    std::env::var_os("WAYLAND_DISPLAY").is_some()
+}
+
+/// Builds a timestamped file path in the capture directory, creating it if needed.
+fn output_path(
+    settings: &CaptureSettings,
+    prefix: &str,
+    extension: &str,
+) -> Result<PathBuf, Box<dyn Error>> {
+    fs::create_dir_all(&settings.directory)?;
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+    Ok(Path::new(&settings.directory).join(format!("{}-{}.{}", prefix, timestamp, extension)))
+}
+
+fn saved(path: &Path, success: bool, what: &str) -> Result<PathBuf, Box<dyn Error>> {
+    if success && path.exists() {
+        log::info!("📸 {} saved to {}", what, path.display());
+        Ok(path.to_path_buf())
+    } else {
+        Err(format!("{} failed, no file was written", what).into())
+    }
+}
@@ -0,0 +1,75 @@
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+/// Casing settings, read from the `cleanup.casing` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct CasingSettings {
+    /// Re-applies the casing Whisper produced to words the correction stages lowercased.
+    pub preserve: bool,
+    /// User dictionary of proper nouns, always written exactly as listed ("VoxAurora", "Jean-Pierre").
+    pub proper_nouns: Vec<String>,
+}
+
+impl Default for CasingSettings {
+    fn default() -> Self {
+        CasingSettings {
+            preserve: true,
+            proper_nouns: Vec::new(),
+        }
+    }
+}
+
+/// Restores the casing of `original` onto `corrected`, then applies the proper noun list.
+/// A word's casing is only carried over if it never appeared lowercase in `original`,
+/// so a sentence-initial capital doesn't leak into later occurrences.
+pub fn restore_casing(original: &str, corrected: &str, settings: &CasingSettings) -> String {
+    let re = Regex::new(r"\p{L}+(?:[’'-]\p{L}+)*").unwrap();
+    let mut result = corrected.to_string();
+
+    if settings.preserve {
+        let mut cased: HashMap<String, &str> = HashMap::new();
+        let mut seen_lowercase: HashSet<String> = HashSet::new();
+        for token in re.find_iter(original) {
+            let word = token.as_str();
+            let lower = word.to_lowercase();
+            if lower == word {
+                seen_lowercase.insert(lower);
+            } else {
+                cased.entry(lower).or_insert(word);
+            }
+        }
+        cased.retain(|lower, _| !seen_lowercase.contains(lower));
+
+        if !cased.is_empty() {
+            result = re
+                .replace_all(&result, |caps: &regex::Captures| {
+                    let word = &caps[0];
+                    match cased.get(word) {
+                        Some(original_form) => {
+                            log::debug!("Restored casing: '{}' -> '{}'", word, original_form);
+                            original_form.to_string()
+                        }
+                        None => word.to_string(),
+                    }
+                })
+                .into_owned();
+        }
+    }
+
+    for noun in &settings.proper_nouns {
+        let pattern = format!(r"(?i)\b{}\b", regex::escape(noun));
+        match Regex::new(&pattern) {
+            Ok(noun_re) => {
+                result = noun_re
+                    .replace_all(&result, regex::NoExpand(noun))
+                    .into_owned();
+            }
+            Err(e) => log::warn!("Invalid proper noun '{}': {}", noun, e),
+        }
+    }
+
+    result
+}
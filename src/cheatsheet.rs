@@ -0,0 +1,160 @@
+use crate::builtins;
+use crate::config::{Command, Config};
+use crate::session::{TranscriptFormat, escape_html};
+use crate::ssh;
+
+/// Commands of one profile, or of none, as listed on the sheet.
+#[derive(Clone)]
+pub struct Section<'a> {
+    pub title: String,
+    pub commands: Vec<&'a Command>,
+}
+
+/// Commands grouped by profile: those without one first, then each profile by name.
+pub fn sections(config: &Config) -> Vec<Section<'_>> {
+    let mut profiles: Vec<&str> = config
+        .commands
+        .iter()
+        .filter_map(|command| command.profile.as_deref())
+        .collect();
+    profiles.sort_unstable();
+    profiles.dedup();
+
+    let general = Section {
+        title: "General".to_string(),
+        commands: config
+            .commands
+            .iter()
+            .filter(|command| command.profile.is_none())
+            .collect(),
+    };
+    let mut sections = vec![general];
+    for profile in profiles {
+        let active = if config.profile.as_deref() == Some(profile) {
+            " (active)"
+        } else {
+            ""
+        };
+        sections.push(Section {
+            title: format!("Profile {}{}", profile, active),
+            commands: config
+                .commands
+                .iter()
+                .filter(|command| command.profile.as_deref() == Some(profile))
+                .collect(),
+        });
+    }
+    sections.retain(|section| !section.commands.is_empty());
+    sections
+}
+
+/// What an action does, in a few words.
+pub fn describe(action: &str) -> String {
+    if let Some(command) = action.strip_prefix("cmd:") {
+        format!("runs `{}`", command.trim())
+    } else if let Some(name) = action.strip_prefix("app:") {
+        format!("opens {}", name.trim())
+    } else if let Some(spec) = action.strip_prefix("screenshot:") {
+        match spec.trim() {
+            "" => "takes a screenshot".to_string(),
+            spec => format!("takes a screenshot ({})", spec),
+        }
+    } else if let Some(spec) = action.strip_prefix("record-screen:") {
+        match spec.trim() {
+            "" => "records the screen".to_string(),
+            seconds => format!("records the screen for {} s", seconds),
+        }
+    } else if let Some(spec) = action.strip_prefix("music:") {
+        format!("music: {}", spec.trim())
+    } else if let Some(spec) = action.strip_prefix("browser:") {
+        format!("browser: {}", spec.trim())
+    } else if let Some(spec) = action.strip_prefix("service:") {
+        format!("service: {}", spec.trim())
+    } else if let Some(name) = action.strip_prefix("macro:") {
+        format!("plays the macro “{}”", name.trim())
+    } else if let Some(spec) = action.strip_prefix("ssh:") {
+        match ssh::parse_action(spec) {
+            Ok((host, command)) => format!("runs `{}` on {}", command, host),
+            Err(_) => format!("ssh: {}", spec.trim()),
+        }
+    } else {
+        format!("types “{}”", action)
+    }
+}
+
+/// The other phrasings of `command`.
+fn synonyms(command: &Command) -> String {
+    command.triggers.iter().skip(1).cloned().collect::<Vec<_>>().join(", ")
+}
+
+/// The action of `command` and its follow-ups.
+fn describe_command(command: &Command) -> String {
+    std::iter::once(&command.action)
+        .chain(&command.then)
+        .map(|action| describe(action))
+        .collect::<Vec<_>>()
+        .join(", then ")
+}
+
+/// Every phrase of `config`, built-in ones included, as a printable sheet.
+pub fn render(config: &Config, format: TranscriptFormat) -> String {
+    match format {
+        TranscriptFormat::Markdown => render_markdown(config),
+        TranscriptFormat::Html => render_html(config),
+    }
+}
+
+fn markdown_cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+pub fn render_markdown(config: &Config) -> String {
+    let mut out = "# VoxAurora cheat sheet\n\nWake VoxAurora up, then say:\n".to_string();
+    for section in sections(config) {
+        out.push_str(&format!("\n## {}\n\n| Say | Or | Does |\n|---|---|---|\n", section.title));
+        for command in section.commands {
+            out.push_str(&format!(
+                "| **{}** | {} | {} |\n",
+                markdown_cell(command.trigger()),
+                markdown_cell(&synonyms(command)),
+                markdown_cell(&describe_command(command))
+            ));
+        }
+    }
+    out.push_str("\n## Built-in\n\n| Say | Does |\n|---|---|\n");
+    for (phrase, does) in builtins::examples() {
+        out.push_str(&format!("| **{}** | {} |\n", markdown_cell(phrase), does));
+    }
+    out
+}
+
+pub fn render_html(config: &Config) -> String {
+    let mut out = String::from(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>VoxAurora cheat sheet</title>\n<style>body { font-family: sans-serif; } table { border-collapse: collapse; width: 100%; } td, th { border: 1px solid #ccc; padding: 4px 8px; text-align: left; } h2 { break-after: avoid; }</style>\n</head>\n<body>\n<h1>VoxAurora cheat sheet</h1>\n<p>Wake VoxAurora up, then say:</p>\n",
+    );
+    for section in sections(config) {
+        out.push_str(&format!(
+            "<h2>{}</h2>\n<table>\n<tr><th>Say</th><th>Or</th><th>Does</th></tr>\n",
+            escape_html(&section.title)
+        ));
+        for command in section.commands {
+            out.push_str(&format!(
+                "<tr><td><strong>{}</strong></td><td>{}</td><td>{}</td></tr>\n",
+                escape_html(command.trigger()),
+                escape_html(&synonyms(command)),
+                escape_html(&describe_command(command))
+            ));
+        }
+        out.push_str("</table>\n");
+    }
+    out.push_str("<h2>Built-in</h2>\n<table>\n<tr><th>Say</th><th>Does</th></tr>\n");
+    for (phrase, does) in builtins::examples() {
+        out.push_str(&format!(
+            "<tr><td><strong>{}</strong></td><td>{}</td></tr>\n",
+            escape_html(phrase),
+            escape_html(does)
+        ));
+    }
+    out.push_str("</table>\n</body>\n</html>\n");
+    out
+}
@@ -0,0 +1,279 @@
+use VoxAurora::codec::Codec;
+
+/// How this instance runs.
+pub enum Mode {
+    /// Capture, transcribe and execute commands on this machine.
+    Local,
+    /// Run the full pipeline on segments sent by `--client` instances.
+    Serve(String),
+    /// Capture and segment speech only, sending segments to a `--serve` instance.
+    Client(String),
+    /// Transcribe everything said into a transcript file, without commands.
+    Caption(String),
+    /// Run the full pipeline on the speech of a WAV or FLAC file instead of a microphone.
+    InputFile(String),
+    /// Run the full pipeline on PCM audio streamed to `udp://ADDR` or `ws://ADDR` instead
+    /// of a microphone.
+    Network(String),
+    /// Transcribe a WAV or FLAC file (`--input-file`) into a transcript (`--caption`),
+    /// several segments at a time.
+    CaptionFile { input: String, transcript: String },
+    /// Show how a text scores against every command; positional arguments are configs.
+    Explain(String),
+    /// Write a cheat sheet of every phrase to a file; positional arguments are configs.
+    Docs(String),
+    /// Check the utterance => command pairs of a file; positional arguments are configs.
+    TestConfig(String),
+    /// Mark logged wake decisions as real or false wakes; positional arguments are configs.
+    ReviewWakes,
+    /// List the latest entries of a history table; positional arguments are configs.
+    History(String),
+    /// Transcribe and match the segments of a recorded session again, diffing the results.
+    Replay(String),
+    /// Ask the running instance how its components are doing; positional arguments are configs.
+    Status,
+    /// Write the JSON Schema of the config format to a file, or stdout for `-`.
+    Schema(String),
+    /// Encrypt a plain JSON file of secrets into `secrets.encrypted_file`; positional
+    /// arguments are configs.
+    EncryptSecrets(String),
+}
+
+const EXCLUSIVE_MODES: &str = "--serve, --client, --caption, --input-file, --listen-audio, --explain, --docs, --test-config, --review-wakes, --history, --status, --schema, --encrypt-secrets and --replay can't be combined, except --caption with --input-file";
+
+/// Config used by the modes taking only config paths, when none is given.
+const DEFAULT_CONFIG: &str = "./configs/base_config.json";
+
+/// Parsed command line:
+/// `VoxAurora [--serve ADDR | --client ADDR | --caption FILE | --input-file FILE
+/// | --caption FILE --input-file FILE | --listen-audio udp://ADDR|ws://ADDR]
+/// [--codec wav|flac|opus] [--device NAME...]
+/// [--output text|json] [--clipboard] [--reproducible] [--record-segments DIR]
+/// [MODEL] [CONFIG...]`, or
+/// `VoxAurora --review-wakes [CONFIG...]`, `VoxAurora --explain TEXT [--top K] [CONFIG...]`,
+/// `VoxAurora --docs FILE [CONFIG...]`,
+/// `VoxAurora --test-config FILE [CONFIG...]`,
+/// `VoxAurora --history TABLE [--search TEXT] [--top K] [CONFIG...]` or
+/// `VoxAurora --status [CONFIG...]`,
+/// `VoxAurora --schema FILE`, `VoxAurora --encrypt-secrets FILE [CONFIG...]` or
+/// `VoxAurora --replay DIR [--reproducible] [MODEL] [CONFIG...]`
+pub struct Cli {
+    pub mode: Mode,
+    /// Print every event as one JSON object per line on stdout (`--output json`).
+    pub json_output: bool,
+    /// Copy dictated text to the clipboard instead of typing it (`--clipboard`).
+    pub clipboard: bool,
+    /// Pin decoding so runs over the same audio give the same transcripts
+    /// (`--reproducible`), like `decoding.reproducible`.
+    pub reproducible: bool,
+    /// How `--client` compresses segments (`--codec opus`); raw samples when unset.
+    pub codec: Option<Codec>,
+    /// Microphones to capture from, by name or part of it (`--device "USB"`), replacing
+    /// `input.devices`.
+    pub devices: Vec<String>,
+    /// Folder receiving every finalized segment as a WAV with a JSON sidecar
+    /// (`--record-segments ./segments`).
+    pub record_segments: Option<String>,
+    /// How many candidates `--explain` or entries `--history` shows (`--top 5`).
+    pub top: Option<usize>,
+    /// Only list history entries containing this text (`--search chrome`).
+    pub search: Option<String>,
+    /// Positional arguments: the model path followed by config paths.
+    pub positional: Vec<String>,
+}
+
+/// Parses the arguments following the program name.
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Cli, String> {
+    let mut mode = Mode::Local;
+    let mut json_output = false;
+    let mut clipboard = false;
+    let mut reproducible = false;
+    let mut codec = None;
+    let mut devices = Vec::new();
+    let mut record_segments = None;
+    let mut top = None;
+    let mut search = None;
+    let mut positional = Vec::new();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--serve" | "--client" => {
+                let address = args
+                    .next()
+                    .ok_or_else(|| format!("{} requires an address (e.g. 0.0.0.0:7879)", arg))?;
+                if !matches!(mode, Mode::Local) {
+                    return Err(EXCLUSIVE_MODES.to_string());
+                }
+                mode = if arg == "--serve" {
+                    Mode::Serve(address)
+                } else {
+                    Mode::Client(address)
+                };
+            }
+            "--caption" => {
+                let path = args
+                    .next()
+                    .ok_or("--caption requires a transcript path (e.g. meeting.md)")?;
+                mode = match mode {
+                    Mode::Local => Mode::Caption(path),
+                    Mode::InputFile(input) => Mode::CaptionFile {
+                        input,
+                        transcript: path,
+                    },
+                    _ => return Err(EXCLUSIVE_MODES.to_string()),
+                };
+            }
+            "--input-file" => {
+                let path = args
+                    .next()
+                    .ok_or("--input-file requires a WAV or FLAC file (e.g. commands.wav)")?;
+                mode = match mode {
+                    Mode::Local => Mode::InputFile(path),
+                    Mode::Caption(transcript) => Mode::CaptionFile {
+                        input: path,
+                        transcript,
+                    },
+                    _ => return Err(EXCLUSIVE_MODES.to_string()),
+                };
+            }
+            "--listen-audio" => {
+                let address = args
+                    .next()
+                    .ok_or("--listen-audio requires an address (e.g. udp://0.0.0.0:7880)")?;
+                if !matches!(mode, Mode::Local) {
+                    return Err(EXCLUSIVE_MODES.to_string());
+                }
+                mode = Mode::Network(address);
+            }
+            "--explain" => {
+                let text = args.next().ok_or("--explain requires a text (e.g. \"ouvre chrome\")")?;
+                if !matches!(mode, Mode::Local) {
+                    return Err(EXCLUSIVE_MODES.to_string());
+                }
+                mode = Mode::Explain(text);
+            }
+            "--docs" => {
+                let path = args
+                    .next()
+                    .ok_or("--docs requires an output file (e.g. cheatsheet.html)")?;
+                if !matches!(mode, Mode::Local) {
+                    return Err(EXCLUSIVE_MODES.to_string());
+                }
+                mode = Mode::Docs(path);
+            }
+            "--test-config" => {
+                let path = args
+                    .next()
+                    .ok_or("--test-config requires a file of 'utterance => command' lines")?;
+                if !matches!(mode, Mode::Local) {
+                    return Err(EXCLUSIVE_MODES.to_string());
+                }
+                mode = Mode::TestConfig(path);
+            }
+            "--top" => {
+                top = Some(
+                    args.next()
+                        .and_then(|top| top.parse().ok())
+                        .ok_or("--top expects a number of entries")?,
+                );
+            }
+            "--history" => {
+                let table = args.next().ok_or(
+                    "--history requires a table: transcripts, commands, corrections or wakes",
+                )?;
+                if !matches!(mode, Mode::Local) {
+                    return Err(EXCLUSIVE_MODES.to_string());
+                }
+                mode = Mode::History(table);
+            }
+            "--search" => search = Some(args.next().ok_or("--search requires a text")?),
+            "--review-wakes" => {
+                if !matches!(mode, Mode::Local) {
+                    return Err(EXCLUSIVE_MODES.to_string());
+                }
+                mode = Mode::ReviewWakes;
+            }
+            "--replay" => {
+                let dir = args.next().ok_or("--replay requires a recorded session folder")?;
+                if !matches!(mode, Mode::Local) {
+                    return Err(EXCLUSIVE_MODES.to_string());
+                }
+                mode = Mode::Replay(dir);
+            }
+            "--status" => {
+                if !matches!(mode, Mode::Local) {
+                    return Err(EXCLUSIVE_MODES.to_string());
+                }
+                mode = Mode::Status;
+            }
+            "--schema" => {
+                let path = args
+                    .next()
+                    .ok_or("--schema requires an output file (e.g. config.schema.json), or -")?;
+                if !matches!(mode, Mode::Local) {
+                    return Err(EXCLUSIVE_MODES.to_string());
+                }
+                mode = Mode::Schema(path);
+            }
+            "--encrypt-secrets" => {
+                let path = args
+                    .next()
+                    .ok_or("--encrypt-secrets requires a JSON file of secrets to encrypt")?;
+                if !matches!(mode, Mode::Local) {
+                    return Err(EXCLUSIVE_MODES.to_string());
+                }
+                mode = Mode::EncryptSecrets(path);
+            }
+            "--output" => match args.next().as_deref() {
+                Some("json") => json_output = true,
+                Some("text") => json_output = false,
+                _ => return Err("--output expects 'text' or 'json'".to_string()),
+            },
+            "--clipboard" => clipboard = true,
+            "--reproducible" => reproducible = true,
+            "--codec" => {
+                codec = Some(match args.next().as_deref() {
+                    Some("wav") => Codec::Wav,
+                    Some("flac") => Codec::Flac,
+                    Some("opus") => Codec::Opus,
+                    _ => return Err("--codec expects 'wav', 'flac' or 'opus'".to_string()),
+                })
+            }
+            "--device" => devices.push(
+                args.next()
+                    .ok_or("--device requires a device name (e.g. \"USB\")")?,
+            ),
+            "--record-segments" => {
+                record_segments = Some(
+                    args.next()
+                        .ok_or("--record-segments requires a folder (e.g. ./segments)")?,
+                )
+            }
+            flag if flag.starts_with("--") => return Err(format!("Unknown option: {}", flag)),
+            _ => positional.push(arg),
+        }
+    }
+
+    Ok(Cli {
+        mode,
+        json_output,
+        clipboard,
+        reproducible,
+        codec,
+        devices,
+        record_segments,
+        top,
+        search,
+        positional,
+    })
+}
+
+/// The positional arguments of config-only modes, or the default config.
+pub fn config_paths(positional: Vec<String>) -> Vec<String> {
+    if positional.is_empty() {
+        vec![DEFAULT_CONFIG.to_string()]
+    } else {
+        positional
+    }
+}
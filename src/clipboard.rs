@@ -0,0 +1,71 @@
+use once_cell::sync::Lazy;
+use std::error::Error;
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Sender};
+
+/// Text to copy, and where to report the outcome.
+type CopyRequest = (String, Sender<Result<(), String>>);
+
+/// Longest part of the copied text shown in the notification.
+const NOTIFICATION_PREVIEW_LEN: usize = 80;
+
+/// The clipboard is owned by one long-lived thread: on X11 and Wayland, copied text is
+/// served by the process that set it, so it has to outlive the action that copied it.
+static CLIPBOARD: Lazy<Mutex<Sender<CopyRequest>>> = Lazy::new(|| {
+    let (sender, receiver) = mpsc::channel::<CopyRequest>();
+    std::thread::spawn(move || {
+        let mut clipboard = None;
+        for (text, done) in receiver {
+            if clipboard.is_none() {
+                clipboard = arboard::Clipboard::new()
+                    .map_err(|e| log::error!("Failed to open the clipboard: {}", e))
+                    .ok();
+            }
+            let result = match clipboard.as_mut() {
+                Some(clipboard) => clipboard.set_text(text).map_err(|e| e.to_string()),
+                None => Err("Clipboard unavailable".to_string()),
+            };
+            let _ = done.send(result);
+        }
+    });
+    Mutex::new(sender)
+});
+
+/// Copies `text` to the system clipboard and shows a notification.
+pub fn copy_text(text: &str) -> Result<(), Box<dyn Error>> {
+    set_text(text)?;
+    log::info!("📋 Copied to clipboard: {}", text);
+    notify(text);
+    Ok(())
+}
+
+/// Copies `text` to the system clipboard without notifying.
+pub fn set_text(text: &str) -> Result<(), Box<dyn Error>> {
+    let (done, outcome) = mpsc::channel();
+    CLIPBOARD
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .send((text.to_string(), done))
+        .map_err(|_| "Clipboard thread stopped")?;
+    outcome.recv().map_err(|_| "Clipboard thread stopped")??;
+    Ok(())
+}
+
+/// The text currently in the system clipboard.
+pub fn get_text() -> Result<String, Box<dyn Error>> {
+    Ok(arboard::Clipboard::new()?.get_text()?)
+}
+
+fn notify(text: &str) {
+    let mut preview: String = text.chars().take(NOTIFICATION_PREVIEW_LEN).collect();
+    if preview.len() < text.len() {
+        preview.push('…');
+    }
+    if let Err(e) = notify_rust::Notification::new()
+        .summary("VoxAurora: copied to clipboard")
+        .body(&preview)
+        .show()
+    {
+        log::warn!("Failed to show clipboard notification: {}", e);
+    }
+}
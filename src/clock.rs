@@ -0,0 +1,52 @@
+use chrono::{DateTime, Datelike, Local, TimeZone, Timelike};
+
+const WEEKDAYS: [&str; 7] = [
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+];
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// "It is 14:05"
+pub fn time_answer<Tz: TimeZone>(now: &DateTime<Tz>) -> String {
+    format!("It is {:02}:{:02}", now.hour(), now.minute())
+}
+
+/// "Today is Tuesday 14 October 2026"
+pub fn date_answer<Tz: TimeZone>(now: &DateTime<Tz>) -> String {
+    format!(
+        "Today is {} {} {} {}",
+        WEEKDAYS[now.weekday().num_days_from_monday() as usize],
+        now.day(),
+        MONTHS[now.month0() as usize],
+        now.year()
+    )
+}
+
+/// Answers with the current local time.
+pub fn current_time() -> String {
+    time_answer(&Local::now())
+}
+
+/// Answers with the current local date.
+pub fn current_date() -> String {
+    date_answer(&Local::now())
+}
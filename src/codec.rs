@@ -0,0 +1,161 @@
+use crate::audio;
+use flacenc::component::BitRepr;
+use flacenc::error::Verify;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::io::Cursor;
+
+const SAMPLE_RATE: u32 = 16_000;
+
+/// Opus frame: 20 ms at 16kHz.
+const OPUS_FRAME: usize = 320;
+
+/// Largest Opus packet accepted when encoding or decoding.
+const MAX_OPUS_PACKET: usize = 4000;
+
+/// Magic of the Opus container written here: a sequence of length-prefixed packets.
+/// It isn't an Ogg `.opus` file, just the smallest framing the decoder needs.
+const OPUS_MAGIC: &[u8; 4] = b"VXOP";
+
+/// How 16kHz mono audio is stored or sent.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    /// 16-bit PCM WAV.
+    #[default]
+    Wav,
+    /// Lossless, about half the size of WAV.
+    Flac,
+    /// Lossy speech codec (24 kbit/s), about a tenth of the size of WAV.
+    Opus,
+}
+
+impl Codec {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Codec::Wav => "wav",
+            Codec::Flac => "flac",
+            Codec::Opus => "vxop",
+        }
+    }
+
+    /// Identifier used on the wire.
+    pub fn id(self) -> u8 {
+        match self {
+            Codec::Wav => 0,
+            Codec::Flac => 1,
+            Codec::Opus => 2,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Codec::Wav),
+            1 => Some(Codec::Flac),
+            2 => Some(Codec::Opus),
+            _ => None,
+        }
+    }
+}
+
+/// Encodes 16kHz mono samples.
+pub fn encode(samples: &[f32], codec: Codec) -> Result<Vec<u8>, Box<dyn Error>> {
+    match codec {
+        Codec::Wav => Ok(audio::encode_wav(samples)),
+        Codec::Flac => encode_flac(samples),
+        Codec::Opus => encode_opus(samples),
+    }
+}
+
+/// Decodes WAV, FLAC or Opus audio (detected from its header) to 16kHz mono.
+pub fn decode(bytes: &[u8]) -> Result<Vec<f32>, Box<dyn Error>> {
+    decode_channels(bytes, None)
+}
+
+/// Like `decode`, averaging only the `selection` channels of multi-channel WAV and FLAC
+/// files (all of them when `None`); Opus audio is always mono.
+pub fn decode_channels(
+    bytes: &[u8],
+    selection: Option<&[usize]>,
+) -> Result<Vec<f32>, Box<dyn Error>> {
+    if bytes.starts_with(b"RIFF") {
+        audio::decode_wav_channels(bytes, selection)
+    } else if bytes.starts_with(b"fLaC") {
+        decode_flac(bytes, selection)
+    } else if bytes.starts_with(OPUS_MAGIC) {
+        decode_opus(bytes)
+    } else {
+        Err("Unknown audio format (expected WAV, FLAC or Opus)".into())
+    }
+}
+
+fn encode_flac(samples: &[f32]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let pcm: Vec<i32> = samples
+        .iter()
+        .map(|sample| (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i32)
+        .collect();
+    let config = flacenc::config::Encoder::default()
+        .into_verified()
+        .map_err(|(_, e)| format!("Invalid FLAC encoder config: {:?}", e))?;
+    let source = flacenc::source::MemSource::from_samples(&pcm, 1, 16, SAMPLE_RATE as usize);
+    let stream = flacenc::encode_with_fixed_block_size(&config, source, config.block_size)
+        .map_err(|e| format!("FLAC encoding failed: {:?}", e))?;
+
+    let mut sink = flacenc::bitsink::ByteSink::new();
+    stream
+        .write(&mut sink)
+        .map_err(|e| format!("FLAC encoding failed: {:?}", e))?;
+    Ok(sink.as_slice().to_vec())
+}
+
+fn decode_flac(bytes: &[u8], selection: Option<&[usize]>) -> Result<Vec<f32>, Box<dyn Error>> {
+    let mut reader = claxon::FlacReader::new(Cursor::new(bytes))?;
+    let info = reader.streaminfo();
+    let scale = (1u32 << (info.bits_per_sample - 1)) as f32;
+    let samples = reader
+        .samples()
+        .map(|sample| sample.map(|s| s as f32 / scale))
+        .collect::<Result<Vec<f32>, _>>()?;
+    let mono = audio::downmix(&samples, info.channels as usize, selection);
+    Ok(audio::resample_from_rate(&mono, 1, info.sample_rate))
+}
+
+fn encode_opus(samples: &[f32]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut encoder =
+        opus::Encoder::new(SAMPLE_RATE, opus::Channels::Mono, opus::Application::Voip)?;
+    encoder.set_bitrate(opus::Bitrate::Bits(24_000))?;
+
+    let mut out = OPUS_MAGIC.to_vec();
+    out.extend_from_slice(&(samples.len() as u32).to_le_bytes());
+    for chunk in samples.chunks(OPUS_FRAME) {
+        let mut frame = chunk.to_vec();
+        frame.resize(OPUS_FRAME, 0.0);
+        let packet = encoder.encode_vec_float(&frame, MAX_OPUS_PACKET)?;
+        out.extend_from_slice(&(packet.len() as u16).to_le_bytes());
+        out.extend_from_slice(&packet);
+    }
+    Ok(out)
+}
+
+fn decode_opus(bytes: &[u8]) -> Result<Vec<f32>, Box<dyn Error>> {
+    let header = bytes.get(4..8).ok_or("Truncated Opus header")?;
+    let total = u32::from_le_bytes(header.try_into()?) as usize;
+    let mut decoder = opus::Decoder::new(SAMPLE_RATE, opus::Channels::Mono)?;
+
+    let mut samples = Vec::with_capacity(total);
+    let mut frame = vec![0.0f32; OPUS_FRAME * 6];
+    let mut offset = 8;
+    while offset + 2 <= bytes.len() {
+        let len = u16::from_le_bytes([bytes[offset], bytes[offset + 1]]) as usize;
+        let packet = bytes
+            .get(offset + 2..offset + 2 + len)
+            .ok_or("Truncated Opus packet")?;
+        let decoded = decoder.decode_float(packet, &mut frame, false)?;
+        samples.extend_from_slice(&frame[..decoded]);
+        offset += 2 + len;
+    }
+    // The last frame was padded with silence
+    samples.truncate(total);
+    Ok(samples)
+}
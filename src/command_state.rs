@@ -0,0 +1,127 @@
+use crate::actions::TextSink;
+use crate::config::Command;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Commands, profiles and action kinds disabled at runtime, persisted between runs.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct CommandState {
+    /// Lowercased triggers of disabled commands.
+    pub disabled_commands: BTreeSet<String>,
+    pub disabled_profiles: BTreeSet<String>,
+    pub disabled_groups: BTreeSet<String>,
+    /// Disabled action kinds ("shell", "app", "capture", "music", "browser", "service", "macro",
+    /// "ssh", "text").
+    pub disabled_kinds: BTreeSet<String>,
+    /// Where dictated text goes, overriding the config when set.
+    pub output_sink: Option<TextSink>,
+    /// Profile switched to at runtime, replacing the `profile` of the config when set.
+    pub active_profile: Option<String>,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+/// The command state shared between the main loop and the control server.
+pub type SharedCommandState = Arc<Mutex<CommandState>>;
+
+impl CommandState {
+    /// Loads the state from `path`, starting empty if the file doesn't exist yet.
+    pub fn load(path: &str) -> Self {
+        let mut state = match fs::read_to_string(path) {
+            Ok(data) => serde_json::from_str::<CommandState>(&data).unwrap_or_else(|e| {
+                log::error!("Error parsing command state {}: {}", path, e);
+                CommandState::default()
+            }),
+            Err(_) => CommandState::default(),
+        };
+        state.path = Some(PathBuf::from(path));
+        state
+    }
+
+    /// Writes the state back to the file it was loaded from.
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Whether `command` may currently be matched.
+    pub fn is_enabled(&self, command: &Command) -> bool {
+        !self.disabled_commands.contains(&command.trigger().to_lowercase())
+            && !self.disabled_kinds.contains(command.kind())
+            && !command
+                .profile
+                .as_ref()
+                .is_some_and(|profile| self.disabled_profiles.contains(profile))
+            && !command
+                .group
+                .as_ref()
+                .is_some_and(|group| self.disabled_groups.contains(group))
+    }
+
+    /// Enables or disables a single command by trigger.
+    pub fn set_command(&mut self, trigger: &str, enabled: bool) {
+        toggle(&mut self.disabled_commands, trigger.to_lowercase(), enabled);
+        self.persist();
+    }
+
+    /// Enables or disables every command of a profile.
+    pub fn set_profile(&mut self, profile: &str, enabled: bool) {
+        toggle(&mut self.disabled_profiles, profile.to_string(), enabled);
+        self.persist();
+    }
+
+    /// Enables or disables every command of a group.
+    pub fn set_group(&mut self, group: &str, enabled: bool) {
+        toggle(&mut self.disabled_groups, group.to_string(), enabled);
+        self.persist();
+    }
+
+    /// Enables or disables every command of an action kind.
+    pub fn set_kind(&mut self, kind: &str, enabled: bool) {
+        toggle(&mut self.disabled_kinds, kind.to_string(), enabled);
+        self.persist();
+    }
+
+    /// Overrides where dictated text goes; `None` goes back to the config.
+    pub fn set_output_sink(&mut self, sink: Option<TextSink>) {
+        self.output_sink = sink;
+        self.persist();
+    }
+
+    /// Switches to `profile`; `None` goes back to the one of the config.
+    pub fn set_active_profile(&mut self, profile: Option<String>) {
+        self.active_profile = profile;
+        self.persist();
+    }
+
+    /// The profile in use: the one switched to at runtime, or `configured`.
+    pub fn profile<'a>(&'a self, configured: Option<&'a str>) -> Option<&'a str> {
+        self.active_profile.as_deref().or(configured)
+    }
+
+    fn persist(&self) {
+        if let Err(e) = self.save() {
+            let path = self.path.as_deref().unwrap_or(Path::new(""));
+            log::error!("Failed to save command state to {}: {}", path.display(), e);
+        }
+    }
+}
+
+fn toggle(disabled: &mut BTreeSet<String>, key: String, enabled: bool) {
+    if enabled {
+        disabled.remove(&key);
+    } else {
+        disabled.insert(key);
+    }
+}
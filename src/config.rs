@@ -1,49 +1,510 @@
-use crate::actions;
-use serde::Deserialize;
+use crate::actions::{self, ActionContext, OutputFormat, ShellOptions, TextSink};
+use crate::audio::{AudioSettings, InputSettings};
+use crate::bert::{BertSettings, EmbeddingCacheSettings};
+use crate::browser::BrowserSettings;
+use crate::builtins;
+use crate::capture::CaptureSettings;
+use crate::captioning::CaptionSettings;
+use crate::command_state::SharedCommandState;
+use crate::control::ControlSettings;
+use crate::decoding::DecodingSettings;
+use crate::dialog;
+use crate::dictation::{self, DictationSettings};
+use crate::events::{self, Event};
+use crate::flashback::FlashbackSettings;
+use crate::grid::GridSettings;
+use crate::groups::{self, CommandGroup, ConfirmDialog};
+use crate::history::HistorySettings;
+use crate::language::LanguageSettings;
+use crate::macros::MacroSettings;
+use crate::memory::MemorySettings;
+use crate::messaging::MessagingSettings;
+use crate::music::MusicSettings;
+use crate::performance::PerformanceSettings;
+use crate::permissions::PermissionSettings;
+use crate::playback::PlaybackSettings;
+use crate::power::PowerSettings;
+use crate::privacy::{self, PrivacySettings};
+use crate::replay::RecordingSettings;
+use crate::routing::{self, RoutingRule};
+use crate::secrets::SecretsSettings;
+use crate::services::ServiceSpec;
+use crate::ssh::SshHost;
+use crate::vad::VadSettings;
+use crate::segmentation::SegmentationSettings;
+use crate::stt_fallback::FallbackSettings;
+use crate::summary::SummarySettings;
+use crate::telemetry::{self, TelemetrySettings};
+use crate::timeouts::{self, TimeoutFallback, TimeoutSettings};
+use crate::wakeword::WakeSettings;
+use crate::weather::WeatherSettings;
+use crate::whisper_integration::{
+    CleanupSettings, StreamingSettings, Transcription, WhisperSettings,
+};
+use schemars::r#gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+use std::collections::HashMap;
 use std::error::Error;
 use std::fs;
 
 impl AsRef<str> for Command {
     fn as_ref(&self) -> &str {
-        &self.trigger
+        self.trigger()
     }
 }
 
 #[derive(Deserialize, Clone)]
+#[serde(from = "RawCommand")]
 pub struct Command {
-    pub trigger: String,
+    /// One phrasing, or a list of synonymous phrasings. The first one names the command.
+    pub triggers: Vec<String>,
     pub action: String,
+    /// Where and how `cmd:` actions run, when the action is given as an object.
+    pub shell: ShellOptions,
+    /// Profile this command belongs to, so it can be toggled with the whole profile.
+    pub profile: Option<String>,
+    /// Actions run after `action`, with `{{file}}` replaced by the file it produced.
+    pub then: Vec<String>,
+    /// Group this command belongs to, whose settings it inherits.
+    pub group: Option<String>,
+    /// Similarity a phrasing must exceed to match; `MATCH_THRESHOLD` when unset.
+    pub threshold: Option<f32>,
+    /// Whether to ask before running the command once matched.
+    pub confirm: Option<bool>,
+    /// Apps the command is limited to; empty means everywhere.
+    pub context: Vec<String>,
 }
 
-#[derive(Deserialize, Clone)]
+/// A command as written in the config.
+#[derive(Deserialize, JsonSchema)]
+struct RawCommand {
+    /// A phrase, or a list of synonymous phrases. `triggers` is accepted too.
+    #[serde(rename = "trigger", alias = "triggers", deserialize_with = "one_or_many")]
+    #[schemars(with = "OneOrMany")]
+    triggers: Vec<String>,
+    action: ActionSpec,
+    #[serde(default)]
+    profile: Option<String>,
+    #[serde(default)]
+    then: Vec<String>,
+    #[serde(default)]
+    group: Option<String>,
+    #[serde(default)]
+    threshold: Option<f32>,
+    #[serde(default)]
+    confirm: Option<bool>,
+    #[serde(default)]
+    context: Vec<String>,
+}
+
+/// An action string, or a shell command with its options:
+/// `{"cmd": "cargo build", "cwd": "~/projects/app", "env": {"RUST_LOG": "debug"}}`.
+#[derive(Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum ActionSpec {
+    Plain(String),
+    Shell {
+        cmd: String,
+        #[serde(flatten)]
+        options: ShellOptions,
+    },
+}
+
+/// Commands are written as `RawCommand`s.
+impl JsonSchema for Command {
+    fn schema_name() -> String {
+        "Command".to_string()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        RawCommand::json_schema(generator)
+    }
+}
+
+impl From<RawCommand> for Command {
+    fn from(raw: RawCommand) -> Self {
+        let (action, shell) = match raw.action {
+            ActionSpec::Plain(action) => (action, ShellOptions::default()),
+            ActionSpec::Shell { cmd, options } => (format!("cmd:{}", cmd), options),
+        };
+        Command {
+            triggers: raw.triggers,
+            action,
+            shell,
+            profile: raw.profile,
+            then: raw.then,
+            group: raw.group,
+            threshold: raw.threshold,
+            confirm: raw.confirm,
+            context: raw.context,
+        }
+    }
+}
+
+/// A single string or a list of strings.
+#[derive(Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// Accepts either a single string or a non-empty list of strings.
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(trigger) => Ok(vec![trigger]),
+        OneOrMany::Many(triggers) if !triggers.is_empty() => Ok(triggers),
+        OneOrMany::Many(_) => Err(serde::de::Error::custom("trigger list must not be empty")),
+    }
+}
+
+/// One phrasing of a command, as a standalone candidate for matching.
+#[derive(Clone, Copy)]
+pub struct TriggerPhrase<'a> {
+    pub phrase: &'a str,
+    pub command: &'a Command,
+}
+
+impl AsRef<str> for TriggerPhrase<'_> {
+    fn as_ref(&self) -> &str {
+        self.phrase
+    }
+}
+
+/// Expands commands into one matching candidate per phrasing.
+pub fn trigger_phrases<'a, I>(commands: I) -> Vec<TriggerPhrase<'a>>
+where
+    I: IntoIterator<Item = &'a Command>,
+{
+    commands
+        .into_iter()
+        .flat_map(|command| {
+            command
+                .triggers
+                .iter()
+                .map(move |phrase| TriggerPhrase { phrase, command })
+        })
+        .collect()
+}
+
+impl Command {
+    /// The primary trigger, used to name the command in logs and toggles.
+    pub fn trigger(&self) -> &str {
+        self.triggers.first().map(String::as_str).unwrap_or("")
+    }
+
+    /// Similarity a phrasing of this command must exceed to match.
+    pub fn threshold(&self) -> f32 {
+        self.threshold.unwrap_or(crate::bert::MATCH_THRESHOLD)
+    }
+
+    /// Whether the command waits for a "oui" before running.
+    pub fn needs_confirmation(&self) -> bool {
+        self.confirm.unwrap_or(false)
+    }
+
+    /// Every kind `kind` returns, which can be disabled at runtime.
+    pub const KINDS: &'static [&'static str] = &[
+        "shell", "app", "capture", "music", "browser", "service", "macro", "ssh", "text",
+    ];
+
+    /// The kind of action this command runs: "shell" for `cmd:` actions, "app" for
+    /// `app:` launches, "capture" for screenshots and recordings, "music" for media
+    /// control, "browser" for web actions, "text" otherwise.
+    pub fn kind(&self) -> &'static str {
+        if self.action.starts_with("cmd:") {
+            "shell"
+        } else if self.action.starts_with("app:") {
+            "app"
+        } else if self.action.starts_with("screenshot:")
+            || self.action.starts_with("record-screen:")
+        {
+            "capture"
+        } else if self.action.starts_with("music:") {
+            "music"
+        } else if self.action.starts_with("browser:") {
+            "browser"
+        } else if self.action.starts_with("service:") {
+            "service"
+        } else if self.action.starts_with("macro:") {
+            "macro"
+        } else if self.action.starts_with("ssh:") {
+            "ssh"
+        } else {
+            "text"
+        }
+    }
+}
+
+/// A named set of settings overriding the global ones while it is active.
+#[derive(Deserialize, JsonSchema, Clone, Default)]
+#[serde(default)]
+pub struct Profile {
+    pub output: Option<OutputFormat>,
+    pub input: ProfileInput,
+}
+
+/// Audio settings of a profile, e.g. a far-field speakerphone needing a lower threshold
+/// than a headset. Each section set replaces the global one while the profile is active.
+#[derive(Deserialize, JsonSchema, Clone, Default, Debug)]
+#[serde(default)]
+pub struct ProfileInput {
+    pub vad: Option<VadSettings>,
+    pub timing: Option<AudioSettings>,
+}
+
+/// Version of the config format this build reads and writes.
+pub const CONFIG_VERSION: u32 = 1;
+
+/// Steps upgrading a config file to the next version: the first one upgrades version 1
+/// to 2, and so on. A file without `version` is version 1.
+const MIGRATIONS: &[fn(&mut serde_json::Map<String, Value>)] = &[];
+
+#[derive(Deserialize, JsonSchema, Clone)]
 pub struct Config {
+    /// Version of the config format, upgraded to `CONFIG_VERSION` when the files load.
+    #[serde(default = "current_version")]
+    pub version: u32,
     pub commands: Vec<Command>,
+    /// Formatting of typed text, unless the active profile overrides it.
+    #[serde(default)]
+    pub output: OutputFormat,
+    #[serde(default)]
+    pub profiles: HashMap<String, Profile>,
+    /// Settings shared by the commands naming the group in their `group`.
+    #[serde(default)]
+    pub groups: HashMap<String, CommandGroup>,
+    /// Name of the active profile, if any.
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Per-application output rules, checked in order against the focused window.
+    #[serde(default)]
+    pub routing: Vec<RoutingRule>,
+    #[serde(default)]
+    pub secrets: SecretsSettings,
+    #[serde(default)]
+    pub capture: CaptureSettings,
+    #[serde(default)]
+    pub captioning: CaptionSettings,
+    #[serde(default)]
+    pub input: InputSettings,
+    #[serde(default)]
+    pub wake: WakeSettings,
+    #[serde(default)]
+    pub segmentation: SegmentationSettings,
+    #[serde(default)]
+    pub flashback: FlashbackSettings,
+    #[serde(default)]
+    pub summary: SummarySettings,
+    #[serde(default)]
+    pub weather: WeatherSettings,
+    #[serde(default)]
+    pub music: MusicSettings,
+    #[serde(default)]
+    pub browser: BrowserSettings,
+    #[serde(default)]
+    pub messaging: MessagingSettings,
+    #[serde(default)]
+    pub control: ControlSettings,
+    #[serde(default)]
+    pub stt_fallback: FallbackSettings,
+    #[serde(default)]
+    pub embedding_cache: EmbeddingCacheSettings,
+    #[serde(default)]
+    pub bert: BertSettings,
+    #[serde(default)]
+    pub memory: MemorySettings,
+    #[serde(default)]
+    pub power: PowerSettings,
+    #[serde(default)]
+    pub performance: PerformanceSettings,
+    #[serde(default)]
+    pub cleanup: CleanupSettings,
+    #[serde(default)]
+    pub history: HistorySettings,
+    #[serde(default)]
+    pub privacy: PrivacySettings,
+    #[serde(default)]
+    pub dictation: DictationSettings,
+    #[serde(default)]
+    pub language: LanguageSettings,
+    #[serde(default)]
+    pub decoding: DecodingSettings,
+    #[serde(default)]
+    pub streaming: StreamingSettings,
+    #[serde(default)]
+    pub whisper: WhisperSettings,
+    #[serde(default)]
+    pub telemetry: TelemetrySettings,
+    #[serde(default)]
+    pub recording: RecordingSettings,
+    #[serde(default)]
+    pub permissions: PermissionSettings,
+    #[serde(default)]
+    pub macros: MacroSettings,
+    /// Long-running processes `service:` actions start and stop, by name.
+    #[serde(default)]
+    pub services: HashMap<String, ServiceSpec>,
+    /// Machines `ssh:` actions run commands on, by name.
+    #[serde(default)]
+    pub ssh: HashMap<String, SshHost>,
+    /// Texts "insère le snippet <nom>" types, by name.
+    #[serde(default)]
+    pub snippets: HashMap<String, String>,
+    #[serde(default)]
+    pub grid: GridSettings,
+    #[serde(default)]
+    pub timeouts: TimeoutSettings,
+    #[serde(default)]
+    pub playback: PlaybackSettings,
+}
+
+impl Config {
+    /// Returns the active profile, if one is selected and defined.
+    pub fn active_profile(&self) -> Option<&Profile> {
+        self.profile.as_ref().and_then(|name| self.profiles.get(name))
+    }
+
+    /// Returns the output format of the active profile, or the global one.
+    pub fn output_format(&self) -> &OutputFormat {
+        self.output_format_of(self.profile.as_deref())
+    }
+
+    /// Returns the output format of `profile`, or the global one.
+    pub fn output_format_of(&self, profile: Option<&str>) -> &OutputFormat {
+        profile
+            .and_then(|name| self.profiles.get(name))
+            .and_then(|profile| profile.output.as_ref())
+            .unwrap_or(&self.output)
+    }
+
+    /// The `input` settings with the overrides of `profile`, if it defines any.
+    pub fn input_settings(&self, profile: Option<&str>) -> InputSettings {
+        let mut input = self.input.clone();
+        if let Some(profile) = profile.and_then(|name| self.profiles.get(name)) {
+            if let Some(vad) = &profile.input.vad {
+                input.vad = vad.clone();
+            }
+            if let Some(timing) = &profile.input.timing {
+                input.timing = timing.clone();
+            }
+        }
+        input
+    }
+
+    /// Sends dictated text to `sink` in every output format, profiles included.
+    pub fn force_text_sink(&mut self, sink: TextSink) {
+        self.output.sink = sink;
+        for output in self.profiles.values_mut().filter_map(|p| p.output.as_mut()) {
+            output.sink = sink;
+        }
+    }
+
+    /// Builds the execution context handed to actions.
+    pub fn action_context(&self) -> ActionContext {
+        ActionContext {
+            output: self.output_format().clone(),
+            secrets: self.secrets.clone(),
+            capture: self.capture.clone(),
+            music: self.music.clone(),
+            browser: self.browser.clone(),
+            macros: self.macros.clone(),
+            services: self.services.clone(),
+            ssh: self.ssh.clone(),
+            shell: ShellOptions::default(),
+            transcription: String::new(),
+            confidence: None,
+            score: 0.0,
+        }
+    }
+}
+
+/// Version given to config files that don't set `version`.
+fn current_version() -> u32 {
+    CONFIG_VERSION
+}
+
+/// JSON Schema of a config file, for editors to complete and validate configs.
+pub fn json_schema() -> Value {
+    serde_json::to_value(schemars::schema_for!(Config)).unwrap_or_default()
+}
+
+/// Upgrades one config file to `CONFIG_VERSION`. Files written for a newer VoxAurora
+/// are refused rather than half understood.
+pub fn migrate(value: &mut Value) -> Result<(), String> {
+    let Value::Object(map) = value else {
+        return Err("a config file must be a JSON object".to_string());
+    };
+    let version = match map.get("version") {
+        None => 1,
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .filter(|version| *version >= 1)
+            .ok_or_else(|| format!("invalid config version {}", version))?,
+    };
+    if version > CONFIG_VERSION {
+        return Err(format!(
+            "config version {} is newer than this VoxAurora supports ({}), please update it",
+            version, CONFIG_VERSION
+        ));
+    }
+    for step in &MIGRATIONS[(version - 1) as usize..] {
+        step(map);
+    }
+    if version < CONFIG_VERSION {
+        log::info!(
+            "Upgraded config from version {} to {}; set \"version\": {} once it is updated",
+            version,
+            CONFIG_VERSION,
+            CONFIG_VERSION
+        );
+    }
+    map.insert("version".to_string(), CONFIG_VERSION.into());
+    Ok(())
 }
 
 /// Loads a combined configuration from the given file paths.
 /// It checks for duplicate triggers and logs errors if any file can't be read or parsed.
+/// Commands from every file are concatenated; settings sections from later files
+/// override the ones from earlier files. Each file is upgraded to `CONFIG_VERSION`
+/// first.
 pub fn load_config(paths: Vec<String>) -> Result<Config, Box<dyn Error>> {
-    let mut combined_config = Config {
-        commands: Vec::new(),
-    };
+    let mut merged = Value::Object(serde_json::Map::new());
     let mut seen_triggers = std::collections::HashSet::new();
 
     for path in paths {
         match fs::read_to_string(&path) {
             Ok(data) => {
-                match serde_json::from_str::<Config>(&data) {
-                    Ok(config) => {
-                        // Check for duplicate triggers
-                        for command in &config.commands {
-                            let trigger_lower = command.trigger.to_lowercase();
+                let mut value = match serde_json::from_str::<Value>(&data) {
+                    Ok(value) => value,
+                    Err(e) => {
+                        log::error!("Error parsing config file {}: {}", path, e);
+                        continue;
+                    }
+                };
+                if let Err(e) = migrate(&mut value) {
+                    log::error!("Error loading config file {}: {}", path, e);
+                    continue;
+                }
+                let parsed =
+                    serde_json::from_value::<Config>(value.clone()).map(|config| (value, config));
+                match parsed {
+                    Ok((value, config)) => {
+                        // Check for duplicate triggers, synonyms included
+                        for trigger in config.commands.iter().flat_map(|c| &c.triggers) {
+                            let trigger_lower = trigger.to_lowercase();
                             if !seen_triggers.insert(trigger_lower) {
-                                log::error!("Duplicate trigger found: '{}'", command.trigger);
+                                log::error!("Duplicate trigger found: '{}'", trigger);
                                 panic!("Duplicate triggers are not allowed in configuration");
                             }
                         }
 
-                        // Append the commands from this config file
-                        combined_config.commands.extend(config.commands);
+                        // Append the commands and settings from this config file
+                        merge_config_values(&mut merged, value);
                         log::info!("Loaded config from: {}", path);
                     }
                     Err(e) => {
@@ -57,54 +518,295 @@ pub fn load_config(paths: Vec<String>) -> Result<Config, Box<dyn Error>> {
         }
     }
 
+    if merged.get("commands").is_none() {
+        return Err("No valid configuration found in any of the provided paths".into());
+    }
+    let mut combined_config: Config = serde_json::from_value(merged)?;
+
     if combined_config.commands.is_empty() {
         return Err("No valid configuration found in any of the provided paths".into());
     }
+    groups::inherit(&mut combined_config.commands, &combined_config.groups);
+    if combined_config.privacy.enabled {
+        privacy::enforce(&mut combined_config);
+    }
 
     Ok(combined_config)
 }
 
+/// Merges one config file into the accumulated config.
+/// Top-level `commands` arrays are concatenated, objects are merged key by key,
+/// and any other value from `overlay` replaces the previous one.
+fn merge_config_values(base: &mut Value, overlay: Value) {
+    merge_values(base, overlay, true);
+}
+
+fn merge_values(base: &mut Value, overlay: Value, top_level: bool) {
+    match (base, overlay) {
+        (Value::Object(base_map), Value::Object(overlay_map)) => {
+            for (key, value) in overlay_map {
+                match base_map.get_mut(&key) {
+                    Some(Value::Array(existing)) if top_level && key == "commands" => {
+                        if let Value::Array(new_items) = value {
+                            existing.extend(new_items);
+                        }
+                    }
+                    Some(existing) => merge_values(existing, value, false),
+                    None => {
+                        base_map.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// What `execute_command` did with a transcription.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Handled {
+    /// A built-in command answered.
+    Builtin,
+    /// A command matched, through its primary trigger.
+    Command { trigger: String, score: f32 },
+    /// Nothing matched, so the text was typed or buffered.
+    Dictation,
+    /// Matching took longer than `timeouts` allows and the utterance was dropped.
+    TimedOut,
+}
+
+/// What matching an utterance against the trigger phrases found.
+enum Matched {
+    Command {
+        command: Command,
+        phrase: String,
+        score: f32,
+    },
+    Nothing,
+    /// A stage took too long; its fallback decides what becomes of the utterance.
+    TimedOut(TimeoutFallback),
+}
+
+fn send_error(message: String) -> Box<dyn std::error::Error + Send> {
+    Box::new(std::io::Error::new(std::io::ErrorKind::Other, message))
+}
+
+/// Compares `text` with every phrasing of `commands`, each BERT stage on the blocking
+/// pool within its timeout.
+async fn match_command(
+    text: &str,
+    commands: Vec<Command>,
+    settings: &TimeoutSettings,
+) -> Result<Matched, String> {
+    let embedding = timeouts::run_blocking("Embedding", &settings.embedding, {
+        let text = text.to_string();
+        move || crate::bert::encode_sentence(&text).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| format!("Task join error: {}", e))?;
+    let Some(embedding) = embedding else {
+        return Ok(Matched::TimedOut(settings.embedding.fallback));
+    };
+    let embedding = embedding?;
+
+    // Every phrasing competes; the best one picks its command
+    let best_match = timeouts::run_blocking("Matching", &settings.matching, move || {
+        let phrases = trigger_phrases(&commands);
+        crate::bert::best_match_with(&embedding, &phrases, |phrase| phrase.command.threshold())
+            .map(|best| {
+                best.map(|(phrase, score)| {
+                    (phrase.command.clone(), phrase.phrase.to_string(), score)
+                })
+            })
+            .map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| format!("Task join error: {}", e))?;
+    Ok(match best_match {
+        None => Matched::TimedOut(settings.matching.fallback),
+        Some(best_match) => match best_match? {
+            Some((command, phrase, score)) => Matched::Command {
+                command,
+                phrase,
+                score,
+            },
+            None => Matched::Nothing,
+        },
+    })
+}
+
 /// Executes a command based on the given transcription using the config's triggers.
-/// If a matching command is found (above a threshold), we execute `actions::execute_action`;
+/// Built-in commands are handled first. Then, if a matching enabled command is found
+/// (above a threshold), we execute `actions::execute_action`;
 /// otherwise, we fall back to `actions::execute_enigo_text`.
+/// Matching and typing read the stages of the transcription `cleanup.consumers` picks,
+/// and the matching stages give up after their `timeouts`.
 pub async fn execute_command(
     config: &Config,
-    transcription: String,
-) -> Result<(), Box<dyn std::error::Error + Send>> {
+    state: &SharedCommandState,
+    transcription: Transcription,
+) -> Result<Handled, Box<dyn std::error::Error + Send>> {
+    let matched = transcription.text(config.cleanup.consumers.matcher).to_string();
+    let typed = transcription.text(config.cleanup.consumers.typing).to_string();
+
+    // Built-ins can run actions, so they block too
+    let builtin = tokio::task::spawn_blocking({
+        let (config, state, matched) = (config.clone(), state.clone(), matched.clone());
+        move || {
+            let started = std::time::Instant::now();
+            let response = builtins::handle_builtin(&matched, &config, &state);
+            if let Some(response) = &response {
+                telemetry::record("action", started, vec![("builtin", response.clone())], None);
+            }
+            response
+        }
+    })
+    .await
+    .map_err(|e| send_error(format!("Task join error: {}", e)))?;
+    if let Some(response) = builtin {
+        log::info!("🔧 Built-in command: {}", response);
+        events::emit(Event::Builtin {
+            response: &response,
+        });
+        return Ok(Handled::Builtin);
+    }
+
+    // Only commands that haven't been disabled at runtime can be matched
+    let mut context = config.action_context();
+    let mut commands: Vec<Command> = {
+        let state = state.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(profile) = &state.active_profile {
+            context.output = config.output_format_of(Some(profile)).clone();
+        }
+        if let Some(sink) = state.output_sink {
+            context.output.sink = sink;
+        }
+        config
+            .commands
+            .iter()
+            .filter(|command| state.is_enabled(command))
+            .cloned()
+            .collect()
+    };
+    // Commands limited to some apps only match while one of them has the focus
+    groups::retain_in_context(&mut commands);
+    // Routing rules win over everything, so text never lands in e.g. a password manager
+    if let Some(sink) = routing::route_focused(&config.routing) {
+        context.output.sink = sink;
+    }
+
+    let started = std::time::Instant::now();
+    let best_match = match_command(&matched, commands, &config.timeouts).await;
+    let (attributes, error) = match &best_match {
+        Ok(Matched::Command { command, score, .. }) => (
+            vec![
+                ("trigger", command.trigger().to_string()),
+                ("score", format!("{:.3}", score)),
+            ],
+            None,
+        ),
+        Ok(Matched::TimedOut(_)) => (Vec::new(), Some("timed out".to_string())),
+        Ok(Matched::Nothing) => (Vec::new(), None),
+        Err(e) => (Vec::new(), Some(e.clone())),
+    };
+    telemetry::record("match", started, attributes, error);
+    let best_match = best_match.map_err(send_error)?;
+    if let Matched::TimedOut(TimeoutFallback::Skip) = best_match {
+        return Ok(Handled::TimedOut);
+    }
+
     // Delegate blocking operations to a separate thread
     let handle = tokio::task::spawn_blocking({
-        let transcription = transcription.clone();
         let config = config.clone();
-        move || -> Result<(), Box<dyn std::error::Error + Send>> {
-            match crate::bert::find_best_match(&transcription, &config.commands).map_err(|e| {
-                Box::new(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    format!("{}", e),
-                )) as Box<dyn std::error::Error + Send>
-            })? {
-                Some((command, best_score)) => {
-                    log::info!("✨ Command detected: {} (score = {:.3})", command.trigger, best_score);
-                    match actions::execute_action(&command.action) {
+        move || -> Result<Handled, Box<dyn std::error::Error + Send>> {
+            let started = std::time::Instant::now();
+            match best_match {
+                Matched::Command {
+                    command,
+                    phrase,
+                    score: best_score,
+                } => {
+                    log::info!(
+                        "✨ Command detected: {} via '{}' (score = {:.3})",
+                        command.trigger(),
+                        phrase,
+                        best_score
+                    );
+                    events::emit(Event::Match {
+                        trigger: command.trigger(),
+                        phrase: &phrase,
+                        score: best_score,
+                    });
+                    context.shell = command.shell.clone();
+                    context.transcription = typed;
+                    context.confidence = transcription.confidence;
+                    context.score = best_score;
+                    if command.needs_confirmation() {
+                        let trigger = command.trigger().to_string();
+                        let (confirm, question) = ConfirmDialog::new(command, context);
+                        let question = dialog::start(Box::new(confirm), question);
+                        log::info!("❓ {}", question);
+                        events::emit(Event::Builtin {
+                            response: &question,
+                        });
+                        return Ok(Handled::Command {
+                            trigger,
+                            score: best_score,
+                        });
+                    }
+                    let result = actions::execute_chain(&command.action, &command.then, &context);
+                    telemetry::record(
+                        "action",
+                        started,
+                        vec![("trigger", command.trigger().to_string())],
+                        result.as_ref().err().map(|e| e.to_string()),
+                    );
+                    match &result {
                         Ok(_) => log::info!("Command executed successfully"),
                         Err(e) => log::error!("Failed to execute command: {}", e),
                     }
+                    events::emit(Event::ActionResult {
+                        trigger: Some(command.trigger()),
+                        ok: result.is_ok(),
+                        error: result.err().map(|e| e.to_string()),
+                    });
+                    Ok(Handled::Command {
+                        trigger: command.trigger().to_string(),
+                        score: best_score,
+                    })
+                }
+                Matched::Nothing | Matched::TimedOut(_) if config.dictation.buffer => {
+                    log::info!("No matching command found. Adding the text to the buffer.");
+                    dictation::append(&typed, &config.dictation);
+                    let attributes = vec![("output", "dictation".to_string())];
+                    telemetry::record("action", started, attributes, None);
+                    Ok(Handled::Dictation)
                 }
-                None => {
+                Matched::Nothing | Matched::TimedOut(_) => {
                     log::info!("No matching command found. Executing raw text.");
-                    if let Err(e) = actions::execute_enigo_text(transcription.clone()) {
+                    let result = actions::execute_enigo_text(typed, &context.output);
+                    telemetry::record(
+                        "action",
+                        started,
+                        vec![("output", "text".into())],
+                        result.as_ref().err().map(|e| e.to_string()),
+                    );
+                    if let Err(e) = &result {
                         log::error!("Failed to execute text input: {}", e);
                     }
+                    events::emit(Event::ActionResult {
+                        trigger: None,
+                        ok: result.is_ok(),
+                        error: result.err().map(|e| e.to_string()),
+                    });
+                    Ok(Handled::Dictation)
                 }
             }
-            Ok(())
         }
     });
 
     // Await the blocking task's completion
-    handle.await.map_err(|e| {
-        Box::new(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            format!("Task join error: {}", e),
-        )) as Box<dyn std::error::Error + Send>
-    })?
+    handle
+        .await
+        .map_err(|e| send_error(format!("Task join error: {}", e)))?
 }
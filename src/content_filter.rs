@@ -0,0 +1,84 @@
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// What to do with a filtered word.
+#[derive(Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterMode {
+    /// Keep the first letter and replace the rest with `*` ("merde" -> "m****").
+    #[default]
+    Mask,
+    /// Remove the word entirely.
+    Drop,
+}
+
+/// Sensitive-content filter settings, read from the `cleanup.filter` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug, Default)]
+#[serde(default)]
+pub struct FilterSettings {
+    pub enabled: bool,
+    pub mode: FilterMode,
+    /// Word lists by category name (e.g. "profanity", "names", "work").
+    pub categories: HashMap<String, Vec<String>>,
+    /// Categories to apply. Empty means every configured category.
+    pub active_categories: Vec<String>,
+}
+
+/// Builds a single case-insensitive, whole-word regex for all active categories.
+fn build_pattern(settings: &FilterSettings) -> Option<Regex> {
+    let words: Vec<String> = settings
+        .categories
+        .iter()
+        .filter(|(name, _)| {
+            settings.active_categories.is_empty() || settings.active_categories.contains(name)
+        })
+        .flat_map(|(_, words)| words.iter())
+        .filter(|word| !word.trim().is_empty())
+        .map(|word| regex::escape(word.trim()))
+        .collect();
+
+    if words.is_empty() {
+        return None;
+    }
+
+    let pattern = format!(r"(?i)\b(?:{})\b", words.join("|"));
+    match Regex::new(&pattern) {
+        Ok(re) => Some(re),
+        Err(e) => {
+            log::error!("Invalid content filter word list: {}", e);
+            None
+        }
+    }
+}
+
+/// Masks or drops every configured sensitive word in `text`.
+pub fn apply_filter(text: &str, settings: &FilterSettings) -> String {
+    if !settings.enabled {
+        return text.to_string();
+    }
+    let Some(re) = build_pattern(settings) else {
+        return text.to_string();
+    };
+
+    let filtered = re.replace_all(text, |caps: &regex::Captures| match settings.mode {
+        FilterMode::Mask => {
+            let word = &caps[0];
+            let mut chars = word.chars();
+            let first = chars.next().map(String::from).unwrap_or_default();
+            first + &"*".repeat(chars.count())
+        }
+        FilterMode::Drop => String::new(),
+    });
+
+    if settings.mode == FilterMode::Drop {
+        // Dropped words leave double spaces and dangling spaces before punctuation
+        let re_spaces = Regex::new(r"\s+").unwrap();
+        let re_punct = Regex::new(r"\s+([,.])").unwrap();
+        let collapsed = re_spaces.replace_all(&filtered, " ");
+        re_punct.replace_all(&collapsed, "$1").trim().to_string()
+    } else {
+        filtered.into_owned()
+    }
+}
@@ -0,0 +1,253 @@
+use crate::actions::TextSink;
+use crate::builtins;
+use crate::command_state::SharedCommandState;
+use crate::config::{Command, Config};
+use crate::explain;
+use crate::health;
+use crate::transcription_api;
+use once_cell::sync::Lazy;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use tiny_http::{Header, Method, Request, Response, Server};
+use whisper_rs::WhisperContext;
+
+/// Runtime control settings, read from the `control` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct ControlSettings {
+    /// Address of the REST control server (e.g. "127.0.0.1:7878"). Disabled when unset.
+    pub listen: Option<String>,
+    /// File where commands disabled at runtime are persisted.
+    pub state_file: String,
+}
+
+impl Default for ControlSettings {
+    fn default() -> Self {
+        ControlSettings {
+            listen: None,
+            state_file: "./state/command_state.json".to_string(),
+        }
+    }
+}
+
+/// Model the transcription endpoint and health checks use. It isn't kept alive here so
+/// parking can free it.
+static MODEL: Lazy<Mutex<Weak<WhisperContext>>> = Lazy::new(|| Mutex::new(Weak::new()));
+
+/// Points the control server at `model`, e.g. once it is reloaded after parking.
+pub fn set_model(model: &Arc<WhisperContext>) {
+    *MODEL.lock().unwrap_or_else(|e| e.into_inner()) = Arc::downgrade(model);
+}
+
+/// The Whisper model, unless it is parked.
+fn current_model() -> Option<Arc<WhisperContext>> {
+    MODEL.lock().unwrap_or_else(|e| e.into_inner()).upgrade()
+}
+
+/// Starts the REST control server on a background thread, if an address is configured.
+///
+/// Routes:
+/// - `GET /commands` lists every command and whether it is enabled
+/// - `GET /help` lists the triggers that can currently be said
+/// - `GET /explain?text=<text>&k=<top k>` shows how a text scores against every command
+/// - `GET /healthz` probes every component, answering 503 when one is down
+/// - `POST /commands/<trigger>/{enable,disable}`
+/// - `POST /profiles/<name>/{enable,disable}`
+/// - `POST /groups/<name>/{enable,disable}`
+/// - `POST /kinds/<shell|app|capture|music|browser|service|macro|ssh|text>/{enable,disable}`
+///
+/// Toggling a name the config doesn't know answers 404.
+/// - `POST /output/<keyboard|clipboard|paste|suppress|default>` switches where dictated text goes
+/// - `POST /profile/<name|default>` switches the active profile, audio settings included
+/// - `POST /v1/audio/transcriptions`, compatible with the OpenAI transcription API
+///
+/// Transcriptions and health checks use the model given to `set_model`.
+pub fn start_control_server(
+    config: Arc<Config>,
+    state: SharedCommandState,
+) -> Option<thread::JoinHandle<()>> {
+    let address = config.control.listen.clone()?;
+    let server = match Server::http(&address) {
+        Ok(server) => server,
+        Err(e) => {
+            log::error!("Failed to start control server on {}: {}", address, e);
+            return None;
+        }
+    };
+    log::info!("🛰️ Control server listening on http://{}", address);
+    let language = config.language.default.clone();
+
+    Some(thread::spawn(move || {
+        for request in server.incoming_requests() {
+            // Transcriptions take seconds, so they don't hold up the other routes, and only
+            // a few run at once
+            if transcription_api::is_transcription_request(&request) {
+                let Some(slot) = transcription_api::TranscriptionSlot::acquire() else {
+                    transcription_api::respond_busy(request);
+                    continue;
+                };
+                let (model, language) = (current_model(), language.clone());
+                thread::spawn(move || {
+                    let _slot = slot;
+                    transcription_api::handle_transcription(request, model.as_deref(), &language)
+                });
+                continue;
+            }
+            if request.method() == &Method::Get && request.url() == "/healthz" {
+                let report = health::check(current_model().as_deref());
+                let status = if report.healthy { 200 } else { 503 };
+                respond_json(request, status, json!(report));
+                continue;
+            }
+
+            let (status, body) = route(&request, &config, &state);
+            respond_json(request, status, body);
+        }
+    }))
+}
+
+/// Dispatches a request to its handler, returning the status code and JSON body.
+fn route(
+    request: &Request,
+    config: &Config,
+    state: &SharedCommandState,
+) -> (u16, serde_json::Value) {
+    let url = request.url().split('?').next().unwrap_or("");
+    let segments: Vec<String> = url
+        .trim_matches('/')
+        .split('/')
+        .map(|s| urlencoding::decode(s).map(|d| d.into_owned()).unwrap_or_default())
+        .collect();
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+
+    match (request.method(), segments.as_slice()) {
+        (Method::Get, ["commands"]) => {
+            let state = state.lock().unwrap_or_else(|e| e.into_inner());
+            let commands: Vec<_> = config
+                .commands
+                .iter()
+                .map(|command| {
+                    json!({
+                        "trigger": command.trigger(),
+                        "synonyms": &command.triggers[1..],
+                        "kind": command.kind(),
+                        "profile": command.profile,
+                        "enabled": state.is_enabled(command),
+                    })
+                })
+                .collect();
+            (200, json!({ "commands": commands }))
+        }
+        (Method::Get, ["help"]) => {
+            let state = state.lock().unwrap_or_else(|e| e.into_inner());
+            let triggers: Vec<&str> = builtins::available_commands(config, &state)
+                .into_iter()
+                .flat_map(|command| command.triggers.iter().map(String::as_str))
+                .collect();
+            (200, json!({ "triggers": triggers }))
+        }
+        (Method::Get, ["explain"]) => {
+            let text = query_param(request.url(), "text").unwrap_or_default();
+            if text.trim().is_empty() {
+                return (400, json!({ "error": "Missing text parameter" }));
+            }
+            let top_k = query_param(request.url(), "k")
+                .and_then(|k| k.parse().ok())
+                .unwrap_or(5);
+            let state = state.lock().unwrap_or_else(|e| e.into_inner()).clone();
+            match explain::explain(&text, config, &state, top_k) {
+                Ok(explanation) => (200, json!(explanation)),
+                Err(e) => (500, json!({ "error": e.to_string() })),
+            }
+        }
+        (Method::Post, ["output", sink]) => {
+            let sink = match *sink {
+                "keyboard" => Some(TextSink::Keyboard),
+                "clipboard" => Some(TextSink::Clipboard),
+                "paste" => Some(TextSink::Paste),
+                "suppress" => Some(TextSink::Suppress),
+                "default" => None,
+                _ => return (404, json!({ "error": "Unknown output" })),
+            };
+            let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+            state.set_output_sink(sink);
+            log::info!("Control server: output set to {:?}", sink);
+            (200, json!({ "ok": true }))
+        }
+        (Method::Post, ["profile", name]) => {
+            let profile = match *name {
+                "default" => None,
+                name if config.profiles.contains_key(name) => Some(name.to_string()),
+                _ => return (404, json!({ "error": "Unknown profile" })),
+            };
+            let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+            log::info!("Control server: profile set to {:?}", profile);
+            state.set_active_profile(profile);
+            (200, json!({ "ok": true }))
+        }
+        (Method::Post, [scope, name, toggle @ ("enable" | "disable")]) => {
+            match toggle_target_known(config, scope, name) {
+                Some(true) => {}
+                Some(false) => {
+                    let error = format!("Unknown {} '{}'", scope.trim_end_matches('s'), name);
+                    return (404, json!({ "error": error }));
+                }
+                None => return (404, json!({ "error": "Unknown scope" })),
+            }
+            let enabled = *toggle == "enable";
+            let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+            match *scope {
+                "commands" => state.set_command(name, enabled),
+                "profiles" => state.set_profile(name, enabled),
+                "groups" => state.set_group(name, enabled),
+                "kinds" => state.set_kind(name, enabled),
+                _ => return (404, json!({ "error": "Unknown scope" })),
+            }
+            log::info!("Control server: {} {} {}d", scope, name, toggle);
+            (200, json!({ "ok": true }))
+        }
+        _ => (404, json!({ "error": "Not found" })),
+    }
+}
+
+/// Whether `name` is something of `scope` ("commands", "profiles", "groups" or "kinds")
+/// in `config`, so a typo isn't saved as a toggle; `None` for an unknown scope.
+pub fn toggle_target_known(config: &Config, scope: &str, name: &str) -> Option<bool> {
+    let named = |field: fn(&Command) -> Option<&str>| {
+        config.commands.iter().any(|command| field(command) == Some(name))
+    };
+    match scope {
+        "commands" => Some(config.commands.iter().any(|command| command.trigger() == name)),
+        "profiles" => Some(config.profiles.contains_key(name) || named(|c| c.profile.as_deref())),
+        "groups" => Some(config.groups.contains_key(name) || named(|c| c.group.as_deref())),
+        "kinds" => Some(Command::KINDS.contains(&name)),
+        _ => None,
+    }
+}
+
+/// Decoded value of `name` in the query string of `url`.
+fn query_param(url: &str, name: &str) -> Option<String> {
+    let (_, query) = url.split_once('?')?;
+    query.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        if key != name {
+            return None;
+        }
+        let value = value.replace('+', " ");
+        urlencoding::decode(&value).ok().map(|value| value.into_owned())
+    })
+}
+
+fn respond_json(request: Request, status: u16, body: serde_json::Value) {
+    let header = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+        .expect("Static header is valid");
+    let response = Response::from_string(body.to_string())
+        .with_status_code(status)
+        .with_header(header);
+    if let Err(e) = request.respond(response) {
+        log::warn!("Failed to send control server response: {}", e);
+    }
+}
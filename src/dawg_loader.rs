@@ -1,6 +1,12 @@
+use crate::accents::AccentIndex;
+use crate::memory;
+use crate::privacy;
 use daachorse::DoubleArrayAhoCorasick;
+use rayon::prelude::*;
 use std::collections::HashMap;
 use std::fs;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use unicode_normalization::UnicodeNormalization;
 use strsim::levenshtein;
 
@@ -15,52 +21,88 @@ const DICTIONARIES: &[(&str, &str)] = &[
     ),
 ];
 
-/// Loads DAWGs for multiple languages and returns both the DAWG automata
-/// and the original word lists in separate HashMaps.
-pub fn load_dawgs() -> (
+/// Loads DAWGs for multiple languages and returns the DAWG automata, the original
+/// word lists and the accent restoration indexes in separate HashMaps. The accent
+/// indexes are only built with `index_accents`, e.g. when `cleanup.accents` is enabled.
+/// Languages are downloaded, parsed and compiled in parallel.
+pub fn load_dawgs(index_accents: bool) -> (
     HashMap<&'static str, DoubleArrayAhoCorasick<u32>>,
-    HashMap<&'static str, Vec<String>>,
+    HashMap<&'static str, WordList>,
+    HashMap<&'static str, AccentIndex>,
 ) {
     // Ensure the target directory exists
     fs::create_dir_all("./dics").expect("Failed to create ./dics directory");
 
+    let completed = AtomicUsize::new(0);
+    let loaded: Vec<_> = DICTIONARIES
+        .par_iter()
+        .map(|(lang_code, url)| {
+            let (dawg, words) = load_language(lang_code, url);
+            let done = completed.fetch_add(1, Ordering::SeqCst) + 1;
+            log::info!(
+                "📖 Dictionaries: {}/{} ready ({})",
+                done,
+                DICTIONARIES.len(),
+                lang_code
+            );
+            (*lang_code, dawg, words)
+        })
+        .collect();
+
     let mut dawgs = HashMap::new();
     let mut word_lists = HashMap::new();
+    let mut accent_indexes = HashMap::new();
+
+    for (lang_code, dawg, words) in loaded {
+        dawgs.insert(lang_code, dawg);
 
-    for (lang_code, url) in DICTIONARIES.iter() {
-        let file_path = format!("./dics/{}.dic", lang_code);
+        if index_accents {
+            accent_indexes.insert(lang_code, AccentIndex::new(&words));
+        }
 
-        // Check if we already have a cached dictionary file
-        let content = if fs::metadata(&file_path).is_ok() {
-            log::info!("📂 Using cached dictionary file for {}...", lang_code);
-            fs::read_to_string(&file_path).expect("Error reading cached file")
+        // The DAWG alone is enough for exact lookups; the word list only feeds fuzzy matching
+        if memory::settings().keep_word_lists {
+            word_lists.insert(lang_code, WordList::new(words));
         } else {
-            log::info!("⏬ Downloading dictionary for {}...", lang_code);
-            let content = download_dic(url).expect("Dictionary download failed");
-            fs::write(&file_path, &content).expect("Failed to write dictionary file");
-            content
-        };
-
-        let words = parse_hunspell_dic(&content);
-
-        log::info!(
-            "✅ Extracted {} words for language {}",
-            words.len(),
-            lang_code
-        );
-
-        let dawg = DoubleArrayAhoCorasick::new(&words)
-            .expect("Failed to build DAWG automaton");
-        dawgs.insert(*lang_code, dawg);
-        word_lists.insert(*lang_code, words);
+            log::info!("🗑️ Dropping word list for {} (keep_word_lists = false)", lang_code);
+        }
     }
 
     log::info!("🌟 All DAWGs have been built successfully!");
-    (dawgs, word_lists)
+    (dawgs, word_lists, accent_indexes)
+}
+
+/// Reads (or downloads) the dictionary for one language and builds its DAWG.
+fn load_language(lang_code: &str, url: &str) -> (DoubleArrayAhoCorasick<u32>, Vec<String>) {
+    let file_path = format!("./dics/{}.dic", lang_code);
+
+    // Check if we already have a cached dictionary file
+    let content = if fs::metadata(&file_path).is_ok() {
+        log::info!("📂 Using cached dictionary file for {}...", lang_code);
+        fs::read_to_string(&file_path).expect("Error reading cached file")
+    } else {
+        log::info!("⏬ Downloading dictionary for {}...", lang_code);
+        let content = download_dic(url).expect("Dictionary download failed");
+        fs::write(&file_path, &content).expect("Failed to write dictionary file");
+        content
+    };
+
+    let words = parse_hunspell_dic(&content);
+
+    log::info!(
+        "✅ Extracted {} words for language {}",
+        words.len(),
+        lang_code
+    );
+
+    let dawg = DoubleArrayAhoCorasick::new(&words)
+        .expect("Failed to build DAWG automaton");
+    (dawg, words)
 }
 
 /// Downloads the dictionary content from the given `url`.
 fn download_dic(url: &str) -> Result<String, Box<dyn std::error::Error>> {
+    privacy::check("Downloading dictionaries")?;
     let body = ureq::get(url)
         .call()
         .unwrap()
@@ -88,24 +130,103 @@ fn parse_hunspell_dic(content: &str) -> Vec<String> {
     words
 }
 
+/// Whether the dictionary of `code` is used for `lang`, every one being used for `None`.
+pub fn in_language(code: &str, lang: Option<&str>) -> bool {
+    lang.is_none_or(|lang| lang.eq_ignore_ascii_case(code))
+}
+
 /// Checks if `word` is an exact match in the DAWG (not just a substring).
 pub fn contains_exact(dawg: &DoubleArrayAhoCorasick<u32>, word: &str) -> bool {
     dawg.find_iter(word)
         .any(|m| m.start() == 0 && m.end() == word.len())
 }
 
+/// A dictionary word list indexed by (first letter, length in chars) buckets,
+/// so fuzzy lookups only compare against words that can plausibly be close.
+pub struct WordList {
+    words: Vec<String>,
+    buckets: HashMap<(char, usize), Vec<u32>>,
+}
+
+impl WordList {
+    /// Builds the bucket index over an already-normalized word list.
+    pub fn new(words: Vec<String>) -> Self {
+        let mut buckets: HashMap<(char, usize), Vec<u32>> = HashMap::new();
+        for (i, word) in words.iter().enumerate() {
+            if let Some(first) = word.chars().next() {
+                buckets
+                    .entry((first, word.chars().count()))
+                    .or_default()
+                    .push(i as u32);
+            }
+        }
+        WordList { words, buckets }
+    }
+
+    /// Iterates over the words that share the first letter of `query`
+    /// and whose length is within `max_distance` of it.
+    fn candidates<'a>(
+        &'a self,
+        query: &str,
+        max_distance: usize,
+    ) -> impl Iterator<Item = &'a String> + 'a {
+        let first = query.chars().next();
+        let len = query.chars().count();
+        let lengths = len.saturating_sub(max_distance)..=len + max_distance;
+
+        lengths
+            .filter_map(move |l| first.and_then(|c| self.buckets.get(&(c, l))))
+            .flatten()
+            .map(move |&i| &self.words[i as usize])
+    }
+}
+
+impl Deref for WordList {
+    type Target = [String];
+
+    fn deref(&self) -> &[String] {
+        &self.words
+    }
+}
+
 /// Determines if `query` is similar to at least one word in `word_list` within `max_distance`
 /// using the Levenshtein distance.
+/// Only words sharing the query's first letter and a compatible length are compared, as
+/// in `is_most_similar_bucketed`, which finds them without scanning the whole list.
 pub fn is_most_similar(
     word_list: &[String],
     query: &str,
     max_distance: usize,
 ) -> bool {
     let normalized_query = query.to_lowercase().nfkc().collect::<String>();
+    let first = normalized_query.chars().next();
+    let len = normalized_query.chars().count();
+    let candidates = word_list.iter().filter(|word| {
+        word.chars().next() == first && word.chars().count().abs_diff(len) <= max_distance
+    });
+    within_distance(&normalized_query, candidates, max_distance)
+}
 
-    if let Some(min_distance) = word_list
-        .iter()
-        .map(|word| levenshtein(&normalized_query, word))
+/// `is_most_similar` over a `WordList`, only comparing the words of the buckets
+/// `query` can be close to.
+pub fn is_most_similar_bucketed(
+    word_list: &WordList,
+    query: &str,
+    max_distance: usize,
+) -> bool {
+    let normalized_query = query.to_lowercase().nfkc().collect::<String>();
+    let candidates = word_list.candidates(&normalized_query, max_distance);
+    within_distance(&normalized_query, candidates, max_distance)
+}
+
+/// Whether the closest of `candidates` is within `max_distance` of `normalized_query`.
+fn within_distance<'a>(
+    normalized_query: &str,
+    candidates: impl Iterator<Item = &'a String>,
+    max_distance: usize,
+) -> bool {
+    if let Some(min_distance) = candidates
+        .map(|word| levenshtein(normalized_query, word))
         .min()
     {
         log::info!("Levenshtein distance for {}: {}", normalized_query, min_distance);
@@ -0,0 +1,113 @@
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use once_cell::sync::OnceCell;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::io::Write;
+
+/// Whisper decoding settings, read from the `decoding` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct DecodingSettings {
+    /// Temperatures tried in order until a decode looks right. `[0.0]` disables the fallback.
+    pub temperatures: Vec<f32>,
+    /// A text compressing better than this is repeating itself ("merci merci merci...").
+    pub compression_ratio_threshold: f32,
+    /// Decodes whose mean token log-probability is below this are retried.
+    pub logprob_threshold: f32,
+    /// Pins decoding so that two runs over the same audio give the same transcripts:
+    /// greedy sampling at temperature 0 without the fallback, `REPRODUCIBLE_THREADS`
+    /// threads, and no fallback depending on time (`stt_fallback`, `timeouts`).
+    /// Also set by `--reproducible`.
+    pub reproducible: bool,
+}
+
+/// Whisper threads of a reproducible run, whatever the machine, as the thread count
+/// changes how floating-point sums are split.
+pub const REPRODUCIBLE_THREADS: i32 = 4;
+
+impl Default for DecodingSettings {
+    fn default() -> Self {
+        DecodingSettings {
+            temperatures: vec![0.0, 0.2, 0.4, 0.6, 0.8, 1.0],
+            compression_ratio_threshold: 2.4,
+            logprob_threshold: -1.0,
+            reproducible: false,
+        }
+    }
+}
+
+/// Heuristics telling a bad decode, as in the reference Whisper implementation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DecodeQuality {
+    pub compression_ratio: f32,
+    pub avg_logprob: f32,
+}
+
+impl DecodeQuality {
+    /// Measures `text`, decoded with the given token probabilities.
+    pub fn measure(text: &str, token_probs: &[f32]) -> Self {
+        let avg_logprob = if token_probs.is_empty() {
+            0.0
+        } else {
+            token_probs
+                .iter()
+                .map(|prob| prob.max(f32::MIN_POSITIVE).ln())
+                .sum::<f32>()
+                / token_probs.len() as f32
+        };
+        DecodeQuality {
+            compression_ratio: compression_ratio(text),
+            avg_logprob,
+        }
+    }
+
+    /// Geometric mean of the token probabilities, from 0 to 1.
+    pub fn confidence(&self) -> f32 {
+        self.avg_logprob.exp()
+    }
+
+    /// Whether the decode can be kept without trying a higher temperature.
+    pub fn acceptable(&self, settings: &DecodingSettings) -> bool {
+        self.compression_ratio <= settings.compression_ratio_threshold
+            && self.avg_logprob >= settings.logprob_threshold
+    }
+}
+
+/// Length of `text` over its zlib-compressed length; high for repetitive output.
+pub fn compression_ratio(text: &str) -> f32 {
+    if text.is_empty() {
+        return 1.0;
+    }
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    if encoder.write_all(text.as_bytes()).is_err() {
+        return 1.0;
+    }
+    match encoder.finish() {
+        Ok(compressed) => text.len() as f32 / compressed.len().max(1) as f32,
+        Err(_) => 1.0,
+    }
+}
+
+/// Process-wide decoding settings, set once after the config is loaded.
+static DECODING_SETTINGS: OnceCell<DecodingSettings> = OnceCell::new();
+
+/// Installs the decoding settings used by every transcription.
+pub fn init(settings: DecodingSettings) {
+    if settings.reproducible {
+        log::info!("🔁 Reproducible mode: greedy decoding at temperature 0, no fallbacks");
+    }
+    if DECODING_SETTINGS.set(settings).is_err() {
+        log::warn!("Decoding settings were already initialized, ignoring new values");
+    }
+}
+
+/// Returns the active decoding settings, or the defaults if `init` was never called.
+pub fn settings() -> &'static DecodingSettings {
+    DECODING_SETTINGS.get_or_init(DecodingSettings::default)
+}
+
+/// Whether this run is pinned for reproducibility, see `DecodingSettings::reproducible`.
+pub fn reproducible() -> bool {
+    settings().reproducible
+}
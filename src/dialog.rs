@@ -0,0 +1,70 @@
+use crate::builtins::normalize;
+use once_cell::sync::Lazy;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A dialog left unanswered this long is dropped, so a forgotten question
+/// doesn't swallow the next command.
+const DIALOG_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Answers ending any dialog.
+const CANCEL_PHRASES: &[&str] = &["annule", "laisse tomber", "stop", "cancel"];
+
+/// Outcome of one dialog turn.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Turn {
+    /// Another question to ask; the dialog waits for the next utterance.
+    Continue(String),
+    /// The dialog is over, with its final response.
+    Done(String),
+}
+
+/// A multi-turn built-in: every utterance goes to it until it is done.
+pub trait Dialog: Send {
+    /// Handles the next utterance, as transcribed.
+    fn answer(&mut self, text: &str) -> Turn;
+}
+
+struct ActiveDialog {
+    dialog: Box<dyn Dialog>,
+    last_turn: Instant,
+}
+
+/// The dialog waiting for an answer, if any.
+static ACTIVE: Lazy<Mutex<Option<ActiveDialog>>> = Lazy::new(|| Mutex::new(None));
+
+/// Makes `dialog` receive the next utterances and returns its first question.
+pub fn start(dialog: Box<dyn Dialog>, question: String) -> String {
+    let mut active = ACTIVE.lock().unwrap_or_else(|e| e.into_inner());
+    *active = Some(ActiveDialog {
+        dialog,
+        last_turn: Instant::now(),
+    });
+    question
+}
+
+/// Hands `text` to the active dialog. Returns `None` when no dialog is waiting.
+pub fn handle(text: &str) -> Option<String> {
+    let mut active = ACTIVE.lock().unwrap_or_else(|e| e.into_inner());
+    let current = active.as_mut()?;
+    if current.last_turn.elapsed() > DIALOG_TIMEOUT {
+        log::info!("💬 Dialog timed out");
+        *active = None;
+        return None;
+    }
+
+    if CANCEL_PHRASES.contains(&normalize(text).as_str()) {
+        *active = None;
+        return Some("Cancelled".to_string());
+    }
+    match current.dialog.answer(text) {
+        Turn::Continue(question) => {
+            current.last_turn = Instant::now();
+            Some(question)
+        }
+        Turn::Done(response) => {
+            *active = None;
+            Some(response)
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use crate::events::{self, Event};
+use once_cell::sync::Lazy;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::sync::Mutex;
+
+/// Dictation settings, read from the `dictation` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct DictationSettings {
+    /// Collect dictated text instead of typing it right away; "envoie" or "insère" types
+    /// it all at once, "efface la dernière phrase" removes the last utterance.
+    pub buffer: bool,
+    /// Show the buffer in a desktop notification whenever it changes.
+    pub notify: bool,
+}
+
+impl Default for DictationSettings {
+    fn default() -> Self {
+        DictationSettings {
+            buffer: false,
+            notify: true,
+        }
+    }
+}
+
+/// Dictated utterances waiting to be sent.
+#[derive(Debug, Default)]
+pub struct DictationBuffer {
+    sentences: Vec<String>,
+}
+
+impl DictationBuffer {
+    pub fn push(&mut self, text: &str) {
+        let text = text.trim();
+        if !text.is_empty() {
+            self.sentences.push(text.to_string());
+        }
+    }
+
+    /// Removes the last utterance, returning it.
+    pub fn pop(&mut self) -> Option<String> {
+        self.sentences.pop()
+    }
+
+    pub fn clear(&mut self) {
+        self.sentences.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.sentences.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sentences.is_empty()
+    }
+
+    /// The whole buffer, as it would be typed.
+    pub fn text(&self) -> String {
+        self.sentences.join(" ")
+    }
+
+    /// Empties the buffer, returning its text.
+    pub fn take(&mut self) -> String {
+        let text = self.text();
+        self.clear();
+        text
+    }
+}
+
+static BUFFER: Lazy<Mutex<DictationBuffer>> = Lazy::new(|| Mutex::new(DictationBuffer::default()));
+
+fn buffer() -> std::sync::MutexGuard<'static, DictationBuffer> {
+    BUFFER.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Adds a dictated utterance to the buffer.
+pub fn append(text: &str, settings: &DictationSettings) {
+    let mut buffer = buffer();
+    buffer.push(text);
+    show(&buffer, settings);
+}
+
+/// "efface la dernière phrase": removes the last utterance, returning it.
+pub fn undo(settings: &DictationSettings) -> Option<String> {
+    let mut buffer = buffer();
+    let removed = buffer.pop();
+    show(&buffer, settings);
+    removed
+}
+
+/// Empties the buffer without sending it.
+pub fn clear(settings: &DictationSettings) {
+    let mut buffer = buffer();
+    buffer.clear();
+    show(&buffer, settings);
+}
+
+/// Empties the buffer for sending, returning its text.
+pub fn take() -> String {
+    let mut buffer = buffer();
+    let text = buffer.take();
+    events::emit(Event::Dictation {
+        buffer: "",
+        sentences: 0,
+    });
+    text
+}
+
+/// Logs the buffer, emits it as an event and, when enabled, shows a notification.
+fn show(buffer: &DictationBuffer, settings: &DictationSettings) {
+    let text = buffer.text();
+    log::info!("📝 Dictation buffer ({} sentences): {}", buffer.len(), text);
+    events::emit(Event::Dictation {
+        buffer: &text,
+        sentences: buffer.len(),
+    });
+
+    if settings.notify {
+        let body = if buffer.is_empty() {
+            "(empty)"
+        } else {
+            text.as_str()
+        };
+        if let Err(e) = notify_rust::Notification::new()
+            .summary("VoxAurora: dictation")
+            .body(body)
+            .show()
+        {
+            log::warn!("Failed to show dictation notification: {}", e);
+        }
+    }
+}
@@ -0,0 +1,346 @@
+use crate::audio::{self, DeviceSpec};
+use crate::pipeline::AudioStep;
+use cpal::traits::{DeviceTrait, StreamTrait};
+use once_cell::sync::Lazy;
+use realfft::num_complex::Complex;
+use realfft::{ComplexToReal, RealFftPlanner, RealToComplex};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::sync::{Arc, Mutex, Weak, mpsc};
+
+/// Longest backlog of reference audio kept for a canceller, in seconds, e.g. while
+/// capture is paused.
+const MAX_BACKLOG_SECONDS: f32 = 0.5;
+
+/// How fast the power estimate of the reference follows the latest block.
+const POWER_SMOOTHING: f32 = 0.1;
+
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct EchoCancellationSettings {
+    /// Name, or part of it, of the input device capturing what the machine plays: the
+    /// "Monitor of …" source on PulseAudio or PipeWire, "Stereo Mix" on Windows.
+    pub reference: String,
+    /// Longest echo removed, in milliseconds, the delay from the speakers included.
+    pub tail_ms: u32,
+    /// Adaptation speed of the filter, from 0 to 1: higher converges faster, lower is
+    /// steadier while someone talks over the playback.
+    pub step_size: f32,
+}
+
+impl Default for EchoCancellationSettings {
+    fn default() -> Self {
+        EchoCancellationSettings {
+            reference: "monitor".to_string(),
+            tail_ms: 100,
+            step_size: 0.5,
+        }
+    }
+}
+
+/// Reference audio not yet matched with microphone audio, mono at its own rate.
+pub type ReferenceQueue = Arc<Mutex<VecDeque<f32>>>;
+
+/// A running loopback capture, shared by every canceller using the same device.
+struct Reference {
+    sample_rate: u32,
+    subscribers: Arc<Mutex<Vec<Weak<Mutex<VecDeque<f32>>>>>>,
+}
+
+/// Loopback captures opened since launch, by configured name. Their streams live on
+/// their own threads until exit.
+static REFERENCES: Lazy<Mutex<HashMap<String, Reference>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// A new queue fed with what the device matching `name` captures, and its sample rate.
+/// The device is opened the first time it is asked for.
+fn subscribe(name: &str) -> Result<(u32, ReferenceQueue), Box<dyn Error>> {
+    let mut references = REFERENCES.lock().unwrap_or_else(|e| e.into_inner());
+    let reference = match references.get(name) {
+        Some(reference) => reference,
+        None => {
+            let reference = open_reference(name)?;
+            references.entry(name.to_string()).or_insert(reference)
+        }
+    };
+    let queue: ReferenceQueue = Arc::new(Mutex::new(VecDeque::new()));
+    reference
+        .subscribers
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(Arc::downgrade(&queue));
+    Ok((reference.sample_rate, queue))
+}
+
+/// Starts capturing the device matching `name` on a thread of its own, which keeps the
+/// stream alive.
+fn open_reference(name: &str) -> Result<Reference, Box<dyn Error>> {
+    let (device, _) = audio::find_devices(&[DeviceSpec::Name(name.to_string())], None)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| format!("No input device matches '{}'", name))?;
+    let device_name = device.name().unwrap_or_default();
+    let config = device.default_input_config()?;
+    let sample_format = config.sample_format();
+    let sample_rate = config.sample_rate().0;
+    let channels = config.channels() as usize;
+    let max_backlog = (sample_rate as f32 * MAX_BACKLOG_SECONDS) as usize;
+    let subscribers: Arc<Mutex<Vec<Weak<Mutex<VecDeque<f32>>>>>> = Arc::default();
+    let feed = subscribers.clone();
+
+    let (started, result) = mpsc::channel();
+    std::thread::spawn(move || {
+        let stream = audio::build_input_stream(
+            &device,
+            &config.into(),
+            sample_format,
+            move |data: &[f32]| {
+                let mono = audio::downmix(data, channels, None);
+                let mut subscribers = feed.lock().unwrap_or_else(|e| e.into_inner());
+                subscribers.retain(|queue| {
+                    let Some(queue) = queue.upgrade() else {
+                        return false;
+                    };
+                    let mut queue = queue.lock().unwrap_or_else(|e| e.into_inner());
+                    queue.extend(&mono);
+                    let excess = queue.len().saturating_sub(max_backlog);
+                    queue.drain(..excess);
+                    true
+                });
+            },
+            move |err| log::error!("Echo reference stream error: {}", err),
+        );
+        let stream = match stream.map_err(|e| e.to_string()).and_then(|stream| {
+            stream.play().map_err(|e| e.to_string())?;
+            Ok(stream)
+        }) {
+            Ok(stream) => stream,
+            Err(e) => {
+                let _ = started.send(Err(e));
+                return;
+            }
+        };
+        let _ = started.send(Ok(()));
+        let _stream = stream;
+        loop {
+            std::thread::park();
+        }
+    });
+    result
+        .recv()
+        .map_err(|_| "Echo reference thread exited".to_string())??;
+    log::info!("🔁 Echo reference: {}", device_name);
+    Ok(Reference {
+        sample_rate,
+        subscribers,
+    })
+}
+
+/// Removes what the microphone picks up of the machine's own playback with a
+/// frequency-domain adaptive filter (overlap-save), which learns the path from the
+/// speakers to the microphone. Output lags the input by one block.
+pub struct EchoCanceller {
+    sample_rate: u32,
+    step_size: f32,
+    forward: Arc<dyn RealToComplex<f32>>,
+    inverse: Arc<dyn ComplexToReal<f32>>,
+    /// Where the reference comes from, `None` when its device couldn't be opened.
+    reference: Option<(u32, ReferenceQueue)>,
+    /// Position between the last two reference samples, to resample it linearly.
+    phase: f64,
+    previous: f32,
+    /// Reference block before the current one: each transform covers both.
+    last_block: Vec<f32>,
+    /// Echo path estimate, in the frequency domain.
+    weights: Vec<Complex<f32>>,
+    /// Smoothed power of the reference per frequency, unknown until the first block.
+    power: Option<Vec<f32>>,
+    /// Microphone samples waiting for a full block.
+    pending: Vec<f32>,
+}
+
+impl EchoCanceller {
+    /// Cancels the echo of the configured reference device in audio captured at
+    /// `sample_rate`. Audio passes through unchanged when the device can't be opened.
+    pub fn new(settings: &EchoCancellationSettings, sample_rate: u32) -> Self {
+        let reference = match subscribe(&settings.reference) {
+            Ok(reference) => Some(reference),
+            Err(e) => {
+                log::warn!("Echo cancellation disabled: {}", e);
+                None
+            }
+        };
+        Self::build(settings, sample_rate, reference)
+    }
+
+    /// Cancels the echo of the reference samples, captured at `reference_rate`, pushed to
+    /// `queue`.
+    pub fn with_reference(
+        settings: &EchoCancellationSettings,
+        sample_rate: u32,
+        reference_rate: u32,
+        queue: ReferenceQueue,
+    ) -> Self {
+        Self::build(settings, sample_rate, Some((reference_rate, queue)))
+    }
+
+    fn build(
+        settings: &EchoCancellationSettings,
+        sample_rate: u32,
+        reference: Option<(u32, ReferenceQueue)>,
+    ) -> Self {
+        let block_len = ((sample_rate as f32 * settings.tail_ms as f32 / 1000.0) as usize)
+            .next_power_of_two()
+            .max(64);
+        let mut planner = RealFftPlanner::<f32>::new();
+        let forward = planner.plan_fft_forward(2 * block_len);
+        let weights = forward.make_output_vec();
+        EchoCanceller {
+            sample_rate,
+            step_size: settings.step_size.clamp(0.0, 1.0),
+            inverse: planner.plan_fft_inverse(2 * block_len),
+            forward,
+            reference,
+            phase: 0.0,
+            previous: 0.0,
+            last_block: vec![0.0; block_len],
+            weights,
+            power: None,
+            pending: Vec::new(),
+        }
+    }
+
+    fn block_len(&self) -> usize {
+        self.last_block.len()
+    }
+
+    /// Drops the reference audio older than the microphone audio waiting in `pending`.
+    /// Reference audio paired with later microphone audio would put the echo before its
+    /// source, which the filter can't model; a reference arriving late only lengthens the
+    /// echo path.
+    fn align_reference(&mut self) {
+        let Some((reference_rate, queue)) = &self.reference else {
+            return;
+        };
+        let step = *reference_rate as f64 / self.sample_rate as f64;
+        let wanted = (self.pending.len() as f64 * step).ceil() as usize + 1;
+        let mut queue = queue.lock().unwrap_or_else(|e| e.into_inner());
+        let excess = queue.len().saturating_sub(wanted);
+        queue.drain(..excess);
+    }
+
+    /// The next `count` reference samples at the microphone rate, silence where playback
+    /// hasn't been captured yet.
+    fn take_reference(&mut self, count: usize) -> Vec<f32> {
+        let Some((reference_rate, queue)) = &self.reference else {
+            return vec![0.0; count];
+        };
+        let step = *reference_rate as f64 / self.sample_rate as f64;
+        let mut queue = queue.lock().unwrap_or_else(|e| e.into_inner());
+        let mut samples = Vec::with_capacity(count);
+        for _ in 0..count {
+            while self.phase >= 1.0 {
+                self.previous = queue.pop_front().unwrap_or(0.0);
+                self.phase -= 1.0;
+            }
+            let next = queue.front().copied().unwrap_or(self.previous);
+            samples.push(self.previous + (next - self.previous) * self.phase as f32);
+            self.phase += step;
+        }
+        samples
+    }
+
+    fn transform(&self, mut samples: Vec<f32>) -> Option<Vec<Complex<f32>>> {
+        let mut spectrum = self.forward.make_output_vec();
+        self.forward.process(&mut samples, &mut spectrum).ok()?;
+        Some(spectrum)
+    }
+
+    /// Back to the time domain, `spectrum` being the transform of a real signal.
+    fn inverse_transform(&self, mut spectrum: Vec<Complex<f32>>) -> Option<Vec<f32>> {
+        // The inverse transform of a real signal has no imaginary part at 0 and Nyquist
+        if let Some(first) = spectrum.first_mut() {
+            *first = Complex::new(first.re, 0.0);
+        }
+        if let Some(last) = spectrum.last_mut() {
+            *last = Complex::new(last.re, 0.0);
+        }
+        let mut samples = self.inverse.make_output_vec();
+        self.inverse.process(&mut spectrum, &mut samples).ok()?;
+        let scale = 1.0 / samples.len() as f32;
+        Some(samples.into_iter().map(|sample| sample * scale).collect())
+    }
+
+    /// Cancels the echo in one block of microphone samples and adapts the filter to
+    /// what is left.
+    fn process_block(&mut self, microphone: &[f32]) -> Option<Vec<f32>> {
+        let block_len = self.block_len();
+        let reference = self.take_reference(block_len);
+        let mut window = std::mem::replace(&mut self.last_block, reference.clone());
+        window.extend_from_slice(&reference);
+        let spectrum = self.transform(window)?;
+
+        let echo_spectrum = spectrum
+            .iter()
+            .zip(&self.weights)
+            .map(|(x, w)| x * w)
+            .collect();
+        let echo = self.inverse_transform(echo_spectrum)?;
+        let residual: Vec<f32> = microphone
+            .iter()
+            .zip(&echo[block_len..])
+            .map(|(sample, echo)| sample - echo)
+            .collect();
+
+        let mut padded = vec![0.0; block_len];
+        padded.extend_from_slice(&residual);
+        let error = self.transform(padded)?;
+        let power = self
+            .power
+            .get_or_insert_with(|| spectrum.iter().map(|x| x.norm_sqr()).collect());
+        let mut gradient = Vec::with_capacity(spectrum.len());
+        for ((x, e), power) in spectrum.iter().zip(&error).zip(power.iter_mut()) {
+            *power += (x.norm_sqr() - *power) * POWER_SMOOTHING;
+            gradient.push(x.conj() * e * (self.step_size / (*power + 1e-6)));
+        }
+        // Only the first half of the filter is kept, or it would wrap around circularly
+        let mut gradient = self.inverse_transform(gradient)?;
+        gradient[block_len..].fill(0.0);
+        for (weight, step) in self.weights.iter_mut().zip(self.transform(gradient)?) {
+            *weight += step;
+        }
+        Some(residual)
+    }
+}
+
+impl AudioStep for EchoCanceller {
+    fn process(&mut self, chunk: &[f32]) -> Vec<f32> {
+        if self.reference.is_none() {
+            return chunk.to_vec();
+        }
+        self.pending.extend_from_slice(chunk);
+        self.align_reference();
+        let block_len = self.block_len();
+        let mut output = Vec::with_capacity(self.pending.len());
+        while self.pending.len() >= block_len {
+            let block: Vec<f32> = self.pending.drain(..block_len).collect();
+            match self.process_block(&block) {
+                Some(residual) => output.extend(residual),
+                None => output.extend(block),
+            }
+        }
+        output
+    }
+
+    fn reset(&mut self) {
+        self.pending.clear();
+        if let Some((_, queue)) = &self.reference {
+            queue.lock().unwrap_or_else(|e| e.into_inner()).clear();
+        }
+        self.last_block.fill(0.0);
+        self.phase = 0.0;
+        self.previous = 0.0;
+    }
+}
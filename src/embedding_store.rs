@@ -0,0 +1,394 @@
+use crate::bert::cosine_similarity;
+use instant_distance::{Builder, HnswMap, Search};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// Where embeddings are kept, read from `embedding_cache.backend`.
+#[derive(Deserialize, JsonSchema, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreBackend {
+    /// Nothing is persisted.
+    Memory,
+    /// A JSON file rewritten when entries change.
+    #[default]
+    Json,
+    /// A SQLite database updated entry by entry.
+    Sqlite,
+}
+
+/// Embeddings of phrases, looked up by text or by similarity.
+pub trait EmbeddingStore: Send {
+    fn get(&self, text: &str) -> Option<Vec<f32>>;
+    fn contains(&self, text: &str) -> bool;
+    fn insert(&mut self, text: &str, embedding: Vec<f32>);
+    /// Drops the entries for which `keep` returns false.
+    fn retain(&mut self, keep: &dyn Fn(&str) -> bool);
+    fn len(&self) -> usize;
+    fn entries(&self) -> Box<dyn Iterator<Item = (&str, &[f32])> + '_>;
+    /// The `k` stored phrases most similar to `query` for which `accept` returns true,
+    /// best first.
+    fn nearest(
+        &self,
+        query: &[f32],
+        k: usize,
+        accept: &dyn Fn(&str) -> bool,
+    ) -> Vec<(String, f32)> {
+        let mut scored: Vec<(String, f32)> = self
+            .entries()
+            .filter(|(text, _)| accept(text))
+            .map(|(text, embedding)| (text.to_string(), cosine_similarity(query, embedding)))
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored.truncate(k);
+        scored
+    }
+    /// Persists pending changes, for backends that don't write them right away.
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+/// Entries kept in memory only; the other backends load into one.
+#[derive(Default)]
+pub struct MemoryStore {
+    entries: HashMap<String, Vec<f32>>,
+}
+
+impl EmbeddingStore for MemoryStore {
+    fn get(&self, text: &str) -> Option<Vec<f32>> {
+        self.entries.get(text).cloned()
+    }
+
+    fn contains(&self, text: &str) -> bool {
+        self.entries.contains_key(text)
+    }
+
+    fn insert(&mut self, text: &str, embedding: Vec<f32>) {
+        self.entries.insert(text.to_string(), embedding);
+    }
+
+    fn retain(&mut self, keep: &dyn Fn(&str) -> bool) {
+        self.entries.retain(|text, _| keep(text));
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn entries(&self) -> Box<dyn Iterator<Item = (&str, &[f32])> + '_> {
+        Box::new(
+            self.entries
+                .iter()
+                .map(|(text, embedding)| (text.as_str(), embedding.as_slice())),
+        )
+    }
+}
+
+/// On-disk layout of `JsonStore`.
+#[derive(Serialize, Deserialize, Default)]
+struct JsonFile {
+    model_id: String,
+    entries: HashMap<String, Vec<f32>>,
+}
+
+/// Entries saved as one JSON file, rewritten by `flush` when they changed.
+pub struct JsonStore {
+    path: String,
+    model_id: String,
+    memory: MemoryStore,
+    dirty: bool,
+}
+
+impl JsonStore {
+    /// Opens the file at `path`, discarding it if it was built with another model.
+    pub fn open(path: &str, model_id: &str) -> Self {
+        let entries = fs::read_to_string(path)
+            .ok()
+            .and_then(|data| serde_json::from_str::<JsonFile>(&data).ok())
+            .filter(|file| {
+                let same_model = file.model_id == model_id;
+                if !same_model {
+                    log::info!("Embedding cache was built with another model, discarding it");
+                }
+                same_model
+            })
+            .map(|file| file.entries)
+            .unwrap_or_default();
+        JsonStore {
+            path: path.to_string(),
+            model_id: model_id.to_string(),
+            memory: MemoryStore { entries },
+            dirty: false,
+        }
+    }
+}
+
+impl EmbeddingStore for JsonStore {
+    fn get(&self, text: &str) -> Option<Vec<f32>> {
+        self.memory.get(text)
+    }
+
+    fn contains(&self, text: &str) -> bool {
+        self.memory.contains(text)
+    }
+
+    fn insert(&mut self, text: &str, embedding: Vec<f32>) {
+        self.memory.insert(text, embedding);
+        self.dirty = true;
+    }
+
+    fn retain(&mut self, keep: &dyn Fn(&str) -> bool) {
+        let before = self.memory.len();
+        self.memory.retain(keep);
+        self.dirty |= self.memory.len() != before;
+    }
+
+    fn len(&self) -> usize {
+        self.memory.len()
+    }
+
+    fn entries(&self) -> Box<dyn Iterator<Item = (&str, &[f32])> + '_> {
+        self.memory.entries()
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        if !self.dirty {
+            return Ok(());
+        }
+        if let Some(dir) = Path::new(&self.path).parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = JsonFile {
+            model_id: self.model_id.clone(),
+            entries: self.memory.entries.clone(),
+        };
+        fs::write(&self.path, serde_json::to_string(&file)?)?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+/// Entries saved in a SQLite database, written as they are inserted.
+pub struct SqliteStore {
+    connection: rusqlite::Connection,
+    model_id: String,
+    memory: MemoryStore,
+}
+
+impl SqliteStore {
+    /// Opens (or creates) the database at `path` and loads the entries of `model_id`.
+    pub fn open(path: &str, model_id: &str) -> Result<Self, Box<dyn Error>> {
+        if let Some(dir) = Path::new(path).parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let connection = rusqlite::Connection::open(path)?;
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS embeddings (
+                model TEXT NOT NULL,
+                text TEXT NOT NULL,
+                embedding BLOB NOT NULL,
+                PRIMARY KEY (model, text)
+            )",
+            [],
+        )?;
+
+        let mut memory = MemoryStore::default();
+        {
+            let mut statement =
+                connection.prepare("SELECT text, embedding FROM embeddings WHERE model = ?1")?;
+            let rows = statement.query_map([model_id], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?))
+            })?;
+            for row in rows {
+                let (text, blob) = row?;
+                memory.insert(&text, from_blob(&blob));
+            }
+        }
+        Ok(SqliteStore {
+            connection,
+            model_id: model_id.to_string(),
+            memory,
+        })
+    }
+}
+
+impl EmbeddingStore for SqliteStore {
+    fn get(&self, text: &str) -> Option<Vec<f32>> {
+        self.memory.get(text)
+    }
+
+    fn contains(&self, text: &str) -> bool {
+        self.memory.contains(text)
+    }
+
+    fn insert(&mut self, text: &str, embedding: Vec<f32>) {
+        let result = self.connection.execute(
+            "INSERT OR REPLACE INTO embeddings (model, text, embedding) VALUES (?1, ?2, ?3)",
+            rusqlite::params![self.model_id, text, to_blob(&embedding)],
+        );
+        if let Err(e) = result {
+            log::error!("Failed to store the embedding of '{}': {}", text, e);
+        }
+        self.memory.insert(text, embedding);
+    }
+
+    fn retain(&mut self, keep: &dyn Fn(&str) -> bool) {
+        let dropped: Vec<String> = self
+            .memory
+            .entries()
+            .map(|(text, _)| text)
+            .filter(|text| !keep(text))
+            .map(str::to_string)
+            .collect();
+        for text in &dropped {
+            let result = self.connection.execute(
+                "DELETE FROM embeddings WHERE model = ?1 AND text = ?2",
+                rusqlite::params![self.model_id, text],
+            );
+            if let Err(e) = result {
+                log::error!("Failed to delete the embedding of '{}': {}", text, e);
+            }
+        }
+        self.memory.retain(keep);
+    }
+
+    fn len(&self) -> usize {
+        self.memory.len()
+    }
+
+    fn entries(&self) -> Box<dyn Iterator<Item = (&str, &[f32])> + '_> {
+        self.memory.entries()
+    }
+}
+
+fn to_blob(embedding: &[f32]) -> Vec<u8> {
+    embedding.iter().flat_map(|value| value.to_le_bytes()).collect()
+}
+
+fn from_blob(blob: &[u8]) -> Vec<f32> {
+    blob.chunks_exact(4)
+        .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect()
+}
+
+/// Point of the HNSW index: an embedding and its cosine distance.
+#[derive(Clone, Debug)]
+struct IndexedEmbedding(Vec<f32>);
+
+impl instant_distance::Point for IndexedEmbedding {
+    fn distance(&self, other: &Self) -> f32 {
+        1.0 - cosine_similarity(&self.0, &other.0)
+    }
+}
+
+/// Adds an approximate nearest-neighbour (HNSW) index to another store, for when it
+/// holds too many phrases to compare them all. The index is rebuilt on the first
+/// lookup after a change. `accept` filters what the search visited, so a lookup
+/// limited to a few phrases of a large store may miss some of them.
+pub struct HnswStore<S: EmbeddingStore> {
+    inner: S,
+    index: RefCell<Option<HnswMap<IndexedEmbedding, String>>>,
+}
+
+impl<S: EmbeddingStore> HnswStore<S> {
+    pub fn new(inner: S) -> Self {
+        HnswStore {
+            inner,
+            index: RefCell::new(None),
+        }
+    }
+}
+
+impl<S: EmbeddingStore> EmbeddingStore for HnswStore<S> {
+    fn get(&self, text: &str) -> Option<Vec<f32>> {
+        self.inner.get(text)
+    }
+
+    fn contains(&self, text: &str) -> bool {
+        self.inner.contains(text)
+    }
+
+    fn insert(&mut self, text: &str, embedding: Vec<f32>) {
+        self.inner.insert(text, embedding);
+        self.index.get_mut().take();
+    }
+
+    fn retain(&mut self, keep: &dyn Fn(&str) -> bool) {
+        self.inner.retain(keep);
+        self.index.get_mut().take();
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn entries(&self) -> Box<dyn Iterator<Item = (&str, &[f32])> + '_> {
+        self.inner.entries()
+    }
+
+    fn nearest(
+        &self,
+        query: &[f32],
+        k: usize,
+        accept: &dyn Fn(&str) -> bool,
+    ) -> Vec<(String, f32)> {
+        if self.inner.len() == 0 {
+            return Vec::new();
+        }
+        let mut index = self.index.borrow_mut();
+        let index = index.get_or_insert_with(|| {
+            let (points, values): (Vec<_>, Vec<_>) = self
+                .inner
+                .entries()
+                .map(|(text, embedding)| (IndexedEmbedding(embedding.to_vec()), text.to_string()))
+                .unzip();
+            Builder::default().build(points, values)
+        });
+
+        let mut search = Search::default();
+        index
+            .search(&IndexedEmbedding(query.to_vec()), &mut search)
+            .filter(|item| accept(item.value))
+            .take(k)
+            .map(|item| (item.value.clone(), 1.0 - item.distance))
+            .collect()
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.inner.flush()
+    }
+}
+
+/// Opens the store configured by `backend` at `path`, with an HNSW index if `hnsw` is set.
+/// Falls back to memory only if the store can't be opened.
+pub fn open(
+    backend: StoreBackend,
+    path: &str,
+    model_id: &str,
+    hnsw: bool,
+) -> Box<dyn EmbeddingStore> {
+    fn indexed<S: EmbeddingStore + 'static>(store: S, hnsw: bool) -> Box<dyn EmbeddingStore> {
+        if hnsw {
+            Box::new(HnswStore::new(store))
+        } else {
+            Box::new(store)
+        }
+    }
+
+    match backend {
+        StoreBackend::Memory => indexed(MemoryStore::default(), hnsw),
+        StoreBackend::Json => indexed(JsonStore::open(path, model_id), hnsw),
+        StoreBackend::Sqlite => match SqliteStore::open(path, model_id) {
+            Ok(store) => indexed(store, hnsw),
+            Err(e) => {
+                log::error!("Failed to open embedding database {}: {}", path, e);
+                indexed(MemoryStore::default(), hnsw)
+            }
+        },
+    }
+}
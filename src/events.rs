@@ -0,0 +1,107 @@
+use crate::history;
+use serde::Serialize;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Whether events are printed on stdout, enabled by `--output json`.
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// Something that happened in the pipeline, emitted as one JSON object per line.
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// The assistant was woken up or put to sleep.
+    StateChange { state: &'a str },
+    /// A cleaned transcription.
+    Transcript { text: &'a str },
+    /// What the streaming transcription heard of the segment being recorded, and which
+    /// leading words of it are stable.
+    PartialTranscript {
+        text: &'a str,
+        stable: &'a str,
+        is_final: bool,
+    },
+    /// A command matched the transcription.
+    Match {
+        trigger: &'a str,
+        phrase: &'a str,
+        score: f32,
+    },
+    /// A built-in command handled the transcription.
+    Builtin { response: &'a str },
+    /// A command action or typed text was executed.
+    ActionResult {
+        trigger: Option<&'a str>,
+        ok: bool,
+        error: Option<String>,
+    },
+    /// An utterance in a language that isn't allowed was set aside, with its
+    /// transcription when `language.foreign` is `"transcript"`.
+    ForeignSpeech {
+        language: &'a str,
+        probability: f32,
+        text: Option<&'a str>,
+    },
+    /// The dictation buffer changed; it is empty once sent.
+    Dictation { buffer: &'a str, sentences: usize },
+    /// An input device sends only silence or nothing at all.
+    InputAlert {
+        device: &'a str,
+        problem: &'a str,
+        hint: &'a str,
+    },
+    /// An input device failed, e.g. unplugged, and capture moved to `device`: the same
+    /// one when it came back, the default input device otherwise.
+    InputChange { lost: &'a str, device: &'a str },
+    /// A subsystem panicked and was restarted or degraded, or recovered.
+    Subsystem {
+        name: &'a str,
+        state: &'a str,
+        error: &'a str,
+    },
+}
+
+/// Turns on JSON event output on stdout.
+pub fn enable_json_output() {
+    JSON_OUTPUT.store(true, Ordering::Relaxed);
+}
+
+/// Whether JSON event output is on.
+pub fn json_output() -> bool {
+    JSON_OUTPUT.load(Ordering::Relaxed)
+}
+
+/// Records `event` in the history, then prints it as a JSON line if JSON output is on.
+pub fn emit(event: Event) {
+    match &event {
+        Event::Transcript { text } | Event::ForeignSpeech { text: Some(text), .. } => {
+            history::record_transcript(text)
+        }
+        Event::ActionResult { trigger, ok, error } => {
+            history::record_command(*trigger, *ok, error.as_deref())
+        }
+        _ => {}
+    }
+    if !json_output() {
+        return;
+    }
+
+    let timestamp_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    let mut value = match serde_json::to_value(&event) {
+        Ok(value) => value,
+        Err(e) => {
+            log::error!("Failed to serialize event {:?}: {}", event, e);
+            return;
+        }
+    };
+    value["timestamp_ms"] = timestamp_ms.into();
+
+    // Lock stdout so lines from concurrent threads never interleave
+    let mut stdout = std::io::stdout().lock();
+    let _ = writeln!(stdout, "{}", value);
+    let _ = stdout.flush();
+}
@@ -0,0 +1,176 @@
+use crate::bert::{self, MATCH_THRESHOLD};
+use crate::command_state::CommandState;
+use crate::config::Config;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::error::Error;
+use strsim::normalized_levenshtein;
+
+/// How a command scored against the explained text, through its best phrasing.
+#[derive(Serialize, Debug, Clone)]
+pub struct CandidateScore {
+    pub trigger: String,
+    /// The phrasing that scored best.
+    pub phrase: String,
+    pub kind: &'static str,
+    /// Cosine similarity of the sentence embeddings.
+    pub embedding: f32,
+    /// Normalized Levenshtein similarity, shown to spot near-identical wordings; the
+    /// matcher itself doesn't use it.
+    pub fuzzy: f64,
+    /// The score the matcher ranks on, currently the embedding similarity alone.
+    pub score: f32,
+    /// Disabled commands are scored but can't win.
+    pub enabled: bool,
+    pub above_threshold: bool,
+}
+
+/// Why a text matches a command, or doesn't.
+#[derive(Serialize, Debug)]
+pub struct Explanation {
+    pub text: String,
+    /// The global threshold; commands may set their own.
+    pub threshold: f32,
+    /// The best `top_k` commands, best first.
+    pub candidates: Vec<CandidateScore>,
+    pub winner: Option<String>,
+    pub reason: String,
+}
+
+/// Scores every command against `text` the way `execute_command` does.
+/// Built-in phrases are checked before commands and aren't covered.
+pub fn explain(
+    text: &str,
+    config: &Config,
+    state: &CommandState,
+    top_k: usize,
+) -> Result<Explanation, Box<dyn Error + Send + Sync>> {
+    let embedding = bert::encode_sentence(text)?;
+    let lowercase = text.to_lowercase();
+
+    let phrases = config.commands.iter().flat_map(|command| &command.triggers);
+    let similarities: HashMap<String, f32> =
+        bert::nearest(&embedding, phrases.map(String::as_str), usize::MAX)?.into_iter().collect();
+
+    let mut candidates = Vec::with_capacity(config.commands.len());
+    for command in &config.commands {
+        let mut best: Option<(f32, &str)> = None;
+        for phrase in &command.triggers {
+            let Some(&similarity) = similarities.get(phrase) else {
+                continue;
+            };
+            if best.is_none_or(|(score, _)| similarity > score) {
+                best = Some((similarity, phrase.as_str()));
+            }
+        }
+        let Some((score, phrase)) = best else {
+            continue;
+        };
+        candidates.push(CandidateScore {
+            trigger: command.trigger().to_string(),
+            phrase: phrase.to_string(),
+            kind: command.kind(),
+            embedding: score,
+            fuzzy: normalized_levenshtein(&lowercase, &phrase.to_lowercase()),
+            score,
+            enabled: state.is_enabled(command),
+            above_threshold: score > command.threshold(),
+        });
+    }
+    candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+    let winner = candidates
+        .iter()
+        .find(|candidate| candidate.enabled && candidate.above_threshold)
+        .map(|candidate| candidate.trigger.clone());
+    let reason = reason(&candidates, MATCH_THRESHOLD);
+    candidates.truncate(top_k.max(1));
+
+    Ok(Explanation {
+        text: text.to_string(),
+        threshold: MATCH_THRESHOLD,
+        candidates,
+        winner,
+        reason,
+    })
+}
+
+/// How far the winner of the candidates, sorted best first, is ahead of the next
+/// enabled one; its whole score when it has no rival, `None` without a winner.
+pub fn margin(candidates: &[CandidateScore]) -> Option<f32> {
+    let index = candidates
+        .iter()
+        .position(|candidate| candidate.enabled && candidate.above_threshold)?;
+    let winner = &candidates[index];
+    let runner_up = candidates[index + 1..].iter().find(|candidate| candidate.enabled);
+    Some(winner.score - runner_up.map_or(0.0, |runner_up| runner_up.score))
+}
+
+/// Explains the outcome from the candidates, sorted best first.
+pub fn reason(candidates: &[CandidateScore], threshold: f32) -> String {
+    let Some(index) = candidates
+        .iter()
+        .position(|candidate| candidate.enabled && candidate.above_threshold)
+    else {
+        return match candidates.iter().find(|candidate| candidate.enabled) {
+            Some(best) => format!(
+                "No enabled command scored above the threshold {:.2} (best: '{}' at {:.3}), so the text is typed as dictation",
+                threshold, best.trigger, best.score
+            ),
+            None => "No enabled command, so the text is typed as dictation".to_string(),
+        };
+    };
+
+    let winner = &candidates[index];
+    let mut reason = format!(
+        "'{}' has the best score among enabled commands: {:.3} via '{}', above the threshold {:.2}",
+        winner.trigger, winner.score, winner.phrase, threshold
+    );
+    if let Some(runner_up) = candidates[index + 1..].iter().find(|candidate| candidate.enabled) {
+        reason.push_str(&format!(
+            ", {:.3} ahead of '{}'",
+            winner.score - runner_up.score,
+            runner_up.trigger
+        ));
+    }
+    let skipped: Vec<&str> = candidates[..index]
+        .iter()
+        .map(|candidate| candidate.trigger.as_str())
+        .collect();
+    if !skipped.is_empty() {
+        reason.push_str(&format!(
+            "; higher-scoring disabled commands were skipped: {}",
+            skipped.join(", ")
+        ));
+    }
+    reason
+}
+
+/// Human-readable table of an explanation.
+pub fn render(explanation: &Explanation) -> String {
+    let mut out = format!(
+        "'{}' (threshold {:.2})\n   #  score  embed  fuzzy  command\n",
+        explanation.text, explanation.threshold
+    );
+    for (rank, candidate) in explanation.candidates.iter().enumerate() {
+        let mut notes = vec![candidate.kind];
+        if !candidate.enabled {
+            notes.push("disabled");
+        }
+        if candidate.above_threshold {
+            notes.push("above threshold");
+        }
+        out.push_str(&format!(
+            "  {:>2}  {:.3}  {:.3}  {:.3}  {} (via '{}') [{}]\n",
+            rank + 1,
+            candidate.score,
+            candidate.embedding,
+            candidate.fuzzy,
+            candidate.trigger,
+            candidate.phrase,
+            notes.join(", ")
+        ));
+    }
+    out.push_str(&format!("→ {}\n", explanation.reason));
+    out
+}
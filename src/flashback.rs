@@ -0,0 +1,175 @@
+use crate::audio;
+use crate::codec::{self, Codec};
+use crate::whisper_integration;
+use once_cell::sync::Lazy;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use whisper_rs::WhisperContext;
+
+/// Audio is compressed one second at a time.
+const BLOCK_SAMPLES: usize = 16_000;
+
+/// Always-on recording of the last seconds of audio, read from the `flashback`
+/// section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct FlashbackSettings {
+    /// Keep recording even while sleeping, so "transcris les 30 dernières secondes"
+    /// can recover something said before the wake word.
+    pub enabled: bool,
+    /// How much audio is kept, in seconds. It is stored as Opus (about 3 kB per second).
+    pub seconds: u64,
+}
+
+impl Default for FlashbackSettings {
+    fn default() -> Self {
+        FlashbackSettings {
+            enabled: false,
+            seconds: 60,
+        }
+    }
+}
+
+/// Ring buffer of the last seconds of captured audio, compressed in one-second blocks.
+pub struct FlashbackBuffer {
+    sample_rate: u32,
+    capacity: usize,
+    blocks: VecDeque<Vec<u8>>,
+    /// Samples at `sample_rate` not yet making a full block.
+    pending: Vec<f32>,
+    /// Length of the returned audio when the utterance being captured started, so a
+    /// flashback doesn't include the command asking for it.
+    speech_start: Option<usize>,
+}
+
+impl FlashbackBuffer {
+    /// Keeps `seconds` of mono audio captured at `sample_rate`.
+    pub fn new(seconds: u64, sample_rate: u32) -> Self {
+        FlashbackBuffer {
+            sample_rate,
+            capacity: seconds.max(1) as usize,
+            blocks: VecDeque::new(),
+            pending: Vec::new(),
+            speech_start: None,
+        }
+    }
+
+    pub fn push(&mut self, samples: &[f32]) {
+        self.pending.extend_from_slice(samples);
+        let block_len = self.sample_rate as usize;
+        while self.pending.len() >= block_len {
+            let rest = self.pending.split_off(block_len);
+            let mut block = audio::resample_from_rate(&self.pending, 1, self.sample_rate);
+            // The resampler pads its last chunk; a block is exactly one second
+            block.resize(BLOCK_SAMPLES, 0.0);
+            self.pending = rest;
+
+            match codec::encode(&block, Codec::Opus) {
+                Ok(encoded) => self.blocks.push_back(encoded),
+                Err(e) => {
+                    log::warn!("Failed to compress flashback audio: {}", e);
+                    continue;
+                }
+            }
+            if self.blocks.len() > self.capacity {
+                self.blocks.pop_front();
+                self.speech_start = self
+                    .speech_start
+                    .map(|start| start.saturating_sub(BLOCK_SAMPLES));
+            }
+        }
+    }
+
+    /// Follows the capture to a device running at another rate. Samples not yet making a
+    /// block are dropped.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.pending.clear();
+    }
+
+    /// Marks the start of an utterance: flashbacks end there until the next one.
+    pub fn mark_speech(&mut self) {
+        let pending = self.pending.len() * BLOCK_SAMPLES / self.sample_rate as usize;
+        self.speech_start = Some(self.blocks.len() * BLOCK_SAMPLES + pending);
+    }
+
+    /// Returns up to `duration` of 16kHz audio, ending where the last utterance started.
+    pub fn recent(&self, duration: Duration) -> Result<Vec<f32>, Box<dyn Error>> {
+        let wanted = (duration.as_secs_f32() * BLOCK_SAMPLES as f32) as usize;
+        let end = self.speech_start.unwrap_or(usize::MAX);
+        let mut samples = Vec::with_capacity(self.blocks.len() * BLOCK_SAMPLES);
+        for block in &self.blocks {
+            samples.extend(codec::decode(block)?);
+        }
+        if !self.pending.is_empty() {
+            samples.extend(audio::resample_from_rate(&self.pending, 1, self.sample_rate));
+        }
+        samples.truncate(end);
+        Ok(samples.split_off(samples.len().saturating_sub(wanted)))
+    }
+}
+
+/// The buffer fed by the capture, when enabled.
+static BUFFER: Lazy<Mutex<Option<FlashbackBuffer>>> = Lazy::new(|| Mutex::new(None));
+
+/// Model used to transcribe flashbacks. It isn't kept alive here so parking can free it.
+static MODEL: Lazy<Mutex<Weak<WhisperContext>>> = Lazy::new(|| Mutex::new(Weak::new()));
+
+/// Starts recording audio captured at `sample_rate`, if `settings` enable it.
+pub fn init(settings: &FlashbackSettings, sample_rate: u32) {
+    if !settings.enabled {
+        return;
+    }
+    log::info!("⏪ Keeping the last {} seconds of audio", settings.seconds);
+    let buffer = FlashbackBuffer::new(settings.seconds, sample_rate);
+    *BUFFER.lock().unwrap_or_else(|e| e.into_inner()) = Some(buffer);
+}
+
+pub fn set_model(model: &Arc<WhisperContext>) {
+    *MODEL.lock().unwrap_or_else(|e| e.into_inner()) = Arc::downgrade(model);
+}
+
+/// Adds captured mono samples to the buffer, if recording.
+pub fn record(samples: &[f32]) {
+    if let Some(buffer) = BUFFER.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        buffer.push(samples);
+    }
+}
+
+/// Records audio captured at `sample_rate` from now on, e.g. after a device change.
+pub fn set_sample_rate(sample_rate: u32) {
+    if let Some(buffer) = BUFFER.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        buffer.set_sample_rate(sample_rate);
+    }
+}
+
+pub fn mark_speech() {
+    if let Some(buffer) = BUFFER.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        buffer.mark_speech();
+    }
+}
+
+/// Transcribes the `duration` of audio heard before the current utterance.
+pub fn transcribe_recent(duration: Duration, lang: &str) -> Result<String, Box<dyn Error>> {
+    let samples = {
+        let buffer = BUFFER.lock().unwrap_or_else(|e| e.into_inner());
+        let buffer = buffer.as_ref().ok_or("flashback.enabled is not set")?;
+        buffer.recent(duration)?
+    };
+    if samples.is_empty() {
+        return Err("Nothing was recorded yet".into());
+    }
+    let model = MODEL
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .upgrade()
+        .ok_or("Whisper model isn't loaded")?;
+    // The flashback is typed like dictation
+    let transcription = whisper_integration::transcribe_sync(&model, &samples, lang, None)?;
+    let stage = whisper_integration::cleanup_settings().consumers.typing;
+    Ok(transcription.text(stage).to_string())
+}
@@ -0,0 +1,210 @@
+use crate::builtins::normalize;
+use crate::dialog::{Dialog, Turn};
+use enigo::{Button, Coordinate, Direction, Enigo, Mouse};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::error::Error;
+
+/// Mouse grid, read from the `grid` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct GridSettings {
+    /// Rows and columns of the grid; cells are numbered from 1 in reading order.
+    pub size: u32,
+}
+
+impl Default for GridSettings {
+    fn default() -> Self {
+        GridSettings { size: 3 }
+    }
+}
+
+/// French and English number words Whisper may write instead of digits.
+const NUMBER_WORDS: &[(&str, u32)] = &[
+    ("un", 1),
+    ("une", 1),
+    ("one", 1),
+    ("deux", 2),
+    ("two", 2),
+    ("trois", 3),
+    ("three", 3),
+    ("quatre", 4),
+    ("four", 4),
+    ("cinq", 5),
+    ("five", 5),
+    ("six", 6),
+    ("sept", 7),
+    ("seven", 7),
+    ("huit", 8),
+    ("eight", 8),
+    ("neuf", 9),
+    ("nine", 9),
+];
+
+/// How the mouse clicks once the cell is found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Click {
+    Left,
+    Double,
+    Right,
+}
+
+impl Click {
+    /// The click asked for by `normalized`, if any.
+    pub fn parse(normalized: &str) -> Option<Click> {
+        match normalized {
+            "clic" | "clique" | "click" => Some(Click::Left),
+            "double clic" | "double-clic" | "double clique" | "double click" => Some(Click::Double),
+            "clic droit" | "clique droit" | "right click" => Some(Click::Right),
+            _ => None,
+        }
+    }
+}
+
+/// A screen area, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Rect {
+    pub fn center(&self) -> (i32, i32) {
+        (self.x + self.width / 2, self.y + self.height / 2)
+    }
+
+    /// Cell `number` of this area cut into `size` rows and columns, numbered from 1 in
+    /// reading order.
+    pub fn cell(&self, size: u32, number: u32) -> Option<Rect> {
+        let size = size.max(1) as i32;
+        let number = i32::try_from(number).ok()?;
+        if number < 1 || number > size * size {
+            return None;
+        }
+        let (row, column) = ((number - 1) / size, (number - 1) % size);
+        let left = self.x + self.width * column / size;
+        let right = self.x + self.width * (column + 1) / size;
+        let top = self.y + self.height * row / size;
+        let bottom = self.y + self.height * (row + 1) / size;
+        Some(Rect {
+            x: left,
+            y: top,
+            width: right - left,
+            height: bottom - top,
+        })
+    }
+}
+
+/// The cell number said in `normalized`: digits or a number word, optionally after
+/// "case" or "cell".
+pub fn parse_cell(normalized: &str) -> Option<u32> {
+    let spoken = ["case ", "cell "]
+        .iter()
+        .find_map(|prefix| normalized.strip_prefix(prefix))
+        .unwrap_or(normalized)
+        .trim();
+    spoken.parse().ok().or_else(|| {
+        NUMBER_WORDS
+            .iter()
+            .find(|(word, _)| *word == spoken)
+            .map(|(_, number)| *number)
+    })
+}
+
+/// The grid layout, one row per line.
+pub fn layout(size: u32) -> String {
+    let size = size.max(1);
+    (0..size)
+        .map(|row| {
+            (1..=size)
+                .map(|column| (row * size + column).to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn enigo() -> Result<Enigo, Box<dyn Error>> {
+    Enigo::new(&enigo::Settings::default())
+        .map_err(|e| format!("Failed to create Enigo instance: {}", e).into())
+}
+
+/// Narrows the mouse down to a cell of a numbered grid at each number said, recursively,
+/// until a click is asked for.
+pub struct GridDialog {
+    size: u32,
+    area: Rect,
+}
+
+impl GridDialog {
+    /// Starts with the whole main display, the mouse at its center, and announces the
+    /// grid in a notification.
+    pub fn new(settings: &GridSettings) -> Result<(Self, String), Box<dyn Error>> {
+        let mut enigo = enigo()?;
+        let (width, height) = enigo.main_display()?;
+        let dialog = GridDialog {
+            size: settings.size.max(1),
+            area: Rect {
+                x: 0,
+                y: 0,
+                width,
+                height,
+            },
+        };
+        let (x, y) = dialog.area.center();
+        enigo.move_mouse(x, y, Coordinate::Abs)?;
+        if let Err(e) = notify_rust::Notification::new()
+            .summary("VoxAurora: mouse grid")
+            .body(&layout(dialog.size))
+            .show()
+        {
+            log::warn!("Failed to show the grid: {}", e);
+        }
+        let question = dialog.prompt();
+        Ok((dialog, question))
+    }
+
+    fn prompt(&self) -> String {
+        format!(
+            "Say a cell from 1 to {}, then “clic”",
+            self.size * self.size
+        )
+    }
+
+    fn click(click: Click) -> Result<(), Box<dyn Error>> {
+        let mut enigo = enigo()?;
+        match click {
+            Click::Left => enigo.button(Button::Left, Direction::Click)?,
+            Click::Double => {
+                enigo.button(Button::Left, Direction::Click)?;
+                enigo.button(Button::Left, Direction::Click)?;
+            }
+            Click::Right => enigo.button(Button::Right, Direction::Click)?,
+        }
+        Ok(())
+    }
+}
+
+impl Dialog for GridDialog {
+    fn answer(&mut self, text: &str) -> Turn {
+        let normalized = normalize(text);
+        if let Some(click) = Click::parse(&normalized) {
+            return Turn::Done(match Self::click(click) {
+                Ok(()) => "Clicked".to_string(),
+                Err(e) => format!("Couldn't click: {}", e),
+            });
+        }
+        let Some(cell) = parse_cell(&normalized).and_then(|n| self.area.cell(self.size, n)) else {
+            return Turn::Continue(self.prompt());
+        };
+        self.area = cell;
+        let (x, y) = cell.center();
+        if let Err(e) = enigo().and_then(|mut enigo| Ok(enigo.move_mouse(x, y, Coordinate::Abs)?)) {
+            return Turn::Done(format!("Couldn't move the mouse: {}", e));
+        }
+        Turn::Continue(format!("Mouse at {}, {}: another cell or “clic”", x, y))
+    }
+}
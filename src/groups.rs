@@ -0,0 +1,100 @@
+use crate::actions::{self, ActionContext};
+use crate::builtins::normalize;
+use crate::config::Command;
+use crate::dialog::{Dialog, Turn};
+use crate::window::{self, FocusedWindow};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::borrow::Borrow;
+use std::collections::HashMap;
+
+/// Answers running a command waiting for confirmation.
+const CONFIRM_PHRASES: &[&str] = &["oui", "vas-y", "confirme", "c'est bon", "yes"];
+
+/// Answers dropping it.
+const REJECT_PHRASES: &[&str] = &["non", "no"];
+
+/// Settings shared by the commands of a group, e.g. "navigation" or "media".
+/// Members inherit every setting they don't set themselves.
+#[derive(Deserialize, JsonSchema, Clone, Default, Debug)]
+#[serde(default)]
+pub struct CommandGroup {
+    /// Similarity a phrasing must exceed to match, instead of the global threshold.
+    pub threshold: Option<f32>,
+    /// Whether to ask "oui"/"non" before running a matched command.
+    pub confirm: Option<bool>,
+    /// Apps the commands are limited to, matched case-insensitively against the
+    /// focused app name or window title. Empty means everywhere.
+    pub context: Vec<String>,
+}
+
+/// Fills the settings commands leave unset from their group.
+pub fn inherit(commands: &mut [Command], groups: &HashMap<String, CommandGroup>) {
+    for command in commands {
+        let Some(name) = &command.group else {
+            continue;
+        };
+        let Some(group) = groups.get(name) else {
+            log::warn!("Command '{}' is in unknown group '{}'", command.trigger(), name);
+            continue;
+        };
+        command.threshold = command.threshold.or(group.threshold);
+        command.confirm = command.confirm.or(group.confirm);
+        if command.context.is_empty() {
+            command.context = group.context.clone();
+        }
+    }
+}
+
+/// Whether `command` may match while `window` has the focus. Commands limited to
+/// some apps stay available when the focused window can't be detected.
+pub fn in_context(command: &Command, window: Option<&FocusedWindow>) -> bool {
+    match window {
+        Some(window) if !command.context.is_empty() => {
+            command.context.iter().any(|app| window.matches(app))
+        }
+        _ => true,
+    }
+}
+
+/// Drops the commands that can't match in the focused window. The window is only looked
+/// up when one of them has a `context`.
+pub fn retain_in_context<C: Borrow<Command>>(commands: &mut Vec<C>) {
+    if commands.iter().all(|command| command.borrow().context.is_empty()) {
+        return;
+    }
+    let focused = window::focused_window();
+    commands.retain(|command| in_context(command.borrow(), focused.as_ref()));
+}
+
+/// Asks before running a command of a group with `confirm`.
+pub struct ConfirmDialog {
+    command: Command,
+    context: ActionContext,
+}
+
+impl ConfirmDialog {
+    /// Returns the dialog and its question.
+    pub fn new(command: Command, context: ActionContext) -> (Self, String) {
+        let question = format!("Run '{}'? Say yes or no", command.trigger());
+        (ConfirmDialog { command, context }, question)
+    }
+}
+
+impl Dialog for ConfirmDialog {
+    fn answer(&mut self, text: &str) -> Turn {
+        let normalized = normalize(text);
+        let trigger = self.command.trigger();
+        if CONFIRM_PHRASES.contains(&normalized.as_str()) {
+            let command = &self.command;
+            match actions::execute_chain(&command.action, &command.then, &self.context) {
+                Ok(()) => Turn::Done(format!("Command '{}' executed", trigger)),
+                Err(e) => Turn::Done(format!("Command '{}' failed: {}", trigger, e)),
+            }
+        } else if REJECT_PHRASES.contains(&normalized.as_str()) {
+            Turn::Done(format!("Command '{}' cancelled", trigger))
+        } else {
+            Turn::Continue(format!("Say yes to run '{}', or no", trigger))
+        }
+    }
+}
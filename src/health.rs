@@ -0,0 +1,216 @@
+use crate::bert;
+use crate::memory;
+use crate::performance;
+use crate::permissions::{self, Grant, Permission};
+use crate::whisper_integration::{self, DAWGS};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use whisper_rs::WhisperContext;
+
+/// Audio older than this means the input stream stopped, like the watchdog's stall.
+const AUDIO_MAX_AGE: Duration = Duration::from_secs(5);
+
+/// When the last audio callback ran, in milliseconds since the epoch; 0 before any.
+static LAST_AUDIO: AtomicU64 = AtomicU64::new(0);
+
+/// How a component is doing.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    /// Working, but not as configured (e.g. still loading).
+    Degraded,
+    Down,
+    /// Not used by this instance.
+    Disabled,
+}
+
+/// The result of one probe.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ComponentHealth {
+    pub name: String,
+    pub status: Status,
+    /// How long the probe took.
+    pub latency_ms: Option<f64>,
+    pub detail: String,
+}
+
+impl ComponentHealth {
+    fn new(name: &str, status: Status, latency: Option<Duration>, detail: String) -> Self {
+        ComponentHealth {
+            name: name.to_string(),
+            status,
+            latency_ms: latency.map(|latency| latency.as_secs_f64() * 1000.0),
+            detail,
+        }
+    }
+
+    /// Times `probe`, reporting the component down when it fails.
+    fn probe(name: &str, probe: impl FnOnce() -> Result<String, Box<dyn Error>>) -> Self {
+        let started = Instant::now();
+        let result = probe();
+        let latency = Some(started.elapsed());
+        match result {
+            Ok(detail) => ComponentHealth::new(name, Status::Ok, latency, detail),
+            Err(e) => ComponentHealth::new(name, Status::Down, latency, e.to_string()),
+        }
+    }
+}
+
+/// Served by `GET /healthz` and printed by `--status`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HealthReport {
+    /// No component is down.
+    pub healthy: bool,
+    pub components: Vec<ComponentHealth>,
+}
+
+impl HealthReport {
+    pub fn new(components: Vec<ComponentHealth>) -> Self {
+        HealthReport {
+            healthy: components
+                .iter()
+                .all(|component| component.status != Status::Down),
+            components,
+        }
+    }
+
+    /// One line per component, as printed by `--status`.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for component in &self.components {
+            let icon = match component.status {
+                Status::Ok => "✅",
+                Status::Degraded => "⚠️",
+                Status::Down => "❌",
+                Status::Disabled => "➖",
+            };
+            let latency = component
+                .latency_ms
+                .map(|latency| format!(" ({:.1} ms)", latency))
+                .unwrap_or_default();
+            out.push_str(&format!(
+                "{} {}: {}{}\n",
+                icon, component.name, component.detail, latency
+            ));
+        }
+        out.push_str(if self.healthy { "healthy\n" } else { "unhealthy\n" });
+        out
+    }
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_millis() as u64)
+}
+
+/// Called by the capture callback whenever audio arrives.
+pub fn audio_heartbeat() {
+    LAST_AUDIO.store(unix_millis(SystemTime::now()), Ordering::Relaxed);
+}
+
+/// Whether the input stream is still delivering audio.
+pub fn probe_audio() -> ComponentHealth {
+    let last = LAST_AUDIO.load(Ordering::Relaxed);
+    if last == 0 {
+        let detail = "no audio captured by this instance".to_string();
+        return ComponentHealth::new("audio", Status::Disabled, None, detail);
+    }
+    let age = Duration::from_millis(unix_millis(SystemTime::now()).saturating_sub(last));
+    if age > AUDIO_MAX_AGE {
+        let detail = format!("no audio for {} s", age.as_secs());
+        ComponentHealth::new("audio", Status::Down, Some(age), detail)
+    } else {
+        ComponentHealth::new("audio", Status::Ok, Some(age), "receiving audio".to_string())
+    }
+}
+
+/// Computes the mel spectrogram of a second of silence, which needs a working context.
+pub fn probe_whisper(model: Option<&WhisperContext>) -> ComponentHealth {
+    let Some(model) = model else {
+        let detail = "parked until the next wake word".to_string();
+        return ComponentHealth::new("whisper", Status::Disabled, None, detail);
+    };
+    ComponentHealth::probe("whisper", || {
+        let mut state = model.create_state()?;
+        state.pcm_to_mel(&[0.0; 16_000], performance::whisper_thread_count())?;
+        Ok("model loaded".to_string())
+    })
+}
+
+/// Encodes a short sentence, loading the shared model if it is parked.
+pub fn probe_bert() -> ComponentHealth {
+    ComponentHealth::probe("bert", || {
+        let embedding = bert::encode_sentence("bonjour").map_err(|e| e.to_string())?;
+        Ok(format!("{} dimensions", embedding.len()))
+    })
+}
+
+/// Sends a check request to the LanguageTool server, when the cleanup uses it.
+pub fn probe_languagetool() -> ComponentHealth {
+    if !whisper_integration::cleanup_settings().languagetool {
+        let detail = "cleanup.languagetool is off".to_string();
+        return ComponentHealth::new("languagetool", Status::Disabled, None, detail);
+    }
+    ComponentHealth::probe("languagetool", || {
+        whisper_integration::ping_languagetool()?;
+        Ok("server responding".to_string())
+    })
+}
+
+/// Whether the dictionaries have been built yet; probing never triggers the build.
+pub fn probe_dictionaries() -> ComponentHealth {
+    match Lazy::get(&DAWGS) {
+        Some(dawgs) => {
+            let detail = format!("{} languages loaded", dawgs.0.len());
+            ComponentHealth::new("dictionaries", Status::Ok, None, detail)
+        }
+        None if memory::settings().lazy_dictionaries => {
+            let detail = "loaded on first use".to_string();
+            ComponentHealth::new("dictionaries", Status::Ok, None, detail)
+        }
+        None => {
+            let detail = "still loading".to_string();
+            ComponentHealth::new("dictionaries", Status::Degraded, None, detail)
+        }
+    }
+}
+
+/// Whether macOS granted `permission`; disabled elsewhere.
+pub fn probe_permission(permission: Permission) -> ComponentHealth {
+    let name = format!("{} permission", permission.name());
+    let (status, detail) = match permissions::check(permission) {
+        Grant::Granted => (Status::Ok, "granted".to_string()),
+        Grant::NotDetermined => (Status::Degraded, "not asked yet".to_string()),
+        Grant::Denied => (Status::Down, format!("denied, needed for {}", permission.needed_for())),
+        Grant::NotApplicable => (Status::Disabled, "only needed on macOS".to_string()),
+    };
+    ComponentHealth::new(&name, status, None, detail)
+}
+
+/// Probes every component of this instance.
+pub fn check(model: Option<&WhisperContext>) -> HealthReport {
+    let mut components = vec![
+        probe_audio(),
+        probe_whisper(model),
+        probe_bert(),
+        probe_languagetool(),
+        probe_dictionaries(),
+    ];
+    components.extend(Permission::ALL.map(probe_permission));
+    HealthReport::new(components)
+}
+
+/// Fetches the report of the instance whose control server listens on `address`.
+pub fn fetch(address: &str) -> Result<HealthReport, Box<dyn Error>> {
+    let url = format!("http://{}/healthz", address);
+    let mut response = ureq::get(&url)
+        .config()
+        // An unhealthy instance answers 503 with the report
+        .http_status_as_error(false)
+        .build()
+        .call()?;
+    Ok(response.body_mut().read_json()?)
+}
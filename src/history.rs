@@ -0,0 +1,337 @@
+use crate::wake_log::{Review, WakeDecision};
+use chrono::{DateTime, Local, Utc};
+use once_cell::sync::OnceCell;
+use rusqlite::{params, Connection};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Optional SQLite history, read from the `history` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct HistorySettings {
+    /// Record transcriptions, command executions, corrections and wake decisions.
+    /// The wake decisions are then kept here instead of `wake.log_file`.
+    pub enabled: bool,
+    pub path: String,
+    /// Entries older than this are deleted at startup; `None` keeps them forever.
+    /// Reviewed wake decisions are always kept, since they teach false wakes.
+    pub retention_days: Option<u64>,
+    /// Most entries kept per table, the oldest being deleted first.
+    pub max_entries: Option<u64>,
+}
+
+impl Default for HistorySettings {
+    fn default() -> Self {
+        HistorySettings {
+            enabled: false,
+            path: "./data/history.sqlite".to_string(),
+            retention_days: Some(30),
+            max_entries: Some(10_000),
+        }
+    }
+}
+
+/// What the history records, one table each.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Table {
+    Transcripts,
+    Commands,
+    Corrections,
+    Wakes,
+}
+
+impl Table {
+    pub const ALL: [Table; 4] = [
+        Table::Transcripts,
+        Table::Commands,
+        Table::Corrections,
+        Table::Wakes,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Table::Transcripts => "transcripts",
+            Table::Commands => "commands",
+            Table::Corrections => "corrections",
+            Table::Wakes => "wakes",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Table> {
+        Table::ALL.into_iter().find(|table| table.name() == name)
+    }
+}
+
+/// One entry, as listed by `--history`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub time: String,
+    pub text: String,
+    /// Table-specific details: the outcome of a command, the raw text of a correction...
+    pub detail: String,
+}
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS transcripts (
+        id INTEGER PRIMARY KEY,
+        time INTEGER NOT NULL,
+        text TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS commands (
+        id INTEGER PRIMARY KEY,
+        time INTEGER NOT NULL,
+        trigger TEXT,
+        ok INTEGER NOT NULL,
+        error TEXT
+    );
+    CREATE TABLE IF NOT EXISTS corrections (
+        id INTEGER PRIMARY KEY,
+        time INTEGER NOT NULL,
+        raw TEXT NOT NULL,
+        corrected TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS wakes (
+        id INTEGER PRIMARY KEY,
+        time INTEGER NOT NULL,
+        review TEXT,
+        decision TEXT NOT NULL
+    );
+";
+
+/// The history database.
+pub struct History {
+    connection: Connection,
+}
+
+impl History {
+    /// Opens (or creates) the database at `path`; `":memory:"` keeps it in memory.
+    pub fn open(path: &str) -> Result<Self, Box<dyn Error>> {
+        if let Some(dir) = Path::new(path).parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let connection = Connection::open(path)?;
+        connection.execute_batch(SCHEMA)?;
+        Ok(History { connection })
+    }
+
+    /// Applies the retention policy of `settings`, returning how many entries were deleted.
+    pub fn apply_retention(&self, settings: &HistorySettings) -> rusqlite::Result<usize> {
+        let now = Utc::now().timestamp();
+        let mut deleted = 0;
+        for table in Table::ALL {
+            // Reviewed wake decisions are the negative phrases, they are never dropped
+            let keep = if table == Table::Wakes {
+                " AND review IS NULL"
+            } else {
+                ""
+            };
+            if let Some(days) = settings.retention_days {
+                let cutoff = now - (days * 24 * 3600) as i64;
+                deleted += self.connection.execute(
+                    &format!("DELETE FROM {} WHERE time < ?1{}", table.name(), keep),
+                    [cutoff],
+                )?;
+            }
+            if let Some(max) = settings.max_entries {
+                deleted += self.connection.execute(
+                    &format!(
+                        "DELETE FROM {0} WHERE id NOT IN \
+                         (SELECT id FROM {0} ORDER BY id DESC LIMIT ?1){1}",
+                        table.name(),
+                        keep
+                    ),
+                    [max as i64],
+                )?;
+            }
+        }
+        Ok(deleted)
+    }
+
+    pub fn record_transcript(&self, text: &str) -> rusqlite::Result<()> {
+        self.connection.execute(
+            "INSERT INTO transcripts (time, text) VALUES (?1, ?2)",
+            params![Utc::now().timestamp(), text],
+        )?;
+        Ok(())
+    }
+
+    pub fn record_command(
+        &self,
+        trigger: Option<&str>,
+        ok: bool,
+        error: Option<&str>,
+    ) -> rusqlite::Result<()> {
+        self.connection.execute(
+            "INSERT INTO commands (time, trigger, ok, error) VALUES (?1, ?2, ?3, ?4)",
+            params![Utc::now().timestamp(), trigger, ok, error],
+        )?;
+        Ok(())
+    }
+
+    pub fn record_correction(&self, raw: &str, corrected: &str) -> rusqlite::Result<()> {
+        self.connection.execute(
+            "INSERT INTO corrections (time, raw, corrected) VALUES (?1, ?2, ?3)",
+            params![Utc::now().timestamp(), raw, corrected],
+        )?;
+        Ok(())
+    }
+
+    pub fn record_wake(&self, decision: &WakeDecision) -> Result<(), Box<dyn Error>> {
+        self.connection.execute(
+            "INSERT INTO wakes (time, review, decision) VALUES (?1, ?2, ?3)",
+            params![
+                Utc::now().timestamp(),
+                review_name(decision.review),
+                serde_json::to_string(decision)?
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Every recorded wake decision with its id, oldest first.
+    pub fn wake_decisions(&self) -> Result<Vec<(i64, WakeDecision)>, Box<dyn Error>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT id, decision FROM wakes ORDER BY id")?;
+        let rows = statement.query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })?;
+        let mut decisions = Vec::new();
+        for row in rows {
+            let (id, json) = row?;
+            match serde_json::from_str(&json) {
+                Ok(decision) => decisions.push((id, decision)),
+                Err(e) => log::warn!("Skipping invalid wake decision {}: {}", id, e),
+            }
+        }
+        Ok(decisions)
+    }
+
+    /// Stores the review of the wake decision `id`.
+    pub fn update_wake(&self, id: i64, decision: &WakeDecision) -> Result<(), Box<dyn Error>> {
+        self.connection.execute(
+            "UPDATE wakes SET review = ?1, decision = ?2 WHERE id = ?3",
+            params![review_name(decision.review), serde_json::to_string(decision)?, id],
+        )?;
+        Ok(())
+    }
+
+    /// The latest `limit` entries of `table`, newest first, optionally only those
+    /// containing `search`.
+    pub fn query(
+        &self,
+        table: Table,
+        search: Option<&str>,
+        limit: usize,
+    ) -> rusqlite::Result<Vec<HistoryEntry>> {
+        let (columns, searched) = match table {
+            Table::Transcripts => ("time, text, ''", "text"),
+            Table::Commands => (
+                "time, COALESCE(trigger, '(dictation)'), \
+                 CASE WHEN ok THEN 'ok' ELSE 'failed: ' || COALESCE(error, '') END",
+                "COALESCE(trigger, '')",
+            ),
+            Table::Corrections => ("time, corrected, 'from: ' || raw", "corrected || raw"),
+            Table::Wakes => (
+                "time, json_extract(decision, '$.text'), \
+                 printf('similarity %.3f, %s', json_extract(decision, '$.similarity'), \
+                 CASE WHEN json_extract(decision, '$.woke') THEN 'woke' ELSE 'rejected' END) \
+                 || COALESCE(', reviewed ' || review, '')",
+                "json_extract(decision, '$.text')",
+            ),
+        };
+        let sql = format!(
+            "SELECT {} FROM {} WHERE ?1 IS NULL OR instr(lower({}), lower(?1)) > 0 \
+             ORDER BY id DESC LIMIT ?2",
+            columns,
+            table.name(),
+            searched
+        );
+        let mut statement = self.connection.prepare(&sql)?;
+        let rows = statement.query_map(params![search, limit as i64], |row| {
+            Ok(HistoryEntry {
+                time: format_time(row.get(0)?),
+                text: row.get(1)?,
+                detail: row.get(2)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Number of entries in `table`.
+    pub fn count(&self, table: Table) -> rusqlite::Result<u64> {
+        self.connection
+            .query_row(&format!("SELECT COUNT(*) FROM {}", table.name()), [], |row| {
+                row.get::<_, i64>(0)
+            })
+            .map(|count| count as u64)
+    }
+}
+
+fn review_name(review: Option<Review>) -> Option<&'static str> {
+    review.map(|review| match review {
+        Review::Correct => "correct",
+        Review::FalseWake => "false_wake",
+    })
+}
+
+fn format_time(timestamp: i64) -> String {
+    DateTime::from_timestamp(timestamp, 0)
+        .map(|time| time.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default()
+}
+
+/// The history of this process, when `history.enabled` is set.
+static HISTORY: OnceCell<Mutex<History>> = OnceCell::new();
+
+/// Opens the configured history and applies its retention policy. Does nothing when
+/// the history is disabled.
+pub fn init(settings: &HistorySettings) -> Result<(), Box<dyn Error>> {
+    if !settings.enabled || HISTORY.get().is_some() {
+        return Ok(());
+    }
+    let history = History::open(&settings.path)?;
+    let deleted = history.apply_retention(settings)?;
+    if deleted > 0 {
+        log::info!("🗄️ Deleted {} history entries past their retention", deleted);
+    }
+    let _ = HISTORY.set(Mutex::new(history));
+    Ok(())
+}
+
+/// Whether a history was opened by `init`.
+pub fn enabled() -> bool {
+    HISTORY.get().is_some()
+}
+
+/// Runs `f` on the history if there is one. Failures are logged, recording must never
+/// interrupt the pipeline.
+pub fn with_history<R>(f: impl FnOnce(&History) -> Result<R, Box<dyn Error>>) -> Option<R> {
+    let history = HISTORY.get()?.lock().unwrap_or_else(|e| e.into_inner());
+    match f(&history) {
+        Ok(result) => Some(result),
+        Err(e) => {
+            log::error!("History error: {}", e);
+            None
+        }
+    }
+}
+
+pub fn record_transcript(text: &str) {
+    with_history(|history| Ok(history.record_transcript(text)?));
+}
+
+pub fn record_command(trigger: Option<&str>, ok: bool, error: Option<&str>) {
+    with_history(|history| Ok(history.record_command(trigger, ok, error)?));
+}
+
+/// Records what the cleanup pipeline changed in a transcription, if anything.
+pub fn record_correction(raw: &str, corrected: &str) {
+    if raw.trim() != corrected.trim() {
+        with_history(|history| Ok(history.record_correction(raw, corrected)?));
+    }
+}
@@ -0,0 +1,147 @@
+use once_cell::sync::{Lazy, OnceCell};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::sync::Mutex;
+use tokio::sync::watch;
+
+/// Push-to-talk, read from the `input.push_to_talk` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct PushToTalkSettings {
+    /// Record only while the hotkey is held (or toggled on), instead of listening for
+    /// the wake word.
+    pub enabled: bool,
+    /// Key name as `rdev` spells it, e.g. "F9", "ControlRight", "ScrollLock" or "KeyP".
+    pub key: String,
+    pub mode: PushToTalkMode,
+}
+
+impl Default for PushToTalkSettings {
+    fn default() -> Self {
+        PushToTalkSettings {
+            enabled: false,
+            key: "F9".to_string(),
+            mode: PushToTalkMode::Hold,
+        }
+    }
+}
+
+/// How the hotkey opens and closes capture.
+#[derive(Deserialize, JsonSchema, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PushToTalkMode {
+    /// Records while the key is held down.
+    #[default]
+    Hold,
+    /// A press starts recording, the next one stops it.
+    Toggle,
+}
+
+/// Whether capture is open, following the presses and releases of the hotkey.
+#[derive(Debug, Clone)]
+pub struct KeyGate {
+    mode: PushToTalkMode,
+    held: bool,
+    open: bool,
+}
+
+impl KeyGate {
+    pub fn new(mode: PushToTalkMode) -> Self {
+        KeyGate {
+            mode,
+            held: false,
+            open: false,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Returns the new state when the press changes it. Key repeat sends more presses
+    /// while the key is held; they are ignored.
+    pub fn press(&mut self) -> Option<bool> {
+        if self.held {
+            return None;
+        }
+        self.held = true;
+        self.open = match self.mode {
+            PushToTalkMode::Hold => true,
+            PushToTalkMode::Toggle => !self.open,
+        };
+        Some(self.open)
+    }
+
+    /// Returns the new state when the release changes it.
+    pub fn release(&mut self) -> Option<bool> {
+        if !self.held {
+            return None;
+        }
+        self.held = false;
+        match self.mode {
+            PushToTalkMode::Hold => {
+                self.open = false;
+                Some(false)
+            }
+            PushToTalkMode::Toggle => None,
+        }
+    }
+}
+
+/// Whether `key` is the one named `name`, ignoring case.
+pub fn key_matches(key: rdev::Key, name: &str) -> bool {
+    format!("{:?}", key).eq_ignore_ascii_case(name)
+}
+
+type EventHandler = Box<dyn FnMut(&rdev::Event) + Send>;
+
+/// Everything that wants global input events. rdev installs one hook per process, on
+/// Windows the last one replacing the others, so they all share it.
+static HANDLERS: Lazy<Mutex<Vec<EventHandler>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+static HOOK: OnceCell<()> = OnceCell::new();
+
+/// Passes every keyboard and mouse event of the session to `handler`, starting the global
+/// hook on its first call.
+pub fn on_event(handler: impl FnMut(&rdev::Event) + Send + 'static) {
+    HANDLERS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(Box::new(handler));
+    HOOK.get_or_init(|| {
+        std::thread::spawn(|| {
+            let result = rdev::listen(|event| {
+                let mut handlers = HANDLERS.lock().unwrap_or_else(|e| e.into_inner());
+                for handler in handlers.iter_mut() {
+                    handler(&event);
+                }
+            });
+            // On Linux the hook needs an X11 session; Wayland doesn't allow it
+            if let Err(e) = result {
+                log::error!("Global input hook stopped: {:?}", e);
+            }
+            // Dropping the handlers closes the push-to-talk gate, so capture reports it
+            HANDLERS.lock().unwrap_or_else(|e| e.into_inner()).clear();
+        });
+    });
+}
+
+/// Listens for the hotkey on the global input hook. The receiver tells whether capture
+/// is open; it is closed when the hook stops.
+pub fn listen(settings: &PushToTalkSettings) -> watch::Receiver<bool> {
+    let (sender, receiver) = watch::channel(false);
+    let settings = settings.clone();
+    log::info!("⌨️ Push-to-talk on {} ({:?})", settings.key, settings.mode);
+    let mut gate = KeyGate::new(settings.mode);
+    on_event(move |event| {
+        let changed = match event.event_type {
+            rdev::EventType::KeyPress(key) if key_matches(key, &settings.key) => gate.press(),
+            rdev::EventType::KeyRelease(key) if key_matches(key, &settings.key) => gate.release(),
+            _ => None,
+        };
+        if let Some(open) = changed {
+            let _ = sender.send(open);
+        }
+    });
+    receiver
+}
@@ -0,0 +1,145 @@
+use crate::performance;
+use once_cell::sync::OnceCell;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::error::Error;
+use whisper_rs::WhisperContext;
+
+/// Language gating, read from the `language` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct LanguageSettings {
+    /// Detect the language of every utterance before it is corrected and matched.
+    pub enabled: bool,
+    /// Languages processed as usual (Whisper codes: "fr", "en"...), each transcribed
+    /// and corrected in its own language.
+    pub allowed: Vec<String>,
+    /// Language utterances are transcribed in when detection is off or unsure.
+    pub default: String,
+    /// Below this detection probability the utterance is processed as usual, since
+    /// short utterances are often detected wrongly.
+    pub min_probability: f32,
+    /// What happens to utterances in another language.
+    pub foreign: ForeignSpeech,
+}
+
+impl Default for LanguageSettings {
+    fn default() -> Self {
+        LanguageSettings {
+            enabled: false,
+            allowed: vec!["fr".to_string()],
+            default: "fr".to_string(),
+            min_probability: 0.6,
+            foreign: ForeignSpeech::Drop,
+        }
+    }
+}
+
+/// Handling of utterances detected in a language that isn't allowed.
+#[derive(Deserialize, JsonSchema, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ForeignSpeech {
+    /// Ignored entirely, like background TV.
+    #[default]
+    Drop,
+    /// Transcribed in their own language and emitted as a `foreign_speech` event and in
+    /// the history, but never corrected, matched or typed.
+    Transcript,
+}
+
+/// The most likely language of an utterance.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Detection {
+    pub language: &'static str,
+    pub probability: f32,
+}
+
+/// What to do with an utterance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Gate {
+    Accept,
+    Foreign(ForeignSpeech),
+}
+
+/// Runs Whisper's language detection on `audio`.
+pub fn detect(model: &WhisperContext, audio: &[f32]) -> Result<Detection, Box<dyn Error>> {
+    let threads = performance::whisper_thread_count();
+    let mut state = model.create_state()?;
+    state.pcm_to_mel(audio, threads)?;
+    let (_, probabilities) = state.lang_detect(0, threads)?;
+
+    let (id, probability) = probabilities
+        .iter()
+        .copied()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .ok_or("Whisper returned no language probability")?;
+    let language = whisper_rs::get_lang_str(id as i32).ok_or("unknown language id")?;
+    Ok(Detection {
+        language,
+        probability,
+    })
+}
+
+/// Process-wide language settings, set once after the config is loaded.
+static LANGUAGE_SETTINGS: OnceCell<LanguageSettings> = OnceCell::new();
+
+/// Installs the language settings read by `undetected_language`.
+pub fn init(settings: LanguageSettings) {
+    if LANGUAGE_SETTINGS.set(settings).is_err() {
+        log::warn!("Language settings were already initialized, ignoring new values");
+    }
+}
+
+/// Returns the active language settings, or the defaults if `init` was never called.
+pub fn settings() -> &'static LanguageSettings {
+    LANGUAGE_SETTINGS.get_or_init(LanguageSettings::default)
+}
+
+/// The language Whisper is given for audio that isn't detected first (the wake pass,
+/// keyword spotting, streamed windows): "auto" when several languages are allowed, so
+/// Whisper detects it itself, `default` otherwise.
+pub fn undetected_language() -> &'static str {
+    let settings = settings();
+    if settings.enabled && settings.allowed.len() > 1 {
+        "auto"
+    } else {
+        &settings.default
+    }
+}
+
+/// The language an utterance detected as `detection` is transcribed and corrected in:
+/// the detected one when it is allowed and sure enough, `default` otherwise.
+pub fn transcription_language<'a>(
+    detection: &Detection,
+    settings: &'a LanguageSettings,
+) -> &'a str {
+    let allowed = settings
+        .allowed
+        .iter()
+        .find(|language| language.eq_ignore_ascii_case(detection.language));
+    match allowed {
+        Some(language) if detection.probability >= settings.min_probability => language,
+        _ => &settings.default,
+    }
+}
+
+/// The dictionary an utterance transcribed in `lang` is corrected with: only that of
+/// `lang` when utterances are detected, all of them otherwise (`None`), so English words
+/// in French dictation stay known.
+pub fn dictionary_language<'a>(lang: &'a str, settings: &LanguageSettings) -> Option<&'a str> {
+    settings.enabled.then_some(lang)
+}
+
+/// Decides whether an utterance detected as `detection` is processed.
+pub fn gate(detection: &Detection, settings: &LanguageSettings) -> Gate {
+    let allowed = settings
+        .allowed
+        .iter()
+        .any(|language| language.eq_ignore_ascii_case(detection.language));
+    if allowed || detection.probability < settings.min_probability {
+        Gate::Accept
+    } else {
+        Gate::Foreign(settings.foreign)
+    }
+}
@@ -1,7 +1,70 @@
+pub mod accents;
 pub mod actions;
+pub mod apps;
 pub mod audio;
 pub mod bert;
+pub mod browser;
+pub mod builtins;
+pub mod capture;
+pub mod captioning;
+pub mod casing;
+pub mod cheatsheet;
+pub mod clipboard;
+pub mod clock;
+pub mod codec;
+pub mod command_state;
 pub mod config;
+pub mod content_filter;
+pub mod decoding;
+pub mod control;
 pub mod dawg_loader;
+pub mod dialog;
+pub mod dictation;
+pub mod echo;
+pub mod embedding_store;
+pub mod events;
+pub mod explain;
+pub mod flashback;
+pub mod grid;
+pub mod groups;
+pub mod health;
+pub mod history;
+pub mod hotkey;
+pub mod language;
+pub mod macros;
+pub mod match_tests;
+pub mod memory;
+pub mod messaging;
+pub mod music;
+pub mod performance;
+pub mod permissions;
+pub mod pipeline;
+pub mod platform;
+pub mod playback;
+pub mod power;
+pub mod privacy;
+pub mod punctuation;
+pub mod remote;
+pub mod replay;
+pub mod routing;
+pub mod secrets;
+pub mod segment_dump;
+pub mod segmentation;
+pub mod services;
+pub mod session;
+pub mod speaker;
+pub mod ssh;
+pub mod stt_fallback;
+pub mod summary;
+pub mod supervisor;
+pub mod telemetry;
+pub mod timeouts;
+pub mod transcription_api;
+pub mod vad;
+pub mod wake_log;
+pub mod wake_stream;
 pub mod wakeword;
+pub mod watchdog;
+pub mod weather;
 pub mod whisper_integration;
+pub mod window;
@@ -1,8 +1,24 @@
 #[cfg(feature = "with-logs")]
 pub fn init_logger() {
     let _ = env_logger::try_init();
+    // whisper.cpp logs reach env_logger under the `whisper_rs` target
+    quiet_native_libraries("WARNING");
 }
 
 #[cfg(not(feature = "with-logs"))]
 pub fn init_logger() {
+    // Without a `log` backend whisper-rs swallows the whisper.cpp and GGML output
+    quiet_native_libraries("FATAL");
+}
+
+/// whisper.cpp and libtorch write to stderr on their own; this routes the former
+/// through whisper-rs' logging hooks and sets libtorch's level, unless
+/// `TORCH_CPP_LOG_LEVEL` is already set. Builds linking libtorch dynamically may read
+/// it before `main`, so exporting it in the shell is the way to silence those.
+fn quiet_native_libraries(torch_level: &str) {
+    whisper_rs::install_logging_hooks();
+    if std::env::var_os("TORCH_CPP_LOG_LEVEL").is_none() {
+        // SAFETY: called first thing in `main`, before any other thread is spawned
+        unsafe { std::env::set_var("TORCH_CPP_LOG_LEVEL", torch_level) };
+    }
 }
@@ -0,0 +1,191 @@
+use crate::dialog::{Dialog, Turn};
+use crate::hotkey;
+use once_cell::sync::{Lazy, OnceCell};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Longest pause kept between two steps when a macro plays back, so a recording left
+/// running while thinking doesn't replay the wait.
+const MAX_STEP_DELAY: Duration = Duration::from_secs(2);
+
+/// Voice macros, read from the `macros` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct MacroSettings {
+    /// JSON file the recorded macros are kept in, by spoken trigger.
+    pub file: String,
+}
+
+impl Default for MacroSettings {
+    fn default() -> Self {
+        MacroSettings {
+            file: "./macros.json".to_string(),
+        }
+    }
+}
+
+/// One recorded keyboard or mouse event, with the time elapsed since the previous one.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MacroStep {
+    pub delay_ms: u64,
+    pub event: rdev::EventType,
+}
+
+struct Recording {
+    /// The trigger given when the recording started; asked for at the end otherwise.
+    name: Option<String>,
+    steps: Vec<MacroStep>,
+    last_event: Instant,
+}
+
+/// The macro being recorded, if any.
+static RECORDING: Lazy<Mutex<Option<Recording>>> = Lazy::new(|| Mutex::new(None));
+
+static HOOKED: OnceCell<()> = OnceCell::new();
+
+/// Starts recording every keyboard and mouse event until `finish`.
+pub fn start(name: Option<String>) -> Result<(), Box<dyn Error>> {
+    let mut recording = RECORDING.lock().unwrap_or_else(|e| e.into_inner());
+    if recording.is_some() {
+        return Err("A macro is already being recorded".into());
+    }
+    *recording = Some(Recording {
+        name,
+        steps: Vec::new(),
+        last_event: Instant::now(),
+    });
+    drop(recording);
+    HOOKED.get_or_init(|| hotkey::on_event(record));
+    log::info!("⏺️ Recording a macro");
+    Ok(())
+}
+
+fn record(event: &rdev::Event) {
+    let mut recording = RECORDING.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(recording) = recording.as_mut() {
+        let now = Instant::now();
+        recording.steps.push(MacroStep {
+            delay_ms: now.duration_since(recording.last_event).as_millis() as u64,
+            event: event.event_type,
+        });
+        recording.last_event = now;
+    }
+}
+
+pub fn is_recording() -> bool {
+    RECORDING
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .is_some()
+}
+
+/// Stops the recording, returning the name it was started with and its steps.
+pub fn finish() -> Option<(Option<String>, Vec<MacroStep>)> {
+    let recording = RECORDING.lock().unwrap_or_else(|e| e.into_inner()).take()?;
+    let mut steps = recording.steps;
+    // Playback starts right away, however long it took to start demonstrating
+    if let Some(first) = steps.first_mut() {
+        first.delay_ms = 0;
+    }
+    Some((recording.name, steps))
+}
+
+/// Removes the presses and releases of `key`, e.g. the push-to-talk hotkey pressed to say
+/// "fin de macro".
+pub fn without_key(steps: Vec<MacroStep>, key: &str) -> Vec<MacroStep> {
+    let mut removed_delay = 0;
+    steps
+        .into_iter()
+        .filter_map(|mut step| match step.event {
+            rdev::EventType::KeyPress(pressed) | rdev::EventType::KeyRelease(pressed)
+                if hotkey::key_matches(pressed, key) =>
+            {
+                removed_delay += step.delay_ms;
+                None
+            }
+            _ => {
+                step.delay_ms += std::mem::take(&mut removed_delay);
+                Some(step)
+            }
+        })
+        .collect()
+}
+
+/// Every recorded macro, by trigger. A missing file holds none.
+pub fn load(settings: &MacroSettings) -> Result<BTreeMap<String, Vec<MacroStep>>, Box<dyn Error>> {
+    if !Path::new(&settings.file).exists() {
+        return Ok(BTreeMap::new());
+    }
+    Ok(serde_json::from_str(&fs::read_to_string(&settings.file)?)?)
+}
+
+/// Stores `steps` under `name`, replacing any macro of that name.
+pub fn save(
+    settings: &MacroSettings,
+    name: &str,
+    steps: Vec<MacroStep>,
+) -> Result<(), Box<dyn Error>> {
+    let mut macros = load(settings)?;
+    macros.insert(name.to_string(), steps);
+    let path = Path::new(&settings.file);
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(&macros)?)?;
+    Ok(())
+}
+
+/// Replays the macro recorded under `name`, with its original timing.
+pub fn play(name: &str, settings: &MacroSettings) -> Result<(), Box<dyn Error>> {
+    let macros = load(settings)?;
+    let steps = macros
+        .get(name)
+        .ok_or_else(|| format!("No macro named '{}'", name))?;
+    log::info!("▶️ Playing macro '{}' ({} steps)", name, steps.len());
+    for step in steps {
+        std::thread::sleep(Duration::from_millis(step.delay_ms).min(MAX_STEP_DELAY));
+        rdev::simulate(&step.event).map_err(|_| format!("Failed to replay {:?}", step.event))?;
+    }
+    Ok(())
+}
+
+/// Asks which phrase should play a macro recorded without a name, then saves it.
+pub struct NameDialog {
+    settings: MacroSettings,
+    steps: Vec<MacroStep>,
+}
+
+impl NameDialog {
+    pub fn new(settings: &MacroSettings, steps: Vec<MacroStep>) -> (Self, String) {
+        let dialog = NameDialog {
+            settings: settings.clone(),
+            steps,
+        };
+        (dialog, "Which phrase should play this macro?".to_string())
+    }
+}
+
+impl Dialog for NameDialog {
+    fn answer(&mut self, text: &str) -> Turn {
+        let name = crate::builtins::normalize(text);
+        if name.is_empty() {
+            return Turn::Continue("Which phrase should play this macro?".to_string());
+        }
+        Turn::Done(saved(&self.settings, &name, std::mem::take(&mut self.steps)))
+    }
+}
+
+/// Saves a finished recording and describes the outcome.
+pub fn saved(settings: &MacroSettings, name: &str, steps: Vec<MacroStep>) -> String {
+    let count = steps.len();
+    match save(settings, name, steps) {
+        Ok(()) => format!("Macro '{}' saved ({} steps)", name, count),
+        Err(e) => format!("Couldn't save the macro '{}': {}", name, e),
+    }
+}
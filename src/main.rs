@@ -1,181 +1,1256 @@
 use VoxAurora::{
     audio,
     bert,
+    captioning,
+    cheatsheet,
     //actions,
+    command_state::{CommandState, SharedCommandState},
     config,
+    control,
+    decoding,
+    events::{self, Event},
+    explain,
+    flashback,
+    health,
+    history,
+    language,
+    match_tests,
+    memory,
+    performance,
+    permissions,
+    playback,
+    power,
+    privacy,
+    remote,
+    replay,
+    segment_dump::{self, WakeOutcome, WakeSource},
+    segmentation,
+    stt_fallback,
+    supervisor::{self, Subsystem},
+    telemetry,
+    timeouts,
+    wake_log,
+    wake_stream,
     wakeword,
     whisper_integration,
     whisper_integration::DAWGS,
 };
 
+mod cli;
 // On importe notre logger
 mod logger;
+mod review;
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Initialise le logger (activé seulement si la feature "with-logs" est présente)
-    logger::init_logger();
+/// Where speech segments come from: the local microphone, `--client` instances or the
+/// `--input-file`.
+enum SegmentSource {
+    Local(audio::AudioProcessor),
+    Remote(remote::SegmentServer),
+    File(audio::FileSource),
+    Network(audio::NetworkSource),
+}
+
+impl SegmentSource {
+    /// Returns the next segment and, for remote segments, where to send its transcription.
+    async fn next_segment(
+        &mut self,
+    ) -> Result<
+        (audio::SpeechSegment, Option<tokio::sync::oneshot::Sender<String>>),
+        Box<dyn std::error::Error>,
+    > {
+        match self {
+            SegmentSource::Local(processor) => Ok((processor.get_next_speech_segment().await?, None)),
+            SegmentSource::File(file) => Ok((file.get_next_speech_segment().await?, None)),
+            SegmentSource::Network(source) => Ok((source.get_next_speech_segment().await?, None)),
+            SegmentSource::Remote(server) => {
+                let segment = server.next_segment().await?;
+                let speech = audio::SpeechSegment {
+                    samples: segment.samples,
+                    continues: false,
+                };
+                Ok((speech, Some(segment.reply)))
+            }
+        }
+    }
+
+    /// Stops the microphone while a command runs; the other sources can't be paused.
+    fn pause(&mut self) {
+        if let SegmentSource::Local(processor) = self {
+            if let Err(e) = processor.pause() {
+                log::warn!("Failed to pause audio capture: {}", e);
+            }
+        }
+    }
+
+    fn resume(&mut self) {
+        if let SegmentSource::Local(processor) = self {
+            if let Err(e) = processor.resume() {
+                log::error!("Failed to resume audio capture: {}", e);
+            }
+        }
+    }
 
-    log::info!("Loading DAWGS... ({} entries)", DAWGS.0.len());
+    /// Switches the sources that detect speech here to `settings`.
+    fn apply_settings(&mut self, settings: &audio::InputSettings) {
+        match self {
+            SegmentSource::Local(processor) => processor.apply_settings(settings),
+            SegmentSource::File(file) => file.apply_settings(settings),
+            SegmentSource::Network(source) => source.apply_settings(settings),
+            SegmentSource::Remote(_) => {}
+        }
+    }
 
-    let mut _server = whisper_integration::start_languagetool_server();
-    bert::get_model();
+    /// Whether no segment will ever come again.
+    fn finished(&self) -> bool {
+        matches!(self, SegmentSource::File(file) if file.finished())
+    }
+}
 
-    // Build the current-thread runtime manually
+/// Capture and speech detection only: segments are transcribed by a `--serve` instance.
+fn run_client(
+    address: &str,
+    codec: Option<VoxAurora::codec::Codec>,
+    devices: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
     let rt = tokio::runtime::Builder::new_current_thread()
         .enable_all()
         .build()?;
 
-    let local = tokio::task::LocalSet::new();
+    rt.block_on(async {
+        let input = audio::InputSettings {
+            devices: devices.iter().cloned().map(audio::DeviceSpec::Name).collect(),
+            ..Default::default()
+        };
+        let mut audio_processor = audio::open_input(&input)?;
+        audio_processor.start_capture().await?;
 
-    // Retrieve command-line arguments
-    let args: Vec<String> = std::env::args().collect();
+        log::info!("🎙️ Sending speech segments to {}", address);
+        let token = remote::token();
+        remote::run_client(address, &mut audio_processor, codec, token.as_deref()).await
+    })
+}
+
+/// Writes the cheat sheet of the given configs to `path`, as HTML for `.html` files and
+/// Markdown otherwise.
+fn run_docs(path: &str, config_paths: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let config = config::load_config(config_paths)?;
+    let format = VoxAurora::session::TranscriptFormat::from_path(std::path::Path::new(path));
+    std::fs::write(path, cheatsheet::render(&config, format))?;
+    println!("Cheat sheet of {} commands written to {}", config.commands.len(), path);
+    Ok(())
+}
+
+/// Encrypts the plain secrets in `path` into the `secrets.encrypted_file` of the given
+/// configs, for the `file` provider.
+fn run_encrypt_secrets(
+    path: &str,
+    config_paths: Vec<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let config = config::load_config(config_paths)?;
+    let written = VoxAurora::secrets::encrypt_file(path, &config.secrets)?;
+    println!("Secrets encrypted to {}; {} can now be deleted", written, path);
+    Ok(())
+}
+
+/// Prints how `text` scores against the commands of the given configs.
+fn run_explain(
+    text: &str,
+    top: usize,
+    config_paths: Vec<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let config = config::load_config(config_paths)?;
+    let state = CommandState::load(&config.control.state_file);
+    bert::init(&config.bert);
+    bert::init_embedding_cache(&config.embedding_cache);
+
+    let explanation = explain::explain(text, &config, &state, top).map_err(|e| e.to_string())?;
+    if events::json_output() {
+        println!("{}", serde_json::to_string(&explanation)?);
+    } else {
+        print!("{}", explain::render(&explanation));
+    }
+    Ok(())
+}
+
+/// Checks the `utterance => command` pairs of `path` against the given configs and fails
+/// if any doesn't match as expected.
+fn run_match_tests(
+    path: &str,
+    config_paths: Vec<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let config = config::load_config(config_paths)?;
+    let cases = match_tests::parse_cases(&std::fs::read_to_string(path)?)?;
+    bert::init(&config.bert);
+    bert::init_embedding_cache(&config.embedding_cache);
+
+    let results = match_tests::run(&cases, &config).map_err(|e| e.to_string())?;
+    for result in &results {
+        println!("{}", match_tests::render(result));
+    }
+    let failed = results.iter().filter(|result| !result.passed).count();
+    println!("{} passed, {} failed", results.len() - failed, failed);
+    if failed > 0 {
+        return Err(format!("{} of {} cases failed", failed, results.len()).into());
+    }
+    Ok(())
+}
 
-    rt.block_on(local.run_until(async move {
-        // If the user provided a model path as the first argument, use it.
-        // Otherwise, ask interactively.
-        let model_path_input = if args.len() > 1 {
-            args[1].clone()
+/// Lists the latest entries of a history table, newest first.
+fn run_history(
+    table: &str,
+    search: Option<&str>,
+    limit: usize,
+    config_paths: Vec<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let config = config::load_config(config_paths)?;
+    if !config.history.enabled {
+        return Err("history.enabled is not set, so nothing was recorded".into());
+    }
+    let table = history::Table::parse(table).ok_or_else(|| {
+        format!(
+            "Unknown history table '{}', expected transcripts, commands, corrections or wakes",
+            table
+        )
+    })?;
+    let history = history::History::open(&config.history.path)?;
+
+    let entries = history.query(table, search, limit)?;
+    if events::json_output() {
+        println!("{}", serde_json::to_string(&entries)?);
+        return Ok(());
+    }
+    for entry in entries.iter().rev() {
+        if entry.detail.is_empty() {
+            println!("{}  {}", entry.time, entry.text);
         } else {
-            println!("Please enter the path to the Whisper model (or press Enter for default './models/ggml-small.bin'):");
-            let mut input = String::new();
+            println!("{}  {}  ({})", entry.time, entry.text, entry.detail);
+        }
+    }
+    println!(
+        "{} of {} {} shown",
+        entries.len(),
+        history.count(table)?,
+        table.name()
+    );
+    Ok(())
+}
+
+/// Transcribes and matches the segments recorded in `dir` with the current code and
+/// configs, printing what changed; fails if anything did.
+fn run_replay(
+    dir: &str,
+    positional: &[String],
+    reproducible: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let model_path = positional
+        .first()
+        .cloned()
+        .unwrap_or_else(|| "./models/ggml-small.bin".to_string());
+    let config_paths = cli::config_paths(positional.iter().skip(1).cloned().collect());
+    let mut config = config::load_config(config_paths)?;
+    config.decoding.reproducible |= reproducible;
+    memory::init(config.memory.clone());
+    performance::init(config.performance.clone());
+    whisper_integration::init_cleanup(config.cleanup.clone());
+    whisper_integration::init_whisper(config.whisper.clone());
+    language::init(config.language.clone());
+    timeouts::init(config.timeouts.clone());
+    decoding::init(config.decoding.clone());
+
+    let mut server = config
+        .cleanup
+        .languagetool
+        .then(whisper_integration::start_languagetool_server);
+    bert::init(&config.bert);
+    bert::init_embedding_cache(&config.embedding_cache);
+    let model_path = memory::select_model_variant(&model_path, memory::settings());
+    let model = std::sync::Arc::new(whisper_integration::init_model(model_path.clone())?);
+    whisper_integration::pool_states(&model)?;
+
+    // Differences are expected after a model or LanguageTool update
+    let dir = std::path::Path::new(dir);
+    if let Some(recorded) = replay::load_versions(dir) {
+        let differences = recorded.differences(&replay::ComponentVersions::current(&model_path));
+        if !differences.is_empty() {
+            log::warn!("⚠️ Recorded with other components: {}", differences.join(", "));
+        }
+    }
+    let replayed = replay::replay(dir, &model, &config);
+    if let Some(server) = server.as_mut() {
+        let _ = server.kill();
+    }
+    let replayed = replayed?;
+
+    if events::json_output() {
+        println!("{}", serde_json::to_string(&replayed)?);
+    } else {
+        for segment in &replayed {
+            println!("{}", replay::render(segment));
+        }
+    }
+    let changed = replayed.iter().filter(|segment| segment.changed()).count();
+    println!("{} segments replayed, {} changed", replayed.len(), changed);
+    if changed > 0 {
+        return Err(format!("{} of {} segments changed", changed, replayed.len()).into());
+    }
+    Ok(())
+}
+
+/// Writes the JSON Schema of the config format to `path`, or prints it for `-`.
+fn run_schema(path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let schema = serde_json::to_string_pretty(&config::json_schema())?;
+    if path == "-" {
+        println!("{}", schema);
+    } else {
+        std::fs::write(path, schema)?;
+        println!("Config schema written to {}", path);
+    }
+    Ok(())
+}
+
+/// Prints the health of the instance running with the given configs, failing when it
+/// is unhealthy or unreachable.
+fn run_status(config_paths: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let config = config::load_config(config_paths)?;
+    let address = config
+        .control
+        .listen
+        .ok_or("control.listen is not set, so the running instance can't be asked")?;
+    let report = health::fetch(&address)
+        .map_err(|e| format!("No answer from the control server on {}: {}", address, e))?;
+    if events::json_output() {
+        println!("{}", serde_json::to_string(&report)?);
+    } else {
+        print!("{}", report.render());
+    }
+    if !report.healthy {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Detects the language of `utterance`; returns the language it is transcribed and
+/// corrected in, or `None` when it is set aside.
+fn handle_language<'a>(
+    model: &whisper_rs::WhisperContext,
+    utterance: &[f32],
+    settings: &'a language::LanguageSettings,
+) -> Option<&'a str> {
+    let detection = match language::detect(model, utterance) {
+        Ok(detection) => detection,
+        Err(e) => {
+            log::warn!("Language detection failed: {}", e);
+            return Some(&settings.default);
+        }
+    };
+    let language::Gate::Foreign(foreign) = language::gate(&detection, settings) else {
+        let lang = language::transcription_language(&detection, settings);
+        if lang != settings.default {
+            log::info!(
+                "🌐 Transcribing in '{}' (probability {:.2})",
+                lang,
+                detection.probability
+            );
+        }
+        return Some(lang);
+    };
+
+    log::info!(
+        "🌐 Setting aside an utterance in '{}' (probability {:.2})",
+        detection.language,
+        detection.probability
+    );
+    let text = match foreign {
+        language::ForeignSpeech::Drop => None,
+        language::ForeignSpeech::Transcript => {
+            match whisper_integration::transcribe_foreign(model, utterance, detection.language) {
+                Ok(text) => Some(text),
+                Err(e) => {
+                    log::error!("Error transcribing foreign speech: {}", e);
+                    None
+                }
+            }
+        }
+    };
+    events::emit(Event::ForeignSpeech {
+        language: detection.language,
+        probability: detection.probability,
+        text: text.as_deref(),
+    });
+    None
+}
+
+/// Longest wait at the end of a segment for its final streamed transcript.
+const STREAM_SYNC_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(3);
+
+/// Lead over the next command a match needs to fire while the words after the stable
+/// ones are still changing, so "ouvre le" doesn't run one of several "ouvre le …".
+const STREAM_MATCH_MARGIN: f32 = 0.1;
+
+/// What the streaming transcription did with a segment, once its final transcript came.
+struct StreamedSegment {
+    /// Segments finalized so far, this one included.
+    index: u64,
+    /// The stable text that already ran a command, and how it was handled.
+    fired: Option<(String, config::Handled)>,
+}
+
+/// Runs the command of the segment being recorded as soon as its stable words match
+/// one, while `armed`, and reports every segment finalized to `finished`.
+async fn fire_stable_commands(
+    mut partials: tokio::sync::mpsc::UnboundedReceiver<whisper_integration::PartialTranscript>,
+    config: config::Config,
+    state: SharedCommandState,
+    armed: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    finished: tokio::sync::mpsc::UnboundedSender<StreamedSegment>,
+) {
+    let mut finalized = 0;
+    let mut fired: Option<(u64, String, config::Handled)> = None;
+    // The stable text last matched, so each one is only matched once
+    let mut matched: Option<(u64, String)> = None;
+    while let Some(partial) = partials.recv().await {
+        events::emit(Event::PartialTranscript {
+            text: &partial.text,
+            stable: &partial.stable,
+            is_final: partial.is_final,
+        });
+        let already_fired = fired
+            .as_ref()
+            .is_some_and(|(segment, ..)| *segment == partial.segment);
+        let new_words = matched.as_ref().is_none_or(|(segment, text)| {
+            *segment != partial.segment || *text != partial.stable
+        });
+        if !partial.is_final
+            && !already_fired
+            && new_words
+            && !partial.stable.is_empty()
+            && armed.load(std::sync::atomic::Ordering::Relaxed)
+        {
+            matched = Some((partial.segment, partial.stable.clone()));
+            // Trailing words the passes don't agree on yet may still change the command
+            let complete = partial.stable.split_whitespace().count()
+                == partial.text.split_whitespace().count();
+            let fired_now = fire_if_matched(&partial.stable, complete, &config, &state).await;
+            if let Some((text, handled)) = fired_now {
+                fired = Some((partial.segment, text, handled));
+            }
+        }
+        if partial.is_final {
+            finalized += 1;
+            let fired = fired
+                .take()
+                .filter(|(segment, ..)| *segment == partial.segment)
+                .map(|(_, text, handled)| (text, handled));
+            let _ = finished.send(StreamedSegment {
+                index: finalized,
+                fired,
+            });
+        }
+    }
+}
+
+/// Cleans up `stable` and runs its command, when one matches it and either nothing
+/// follows the stable words (`complete`) or no other command comes close.
+async fn fire_if_matched(
+    stable: &str,
+    complete: bool,
+    config: &config::Config,
+    state: &SharedCommandState,
+) -> Option<(String, config::Handled)> {
+    let transcription =
+        performance::run_blocking(|| whisper_integration::clean_transcription(stable));
+    let text = transcription.text(config.cleanup.consumers.matcher).to_string();
+    let explanation = performance::run_blocking(|| {
+        let state = state.lock().unwrap_or_else(|e| e.into_inner());
+        explain::explain(&text, config, &state, config.commands.len())
+    });
+    let winner = match explanation {
+        Ok(explanation) if may_fire_early(complete, &explanation.candidates) => {
+            explanation.winner?
+        }
+        Ok(_) => return None,
+        Err(e) => {
+            log::warn!("Failed to match a streamed transcript: {}", e);
+            return None;
+        }
+    };
+    log::info!("⚡ '{}' is stable, running {} before the segment ends", text, winner);
+    let corrected = transcription.corrected.clone();
+    match config::execute_command(config, state, transcription).await {
+        Ok(handled) => Some((corrected, handled)),
+        Err(e) => {
+            log::error!("Failed to execute command: {}", e);
+            None
+        }
+    }
+}
+
+/// Whether a streamed match may run before the segment ends: its transcript has no
+/// unstable tail, or the winner is `STREAM_MATCH_MARGIN` ahead of the next command.
+fn may_fire_early(complete: bool, candidates: &[explain::CandidateScore]) -> bool {
+    complete || explain::margin(candidates).is_some_and(|margin| margin >= STREAM_MATCH_MARGIN)
+}
+
+/// Waits for the streaming transcription to finalize the `index`th segment and returns
+/// the command it already ran for it, if any.
+async fn streamed_command(
+    finished: &mut tokio::sync::mpsc::UnboundedReceiver<StreamedSegment>,
+    index: u64,
+) -> Option<(String, config::Handled)> {
+    let caught_up = async {
+        while let Some(segment) = finished.recv().await {
+            if segment.index >= index {
+                return segment.fired;
+            }
+        }
+        None
+    };
+    match tokio::time::timeout(STREAM_SYNC_TIMEOUT, caught_up).await {
+        Ok(fired) => fired,
+        Err(_) => {
+            log::warn!("Streaming transcription still running after {:?}", STREAM_SYNC_TIMEOUT);
+            None
+        }
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Initialise le logger (activé seulement si la feature "with-logs" est présente)
+    logger::init_logger();
+
+    // Retrieve command-line arguments
+    let cli = match cli::parse(std::env::args().skip(1)) {
+        Ok(cli) => cli,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!(
+                "Usage: VoxAurora [--serve ADDR | --client ADDR | --caption FILE | --input-file FILE | --listen-audio udp://ADDR|ws://ADDR] [--codec wav|flac|opus] [--device NAME...] [--output text|json] [--clipboard] [--reproducible] [--record-segments DIR] [MODEL] [CONFIG...]\n       VoxAurora --review-wakes [CONFIG...]\n       VoxAurora --explain TEXT [--top K] [CONFIG...]\n       VoxAurora --docs FILE [CONFIG...]\n       VoxAurora --test-config FILE [CONFIG...]\n       VoxAurora --history TABLE [--search TEXT] [--top K] [CONFIG...]\n       VoxAurora --status [CONFIG...]\n       VoxAurora --schema FILE\n       VoxAurora --encrypt-secrets FILE [CONFIG...]\n       VoxAurora --replay DIR [--reproducible] [MODEL] [CONFIG...]"
+            );
+            std::process::exit(2);
+        }
+    };
+
+    if cli.json_output {
+        events::enable_json_output();
+    }
+
+    // Thin clients don't load any model or config
+    if let cli::Mode::Client(address) = &cli.mode {
+        return run_client(address, cli.codec, &cli.devices);
+    }
+    if let cli::Mode::ReviewWakes = cli.mode {
+        return review::run(cli.positional);
+    }
+    if let cli::Mode::Explain(text) = &cli.mode {
+        let config_paths = cli::config_paths(cli.positional.clone());
+        return run_explain(text, cli.top.unwrap_or(5), config_paths);
+    }
+    if let cli::Mode::Docs(path) = &cli.mode {
+        return run_docs(path, cli::config_paths(cli.positional.clone()));
+    }
+    if let cli::Mode::EncryptSecrets(path) = &cli.mode {
+        return run_encrypt_secrets(path, cli::config_paths(cli.positional.clone()));
+    }
+    if let cli::Mode::TestConfig(path) = &cli.mode {
+        return run_match_tests(path, cli::config_paths(cli.positional.clone()));
+    }
+    if let cli::Mode::History(table) = &cli.mode {
+        return run_history(
+            table,
+            cli.search.as_deref(),
+            cli.top.unwrap_or(20),
+            cli::config_paths(cli.positional.clone()),
+        );
+    }
+    if let cli::Mode::Replay(dir) = &cli.mode {
+        return run_replay(dir, &cli.positional, cli.reproducible);
+    }
+    if let cli::Mode::Status = cli.mode {
+        return run_status(cli::config_paths(cli.positional));
+    }
+    if let cli::Mode::Schema(path) = &cli.mode {
+        return run_schema(path);
+    }
+    let args = &cli.positional;
+
+    // If the user provided a model path as the first argument, use it.
+    // Otherwise, ask interactively.
+    let model_path_input = if let Some(path) = args.first() {
+        path.clone()
+    } else {
+        println!("Please enter the path to the Whisper model (or press Enter for default './models/ggml-small.bin'):");
+        let mut input = String::new();
+        std::io::stdin()
+            .read_line(&mut input)
+            .expect("Failed to read input");
+        input.trim().to_string()
+    };
+
+    let model_path = if model_path_input.is_empty() {
+        "./models/ggml-small.bin".to_string()
+    } else {
+        model_path_input
+    };
+
+    // If additional arguments are provided after the model path, use them as config paths.
+    // Otherwise, ask the user interactively.
+    let config_paths: Vec<String> = if args.len() > 1 {
+        args[1..].to_vec()
+    } else {
+        println!("Please enter the path(s) to config file(s). Type 'done' when finished:");
+        let mut paths = Vec::new();
+        loop {
+            let mut line = String::new();
             std::io::stdin()
-                .read_line(&mut input)
+                .read_line(&mut line)
                 .expect("Failed to read input");
-            input.trim().to_string()
-        };
+            let trimmed = line.trim();
+            if trimmed.eq_ignore_ascii_case("done") {
+                break;
+            }
+            if !trimmed.is_empty() {
+                paths.push(trimmed.to_string());
+            }
+        }
+        if paths.is_empty() {
+            paths.push("./configs/base_config.json".to_string());
+        }
+        paths
+    };
 
-        let model_path = if model_path_input.is_empty() {
-            "./models/ggml-small.bin".to_string()
-        } else {
-            model_path_input
-        };
+    log::info!("Loading config from: {:?}", config_paths);
+
+    // The config is loaded first so its memory budget applies to every resource below
+    let mut config = match config::load_config(config_paths) {
+        Ok(config) => config,
+        Err(e) => {
+            log::error!("Error loading config: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if cli.clipboard {
+        config.force_text_sink(VoxAurora::actions::TextSink::Clipboard);
+    }
+    config.decoding.reproducible |= cli.reproducible;
+    if !cli.devices.is_empty() {
+        config.input.devices = cli.devices.iter().cloned().map(audio::DeviceSpec::Name).collect();
+    }
+    memory::init(config.memory.clone());
+    performance::init(config.performance.clone());
+    whisper_integration::init_cleanup(config.cleanup.clone());
+    whisper_integration::init_whisper(config.whisper.clone());
+    language::init(config.language.clone());
+    timeouts::init(config.timeouts.clone());
+    decoding::init(config.decoding.clone());
+    telemetry::init(config.telemetry.clone());
+    playback::init(config.playback.clone());
+    if let Err(e) = history::init(&config.history) {
+        log::error!("Failed to open history {}: {}", config.history.path, e);
+    }
+    if let Err(e) = replay::init(&config.recording) {
+        log::error!("Failed to start recording the session: {}", e);
+    }
+    if let Some(dir) = &cli.record_segments {
+        if privacy::enabled() {
+            log::warn!("🔒 --record-segments is ignored in privacy mode");
+        } else if let Err(e) = segment_dump::init(dir) {
+            log::error!("Failed to create {}: {}", dir, e);
+        }
+    }
+    memory::report_usage("config loading");
+    // On macOS, missing permissions otherwise only show as silence and lost keystrokes
+    permissions::check_at_startup(&config.permissions);
+
+    // Dictionaries are built on a background thread while the models load
+    let dictionary_loader = if memory::settings().lazy_dictionaries {
+        log::info!("Dictionaries will be loaded on first use");
+        None
+    } else {
+        log::info!("Loading DAWGS in the background...");
+        Some(std::thread::spawn(|| DAWGS.0.len()))
+    };
+
+    let mut _server = config
+        .cleanup
+        .languagetool
+        .then(whisper_integration::start_languagetool_server);
+    bert::init(&config.bert);
+    bert::load_model();
+    memory::report_usage("BERT model loading");
+
+    // Pre-encode triggers and wake variants, reusing embeddings cached by previous runs
+    bert::init_embedding_cache(&config.embedding_cache);
+    let mut fixed_phrases: Vec<&str> = config::trigger_phrases(&config.commands)
+        .iter()
+        .map(|phrase| phrase.phrase)
+        .collect();
+    fixed_phrases.extend_from_slice(wakeword::wake_variants());
+    bert::warm_up(&fixed_phrases);
+
+    // Current-thread by default; `performance.runtime` switches to a worker pool
+    let rt = performance::build_runtime(&config.performance)?;
+    // Captioning and input files end, unlike live listening
+    let finite = matches!(
+        cli.mode,
+        cli::Mode::Caption(_) | cli::Mode::InputFile(_) | cli::Mode::CaptionFile { .. }
+    );
 
+    rt.block_on(async move {
+        let model_path = memory::select_model_variant(&model_path, memory::settings());
         log::info!("Loading Whisper model from: {}", model_path);
 
-        let whisper_model = match whisper_integration::init_model(model_path) {
-            Ok(model) => model,
+        let whisper_model = match whisper_integration::init_model(model_path.clone()) {
+            Ok(model) => std::sync::Arc::new(model),
             Err(e) => {
                 log::error!("Error initializing Whisper model: {}", e);
                 std::process::exit(1);
             }
         };
+        if let Err(e) = whisper_integration::pool_states(&whisper_model) {
+            log::error!("Error creating Whisper states: {}", e);
+        }
+        memory::report_usage("Whisper model loading");
+        replay::record_versions(&model_path);
 
-        // If additional arguments are provided after the model path, use them as config paths.
-        // Otherwise, ask the user interactively.
-        let config_paths: Vec<String> = if args.len() > 2 {
-            args[2..].to_vec()
-        } else {
-            println!("Please enter the path(s) to config file(s). Type 'done' when finished:");
-            let mut paths = Vec::new();
-            loop {
-                let mut line = String::new();
-                std::io::stdin()
-                    .read_line(&mut line)
-                    .expect("Failed to read input");
-                let trimmed = line.trim();
-                if trimmed.eq_ignore_ascii_case("done") {
-                    break;
+        if let Some(loader) = dictionary_loader {
+            match loader.join() {
+                Ok(entries) => log::info!("DAWGS loaded ({} entries)", entries),
+                Err(_) => {
+                    log::error!("Dictionary loading panicked");
+                    std::process::exit(1);
                 }
-                if !trimmed.is_empty() {
-                    paths.push(trimmed.to_string());
+            }
+            memory::report_usage("dictionary loading");
+        }
+
+        // Commands toggled at runtime, shared with the control server
+        let command_state = std::sync::Arc::new(std::sync::Mutex::new(CommandState::load(
+            &config.control.state_file,
+        )));
+        // Audio settings follow the active profile, which can be switched at runtime
+        let mut active_profile = command_state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .profile(config.profile.as_deref())
+            .map(str::to_string);
+        let input = config.input_settings(active_profile.as_deref());
+
+        // Captioning transcribes everything and skips the command pipeline entirely
+        if let cli::Mode::Caption(path) = &cli.mode {
+            let mut audio_processor =
+                audio::open_input(&input).expect("Failed to get audio device");
+            audio_processor
+                .start_capture()
+                .await
+                .expect("Failed to start capture");
+            let session =
+                captioning::run(&mut audio_processor, whisper_model, &config.captioning, path);
+            match session.await {
+                Ok(recorder) => {
+                    if let Err(e) = recorder.finish(&config.summary, &config.secrets) {
+                        log::error!("Failed to write transcript {}: {}", path, e);
+                    }
                 }
+                Err(e) => log::error!("Captioning failed: {}", e),
             }
-            if paths.is_empty() {
-                paths.push("./configs/base_config.json".to_string());
+            return;
+        }
+        if let cli::Mode::CaptionFile {
+            input: path,
+            transcript,
+        } = &cli.mode
+        {
+            let source = match audio::FileSource::open(path, &input) {
+                Ok(source) => source,
+                Err(e) => {
+                    log::error!("{}", e);
+                    std::process::exit(1);
+                }
+            };
+            let recorder = performance::run_blocking(|| {
+                captioning::run_file(source, whisper_model, &config.captioning, transcript)
+            });
+            if let Err(e) = recorder.finish(&config.summary, &config.secrets) {
+                log::error!("Failed to write transcript {}: {}", transcript, e);
             }
-            paths
-        };
+            return;
+        }
 
-        log::info!("Loading config from: {:?}", config_paths);
+        // False wakes marked with `--review-wakes` are never accepted again
+        let wake_settings = wake_log::with_reviewed_negatives(&config.wake);
+        // Set when the wake word is listened for on sliding windows of the live input
+        let mut wake_listener = None;
+        // Set when the live input is transcribed while it is recorded; commands then fire
+        // once their trigger phrase is stable, while `stream_armed`
+        let mut speech_stream: Option<(
+            whisper_integration::StreamModel,
+            tokio::sync::mpsc::UnboundedReceiver<StreamedSegment>,
+        )> = None;
+        let stream_armed = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
 
-        let config = match config::load_config(config_paths) {
-            Ok(config) => config,
-            Err(e) => {
-                log::error!("Error loading config: {}", e);
+        let serve_token = remote::token();
+        let mut segments = match &cli.mode {
+            cli::Mode::Serve(address) if privacy::enabled() && !privacy::is_loopback(address) => {
+                log::error!("Privacy mode only allows --serve on a loopback address");
+                std::process::exit(1);
+            }
+            // Clients run commands here, so only those holding the token may connect from afar
+            cli::Mode::Serve(address)
+                if serve_token.is_none() && !privacy::is_loopback(address) =>
+            {
+                log::error!(
+                    "--serve on a non-loopback address requires a token in {}",
+                    remote::TOKEN_ENV
+                );
                 std::process::exit(1);
             }
+            cli::Mode::Serve(address) => {
+                match remote::SegmentServer::bind(address, serve_token).await {
+                    Ok(server) => SegmentSource::Remote(server),
+                    Err(e) => {
+                        log::error!("Failed to listen on {}: {}", address, e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            cli::Mode::Network(address)
+                if privacy::enabled() && !privacy::is_loopback(address) =>
+            {
+                log::error!("Privacy mode only allows --listen-audio on a loopback address");
+                std::process::exit(1);
+            }
+            cli::Mode::Network(address) => {
+                match audio::NetworkSource::bind(address, &input) {
+                    Ok(source) => SegmentSource::Network(source),
+                    Err(e) => {
+                        log::error!("Failed to listen on {}: {}", address, e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            cli::Mode::InputFile(path) => match audio::FileSource::open(path, &input) {
+                Ok(file) => SegmentSource::File(file),
+                Err(e) => {
+                    log::error!("{}", e);
+                    std::process::exit(1);
+                }
+            },
+            _ => {
+                let mut audio_processor =
+                    audio::open_input(&input).expect("Failed to get audio device");
+                // The flashback records the first microphone
+                flashback::init(&config.flashback, audio_processor.sample_rate(0));
+                if config.wake.stream.enabled && !config.input.push_to_talk.enabled {
+                    let (feed, listener) = wake_stream::start(
+                        &config.wake.stream,
+                        wake_settings.clone(),
+                        &whisper_model,
+                    );
+                    audio_processor.set_wake_feed(feed);
+                    wake_listener = Some(listener);
+                }
+                if config.streaming.enabled {
+                    let (feed, stream) =
+                        whisper_integration::start_streaming(&config.streaming, &whisper_model);
+                    audio_processor.set_speech_feed(feed);
+                    let (finished, finished_receiver) = tokio::sync::mpsc::unbounded_channel();
+                    tokio::spawn(fire_stable_commands(
+                        stream.partials,
+                        config.clone(),
+                        command_state.clone(),
+                        stream_armed.clone(),
+                        finished,
+                    ));
+                    speech_stream = Some((stream.model, finished_receiver));
+                }
+
+                audio_processor
+                    .start_capture()
+                    .await
+                    .expect("Failed to start capture");
+                SegmentSource::Local(audio_processor)
+            }
         };
 
-        let device = audio::get_device().expect("Failed to get audio device");
-        let mut audio_processor = audio::AudioProcessor::new(device);
+        control::set_model(&whisper_model);
+        let _control_server = control::start_control_server(
+            std::sync::Arc::new(config.clone()),
+            command_state.clone(),
+        );
 
-        audio_processor
-            .start_capture()
-            .await
-            .expect("Failed to start capture");
+        flashback::set_model(&whisper_model);
 
-        log::info!("Listening continuously. Speak to activate commands.");
+        // The hotkey bounds local segments, which then skip the wake word
+        let push_to_talk =
+            config.input.push_to_talk.enabled && matches!(segments, SegmentSource::Local(_));
+        if push_to_talk {
+            log::info!(
+                "Push-to-talk on {}, the wake word isn't listened for.",
+                config.input.push_to_talk.key
+            );
+        } else {
+            log::info!("Listening continuously. Speak to activate commands.");
+        }
+
+        // Power saving: after a long idle period the heavy models are parked
+        let idle_after = config.power.idle_after_secs.map(std::time::Duration::from_secs);
+        let mut last_activity = std::time::Instant::now();
+        let mut whisper_model = Some(whisper_model);
+        let mut keyword_model: Option<whisper_rs::WhisperContext> = None;
+        let mut parked = false;
 
         // Main audio processing loop
         let mut awake = false;
+        // Transcription of the segments cut mid-speech, waiting for the end of the utterance
+        let mut partial: Option<whisper_integration::Transcription> = None;
+        // Segments returned by the capture, which the streaming transcription finalizes too
+        let mut streamed_segments = 0;
         loop {
-            let audio_data = match audio_processor.get_next_speech_segment().await {
-                Ok(data) => data,
+            // Streamed commands only fire where the whole segment would be a command
+            stream_armed.store(push_to_talk || awake, std::sync::atomic::Ordering::Relaxed);
+            // A profile switched to at runtime brings its own audio settings
+            let profile = command_state
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .profile(config.profile.as_deref())
+                .map(str::to_string);
+            if profile != active_profile {
+                log::info!(
+                    "🎛️ Using the audio settings of profile {}",
+                    profile.as_deref().unwrap_or("default")
+                );
+                segments.apply_settings(&config.input_settings(profile.as_deref()));
+                active_profile = profile;
+            }
+
+            let (segment, reply) = match segments.next_segment().await {
+                Ok(segment) => segment,
+                Err(_) if segments.finished() => {
+                    log::info!("📁 End of the input file");
+                    break;
+                }
                 Err(e) => {
                     log::error!("Error during audio capture: {}", e);
                     continue;
                 }
             };
+            let audio::SpeechSegment {
+                samples: audio_data,
+                continues,
+            } = segment;
+            // Taken for every segment, so that a wake heard in one never carries over
+            let stream_woke = match &wake_listener {
+                Some(listener) => Some(listener.woke_during_segment().await),
+                None => None,
+            };
+            let streamed = match &mut speech_stream {
+                Some((_, finished)) => {
+                    streamed_segments += 1;
+                    streamed_command(finished, streamed_segments).await
+                }
+                None => None,
+            };
 
             if audio_data.len() < 1000 {
                 continue;
             }
+            // Its sidecar is written once the segment is done with, however that happens
+            let mut dumped = segment_dump::begin(&audio_data);
 
-            let mut wake_params = whisper_rs::FullParams::new(whisper_rs::SamplingStrategy::default());
-            wake_params.set_print_special(false);
-            wake_params.set_print_progress(false);
-            wake_params.set_print_realtime(false);
-            wake_params.set_token_timestamps(false);
-            wake_params.set_language(Some("fr"));
-
-            let mut wake_state = whisper_model.create_state().expect("Failed to create wake_state");
-            if let Err(e) = wake_state.full(wake_params, &audio_data) {
-                log::error!("Error processing audio data for wake word detection: {}", e);
-                continue;
+            // Parked models wait for the wake word, which push-to-talk never listens for
+            let idle = idle_after.is_some_and(|idle| last_activity.elapsed() > idle);
+            if !parked && !push_to_talk && idle {
+                log::info!("💤 Idle for a while, parking models until the next wake word");
+                parked = true;
+                awake = false;
+                events::emit(Event::StateChange { state: "parked" });
+                bert::unload_model();
+                keyword_model = power::load_keyword_model(&config.power);
+                if keyword_model.is_some() {
+                    whisper_model = None;
+                    whisper_integration::release_states();
+                }
+                memory::report_usage("parking models");
             }
 
-            match wakeword::is_wake_word_present(std::sync::Arc::new(wake_state), 0).await {
-                Ok(true) => {
-                    awake = !awake;
+            if parked {
+                let Some(spotter) = keyword_model.as_ref().or(whisper_model.as_deref()) else {
+                    continue;
+                };
+                let heard = match power::spot_wake_word(spotter, &audio_data) {
+                    Ok(heard) => heard,
+                    Err(e) => {
+                        log::error!("Error during keyword spotting: {}", e);
+                        continue;
+                    }
+                };
+                if let Some(dumped) = &mut dumped {
+                    dumped.set_wake(WakeOutcome::heard(WakeSource::Keyword, heard));
+                }
+                if !heard {
+                    continue;
+                }
+
+                log::info!("🌅 Wake word heard, reloading models");
+                if whisper_model.is_none() {
+                    match whisper_integration::init_model(model_path.clone()) {
+                        Ok(model) => {
+                            let model = std::sync::Arc::new(model);
+                            if let Err(e) = whisper_integration::pool_states(&model) {
+                                log::error!("Error creating Whisper states: {}", e);
+                            }
+                            flashback::set_model(&model);
+                            control::set_model(&model);
+                            if let Some(listener) = &wake_listener {
+                                listener.set_model(&model);
+                            }
+                            if let Some((stream_model, _)) = &speech_stream {
+                                stream_model.set(&model);
+                            }
+                            whisper_model = Some(model);
+                        }
+                        Err(e) => {
+                            log::error!("Error reloading Whisper model: {}", e);
+                            continue;
+                        }
+                    }
                 }
-                Ok(false) => {}
-                Err(e) => log::error!("Error during wake word detection: {}", e),
+                keyword_model = None;
+                bert::load_model();
+                parked = false;
+                awake = true;
+                events::emit(Event::StateChange { state: "awake" });
+                last_activity = std::time::Instant::now();
+                memory::report_usage("model reloading");
+                continue;
             }
+            let whisper_model = whisper_model
+                .clone()
+                .expect("Whisper model is loaded while not parked");
 
-            if !awake {
+            // The streaming transcription already ran its command while it was said
+            if let Some((text, handled)) = streamed {
+                log::info!("⚡ Already handled while it was said: {}", text);
+                last_activity = std::time::Instant::now();
+                partial = None;
+                if let Some(dumped) = &mut dumped {
+                    dumped.add_utterance(&text, Some(&handled));
+                }
+                if let Some(reply) = reply {
+                    let _ = reply.send(text);
+                }
                 continue;
             }
 
-            log::info!("System is now {}", if awake { "awake" } else { "sleeping" });
+            // Push-to-talk segments are commands as they are: no wake word, no splitting.
+            // Neither are the ones carrying on from a segment cut mid-speech.
+            let utterances = if push_to_talk || (awake && partial.is_some()) {
+                vec![audio_data]
+            } else {
+                let (woke, wake_state) = match stream_woke {
+                    // The wake stream already listened while the segment was recorded
+                    Some(woke) => {
+                        if let Some(dumped) = &mut dumped {
+                            dumped.set_wake(WakeOutcome::heard(WakeSource::Stream, woke));
+                        }
+                        (woke, None)
+                    }
+                    None => {
+                        let wake_params = wakeword::wake_params();
+                        let wake_pass = performance::run_blocking(|| {
+                            supervisor::run(Subsystem::Whisper, || {
+                                let mut wake_state = whisper_model.create_state()?;
+                                wake_state.full(wake_params, &audio_data)?;
+                                Ok::<_, whisper_rs::WhisperError>(wake_state)
+                            })
+                        });
+                        let wake_state = match wake_pass {
+                            Ok(Ok(wake_state)) => wake_state,
+                            Ok(Err(e)) => {
+                                log::error!(
+                                    "Error processing audio data for wake word detection: {}",
+                                    e
+                                );
+                                continue;
+                            }
+                            Err(e) => {
+                                log::error!("Wake word detection skipped: {}", e);
+                                continue;
+                            }
+                        };
 
-            let transcription = match whisper_integration::transcribe(&whisper_model, &audio_data, "fr").await {
-                Ok(text) => text,
-                Err(e) => {
-                    log::error!("Error during audio transcription: {}", e);
+                        let wake_state = std::sync::Arc::new(wake_state);
+                        let check = wakeword::is_wake_word_present(
+                            wake_state.clone(),
+                            0,
+                            wake_settings.clone(),
+                        )
+                        .await;
+                        let woke = match check {
+                            Ok(check) => {
+                                wake_log::record(&check, &audio_data, &wake_settings);
+                                if let Some(dumped) = &mut dumped {
+                                    dumped.set_wake(WakeOutcome::from_check(&check));
+                                }
+                                check.woke
+                            }
+                            Err(e) => {
+                                log::error!("Error during wake word detection: {}", e);
+                                false
+                            }
+                        };
+                        (woke, Some(wake_state))
+                    }
+                };
+                if woke {
+                    awake = !awake;
+                    last_activity = std::time::Instant::now();
+                    events::emit(Event::StateChange {
+                        state: if awake { "awake" } else { "sleeping" },
+                    });
+                }
+
+                if !awake {
                     continue;
                 }
+
+                log::info!("System is now {}", if awake { "awake" } else { "sleeping" });
+
+                // The wake pass already decoded the segment; its timestamps split utterances
+                match wake_state {
+                    Some(wake_state) if config.segmentation.enabled => {
+                        segmentation::split_utterances(
+                            &audio_data,
+                            &wake_state,
+                            &config.segmentation,
+                        )
+                    }
+                    _ => vec![audio_data],
+                }
             };
 
-            if transcription.is_empty() {
-                continue;
-            }
+            let mut transcriptions = Vec::new();
+            let last = utterances.len().saturating_sub(1);
+            for (index, utterance) in utterances.into_iter().enumerate() {
+                // Segments are 16 kHz once resampled
+                telemetry::begin_utterance(std::time::Duration::from_secs_f32(
+                    utterance.len() as f32 / 16_000.0,
+                ));
 
-            log::info!("---------------------------------------------------");
-            log::info!("{}", &transcription);
-            log::info!("---------------------------------------------------");
+                // Background speech in another language is set aside before any correction,
+                // the rest is transcribed in the language it was spoken in
+                let lang = if config.language.enabled {
+                    let lang = performance::run_blocking(|| {
+                        handle_language(&whisper_model, &utterance, &config.language)
+                    });
+                    match lang {
+                        Some(lang) => lang,
+                        None => {
+                            telemetry::end_utterance();
+                            continue;
+                        }
+                    }
+                } else {
+                    config.language.default.as_str()
+                };
 
-            match config::execute_command(&config, transcription).await {
-                Ok(_) => log::info!("Command execution completed"),
-                Err(e) => {
-                    log::error!("Failed to execute command: {}", e);
+                // Kept for `--replay`, as the transcription takes the samples
+                let recorded_audio = replay::enabled().then(|| utterance.clone());
+                let started = std::time::Instant::now();
+                let transcription = match stt_fallback::transcribe_with_fallback(
+                    whisper_model.clone(),
+                    utterance,
+                    lang,
+                    &config.stt_fallback,
+                    &config.secrets,
+                )
+                .await
+                {
+                    Ok(transcription) => {
+                        telemetry::record("stt", started, vec![("language", lang.into())], None);
+                        transcription
+                    }
+                    Err(e) => {
+                        log::error!("Error during audio transcription: {}", e);
+                        telemetry::record("stt", started, Vec::new(), Some(e.to_string()));
+                        telemetry::end_utterance();
+                        continue;
+                    }
+                };
+
+                // An utterance cut at the maximum duration runs once its end is heard
+                let transcription = match partial.take() {
+                    Some(previous) => previous.stitch(transcription),
+                    None => transcription,
+                };
+                if continues && index == last {
+                    log::info!("➿ Waiting for the rest of: {}", transcription.corrected);
+                    if let Some(dumped) = &mut dumped {
+                        dumped.add_partial(&transcription.corrected);
+                    }
+                    partial = Some(transcription);
+                    telemetry::end_utterance();
                     continue;
                 }
-            };
+
+                if transcription.is_empty() {
+                    telemetry::end_utterance();
+                    continue;
+                }
+                last_activity = std::time::Instant::now();
+
+                log::info!("---------------------------------------------------");
+                log::info!("{}", &transcription.corrected);
+                log::info!("---------------------------------------------------");
+                events::emit(Event::Transcript {
+                    text: &transcription.corrected,
+                });
+                transcriptions.push(transcription.corrected.clone());
+
+                if config.input.pause_during_actions {
+                    segments.pause();
+                }
+                let executed =
+                    config::execute_command(&config, &command_state, transcription.clone()).await;
+                if config.input.pause_during_actions {
+                    segments.resume();
+                }
+                telemetry::end_utterance();
+                match executed {
+                    Ok(handled) => {
+                        log::info!("Command execution completed");
+                        if let Some(dumped) = &mut dumped {
+                            dumped.add_utterance(&transcription.corrected, Some(&handled));
+                        }
+                        if let Some(audio) = &recorded_audio {
+                            replay::record(audio, &transcription.corrected, &handled);
+                        }
+                    }
+                    Err(e) => {
+                        log::error!("Failed to execute command: {}", e);
+                        if let Some(dumped) = &mut dumped {
+                            dumped.add_utterance(&transcription.corrected, None);
+                        }
+                        continue;
+                    }
+                };
+            }
+
+            if let Some(reply) = reply {
+                let _ = reply.send(transcriptions.join(" "));
+            }
         }
-    }));
+    });
+
+    // Only captioning sessions and input files end; the server isn't needed anymore
+    if finite {
+        if let Some(server) = _server.as_mut() {
+            let _ = server.kill();
+        }
+    }
 
     // Wait for the LanguageTool server to exit
-    if let Ok(exit_status) = _server.wait() {
+    if let Some(Ok(exit_status)) = _server.as_mut().map(|server| server.wait()) {
         log::info!("LanguageTool server exited with status: {}", exit_status);
     }
 
@@ -217,11 +1292,80 @@ mod tests {
     #[test]
     fn test_merge_separated_words_dawg_regex() {
         let input_text = "Il est au jour d hui un bel après midi.";
-        let merged = whisper_integration::merge_separated_words_dawg_regex(input_text, 4);
+        let options = whisper_integration::MergeOptions {
+            max_merge: 4,
+            ..Default::default()
+        };
+        let merged = whisper_integration::merge_separated_words_dawg_regex(input_text, &options);
         println!("Merged text: '{}'", merged);
         assert!(merged.contains("aujourd'hui"), "Merged text: '{}'", merged);
     }
 
+    // Lost hyphens are restored when the hyphenated form is a dictionary word.
+    #[test]
+    fn test_merge_restores_hyphen() {
+        let input_text = "Il viendra peut être demain.";
+        let merged = whisper_integration::merge_separated_words_dawg_regex(
+            input_text,
+            &whisper_integration::MergeOptions::default(),
+        );
+        assert!(merged.contains("peut-être"), "Merged text: '{}'", merged);
+    }
+
+    // Accent folding is the key of the accent restoration index.
+    #[test]
+    fn test_strip_diacritics() {
+        assert_eq!(VoxAurora::accents::strip_diacritics("déjà"), "deja");
+        assert_eq!(VoxAurora::accents::strip_diacritics("écran"), "ecran");
+        assert_eq!(VoxAurora::accents::strip_diacritics("maison"), "maison");
+    }
+
+    // Casing lost during correction is restored, and proper nouns are enforced.
+    #[test]
+    fn test_restore_casing() {
+        let settings = VoxAurora::casing::CasingSettings {
+            preserve: true,
+            proper_nouns: vec!["VoxAurora".to_string()],
+        };
+        let restored = VoxAurora::casing::restore_casing(
+            "j'habite à Paris avec voxaurora",
+            "j'habite à paris avec voxaurora",
+            &settings,
+        );
+        assert_eq!(restored, "j'habite à Paris avec VoxAurora");
+    }
+
+    // Output formatting controls what follows dictated text.
+    #[test]
+    fn test_output_format_apply() {
+        let format = VoxAurora::actions::OutputFormat {
+            trailing: VoxAurora::actions::Trailing::Nothing,
+            strip_final_punctuation: true,
+            ..Default::default()
+        };
+        assert_eq!(format.apply("git status ."), "git status");
+        let default = VoxAurora::actions::OutputFormat::default();
+        assert_eq!(default.apply("Bonjour."), "Bonjour. ");
+    }
+
+    // Fuzzy dictionary lookups only look at same-letter, similar-length words, whether
+    // the list is bucketed or a plain slice.
+    #[test]
+    fn test_is_most_similar_with_buckets() {
+        use VoxAurora::dawg_loader::{is_most_similar, is_most_similar_bucketed, WordList};
+
+        let plain = vec!["bonjour".to_string(), "bonsoir".to_string(), "merci".to_string()];
+        let words = WordList::new(plain.clone());
+        assert!(is_most_similar_bucketed(&words, "bonjoure", 1));
+        assert!(is_most_similar_bucketed(&words, "Merci", 0));
+        assert!(!is_most_similar_bucketed(&words, "salut", 1));
+
+        assert!(is_most_similar(&plain, "bonjoure", 1));
+        assert!(is_most_similar(&plain, "Merci", 0));
+        assert!(!is_most_similar(&plain, "salut", 1));
+        assert!(!is_most_similar(&plain, "ponjour", 1));
+    }
+
     // Test that checks punctuation cleanup and spacing.
     #[test]
     fn test_clean_whisper_text_with_punctuation() {
@@ -234,4 +1378,1914 @@ mod tests {
         assert!(!cleaned.contains(" ,"));
         assert!(cleaned.contains("Bonjour"));
     }
+
+    // Segments survive the client/server wire format unchanged.
+    #[test]
+    fn test_remote_segment_roundtrip() {
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let samples = vec![0.0, 0.25, -1.0, 0.5];
+        let decoded = rt.block_on(async {
+            let mut frame = Vec::new();
+            remote::write_segment(&mut frame, &samples).await.unwrap();
+            remote::read_segment(&mut frame.as_slice()).await.unwrap()
+        });
+        assert_eq!(decoded, samples);
+    }
+
+    // A server with a token turns away clients sending another one, or none.
+    #[test]
+    fn test_remote_token_handshake() {
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        rt.block_on(async {
+            let mut frame = Vec::new();
+            remote::write_token(&mut frame, "s3cret").await.unwrap();
+            assert!(remote::authenticate(&mut frame.as_slice(), "s3cret").await.is_ok());
+            assert!(remote::authenticate(&mut frame.as_slice(), "s3cre").await.is_err());
+            assert!(remote::authenticate(&mut frame.as_slice(), "s3cret!").await.is_err());
+
+            let mut frame = Vec::new();
+            remote::write_segment(&mut frame, &[0.0; 4]).await.unwrap();
+            assert!(remote::authenticate(&mut frame.as_slice(), "s3cret").await.is_err());
+        });
+    }
+
+    // Form fields are extracted from multipart uploads, file names included.
+    #[test]
+    fn test_parse_multipart() {
+        let body = concat!(
+            "--XyZ\r\n",
+            "Content-Disposition: form-data; name=\"language\"\r\n\r\n",
+            "en\r\n",
+            "--XyZ\r\n",
+            "Content-Disposition: form-data; name=\"file\"; filename=\"a.wav\"\r\n",
+            "Content-Type: audio/wav\r\n\r\n",
+            "RIFF\r\n",
+            "--XyZ--\r\n"
+        );
+        let parts = VoxAurora::transcription_api::parse_multipart(body.as_bytes(), "XyZ");
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].name, "language");
+        assert_eq!(parts[0].data, b"en");
+        assert_eq!(parts[1].filename.as_deref(), Some("a.wav"));
+        assert_eq!(parts[1].data, b"RIFF");
+    }
+
+    // Only a few uploads are transcribed at once; a slot frees up when dropped.
+    #[test]
+    fn test_transcription_slots() {
+        use VoxAurora::transcription_api::{MAX_CONCURRENT_TRANSCRIPTIONS, TranscriptionSlot};
+
+        let slots: Vec<_> = (0..MAX_CONCURRENT_TRANSCRIPTIONS)
+            .map(|_| TranscriptionSlot::acquire().unwrap())
+            .collect();
+        assert!(TranscriptionSlot::acquire().is_none());
+        drop(slots);
+        assert!(TranscriptionSlot::acquire().is_some());
+    }
+
+    // Segments sent to a remote backend decode back to the same 16kHz audio.
+    #[test]
+    fn test_wav_roundtrip() {
+        let samples = vec![0.0, 0.5, -0.5, 1.0];
+        let decoded = audio::decode_wav(&audio::encode_wav(&samples)).unwrap();
+        assert_eq!(decoded.len(), samples.len());
+        for (a, b) in decoded.iter().zip(&samples) {
+            assert!((a - b).abs() < 1e-3, "{} != {}", a, b);
+        }
+    }
+
+    // While parked, the wake word is recognised from plain text alone.
+    #[test]
+    fn test_matches_wake_text() {
+        assert!(wakeword::matches_wake_text("Aurora !"));
+        assert!(wakeword::matches_wake_text("vox aurore"));
+        assert!(!wakeword::matches_wake_text("ouvre le terminal"));
+        assert!(wakeword::matches_wake_text("Ok Aurora, ouvre le terminal"));
+        assert!(wakeword::matches_wake_text("Vox-Oroha."));
+        assert!(!wakeword::matches_wake_text("j'ai vu laura hier"));
+        assert!(!wakeword::matches_wake_text("la famille arorava"));
+    }
+
+    // Options can come before or after the positional model and config paths.
+    #[test]
+    fn test_cli_parse() {
+        let args = ["--output", "json", "model.bin", "--serve", "0.0.0.0:7879", "a.json"];
+        let cli = cli::parse(args.map(String::from)).unwrap();
+        assert!(cli.json_output);
+        assert!(matches!(cli.mode, cli::Mode::Serve(ref address) if address == "0.0.0.0:7879"));
+        assert_eq!(cli.positional, ["model.bin", "a.json"]);
+        assert!(cli::parse(["--output".to_string(), "xml".to_string()]).is_err());
+    }
+
+    // The first routing rule matching the focused app or title decides the sink.
+    #[test]
+    fn test_routing_rules() {
+        use VoxAurora::actions::TextSink;
+        use VoxAurora::routing::{RoutingRule, route};
+        use VoxAurora::window::FocusedWindow;
+
+        let rules = vec![
+            RoutingRule { app: "keepass".to_string(), sink: TextSink::Suppress },
+            RoutingRule { app: "terminal".to_string(), sink: TextSink::Paste },
+        ];
+        let window = |app: &str, title: &str| FocusedWindow {
+            app: app.to_string(),
+            title: title.to_string(),
+        };
+        assert_eq!(route(&rules, &window("KeePassXC", "Passwords")), Some(TextSink::Suppress));
+        assert_eq!(route(&rules, &window("kitty", "Terminal - zsh")), Some(TextSink::Paste));
+        assert_eq!(route(&rules, &window("firefox", "Docs")), None);
+    }
+
+    // Time and date answers are formatted from the local clock.
+    #[test]
+    fn test_clock_answers() {
+        use chrono::TimeZone;
+        let now = chrono::Utc.with_ymd_and_hms(2026, 10, 14, 9, 5, 0).unwrap();
+        assert_eq!(VoxAurora::clock::time_answer(&now), "It is 09:05");
+        assert_eq!(
+            VoxAurora::clock::date_answer(&now),
+            "Today is Wednesday 14 October 2026"
+        );
+    }
+
+    // Desktop entries are matched on localized and generic names, ignoring accents.
+    #[test]
+    fn test_resolve_desktop_entry() {
+        let entry = VoxAurora::apps::parse_desktop_entry(concat!(
+            "[Desktop Entry]\n",
+            "Type=Application\n",
+            "Name=Text Editor\n",
+            "GenericName[fr]=Éditeur de texte\n",
+            "Exec=gedit %U\n",
+            "[Desktop Action new-window]\n",
+            "Name=New Window\n",
+        ))
+        .unwrap();
+        assert_eq!(entry.name, "Text Editor");
+        let apps = vec![entry];
+        let found = VoxAurora::apps::resolve("l'éditeur de texte", &apps);
+        assert_eq!(found.map(|app| app.name.as_str()), Some("Text Editor"));
+        assert!(VoxAurora::apps::resolve("calculatrice", &apps).is_none());
+    }
+
+    #[test]
+    fn test_parse_music_command() {
+        use VoxAurora::music::MusicCommand;
+        assert_eq!(MusicCommand::parse("next").unwrap(), MusicCommand::Next);
+        assert_eq!(MusicCommand::parse(" toggle ").unwrap(), MusicCommand::Toggle);
+        assert_eq!(
+            MusicCommand::parse("search daft punk").unwrap(),
+            MusicCommand::Search("daft punk".to_string())
+        );
+        assert!(MusicCommand::parse("search").is_err());
+        assert!(MusicCommand::parse("rewind").is_err());
+    }
+
+    #[test]
+    fn test_browser_command() {
+        use VoxAurora::browser::{self, BrowserCommand, BrowserSettings};
+        assert_eq!(BrowserCommand::parse("tab 3").unwrap(), BrowserCommand::Tab(3));
+        assert!(BrowserCommand::parse("tab 12").is_err());
+        assert_eq!(BrowserCommand::parse("new-tab").unwrap(), BrowserCommand::NewTab(None));
+        assert_eq!(browser::normalize_url("github.com"), "https://github.com");
+        assert_eq!(
+            browser::search_url("rust async", &BrowserSettings::default()),
+            "https://duckduckgo.com/?q=rust%20async"
+        );
+    }
+
+    #[test]
+    fn test_message_dialog() {
+        use VoxAurora::dialog::{Dialog, Turn};
+        use VoxAurora::messaging::{self, MessageDialog, MessagingSettings};
+        let mut settings = MessagingSettings::default();
+        settings
+            .contacts
+            .insert("Marie".to_string(), "marie@example.com".to_string());
+
+        let found = messaging::resolve_recipient("marie", &settings.contacts).unwrap();
+        assert_eq!(found.address, "marie@example.com");
+        assert!(messaging::resolve_recipient("gérard", &settings.contacts).is_none());
+
+        let (mut dialog, question) =
+            MessageDialog::new(&settings, &Default::default(), Some("marie"));
+        assert_eq!(question, "What should I tell Marie?");
+        assert!(matches!(dialog.answer("J'arrive dans dix minutes."), Turn::Continue(_)));
+        assert!(matches!(dialog.answer("peut-être"), Turn::Continue(_)));
+        assert_eq!(dialog.answer("Non."), Turn::Done("Message cancelled".to_string()));
+    }
+
+    #[test]
+    fn test_caption_transcript() {
+        use VoxAurora::session::{self, SpeakerTurn};
+        use VoxAurora::speaker::SpeakerTracker;
+
+        // A steady tone always sounds like the same speaker
+        let tone: Vec<f32> = (0..16000)
+            .map(|i| (i as f32 * 2.0 * std::f32::consts::PI * 220.0 / 16000.0).sin() * 0.3)
+            .collect();
+        let mut tracker = SpeakerTracker::new(0.9, 4);
+        assert_eq!(tracker.identify(&tone), 1);
+        assert_eq!(tracker.identify(&tone), 1);
+
+        let turns = vec![SpeakerTurn {
+            offset: Duration::from_secs(65),
+            speaker: 2,
+            text: "On commence <vraiment>".to_string(),
+        }];
+        let markdown = session::render_markdown("Transcript", &turns);
+        assert!(markdown.contains("**[00:01:05] Speaker 2:** On commence <vraiment>"));
+        let html = session::render_html("Transcript", &turns);
+        assert!(html.contains("On commence &lt;vraiment&gt;"));
+    }
+
+    #[test]
+    fn test_summary_path() {
+        use std::path::Path;
+        use VoxAurora::summary::summary_path;
+        assert_eq!(
+            summary_path(Path::new("notes/standup.html")),
+            Path::new("notes/standup.summary.md")
+        );
+    }
+
+    #[test]
+    fn test_utterance_ranges() {
+        use VoxAurora::segmentation::{SegmentationSettings, utterance_ranges};
+        let settings = SegmentationSettings::default();
+        // Two commands 1s apart, plus a segment 100ms after the second one
+        let bounds = vec![1600..16000, 32000..40000, 41600..48000];
+        assert_eq!(
+            utterance_ranges(&bounds, 64000, &settings),
+            vec![0..18400, 29600..50400]
+        );
+        assert_eq!(utterance_ranges(&[], 500, &settings), vec![0..500]);
+    }
+
+    #[test]
+    fn test_downmix_channel_selection() {
+        use VoxAurora::audio::downmix;
+        let stereo = [0.0, 0.75, 0.25, 0.5];
+        assert_eq!(downmix(&stereo, 2, Some(&[1])), vec![0.75, 0.5]);
+        assert_eq!(downmix(&stereo, 2, None), vec![0.375, 0.375]);
+        assert_eq!(downmix(&stereo, 2, Some(&[5])), vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_codec_roundtrip() {
+        use VoxAurora::codec::{self, Codec};
+        let samples: Vec<f32> = (0..4000).map(|i| ((i % 100) as f32 / 100.0) - 0.5).collect();
+        let flac = codec::encode(&samples, Codec::Flac).unwrap();
+        let decoded = codec::decode(&flac).unwrap();
+        assert_eq!(decoded.len(), samples.len());
+        assert!(decoded.iter().zip(&samples).all(|(a, b)| (a - b).abs() < 1e-3));
+
+        let opus = codec::encode(&samples, Codec::Opus).unwrap();
+        assert!(opus.len() < flac.len());
+        assert_eq!(codec::decode(&opus).unwrap().len(), samples.len());
+    }
+
+    #[test]
+    fn test_flashback_buffer() {
+        use VoxAurora::flashback::FlashbackBuffer;
+        let mut buffer = FlashbackBuffer::new(2, 16_000);
+        buffer.push(&vec![0.1; 16_000 * 3 + 8_000]);
+        let kept = buffer.recent(Duration::from_secs(10)).unwrap();
+        assert_eq!(kept.len(), 16_000 * 2 + 8_000);
+
+        // The utterance asking for the flashback isn't part of it
+        buffer.mark_speech();
+        buffer.push(&vec![0.1; 4_000]);
+        let recent = buffer.recent(Duration::from_secs(1)).unwrap();
+        assert_eq!(recent.len(), 16_000);
+        assert_eq!(buffer.recent(Duration::from_secs(10)).unwrap().len(), kept.len());
+    }
+
+    #[test]
+    fn test_input_watchdog() {
+        use VoxAurora::watchdog::{InputWatchdog, Problem, WatchdogSettings};
+        let settings = WatchdogSettings {
+            silence_secs: 1,
+            stall_secs: 5,
+            ..Default::default()
+        };
+        let mut watchdog = InputWatchdog::new(settings, 44_100);
+        let start = std::time::Instant::now();
+
+        let zeros = vec![0.0; 44_100];
+        assert_eq!(watchdog.chunk(&zeros, start), Some(Problem::Silence));
+        // Reported once per episode
+        assert_eq!(watchdog.chunk(&zeros, start), None);
+        assert_eq!(watchdog.chunk(&[0.02; 512], start), None);
+
+        assert_eq!(watchdog.check_stall(start + Duration::from_secs(2)), None);
+        assert_eq!(watchdog.check_stall(start + Duration::from_secs(6)), Some(Problem::Stall));
+    }
+
+    #[test]
+    fn test_clean_wake_text_skips_languagetool() {
+        // No LanguageTool server is started: the light path must not need one
+        let cleaned = whisper_integration::clean_wake_text(" [_BEG_]Vox  Aurora[_TT_42] ");
+        assert_eq!(cleaned, "vox aurora");
+    }
+
+    #[test]
+    fn test_wake_token_confidence() {
+        let tokens = |list: &[(&str, f32)]| -> Vec<(String, f32)> {
+            list.iter().map(|(text, prob)| (text.to_string(), *prob)).collect()
+        };
+        // Only the tokens spelling the wake word count; punctuation is ignored
+        let wake = tokens(&[("Vox", 0.9), (" Aur", 0.2), ("ora", 0.4), (".", 0.99)]);
+        let confidence = wakeword::wake_token_confidence(&wake).unwrap();
+        assert!((confidence - 0.5).abs() < 1e-6);
+        assert_eq!(wakeword::wake_token_confidence(&tokens(&[(" Bonjour", 0.8)])), Some(0.8));
+        assert_eq!(wakeword::wake_token_confidence(&tokens(&[("!", 0.8)])), None);
+    }
+
+    #[test]
+    fn test_wake_review_stats() {
+        use VoxAurora::wake_log::{self, Review, WakeDecision};
+        let decision = |text: &str, similarity: f32, review: Option<Review>| WakeDecision {
+            timestamp: String::new(),
+            text: text.to_string(),
+            variant: Some("aurora".to_string()),
+            similarity,
+            token_confidence: Some(0.8),
+            woke: true,
+            negative: false,
+            audio: None,
+            review,
+        };
+        let decisions = vec![
+            decision("aurora", 0.9, Some(Review::Correct)),
+            decision("laura", 0.75, Some(Review::FalseWake)),
+            decision("laura", 0.78, Some(Review::FalseWake)),
+            decision("auroha", 0.85, None),
+        ];
+        assert_eq!(wake_log::false_wake_phrases(&decisions), vec!["laura".to_string()]);
+        let stats = wake_log::stats(&decisions);
+        assert_eq!((stats.reviewed, stats.false_wakes), (3, 2));
+        assert!((stats.suggested_threshold.unwrap() - 0.84).abs() < 1e-6);
+        assert!(wakeword::is_negative_phrase("Laura.", &["laura".to_string()]));
+    }
+
+    #[test]
+    fn test_explain_reason() {
+        use VoxAurora::explain::{self, CandidateScore};
+        let candidate = |trigger: &str, score: f32, enabled: bool| CandidateScore {
+            trigger: trigger.to_string(),
+            phrase: trigger.to_string(),
+            kind: "app",
+            embedding: score,
+            fuzzy: 0.0,
+            score,
+            enabled,
+            above_threshold: score > 0.75,
+        };
+        let candidates = vec![
+            candidate("lance firefox", 0.92, false),
+            candidate("lance chrome", 0.88, true),
+            candidate("lance le terminal", 0.70, true),
+        ];
+        let reason = explain::reason(&candidates, 0.75);
+        assert!(reason.starts_with("'lance chrome' has the best score"), "{}", reason);
+        assert!(reason.contains("0.180 ahead of 'lance le terminal'"), "{}", reason);
+        assert!(reason.contains("disabled commands were skipped: lance firefox"), "{}", reason);
+
+        let reason = explain::reason(&candidates[2..], 0.75);
+        assert!(reason.starts_with("No enabled command scored above"), "{}", reason);
+    }
+
+    #[test]
+    fn test_parse_match_cases() {
+        let content = "# Browser\nouvre le navigateur => lance chrome\n\nbonjour à tous => none\n";
+        let cases = match_tests::parse_cases(content).unwrap();
+        assert_eq!(cases.len(), 2);
+        assert_eq!(cases[0].line, 2);
+        assert_eq!(cases[0].utterance, "ouvre le navigateur");
+        assert_eq!(cases[0].expected.as_deref(), Some("lance chrome"));
+        assert_eq!(cases[1].expected, None);
+        assert!(match_tests::parse_cases("ouvre le navigateur").is_err());
+    }
+
+    // Lookups by similarity only return the phrases they accept, with or without the HNSW
+    // index.
+    #[test]
+    fn test_memory_embedding_store() {
+        use VoxAurora::embedding_store::{EmbeddingStore, HnswStore, MemoryStore};
+
+        let mut store = MemoryStore::default();
+        store.insert("ouvre chrome", vec![1.0, 0.0]);
+        store.insert("ferme chrome", vec![0.0, 1.0]);
+        store.insert("lance chrome", vec![0.9, 0.1]);
+
+        let nearest = store.nearest(&[1.0, 0.0], 2, &|_| true);
+        let texts: Vec<&str> = nearest.iter().map(|(text, _)| text.as_str()).collect();
+        assert_eq!(texts, ["ouvre chrome", "lance chrome"]);
+        let nearest = store.nearest(&[1.0, 0.0], 2, &|text| text != "ouvre chrome");
+        assert_eq!(nearest[0].0, "lance chrome");
+
+        let mut indexed = HnswStore::new(MemoryStore::default());
+        for (text, embedding) in store.entries() {
+            indexed.insert(text, embedding.to_vec());
+        }
+        let nearest = indexed.nearest(&[1.0, 0.0], 1, &|text| text.starts_with("ferme"));
+        assert_eq!(nearest[0].0, "ferme chrome");
+        assert!(indexed.contains("lance chrome"));
+
+        store.retain(&|text| text != "ouvre chrome");
+        assert_eq!(store.len(), 2);
+        assert!(store.get("ouvre chrome").is_none() && !store.contains("ouvre chrome"));
+        assert_eq!(store.nearest(&[1.0, 0.0], 1, &|_| true)[0].0, "lance chrome");
+    }
+
+    #[test]
+    fn test_history_retention_and_query() {
+        use VoxAurora::history::{History, HistorySettings, Table};
+
+        let history = History::open(":memory:").unwrap();
+        for text in ["ouvre chrome", "bonjour", "ferme chrome"] {
+            history.record_transcript(text).unwrap();
+        }
+        history.record_command(Some("lance chrome"), false, Some("not found")).unwrap();
+
+        let entries = history.query(Table::Transcripts, Some("CHROME"), 10).unwrap();
+        let texts: Vec<&str> = entries.iter().map(|entry| entry.text.as_str()).collect();
+        assert_eq!(texts, ["ferme chrome", "ouvre chrome"]);
+        let commands = history.query(Table::Commands, None, 10).unwrap();
+        assert_eq!(commands[0].detail, "failed: not found");
+
+        let settings = HistorySettings {
+            retention_days: None,
+            max_entries: Some(1),
+            ..HistorySettings::default()
+        };
+        assert_eq!(history.apply_retention(&settings).unwrap(), 2);
+        assert_eq!(history.count(Table::Transcripts).unwrap(), 1);
+        assert_eq!(history.query(Table::Transcripts, None, 10).unwrap()[0].text, "ferme chrome");
+    }
+
+    #[test]
+    fn test_privacy_loopback_addresses() {
+        assert!(privacy::is_loopback("http://localhost:11434/v1/chat/completions"));
+        assert!(privacy::is_loopback("127.0.0.1:7878"));
+        assert!(privacy::is_loopback("http://[::1]:8080/v1/audio/transcriptions"));
+        assert!(!privacy::is_loopback("https://api.openai.com/v1/audio/transcriptions"));
+        assert!(!privacy::is_loopback("0.0.0.0:7879"));
+        assert!(!privacy::is_loopback("https://localhost.example.com"));
+    }
+
+    #[test]
+    fn test_dictation_buffer() {
+        use VoxAurora::dictation::DictationBuffer;
+
+        let mut buffer = DictationBuffer::default();
+        buffer.push("Bonjour à tous.");
+        buffer.push("  ");
+        buffer.push("Je suis en retard.");
+        buffer.push("Voici le compte rendu.");
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.pop().as_deref(), Some("Voici le compte rendu."));
+        assert_eq!(buffer.take(), "Bonjour à tous. Je suis en retard.");
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.pop(), None);
+    }
+
+    #[test]
+    fn test_punctuation_restore() {
+        use VoxAurora::punctuation::{self, PunctuationSettings};
+
+        let settings = PunctuationSettings {
+            enabled: true,
+            ..PunctuationSettings::default()
+        };
+        assert_eq!(
+            punctuation::restore("je suis en retard. on se voit demain,", &settings),
+            "Je suis en retard. On se voit demain."
+        );
+        assert_eq!(punctuation::restore("comment ça va", &settings), "Comment ça va ?");
+        assert_eq!(punctuation::restore("peux-tu venir", &settings), "Peux-tu venir ?");
+        assert_eq!(punctuation::restore("C'est fini !", &settings), "C'est fini !");
+        assert_eq!(punctuation::restore("ouvre", &settings), "Ouvre");
+    }
+
+    #[test]
+    fn test_language_gate() {
+        use language::{Detection, ForeignSpeech, Gate, LanguageSettings};
+
+        let settings = LanguageSettings {
+            enabled: true,
+            foreign: ForeignSpeech::Transcript,
+            ..LanguageSettings::default()
+        };
+        let detected = |language, probability| Detection {
+            language,
+            probability,
+        };
+        assert_eq!(language::gate(&detected("fr", 0.95), &settings), Gate::Accept);
+        assert_eq!(
+            language::gate(&detected("en", 0.9), &settings),
+            Gate::Foreign(ForeignSpeech::Transcript)
+        );
+        // Unsure detections are processed as usual
+        assert_eq!(language::gate(&detected("en", 0.4), &settings), Gate::Accept);
+    }
+
+    // Allowed languages are transcribed in the language they were detected in, unsure
+    // detections in the default one.
+    #[test]
+    fn test_transcription_language() {
+        use language::{Detection, LanguageSettings};
+
+        let settings = LanguageSettings {
+            enabled: true,
+            allowed: vec!["fr".to_string(), "en".to_string()],
+            ..LanguageSettings::default()
+        };
+        let detected = |language, probability| Detection {
+            language,
+            probability,
+        };
+        assert_eq!(language::transcription_language(&detected("en", 0.9), &settings), "en");
+        assert_eq!(language::transcription_language(&detected("en", 0.4), &settings), "fr");
+        assert_eq!(language::transcription_language(&detected("de", 0.9), &settings), "fr");
+        assert!(VoxAurora::dawg_loader::in_language("en", Some("EN")));
+        assert!(!VoxAurora::dawg_loader::in_language("fr", Some("en")));
+        assert!(VoxAurora::dawg_loader::in_language("fr", None));
+        assert_eq!(language::dictionary_language("en", &settings), Some("en"));
+        let disabled = LanguageSettings::default();
+        assert_eq!(language::dictionary_language("fr", &disabled), None);
+    }
+
+    #[test]
+    fn test_decode_quality() {
+        use VoxAurora::decoding::{DecodeQuality, DecodingSettings};
+
+        let settings = DecodingSettings::default();
+        let good = DecodeQuality::measure("Ouvre le navigateur.", &[0.9, 0.8, 0.95]);
+        assert!(good.acceptable(&settings), "{:?}", good);
+
+        let repeated = "merci ".repeat(40);
+        let looping = DecodeQuality::measure(&repeated, &[0.9; 40]);
+        assert!(looping.compression_ratio > settings.compression_ratio_threshold);
+        assert!(!looping.acceptable(&settings));
+
+        let unsure = DecodeQuality::measure("bonjour à tous", &[0.2, 0.3, 0.1]);
+        assert!(!unsure.acceptable(&settings));
+    }
+
+    // The microphones stay ignored while a speaking command runs and for the echo after.
+    #[test]
+    fn test_half_duplex() {
+        let options: VoxAurora::actions::ShellOptions =
+            serde_json::from_str(r#"{ "speaks": true }"#).unwrap();
+        assert!(options.speaks);
+
+        assert!(!playback::mutes_input());
+        let speaking = playback::start();
+        assert!(playback::mutes_input());
+        drop(speaking);
+        assert!(playback::mutes_input());
+        std::thread::sleep(std::time::Duration::from_millis(400));
+        assert!(!playback::mutes_input());
+    }
+
+    // A recorded file is captioned with offsets in file time, whatever the flag order.
+    #[test]
+    fn test_caption_file() {
+        use VoxAurora::captioning::CaptionSettings;
+        use VoxAurora::session::SessionRecorder;
+
+        for args in [
+            ["--input-file", "talk.flac", "--caption", "talk.md"],
+            ["--caption", "talk.md", "--input-file", "talk.flac"],
+        ] {
+            let cli = cli::parse(args.map(String::from)).unwrap();
+            assert!(matches!(
+                cli.mode,
+                cli::Mode::CaptionFile { ref input, ref transcript }
+                    if input == "talk.flac" && transcript == "talk.md"
+            ));
+        }
+        let args = ["--caption", "talk.md", "--serve", "0.0.0.0:7879"];
+        assert!(cli::parse(args.map(String::from)).is_err());
+
+        let settings = CaptionSettings {
+            workers: Some(0),
+            ..CaptionSettings::default()
+        };
+        assert_eq!(settings.worker_count(), 1);
+
+        let path = std::env::temp_dir().join("voxaurora_test_caption_file.md");
+        let mut recorder = SessionRecorder::new(&path);
+        recorder.set_position(std::time::Duration::from_secs(75));
+        recorder.record(0, "Bonjour", std::time::Duration::from_secs(5)).unwrap();
+        assert_eq!(recorder.turns()[0].offset, std::time::Duration::from_secs(70));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    // The wake stream stays off unless asked for, and keeps its window lengths.
+    #[test]
+    fn test_wake_stream_settings() {
+        let config: config::Config = serde_json::from_str("{}").unwrap();
+        assert!(!config.wake.stream.enabled);
+        let config: config::Config = serde_json::from_str(
+            r#"{ "wake": { "stream": { "enabled": true, "window_secs": 1.5 } } }"#,
+        )
+        .unwrap();
+        assert!(config.wake.stream.enabled);
+        assert_eq!(config.wake.stream.window_secs, 1.5);
+        assert_eq!(config.wake.stream.hop_secs, 1.0);
+    }
+
+    // A profile replaces the audio sections it sets, and a runtime switch wins over the
+    // profile of the config.
+    #[test]
+    fn test_profile_audio_settings() {
+        let config: config::Config = serde_json::from_str(
+            r#"{
+                "profile": "casque",
+                "input": { "timing": { "max_speech_secs": 20.0 } },
+                "profiles": {
+                    "casque": {},
+                    "salon": { "input": { "vad": { "energy_threshold": 0.004 } } }
+                }
+            }"#,
+        )
+        .unwrap();
+        let headset = config.input_settings(Some("casque"));
+        assert_eq!(headset.vad.energy_threshold, 0.01);
+        assert_eq!(headset.timing.max_speech_secs, 20.0);
+        let far_field = config.input_settings(Some("salon"));
+        assert_eq!(far_field.vad.energy_threshold, 0.004);
+        assert_eq!(far_field.timing.max_speech_secs, 20.0);
+
+        let mut state = CommandState::default();
+        assert_eq!(state.profile(config.profile.as_deref()), Some("casque"));
+        state.set_active_profile(Some("salon".to_string()));
+        assert_eq!(state.profile(config.profile.as_deref()), Some("salon"));
+    }
+
+    // A replay tells which components changed since the session was recorded.
+    #[test]
+    fn test_component_versions() {
+        let args = ["--replay", "sessions/today", "--reproducible"];
+        let cli = cli::parse(args.map(String::from)).unwrap();
+        assert!(cli.reproducible);
+        assert!(matches!(cli.mode, cli::Mode::Replay(_)));
+
+        let model = std::env::temp_dir().join("voxaurora_test_versions.bin");
+        std::fs::write(&model, b"123456789").unwrap();
+        let versions = replay::ComponentVersions::current(model.to_str().unwrap());
+        assert_eq!(
+            versions.whisper_model,
+            "voxaurora_test_versions.bin (9 bytes, crc32 cbf43926)"
+        );
+        assert!(versions.differences(&versions).is_empty());
+        let updated = replay::ComponentVersions {
+            embeddings_model: "rust-bert/AllMiniLmL12V2".to_string(),
+            ..versions.clone()
+        };
+        assert_eq!(
+            versions.differences(&updated),
+            vec!["embeddings model: rust-bert/AllMiniLmL6V2 => rust-bert/AllMiniLmL12V2"]
+        );
+        std::fs::remove_file(model).unwrap();
+    }
+
+    // The energy threshold follows the room's noise floor, whatever is said meanwhile.
+    #[test]
+    fn test_noise_calibration() {
+        use VoxAurora::vad::{
+            self, Calibration, CalibrationSettings, EnergyVad, VoiceActivityDetector,
+        };
+
+        let settings = CalibrationSettings::default();
+        let mut calibration = Calibration::new(&settings, 16_000);
+        let mut noise_floor = None;
+        for index in 0..40 {
+            // A word in the middle of the calibration
+            let level = if index == 20 { 0.3 } else { 0.004 };
+            noise_floor = noise_floor.or(calibration.add(&[level; 1024]));
+        }
+        let noise_floor = noise_floor.expect("2.5 s of audio were heard");
+        assert!((noise_floor - 0.004).abs() < 1e-6);
+        assert!((settings.threshold(noise_floor) - 0.012).abs() < 1e-6);
+        // Silent and very noisy rooms stay within bounds
+        assert_eq!(settings.threshold(0.0), settings.min_threshold);
+        assert_eq!(settings.threshold(1.0), settings.max_threshold);
+
+        let mut detector = EnergyVad { threshold: 0.01 };
+        assert!(detector.is_speech(&[0.011; 512]));
+        detector.set_energy_threshold(settings.threshold(noise_floor));
+        assert!(!detector.is_speech(&[0.011; 512]));
+
+        vad::request_recalibration();
+        assert!(vad::take_recalibration_request());
+        assert!(!vad::take_recalibration_request());
+    }
+
+    // Integer samples from devices that don't capture in f32 are scaled to [-1, 1].
+    #[test]
+    fn test_sample_conversion() {
+        assert_eq!(audio::to_f32(&[i16::MIN, 0, 16_384]), vec![-1.0, 0.0, 0.5]);
+        assert_eq!(audio::to_f32(&[0u16, 32_768]), vec![-1.0, 0.0]);
+        assert_eq!(audio::to_f32(&[i32::MIN, 0]), vec![-1.0, 0.0]);
+        assert_eq!(audio::to_f32(&[0.25f32]), vec![0.25]);
+    }
+
+    // Speech longer than the maximum duration goes on in the next segment, which starts
+    // with the overlap, and the transcripts are joined without repeating it.
+    #[test]
+    fn test_segment_continuation() {
+        use VoxAurora::audio::FileSource;
+        use VoxAurora::vad::VadSettings;
+        use whisper_integration::{Transcription, stitch_text};
+
+        let mut samples = vec![0.2; 12 * 16_000];
+        samples.extend(vec![0.0; 2 * 16_000]);
+        let mut file = FileSource::from_samples(samples, &VadSettings::default());
+        let first = file.next_segment().unwrap();
+        assert!(first.continues);
+        let second = file.next_segment().unwrap();
+        assert!(!second.continues);
+        assert_eq!(second.samples[..16_000], first.samples[first.samples.len() - 16_000..]);
+        assert!(file.next_segment().is_none());
+
+        assert_eq!(
+            stitch_text("je voudrais dire que le", "Que le projet avance"),
+            "je voudrais dire Que le projet avance"
+        );
+        // The word the first segment was cut on is heard whole in the second
+        assert_eq!(
+            stitch_text("on part demain mat", "demain matin à huit heures"),
+            "on part demain matin à huit heures"
+        );
+        assert_eq!(stitch_text("bonjour", "à tous"), "bonjour à tous");
+
+        let first = Transcription {
+            raw_cleaned: "note le rendez".into(),
+            corrected: "Note le rendez".into(),
+            confidence: Some(0.9),
+        };
+        let second = Transcription {
+            raw_cleaned: "le rendez-vous de lundi".into(),
+            corrected: "Le rendez-vous de lundi.".into(),
+            confidence: Some(0.7),
+        };
+        let stitched = first.stitch(second);
+        assert_eq!(stitched.corrected, "Note Le rendez-vous de lundi.");
+        assert_eq!(stitched.confidence, Some(0.7));
+    }
+
+    // The embeddings model runs where `bert.device` says, on the GPU when there is one.
+    #[test]
+    fn test_bert_device() {
+        use bert::{BertDevice, BertSettings};
+
+        assert_eq!(BertDevice::parse("CPU"), Ok(BertDevice::Cpu));
+        assert_eq!(BertDevice::parse("cuda"), Ok(BertDevice::Cuda(0)));
+        assert_eq!(BertDevice::parse("cuda:1"), Ok(BertDevice::Cuda(1)));
+        assert!(BertDevice::parse("gpu").is_err());
+        assert!(BertDevice::parse("cuda:x").is_err());
+
+        let settings: BertSettings = serde_json::from_str(r#"{"device": "cuda:0"}"#).unwrap();
+        assert_eq!(settings.device, BertDevice::Cuda(0));
+        assert_eq!(BertSettings::default().device, BertDevice::Auto);
+        assert!(serde_json::from_str::<BertSettings>(r#"{"device": "tpu"}"#).is_err());
+    }
+
+    // A stage that hangs is given up on after its timeout, with its fallback; 0 waits.
+    #[test]
+    fn test_stage_timeouts() {
+        use timeouts::{StageTimeout, TimeoutFallback, TimeoutSettings};
+
+        let settings: TimeoutSettings =
+            serde_json::from_str(r#"{"matching": {"timeout_ms": 50, "fallback": "raw_text"}}"#)
+                .unwrap();
+        assert_eq!(settings.matching.fallback, TimeoutFallback::RawText);
+        assert_eq!(settings.correction.timeout_ms, 5000);
+        let hang = || {
+            thread::sleep(Duration::from_millis(500));
+            42
+        };
+
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let late = rt.block_on(timeouts::run_blocking("Matching", &settings.matching, hang));
+        assert_eq!(late.unwrap(), None);
+        let quick = rt.block_on(timeouts::run_blocking("Matching", &settings.matching, || 42));
+        assert_eq!(quick.unwrap(), Some(42));
+
+        assert_eq!(timeouts::run_on_thread("LanguageTool", &settings.matching, hang), None);
+        let unlimited = StageTimeout {
+            timeout_ms: 0,
+            ..Default::default()
+        };
+        assert_eq!(timeouts::run_on_thread("LanguageTool", &unlimited, hang), Some(42));
+    }
+
+    // Echo cancellation learns the path from the speakers to the microphone and removes
+    // the playback, and passes audio through while nothing plays.
+    #[test]
+    fn test_echo_cancellation() {
+        use VoxAurora::echo::{EchoCancellationSettings, EchoCanceller};
+        use VoxAurora::pipeline::{AudioStep, PipelineStep};
+        use std::collections::VecDeque;
+        use std::sync::{Arc, Mutex};
+
+        let mut seed: u32 = 7;
+        let playback: Vec<f32> = (0..48_000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (seed >> 8) as f32 / 16_777_216.0 * 0.2 - 0.1
+            })
+            .collect();
+        // Heard 300 and 350 samples later, through the room
+        let microphone: Vec<f32> = (0..playback.len())
+            .map(|index| {
+                let delayed = |delay: usize| index.checked_sub(delay).map_or(0.0, |i| playback[i]);
+                0.6 * delayed(300) + 0.2 * delayed(350)
+            })
+            .collect();
+        let energy = |samples: &[f32]| {
+            samples.iter().map(|sample| sample * sample).sum::<f32>() / samples.len() as f32
+        };
+
+        let queue = Arc::new(Mutex::new(VecDeque::new()));
+        let settings = EchoCancellationSettings::default();
+        let mut canceller =
+            EchoCanceller::with_reference(&settings, 16_000, 16_000, queue.clone());
+        let mut cancelled = Vec::new();
+        for (played, heard) in playback.chunks(1024).zip(microphone.chunks(1024)) {
+            queue.lock().unwrap().extend(played);
+            cancelled.extend(canceller.process(heard));
+        }
+        // Blocks are 2048 samples at 16 kHz: the last ones come after 16 of them
+        let tail = cancelled.len() - 8192..cancelled.len();
+        assert!(energy(&cancelled[tail.clone()]) < 0.05 * energy(&microphone[tail]));
+
+        let queue = Arc::new(Mutex::new(VecDeque::new()));
+        let mut canceller = EchoCanceller::with_reference(&settings, 16_000, 16_000, queue);
+        let silent = canceller.process(&microphone[..4096]);
+        assert_eq!(silent, microphone[..4096].to_vec());
+
+        let steps: Vec<PipelineStep> =
+            serde_json::from_str(r#"[{"echo_cancellation": {"reference": "Stereo Mix"}}]"#)
+                .unwrap();
+        assert!(
+            matches!(&steps[0], PipelineStep::EchoCancellation(settings) if settings.tail_ms == 100)
+        );
+    }
+
+    // Shell actions see the utterance, its confidence and the match score, unless the
+    // command sets those variables itself.
+    #[test]
+    fn test_utterance_env() {
+        use VoxAurora::actions::ActionContext;
+
+        let mut context = ActionContext {
+            transcription: "redémarre le serveur".to_string(),
+            score: 0.9123,
+            ..Default::default()
+        };
+        let env = context.utterance_env();
+        assert_eq!(env["VOXAURORA_TRANSCRIPTION"], "redémarre le serveur");
+        assert_eq!(env["VOXAURORA_SCORE"], "0.912");
+        assert!(!env.contains_key("VOXAURORA_CONFIDENCE"));
+
+        context.confidence = Some(0.6977);
+        assert_eq!(context.utterance_env()["VOXAURORA_CONFIDENCE"], "0.698");
+    }
+
+    // Segments with too little speech are skipped, and the next one is cut instead.
+    #[test]
+    fn test_min_speech_duration() {
+        use VoxAurora::audio::{AudioSettings, FileSource};
+        use VoxAurora::vad::VadSettings;
+
+        let mut samples = vec![0.5; 3072];
+        samples.extend(vec![0.0; 24_576]);
+        samples.extend(vec![0.5; 16_384]);
+        samples.extend(vec![0.0; 24_576]);
+
+        let mut file = FileSource::from_samples(samples.clone(), &VadSettings::default());
+        assert_eq!(file.next_segment().map(|segment| segment.samples.len()), Some(19_456));
+
+        let mut file = FileSource::from_samples(samples, &VadSettings::default());
+        file.set_timing(AudioSettings {
+            min_speech_secs: 0.5,
+            ..Default::default()
+        });
+        assert_eq!(file.next_segment().map(|segment| segment.samples.len()), Some(32_768));
+        assert_eq!(file.next_segment(), None);
+    }
+
+    // Levels report the RMS and peak of a block, and dBFS bottoms out on silence.
+    #[test]
+    fn test_audio_level() {
+        use VoxAurora::audio::AudioLevel;
+
+        let level = AudioLevel::measure(&[0.5, -0.5, 0.5, -1.0]);
+        assert!((level.rms - 0.661).abs() < 1e-3);
+        assert_eq!(level.peak, 1.0);
+        assert!((AudioLevel::measure(&[0.5; 8]).rms_dbfs() + 6.02).abs() < 0.01);
+        assert!((AudioLevel::measure(&[]).rms_dbfs() + 100.0).abs() < 0.01);
+    }
+
+    // Grid cells are numbered in reading order and refine recursively.
+    #[test]
+    fn test_mouse_grid_cells() {
+        use VoxAurora::grid::{self, Click, Rect};
+
+        let screen = Rect {
+            x: 0,
+            y: 0,
+            width: 1920,
+            height: 1080,
+        };
+        let cell = screen.cell(3, 6).unwrap();
+        assert_eq!(
+            cell,
+            Rect {
+                x: 1280,
+                y: 360,
+                width: 640,
+                height: 360
+            }
+        );
+        assert_eq!(cell.cell(3, 1).unwrap().center(), (1386, 420));
+        assert_eq!(screen.cell(3, 10), None);
+        assert_eq!(screen.cell(3, 0), None);
+        assert_eq!(grid::parse_cell("cinq"), Some(5));
+        assert_eq!(grid::parse_cell("case 7"), Some(7));
+        assert_eq!(grid::parse_cell("ouvre chrome"), None);
+        assert_eq!(Click::parse("clic droit"), Some(Click::Right));
+        assert_eq!(grid::layout(2), "1 2\n3 4");
+    }
+
+    // Multi-channel files keep only the selected channels, like a live device.
+    #[test]
+    fn test_decode_channel_selection() {
+        use VoxAurora::codec;
+
+        let mut data = Vec::new();
+        for _ in 0..160 {
+            data.extend_from_slice(&(i16::MAX / 2).to_le_bytes());
+            data.extend_from_slice(&0i16.to_le_bytes());
+        }
+        let mut wav = b"RIFF".to_vec();
+        wav.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&2u16.to_le_bytes());
+        wav.extend_from_slice(&16_000u32.to_le_bytes());
+        wav.extend_from_slice(&64_000u32.to_le_bytes());
+        wav.extend_from_slice(&4u16.to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        wav.extend_from_slice(&data);
+
+        let mean = |samples: Vec<f32>| samples.iter().sum::<f32>() / samples.len() as f32;
+        assert!((mean(codec::decode_channels(&wav, Some(&[0])).unwrap()) - 0.5).abs() < 0.01);
+        assert!(mean(codec::decode_channels(&wav, Some(&[1])).unwrap()).abs() < 0.01);
+        assert!((mean(codec::decode(&wav).unwrap()) - 0.25).abs() < 0.01);
+    }
+
+    // Snippets are picked by the closest name; unknown names are reported, not typed.
+    #[test]
+    fn test_snippets() {
+        let config: config::Config = serde_json::from_str(
+            r#"{"commands": [], "output": {"sink": "suppress"},
+                "snippets": {"signature": "Cordialement,\nZied"}}"#,
+        )
+        .unwrap();
+        let state = std::sync::Arc::new(std::sync::Mutex::new(CommandState::default()));
+        let ask = |text: &str| VoxAurora::builtins::handle_builtin(text, &config, &state);
+        assert_eq!(
+            ask("Insère le snippet signatures.").as_deref(),
+            Some("Inserted snippet 'signature'")
+        );
+        assert_eq!(
+            ask("insère le snippet adresse").as_deref(),
+            Some("No snippet named 'adresse'")
+        );
+        assert_eq!(ask("insère la date"), None);
+    }
+
+    // Text actions expand date, time and transcription placeholders, and reject invalid
+    // date formats.
+    #[test]
+    fn test_text_placeholders() {
+        use VoxAurora::actions::{ActionContext, expand_placeholders};
+        use chrono::TimeZone;
+
+        let now = chrono::Local.with_ymd_and_hms(2026, 10, 14, 9, 5, 0).unwrap();
+        let context = ActionContext {
+            transcription: "insère la date".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            expand_placeholders("Le {{date}} à {{time}}", &context, now).unwrap(),
+            "Le 14/10/2026 à 09:05"
+        );
+        assert_eq!(
+            expand_placeholders("{{date:%Y-%m-%d}} {{file}}", &context, now).unwrap(),
+            "2026-10-14 {{file}}"
+        );
+        assert_eq!(
+            expand_placeholders("« {{transcription}} »", &context, now).unwrap(),
+            "« insère la date »"
+        );
+        assert!(expand_placeholders("{{date:%Q}}", &context, now).is_err());
+    }
+
+    // `--device` can be repeated and needs a name.
+    #[test]
+    fn test_cli_devices() {
+        let args = ["--device", "USB", "model.bin", "--device", "webcam"];
+        let cli = cli::parse(args.map(String::from)).unwrap();
+        assert_eq!(cli.devices, ["USB", "webcam"]);
+        assert_eq!(cli.positional, ["model.bin"]);
+        assert!(cli::parse(["--device".to_string()]).is_err());
+    }
+
+    // SSH actions name a configured host and a command, quoted or not, and unknown hosts
+    // fail before connecting.
+    #[test]
+    fn test_ssh_action() {
+        use VoxAurora::ssh;
+
+        let config: config::Config = serde_json::from_str(
+            r#"{"commands": [{"trigger": "redémarre jellyfin", "action": "ssh:nas \"systemctl restart jellyfin\""}],
+                "ssh": {"nas": {"host": "192.168.1.20", "user": "admin", "key": "~/.ssh/id_ed25519"}}}"#,
+        )
+        .unwrap();
+        assert_eq!(config.commands[0].kind(), "ssh");
+        assert_eq!(config.ssh["nas"].port, 22);
+        assert_eq!(config.ssh["nas"].known_hosts, "~/.ssh/known_hosts");
+        assert_eq!(
+            ssh::parse_action(r#"nas "systemctl restart jellyfin""#).unwrap(),
+            ("nas", "systemctl restart jellyfin")
+        );
+        assert_eq!(ssh::parse_action("nas uptime").unwrap(), ("nas", "uptime"));
+        assert!(ssh::parse_action("nas").is_err());
+        assert!(ssh::parse_action(r#"nas """#).is_err());
+        let error = ssh::execute_ssh("inconnu uptime", &config.ssh, &config.secrets).unwrap_err();
+        assert!(error.to_string().contains("inconnu"));
+    }
+
+    // After a device change the flashback follows the new rate, and the change is
+    // reported as an event.
+    #[test]
+    fn test_input_device_change() {
+        let mut buffer = VoxAurora::flashback::FlashbackBuffer::new(10, 48_000);
+        buffer.push(&vec![0.1; 48_000 + 100]);
+        buffer.set_sample_rate(16_000);
+        buffer.push(&vec![0.1; 16_000]);
+        let recent = buffer.recent(std::time::Duration::from_secs(10)).unwrap();
+        assert_eq!(recent.len(), 2 * 16_000);
+
+        let event = VoxAurora::events::Event::InputChange {
+            lost: "USB Microphone",
+            device: "Built-in Microphone",
+        };
+        let value = serde_json::to_value(&event).unwrap();
+        assert_eq!(value["event"], "input_change");
+        assert_eq!(value["device"], "Built-in Microphone");
+    }
+
+    // Services are tracked in the job table, which answers spoken status questions.
+    #[test]
+    fn test_services() {
+        use VoxAurora::services::{self, ServiceStatus};
+
+        let config: config::Config = serde_json::from_str(
+            r#"{"commands": [], "services": {"veilleur": {"cmd": "sleep 30", "cwd": "/"}}}"#,
+        )
+        .unwrap();
+        assert_eq!(config.services["veilleur"].cmd, "sleep 30");
+        assert_eq!(config.services["veilleur"].shell.cwd.as_deref(), Some("/"));
+        assert!(services::execute_service("restart veilleur", &config.services).is_err());
+        assert!(services::execute_service("start", &config.services).is_err());
+        assert!(services::execute_service("start inconnu", &config.services).is_err());
+        assert_eq!(ServiceStatus::Exited(Some(1)).describe("x"), "x exited with code 1");
+
+        let state = std::sync::Arc::new(std::sync::Mutex::new(CommandState::default()));
+        let ask = |text: &str| VoxAurora::builtins::handle_builtin(text, &config, &state);
+        assert_eq!(services::status("veilleur"), ServiceStatus::NotStarted);
+        assert_eq!(ask("Est-ce que le veilleur tourne ?").as_deref(), Some("veilleur isn't running"));
+
+        #[cfg(unix)]
+        {
+            let pid = services::start("veilleur", &config.services).unwrap();
+            assert!(matches!(
+                services::status("veilleur"),
+                ServiceStatus::Running { pid: running, .. } if running == pid
+            ));
+            assert!(services::start("veilleur", &config.services).is_err());
+            assert!(ask("is the veilleur running").unwrap().starts_with("veilleur is running"));
+            assert_eq!(ask("Quels services tournent ?").as_deref(), Some("Running: veilleur"));
+
+            services::stop("veilleur").unwrap();
+            assert_eq!(services::status("veilleur"), ServiceStatus::NotStarted);
+            assert!(services::stop("veilleur").is_err());
+        }
+    }
+
+    // Shell actions given as objects carry their working directory and environment.
+    #[test]
+    fn test_shell_action_options() {
+        let config: config::Config = serde_json::from_str(
+            r#"{"commands": [
+                {"trigger": "compile", "action": {"cmd": "cargo build", "cwd": "~/app",
+                    "env": {"RUST_LOG": "debug"}}},
+                {"trigger": "liste", "action": "cmd:ls"}
+            ]}"#,
+        )
+        .unwrap();
+        let build = &config.commands[0];
+        assert_eq!(build.action, "cmd:cargo build");
+        assert_eq!(build.kind(), "shell");
+        assert_eq!(build.shell.cwd.as_deref(), Some("~/app"));
+        assert_eq!(build.shell.env["RUST_LOG"], "debug");
+        assert_eq!(config.commands[1].shell, VoxAurora::actions::ShellOptions::default());
+
+        let home = std::env::var_os(if cfg!(windows) { "USERPROFILE" } else { "HOME" });
+        if let Some(home) = home {
+            let expanded = VoxAurora::platform::expand_home("~/app");
+            assert_eq!(expanded, std::path::Path::new(&home).join("app"));
+        }
+        assert_eq!(
+            VoxAurora::platform::expand_home("/tmp/~app"),
+            std::path::PathBuf::from("/tmp/~app")
+        );
+
+        #[cfg(unix)]
+        {
+            let options = VoxAurora::actions::ShellOptions {
+                cwd: Some("/".to_string()),
+                env: [("VOX_TEST".to_string(), "ok".to_string())].into(),
+                user: None,
+                speaks: false,
+            };
+            let check = r#"test "$(pwd)" = / && test "$VOX_TEST" = ok"#;
+            assert!(VoxAurora::actions::execute_shell_command(check, &options).is_ok());
+            let options = VoxAurora::actions::ShellOptions::default();
+            assert!(VoxAurora::actions::execute_shell_command(check, &options).is_err());
+        }
+    }
+
+    // Recorded macros drop the push-to-talk key, keeping the timing of what remains, and
+    // survive a round trip through the macros file.
+    #[test]
+    fn test_macro_steps() {
+        use VoxAurora::macros::{self, MacroSettings, MacroStep};
+        use rdev::{EventType, Key};
+
+        let step = |delay_ms, event| MacroStep { delay_ms, event };
+        let steps = vec![
+            step(0, EventType::KeyPress(Key::ControlLeft)),
+            step(50, EventType::KeyPress(Key::KeyT)),
+            step(30, EventType::KeyRelease(Key::KeyT)),
+            step(400, EventType::KeyPress(Key::F9)),
+            step(100, EventType::MouseMove { x: 10.0, y: 20.0 }),
+        ];
+        let kept = macros::without_key(steps, "f9");
+        assert_eq!(kept.len(), 4);
+        assert_eq!(kept[3], step(500, EventType::MouseMove { x: 10.0, y: 20.0 }));
+
+        let dir = std::env::temp_dir().join(format!("voxaurora-macros-{}", std::process::id()));
+        let settings = MacroSettings {
+            file: dir.join("macros.json").to_string_lossy().into_owned(),
+        };
+        assert!(macros::load(&settings).unwrap().is_empty());
+        macros::save(&settings, "ouvre un onglet", kept.clone()).unwrap();
+        assert_eq!(macros::load(&settings).unwrap()["ouvre un onglet"], kept);
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(
+            cheatsheet::describe("macro: ouvre un onglet"),
+            "plays the macro “ouvre un onglet”"
+        );
+    }
+
+    // Spectral subtraction passes audio through unchanged without reduction, removes
+    // steady noise, and keeps what rises above it.
+    #[test]
+    fn test_spectral_subtraction() {
+        use VoxAurora::pipeline::{AudioPipeline, PipelineStep, SpectralSubtractionSettings};
+
+        let mut seed: u32 = 1;
+        let noise: Vec<f32> = (0..16_000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (seed >> 8) as f32 / 16_777_216.0 * 0.1 - 0.05
+            })
+            .collect();
+        let energy = |samples: &[f32]| {
+            samples.iter().map(|sample| sample * sample).sum::<f32>() / samples.len() as f32
+        };
+        let step = |reduction: f32| {
+            let settings = SpectralSubtractionSettings {
+                reduction,
+                ..Default::default()
+            };
+            AudioPipeline::new(&[PipelineStep::SpectralSubtraction(settings)], 16_000)
+        };
+
+        // At 16 kHz frames are 512 samples, so the output lags by 256
+        let passthrough = step(0.0).process(noise.clone());
+        for (index, sample) in passthrough.iter().enumerate().skip(256) {
+            assert!((sample - noise[index - 256]).abs() < 1e-4);
+        }
+
+        // Fed in chunks, the way captured audio arrives
+        let mut pipeline = step(1.5);
+        let denoised: Vec<f32> = noise
+            .chunks(1000)
+            .flat_map(|chunk| pipeline.process(chunk.to_vec()))
+            .collect();
+        assert!(energy(&denoised[8_000..]) < 0.7 * energy(&noise[8_000..]));
+
+        // A loud burst after half a second of noise
+        let burst: Vec<f32> = (0..16_000)
+            .map(|index| if (8_000..11_200).contains(&index) { 0.3 } else { 0.0 })
+            .collect();
+        let noisy: Vec<f32> = burst.iter().zip(&noise).map(|(a, b)| a + b).collect();
+        let denoised = step(1.5).process(noisy);
+        assert!(energy(&denoised[8_756..11_256]) > 0.5 * energy(&burst[8_500..11_000]));
+
+        let steps: Vec<PipelineStep> =
+            serde_json::from_str(r#"[{"spectral_subtraction": {"reduction": 2.0}}]"#).unwrap();
+        assert!(!AudioPipeline::new(&steps, 48_000).is_empty());
+    }
+
+    // Pausing capture during actions is opt-in, as speech said meanwhile is lost.
+    #[test]
+    fn test_pause_during_actions_setting() {
+        let settings: audio::InputSettings = serde_json::from_str("{}").unwrap();
+        assert!(!settings.pause_during_actions);
+        let settings: audio::InputSettings =
+            serde_json::from_str(r#"{"pause_during_actions": true}"#).unwrap();
+        assert!(settings.pause_during_actions);
+    }
+
+    // The cheat sheet groups commands by profile and lists the built-in phrases.
+    #[test]
+    fn test_cheat_sheet() {
+        let config: config::Config = serde_json::from_str(
+            r#"{
+                "profile": "code",
+                "commands": [
+                    {"trigger": ["ouvre firefox", "lance firefox"], "action": "cmd:firefox"},
+                    {"trigger": "signature", "action": "Cordialement | Zied"},
+                    {"trigger": "ouvre le terminal", "action": "app:Terminal", "profile": "code"}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let sections = cheatsheet::sections(&config);
+        let titles: Vec<&str> = sections.iter().map(|section| section.title.as_str()).collect();
+        assert_eq!(titles, vec!["General", "Profile code (active)"]);
+        assert_eq!(sections[0].commands.len(), 2);
+
+        assert_eq!(cheatsheet::describe("cmd:firefox"), "runs `firefox`");
+        assert_eq!(cheatsheet::describe("app:Terminal"), "opens Terminal");
+        assert_eq!(cheatsheet::describe("bonjour"), "types “bonjour”");
+
+        let markdown = cheatsheet::render_markdown(&config);
+        assert!(markdown.contains("| **ouvre firefox** | lance firefox | runs `firefox` |"));
+        assert!(markdown.contains("Cordialement \\| Zied"));
+        assert!(markdown.contains("## Built-in"));
+
+        let html = cheatsheet::render_html(&config);
+        assert!(html.contains("<h2>Profile code (active)</h2>"));
+        assert!(html.contains("<strong>ouvre le terminal</strong>"));
+    }
+
+    // Missing permissions come with advice, and show in the health report.
+    #[test]
+    fn test_permission_advice() {
+        use VoxAurora::permissions::{self, Grant, Permission};
+
+        let advice = permissions::advice(Permission::Accessibility, Grant::Denied).unwrap();
+        assert!(advice.contains("typing text"));
+        assert!(permissions::advice(Permission::Microphone, Grant::NotDetermined).is_some());
+        assert!(permissions::advice(Permission::Microphone, Grant::Granted).is_none());
+        assert!(Permission::Microphone.settings_url().ends_with("Privacy_Microphone"));
+
+        let health = health::probe_permission(Permission::Microphone);
+        if cfg!(not(target_os = "macos")) {
+            assert_eq!(health.status, health::Status::Disabled);
+        }
+        assert_eq!(health.name, "microphone permission");
+    }
+
+    // A file is cut into segments at its silences, and runs out once read.
+    #[test]
+    fn test_file_source_segments() {
+        use VoxAurora::audio::FileSource;
+        use VoxAurora::vad::VadSettings;
+
+        let second = |amplitude: f32| vec![amplitude; 16_000];
+        let mut samples = second(0.0);
+        samples.extend(second(0.2).repeat(2));
+        samples.extend(second(0.0).repeat(2));
+        samples.extend(second(0.2));
+
+        let mut file = FileSource::from_samples(samples, &VadSettings::default());
+        let first = file.next_segment().unwrap().samples;
+        // The speech, then the silence that ended it
+        assert!(first.len() > 2 * 16_000 && first.len() < 3 * 16_000 + 2048);
+        assert!(!file.finished());
+        // The last one ends with the file
+        let last = file.next_segment().unwrap().samples;
+        assert!(last.len() >= 16_000 && last.len() < 16_000 + 1024);
+        assert!(file.finished());
+        assert!(file.next_segment().is_none());
+    }
+
+    // Streamed PCM is regrouped into 16 kHz mono chunks, whatever the datagram sizes.
+    #[test]
+    fn test_network_pcm_conversion() {
+        use VoxAurora::audio::{NetworkSettings, PcmConverter};
+
+        let pcm = |count: usize| 16_384i16.to_le_bytes().repeat(count);
+        let mut converter = PcmConverter::new(&NetworkSettings::default());
+        assert!(converter.push(&pcm(750)).is_empty());
+        // A sample split between two datagrams
+        let mut datagram = pcm(298);
+        datagram.push(0);
+        let chunks = converter.push(&datagram);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].len(), 1024);
+        assert!(chunks[0].iter().all(|sample| *sample == 0.5));
+        converter.push(&[0x40]);
+        assert_eq!(converter.push(&pcm(1023)).len(), 1);
+
+        let mut converter = PcmConverter::new(&NetworkSettings {
+            sample_rate: 48_000,
+            channels: 2,
+        });
+        let chunks = converter.push(&pcm(2 * 3072));
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].len(), 1024);
+
+        let args = ["--listen-audio", "ws://0.0.0.0:7881"].into_iter();
+        let cli = cli::parse(args.map(String::from)).unwrap();
+        assert!(
+            matches!(cli.mode, cli::Mode::Network(ref address) if address == "ws://0.0.0.0:7881")
+        );
+    }
+
+    // Scripts go through the platform's shell, and classpaths use its separator.
+    #[test]
+    fn test_platform_layer() {
+        use VoxAurora::platform;
+        use std::path::PathBuf;
+
+        let command = platform::shell("echo bonjour");
+        let separator = if cfg!(windows) {
+            assert_eq!(command.get_program(), "cmd");
+            ";"
+        } else {
+            assert_eq!(command.get_program(), "sh");
+            let args: Vec<_> = command.get_args().collect();
+            assert_eq!(args, ["-c", "echo bonjour"]);
+            ":"
+        };
+        let classpath =
+            platform::classpath(&[PathBuf::from("a.jar"), PathBuf::from("b.jar")]).unwrap();
+        assert_eq!(classpath, format!("a.jar{}b.jar", separator).as_str());
+        assert!(platform::resource("src/platform.rs").is_absolute());
+        assert_eq!(platform::resource("missing/file"), PathBuf::from("missing").join("file"));
+    }
+
+    // Holding the key opens capture until it's released; toggling needs a second press.
+    #[test]
+    fn test_push_to_talk_gate() {
+        use VoxAurora::hotkey::{KeyGate, PushToTalkMode, key_matches};
+
+        let mut hold = KeyGate::new(PushToTalkMode::Hold);
+        assert_eq!(hold.press(), Some(true));
+        // Key repeat
+        assert_eq!(hold.press(), None);
+        assert_eq!(hold.release(), Some(false));
+        assert!(!hold.is_open());
+
+        let mut toggle = KeyGate::new(PushToTalkMode::Toggle);
+        assert_eq!(toggle.press(), Some(true));
+        assert_eq!(toggle.release(), None);
+        assert!(toggle.is_open());
+        assert_eq!(toggle.press(), Some(false));
+
+        assert!(key_matches(rdev::Key::F9, "f9"));
+        assert!(!key_matches(rdev::Key::ControlRight, "ControlLeft"));
+    }
+
+    // Each consumer reads its own stage of a transcription.
+    #[test]
+    fn test_transcription_stages() {
+        use VoxAurora::whisper_integration::{TextConsumers, TextStage, Transcription};
+
+        let transcription = Transcription {
+            raw_cleaned: "ouvre le navigateur".to_string(),
+            corrected: "Ouvre le navigateur.".to_string(),
+            confidence: None,
+        };
+        let consumers = TextConsumers::default();
+        assert_eq!(transcription.text(consumers.matcher), "ouvre le navigateur");
+        assert_eq!(transcription.text(consumers.typing), "Ouvre le navigateur.");
+        assert_eq!(transcription.text(consumers.captions), transcription.corrected);
+
+        let consumers: TextConsumers = serde_json::from_str(r#"{"typing": "raw_cleaned"}"#).unwrap();
+        assert_eq!(consumers.typing, TextStage::RawCleaned);
+        assert_eq!(consumers.matcher, TextStage::RawCleaned);
+        assert!(Transcription::default().is_empty());
+    }
+
+    // Any capture rate resamples to exactly as many 16kHz samples as the audio lasts,
+    // including rates whose chunks don't divide evenly.
+    #[test]
+    fn test_resample_from_any_rate() {
+        use VoxAurora::audio::resample_from_rate;
+
+        for rate in [48_000, 44_100, 22_050, 8_000] {
+            let second: Vec<f32> = (0..rate)
+                .map(|i| (i as f32 * 440.0 * std::f32::consts::TAU / rate as f32).sin() * 0.5)
+                .collect();
+            let resampled = resample_from_rate(&second, 1, rate);
+            assert_eq!(resampled.len(), 16_000, "{} Hz", rate);
+            // The delay is trimmed, so the tone is there from the middle on
+            assert!(resampled[8_000..].iter().any(|sample| sample.abs() > 0.3), "{} Hz", rate);
+        }
+        let stereo = [0.25, 0.75, 0.25, 0.75];
+        assert_eq!(resample_from_rate(&stereo, 2, 16_000), vec![0.5, 0.5]);
+    }
+
+    // Audio resampled chunk by chunk as it is captured is as smooth as the tone itself,
+    // with no step at the chunk boundaries.
+    #[test]
+    fn test_stream_resampler() {
+        use VoxAurora::audio::StreamResampler;
+
+        let rate = 44_100;
+        let second: Vec<f32> = (0..rate)
+            .map(|i| (i as f32 * 440.0 * std::f32::consts::TAU / rate as f32).sin() * 0.5)
+            .collect();
+        let mut resampler = StreamResampler::new(rate);
+        let mut resampled = Vec::new();
+        for chunk in second.chunks(441) {
+            resampled.extend(resampler.process(chunk));
+        }
+        // Only the last chunk still held back is missing
+        assert!(resampled.len() > 15_000 && resampled.len() <= 16_000, "{}", resampled.len());
+        let largest_step = resampled[100..]
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]).abs())
+            .fold(0.0, f32::max);
+        assert!(largest_step < 0.15, "{}", largest_step);
+
+        resampler.reset();
+        assert!(resampler.process(&second[..441]).len() < 160);
+        assert_eq!(StreamResampler::new(16_000).process(&[0.5; 4]), vec![0.5; 4]);
+    }
+
+    // The energy detector keeps the old threshold; a missing Silero model falls back to it.
+    #[test]
+    fn test_voice_activity_detectors() {
+        use VoxAurora::vad::{self, VadBackend, VadSettings};
+
+        let settings = VadSettings::default();
+        assert_eq!(settings.backend, VadBackend::Energy);
+        let mut detector = vad::create(&settings, 44_100);
+        assert!(!detector.is_speech(&[0.005; 1024]));
+        assert!(detector.is_speech(&[0.2, -0.2, 0.1, -0.1]));
+
+        let settings = VadSettings {
+            backend: VadBackend::Silero,
+            silero_model: "./models/missing_silero_vad.onnx".to_string(),
+            ..Default::default()
+        };
+        let mut fallback = vad::create(&settings, 16_000);
+        assert!(fallback.is_speech(&[0.5; 512]));
+    }
+
+    // Speech started above the threshold survives a softer word and a short dip.
+    #[test]
+    fn test_vad_hysteresis() {
+        use VoxAurora::vad::{self, VadSettings};
+
+        let settings = VadSettings {
+            hangover_secs: 0.064,
+            ..Default::default()
+        };
+        let mut detector = vad::create(&settings, 16_000);
+        // Below the onset threshold, nothing starts
+        assert!(!detector.is_speech(&[0.008; 512]));
+        assert!(detector.is_speech(&[0.02; 512]));
+        // Above 70% of the threshold, speech goes on
+        assert!(detector.is_speech(&[0.008; 512]));
+        // Two chunks of hangover, then silence
+        assert!(detector.is_speech(&[0.001; 512]));
+        assert!(detector.is_speech(&[0.001; 512]));
+        assert!(!detector.is_speech(&[0.001; 512]));
+        assert!(!detector.is_speech(&[0.008; 512]));
+
+        let settings = VadSettings {
+            offset_ratio: 1.0,
+            hangover_secs: 0.0,
+            ..Default::default()
+        };
+        let mut detector = vad::create(&settings, 16_000);
+        assert!(detector.is_speech(&[0.02; 512]));
+        assert!(!detector.is_speech(&[0.008; 512]));
+    }
+
+    // Recorded segments are read back as written; only changes count against a replay.
+    #[test]
+    fn test_session_recording_round_trip() {
+        use VoxAurora::config::Handled;
+        use VoxAurora::replay::{self, RecordingSettings, ReplayedSegment, SessionRecording};
+
+        let settings = RecordingSettings {
+            dir: Some(std::env::temp_dir().join("voxaurora-replay-test").display().to_string()),
+            codec: VoxAurora::codec::Codec::Wav,
+        };
+        let mut recording = SessionRecording::create(&settings).unwrap().unwrap();
+        let handled = Handled::Command {
+            trigger: "lance chrome".to_string(),
+            score: 0.9,
+        };
+        recording.record(&[0.0; 1600], "ouvre chrome", &handled).unwrap();
+        recording.record(&[0.0; 1600], "bonjour", &Handled::Dictation).unwrap();
+
+        let recorded = replay::load(recording.dir()).unwrap();
+        assert_eq!(recorded.len(), 2);
+        assert_eq!(recorded[0].audio, "0001.wav");
+        assert_eq!(recorded[0].trigger.as_deref(), Some("lance chrome"));
+        assert!(recording.dir().join(&recorded[1].audio).exists());
+        std::fs::remove_dir_all(recording.dir()).unwrap();
+
+        let replayed = ReplayedSegment {
+            recorded: recorded[1].clone(),
+            transcript: "bonjour".to_string(),
+            trigger: Some("dis bonjour".to_string()),
+            score: Some(0.8),
+        };
+        assert!(!replayed.transcript_changed());
+        assert!(replayed.changed());
+        assert!(replay::render(&replayed).contains("+ match: dis bonjour"));
+    }
+
+    // A dumped segment leaves its WAV at once and its sidecar when dropped.
+    #[test]
+    fn test_segment_dump_sidecar() {
+        use VoxAurora::config::Handled;
+        use VoxAurora::segment_dump::DumpedSegment;
+
+        let dir = std::env::temp_dir().join("voxaurora-segment-dump-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut dumped = DumpedSegment::create(&dir, &[0.0; 8000]).unwrap();
+        let sidecar_path = dumped.sidecar_path().to_path_buf();
+        dumped.set_wake(WakeOutcome::heard(WakeSource::Stream, true));
+        let handled = Handled::Command {
+            trigger: "lance chrome".to_string(),
+            score: 0.9,
+        };
+        dumped.add_utterance("ouvre chrome", Some(&handled));
+        dumped.add_utterance("bonjour", None);
+        assert!(!sidecar_path.exists());
+        drop(dumped);
+
+        let sidecar = segment_dump::load(&sidecar_path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(sidecar.duration_secs, 0.5);
+        assert_eq!(sidecar.wake.map(|wake| wake.woke), Some(true));
+        assert_eq!(sidecar.utterances[0].trigger.as_deref(), Some("lance chrome"));
+        assert_eq!(sidecar.utterances[1].outcome, "failed");
+        assert!(sidecar.audio.ends_with(".wav"));
+    }
+
+    // Both runtime flavors can run CPU-bound stages from the pipeline.
+    #[test]
+    fn test_configurable_runtime() {
+        let settings: performance::PerformanceSettings =
+            serde_json::from_str(r#"{ "runtime": "multi_thread", "worker_threads": 2 }"#).unwrap();
+        assert_eq!(settings.runtime, performance::RuntimeKind::MultiThread);
+
+        for settings in [settings, performance::PerformanceSettings::default()] {
+            let rt = performance::build_runtime(&settings).unwrap();
+            let sum = rt.block_on(async { performance::run_blocking(|| 20 + 22) });
+            assert_eq!(sum, 42);
+        }
+    }
+
+    // Repeated panics degrade a subsystem until its cooldown is over; a success resets it.
+    #[test]
+    fn test_subsystem_state() {
+        use supervisor::{Outcome, SubsystemState};
+
+        let now = std::time::Instant::now();
+        let mut state = SubsystemState::default();
+        assert_eq!(state.failed(now), Outcome::Restarted);
+        assert_eq!(state.failed(now), Outcome::Restarted);
+        assert!(state.available(now));
+        assert_eq!(state.failed(now), Outcome::Degraded);
+        assert!(!state.available(now));
+        assert!(state.available(now + Duration::from_secs(61)));
+        assert!(state.succeeded());
+        assert!(!state.degraded());
+        assert_eq!(state.failed(now), Outcome::Restarted);
+    }
+
+    // A single component down makes the whole instance unhealthy; disabled ones don't.
+    #[test]
+    fn test_health_report() {
+        use health::{ComponentHealth, HealthReport, Status};
+
+        let component = |name: &str, status| ComponentHealth {
+            name: name.to_string(),
+            status,
+            latency_ms: Some(1.5),
+            detail: String::new(),
+        };
+        let report = HealthReport::new(vec![
+            component("whisper", Status::Ok),
+            component("languagetool", Status::Disabled),
+        ]);
+        assert!(report.healthy);
+        assert!(report.render().contains("whisper:  (1.5 ms)"));
+
+        let report = HealthReport::new(vec![
+            component("audio", Status::Down),
+            component("dictionaries", Status::Degraded),
+        ]);
+        assert!(!report.healthy);
+        assert!(report.render().ends_with("unhealthy\n"));
+    }
+
+    // Utterance spans are exported as children of the root span, in OTLP's JSON encoding.
+    #[test]
+    fn test_utterance_trace_to_otlp() {
+        use VoxAurora::telemetry::UtteranceTrace;
+
+        let start = std::time::UNIX_EPOCH + Duration::from_secs(1);
+        let mut trace = UtteranceTrace::new(start);
+        trace.record("stt", Duration::from_millis(300)).error = Some("timeout".to_string());
+        let export = trace.to_otlp("voxaurora");
+
+        let spans = export["resourceSpans"][0]["scopeSpans"][0]["spans"]
+            .as_array()
+            .unwrap();
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0]["name"], "utterance");
+        assert_eq!(spans[0]["startTimeUnixNano"], "1000000000");
+        assert!(spans[0].get("parentSpanId").is_none());
+        assert_eq!(spans[1]["parentSpanId"], spans[0]["spanId"]);
+        assert_eq!(spans[1]["traceId"].as_str().unwrap().len(), 32);
+        assert_eq!(spans[1]["status"]["code"], 2);
+    }
+
+    // A streamed word is stable once every recent pass heard it, case and punctuation
+    // aside; the words after the first disagreement aren't.
+    #[test]
+    fn test_stable_prefix() {
+        let passes = vec![
+            "Ouvre le".to_string(),
+            "ouvre le terminal".to_string(),
+            "ouvre le terminal, s'il".to_string(),
+        ];
+        assert_eq!(whisper_integration::stable_prefix(&passes[1..]), "ouvre le terminal,");
+        assert_eq!(whisper_integration::stable_prefix(&passes), "ouvre le");
+        let passes = vec!["ferme la".to_string(), "ouvre le".to_string()];
+        assert_eq!(whisper_integration::stable_prefix(&passes), "");
+        assert_eq!(whisper_integration::stable_prefix(&[]), "");
+
+        let config: config::Config = serde_json::from_str("{}").unwrap();
+        assert!(!config.streaming.enabled);
+        assert_eq!(config.streaming.stable_passes, 2);
+    }
+
+    // A stable prefix with words still changing after it only fires a command that
+    // clearly beats the others: "ouvre le" is as close to every "ouvre le …".
+    #[test]
+    fn test_stream_prefix_firing() {
+        use VoxAurora::explain::CandidateScore;
+        let candidate = |trigger: &str, score: f32| CandidateScore {
+            trigger: trigger.to_string(),
+            phrase: trigger.to_string(),
+            kind: "app",
+            embedding: score,
+            fuzzy: 0.0,
+            score,
+            enabled: true,
+            above_threshold: score > 0.75,
+        };
+        let ambiguous = vec![
+            candidate("ouvre le terminal", 0.81),
+            candidate("ouvre le navigateur", 0.79),
+        ];
+        assert!(!may_fire_early(false, &ambiguous));
+        assert!(may_fire_early(true, &ambiguous));
+        let clear = vec![
+            candidate("ouvre le terminal", 0.93),
+            candidate("ouvre le navigateur", 0.7),
+        ];
+        assert!(may_fire_early(false, &clear));
+        assert!(!may_fire_early(false, &[candidate("ferme la fenêtre", 0.6)]));
+    }
+
+    // Unversioned files are read as the current format, and newer ones are refused.
+    #[test]
+    fn test_config_migration() {
+        let mut value = serde_json::json!({ "commands": [] });
+        config::migrate(&mut value).unwrap();
+        assert_eq!(value["version"], config::CONFIG_VERSION);
+        let config: config::Config = serde_json::from_value(value).unwrap();
+        assert_eq!(config.version, config::CONFIG_VERSION);
+
+        let mut newer = serde_json::json!({ "version": config::CONFIG_VERSION + 1 });
+        let error = config::migrate(&mut newer).unwrap_err();
+        assert!(error.contains("please update"), "{}", error);
+        assert!(config::migrate(&mut serde_json::json!({ "version": "1" })).is_err());
+    }
+
+    // Whisper runs on the GPU by default when built with a backend, with its own
+    // thread count taking over the performance one.
+    #[test]
+    fn test_whisper_settings() {
+        let config: config::Config = serde_json::from_str("{}").unwrap();
+        assert!(config.whisper.use_gpu);
+        assert_eq!(config.whisper.gpu_device, 0);
+        assert_eq!(config.whisper.n_threads, None);
+        assert_eq!(config.whisper.pooled_states, 2);
+        let config: config::Config = serde_json::from_str(
+            r#"{ "whisper": { "use_gpu": false, "gpu_device": 1, "n_threads": 6 } }"#,
+        )
+        .unwrap();
+        assert!(!config.whisper.use_gpu);
+        assert_eq!(config.whisper.gpu_device, 1);
+        assert_eq!(config.whisper.n_threads, Some(6));
+        assert_eq!(
+            whisper_integration::gpu_backend().is_some(),
+            cfg!(any(feature = "cuda", feature = "metal", feature = "vulkan"))
+        );
+    }
+
+    // The config schema describes every section, with commands accepting one
+    // trigger or a list of them under `trigger`, the alias being documented.
+    #[test]
+    fn test_config_schema() {
+        let schema = config::json_schema();
+        for key in ["version", "commands", "profiles", "input", "wake", "music", "messaging"] {
+            assert!(schema["properties"][key].is_object(), "missing {}", key);
+        }
+        let command = &schema["definitions"]["Command"];
+        let trigger = &command["properties"]["trigger"];
+        assert!(trigger["description"].as_str().unwrap().contains("`triggers`"));
+        assert!(command["properties"]["triggers"].is_null());
+        let text = serde_json::to_string(&schema).unwrap();
+        assert!(text.contains("OneOrMany"));
+    }
+
+    // Commands inherit the settings of their group unless they set them, and a
+    // whole group can be disabled.
+    #[test]
+    fn test_command_groups() {
+        use VoxAurora::{groups, window::FocusedWindow};
+
+        let mut config: config::Config = serde_json::from_str(
+            r#"{
+                "groups": { "media": { "threshold": 0.85, "confirm": true, "context": ["vlc"] } },
+                "commands": [
+                    { "trigger": "morceau suivant", "action": "music:next", "group": "media" },
+                    { "trigger": "pause", "action": "music:pause", "group": "media", "confirm": false },
+                    { "trigger": "ouvre le terminal", "action": "app:kitty" }
+                ]
+            }"#,
+        )
+        .unwrap();
+        groups::inherit(&mut config.commands, &config.groups);
+        let [next, pause, terminal] = &config.commands[..] else {
+            panic!("expected three commands");
+        };
+        assert_eq!(next.threshold(), 0.85);
+        assert!(next.needs_confirmation());
+        assert!(!pause.needs_confirmation());
+        assert_eq!(terminal.threshold(), bert::MATCH_THRESHOLD);
+
+        let vlc = FocusedWindow {
+            app: "VLC".to_string(),
+            title: String::new(),
+        };
+        let firefox = FocusedWindow {
+            app: "firefox".to_string(),
+            title: String::new(),
+        };
+        assert!(groups::in_context(next, Some(&vlc)));
+        assert!(!groups::in_context(next, Some(&firefox)));
+        assert!(groups::in_context(next, None));
+        assert!(groups::in_context(terminal, Some(&firefox)));
+
+        let mut state = CommandState::default();
+        state.set_group("media", false);
+        assert!(!state.is_enabled(next) && !state.is_enabled(pause));
+        assert!(state.is_enabled(terminal));
+    }
+
+    // Secrets encrypted with --encrypt-secrets are read back by the file provider.
+    #[test]
+    fn test_encrypt_secrets_round_trip() {
+        use VoxAurora::secrets::{self, EncryptedFileProvider, SecretProvider, SecretsSettings};
+
+        let dir = std::env::temp_dir();
+        let plain = dir.join("voxaurora_test_secrets.json");
+        let encrypted = dir.join("voxaurora_test_secrets.bin");
+        std::fs::write(&plain, r#"{ "nas_key": "hunter2" }"#).unwrap();
+        let key_env = "VOXAURORA_TEST_SECRETS_KEY";
+        // Only this test reads the variable
+        unsafe { std::env::set_var(key_env, "42".repeat(32)) };
+        let settings = SecretsSettings {
+            encrypted_file: Some(encrypted.display().to_string()),
+            key_env: key_env.to_string(),
+            ..SecretsSettings::default()
+        };
+        let written = secrets::encrypt_file(plain.to_str().unwrap(), &settings).unwrap();
+
+        let provider = EncryptedFileProvider {
+            path: written,
+            key_env: key_env.to_string(),
+        };
+        assert_eq!(provider.get("nas_key").unwrap().as_deref(), Some("hunter2"));
+        assert_eq!(provider.get("other").unwrap(), None);
+
+        let args = ["--encrypt-secrets", "secrets.json", "config.json"];
+        let cli = cli::parse(args.map(String::from)).unwrap();
+        assert!(matches!(cli.mode, cli::Mode::EncryptSecrets(ref path) if path == "secrets.json"));
+        assert_eq!(cli.positional, vec!["config.json".to_string()]);
+    }
+
+    // `cmd:` actions read secrets from variables, so a value full of shell syntax is
+    // neither run nor visible on the command line.
+    #[test]
+    fn test_cmd_secrets_exported() {
+        use VoxAurora::secrets::{self, ProviderKind, SecretsSettings};
+
+        let value = "a b'; echo pwned";
+        // Only this test reads the variable
+        unsafe { std::env::set_var("VOXAURORA_SECRET_VOX_TEST_TOKEN", value) };
+        let settings = SecretsSettings {
+            providers: vec![ProviderKind::Env],
+            ..SecretsSettings::default()
+        };
+        let (script, env) =
+            secrets::export_secrets("echo \"{{secret:vox-test.token}}\"", &settings).unwrap();
+        let reference = VoxAurora::platform::env_reference("VOXAURORA_SECRET_VOX_TEST_TOKEN");
+        assert_eq!(script, format!("echo \"{}\"", reference));
+        assert_eq!(env["VOXAURORA_SECRET_VOX_TEST_TOKEN"], value);
+        assert!(secrets::export_secrets("{{secret:missing}}", &settings).is_err());
+        assert_eq!(
+            secrets::resolve_secrets("{{secret:vox-test.token}}", &settings).unwrap(),
+            value
+        );
+
+        #[cfg(unix)]
+        {
+            let ctx = VoxAurora::actions::ActionContext {
+                secrets: settings,
+                ..Default::default()
+            };
+            let check = r#"cmd:test "{{secret:vox-test.token}}" = "a b'; echo pwned""#;
+            assert!(VoxAurora::actions::execute_action(check, &ctx).is_ok());
+        }
+    }
+
+    // The control server only toggles commands, profiles, groups and kinds the config
+    // knows, so a typo answers 404 instead of being saved.
+    #[test]
+    fn test_control_toggle_targets() {
+        use VoxAurora::control::toggle_target_known;
+
+        let config: config::Config = serde_json::from_str(
+            r#"{"commands": [{"trigger": "lance chrome", "action": "app:chrome", "profile": "web",
+                              "group": "navigation"}],
+                "profiles": {"salon": {}}}"#,
+        )
+        .unwrap();
+        assert_eq!(toggle_target_known(&config, "commands", "lance chrome"), Some(true));
+        assert_eq!(toggle_target_known(&config, "commands", "lance chrom"), Some(false));
+        assert_eq!(toggle_target_known(&config, "profiles", "salon"), Some(true));
+        assert_eq!(toggle_target_known(&config, "profiles", "web"), Some(true));
+        assert_eq!(toggle_target_known(&config, "profiles", "bureau"), Some(false));
+        assert_eq!(toggle_target_known(&config, "groups", "navigation"), Some(true));
+        assert_eq!(toggle_target_known(&config, "groups", "media"), Some(false));
+        assert_eq!(toggle_target_known(&config, "kinds", "ssh"), Some(true));
+        assert_eq!(toggle_target_known(&config, "kinds", "shel"), Some(false));
+        assert_eq!(toggle_target_known(&config, "output", "clipboard"), None);
+    }
 }
@@ -0,0 +1,119 @@
+use crate::command_state::CommandState;
+use crate::config::Config;
+use crate::explain;
+use std::error::Error;
+
+/// Written after `=>` when an utterance must not match any command.
+const NO_MATCH: &str = "none";
+
+/// One line of a match test file: `ouvre le navigateur => lance chrome`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchCase {
+    pub line: usize,
+    pub utterance: String,
+    /// Any trigger of the expected command, or `None` for "typed as dictation".
+    pub expected: Option<String>,
+}
+
+/// Outcome of one case.
+#[derive(Debug)]
+pub struct CaseResult {
+    pub case: MatchCase,
+    /// Primary trigger of the command that matched.
+    pub actual: Option<String>,
+    /// Score of the winner, or of the best candidate when nothing matched.
+    pub score: Option<f32>,
+    pub passed: bool,
+    /// Set when the case couldn't be checked, e.g. an unknown expected command.
+    pub error: Option<String>,
+}
+
+/// Parses `utterance => expected trigger` lines; blank lines and `#` comments are skipped.
+pub fn parse_cases(content: &str) -> Result<Vec<MatchCase>, String> {
+    let mut cases = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (utterance, expected) = line
+            .split_once("=>")
+            .ok_or_else(|| format!("line {}: expected 'utterance => command'", index + 1))?;
+        let expected = expected.trim();
+        cases.push(MatchCase {
+            line: index + 1,
+            utterance: utterance.trim().to_string(),
+            expected: (!expected.eq_ignore_ascii_case(NO_MATCH)).then(|| expected.to_string()),
+        });
+    }
+    Ok(cases)
+}
+
+/// Matches every case against `config` with all commands enabled, so results don't
+/// depend on runtime toggles.
+pub fn run(
+    cases: &[MatchCase],
+    config: &Config,
+) -> Result<Vec<CaseResult>, Box<dyn Error + Send + Sync>> {
+    let state = CommandState::default();
+    let mut results = Vec::with_capacity(cases.len());
+    for case in cases {
+        // Synonyms name their command too; results compare primary triggers
+        let expected = match &case.expected {
+            Some(expected) => match config.commands.iter().find(|command| {
+                command
+                    .triggers
+                    .iter()
+                    .any(|trigger| trigger.eq_ignore_ascii_case(expected))
+            }) {
+                Some(command) => Some(command.trigger().to_string()),
+                None => {
+                    results.push(CaseResult {
+                        case: case.clone(),
+                        actual: None,
+                        score: None,
+                        passed: false,
+                        error: Some(format!("no command has the trigger '{}'", expected)),
+                    });
+                    continue;
+                }
+            },
+            None => None,
+        };
+
+        let explanation = explain::explain(&case.utterance, config, &state, 1)?;
+        results.push(CaseResult {
+            case: case.clone(),
+            passed: explanation.winner == expected,
+            actual: explanation.winner,
+            score: explanation.candidates.first().map(|candidate| candidate.score),
+            error: None,
+        });
+    }
+    Ok(results)
+}
+
+/// One report line per case.
+pub fn render(result: &CaseResult) -> String {
+    let mark = if result.passed { "✅" } else { "❌" };
+    let expected = result.case.expected.as_deref().unwrap_or(NO_MATCH);
+    if let Some(error) = &result.error {
+        return format!(
+            "{} line {}: '{}': {}",
+            mark, result.case.line, result.case.utterance, error
+        );
+    }
+    let actual = result.actual.as_deref().unwrap_or(NO_MATCH);
+    let score = result
+        .score
+        .map(|score| format!("{:.3}", score))
+        .unwrap_or_else(|| "-".to_string());
+    if result.passed {
+        format!("{} '{}' => {} ({})", mark, result.case.utterance, actual, score)
+    } else {
+        format!(
+            "{} line {}: '{}' => {} ({}), expected {}",
+            mark, result.case.line, result.case.utterance, actual, score, expected
+        )
+    }
+}
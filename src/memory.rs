@@ -0,0 +1,88 @@
+use once_cell::sync::OnceCell;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+/// Memory budget settings, read from the `memory` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct MemorySettings {
+    /// Quantized Whisper variant to prefer (e.g. "q5_0", "q8_0").
+    /// `ggml-small.bin` becomes `ggml-small-q5_0.bin` if that file exists.
+    pub whisper_quantization: Option<String>,
+    /// Keep the raw dictionary word lists in RAM after the DAWGs are built.
+    /// Disabling this saves memory but turns off fuzzy dictionary lookups.
+    pub keep_word_lists: bool,
+    /// Defer dictionary loading until the first text cleanup instead of at startup.
+    pub lazy_dictionaries: bool,
+}
+
+impl Default for MemorySettings {
+    fn default() -> Self {
+        MemorySettings {
+            whisper_quantization: None,
+            keep_word_lists: true,
+            lazy_dictionaries: false,
+        }
+    }
+}
+
+/// Process-wide memory settings, set once after the config is loaded.
+static MEMORY_SETTINGS: OnceCell<MemorySettings> = OnceCell::new();
+
+/// Installs the memory settings. Must be called before the dictionaries are first used.
+pub fn init(settings: MemorySettings) {
+    if MEMORY_SETTINGS.set(settings).is_err() {
+        log::warn!("Memory settings were already initialized, ignoring new values");
+    }
+}
+
+/// Returns the active memory settings, or the defaults if `init` was never called.
+pub fn settings() -> &'static MemorySettings {
+    MEMORY_SETTINGS.get_or_init(MemorySettings::default)
+}
+
+/// Picks the quantized variant of the Whisper model if one is configured and present on disk.
+pub fn select_model_variant(model_path: &str, settings: &MemorySettings) -> String {
+    let Some(quantization) = settings.whisper_quantization.as_deref() else {
+        return model_path.to_string();
+    };
+
+    let path = Path::new(model_path);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    let extension = path.extension().and_then(|s| s.to_str()).unwrap_or("bin");
+    let candidate = path.with_file_name(format!("{}-{}.{}", stem, quantization, extension));
+
+    if candidate.exists() {
+        log::info!("Using quantized Whisper model: {}", candidate.display());
+        candidate.to_string_lossy().into_owned()
+    } else {
+        log::warn!(
+            "Quantized model {} not found, falling back to {}",
+            candidate.display(),
+            model_path
+        );
+        model_path.to_string()
+    }
+}
+
+/// Returns the resident set size of the current process in bytes, when available.
+pub fn resident_set_size() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+    let kb = line.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    Some(kb * 1024)
+}
+
+/// Logs the current memory usage, tagged with the startup stage that just completed.
+pub fn report_usage(stage: &str) {
+    match resident_set_size() {
+        Some(bytes) => log::info!(
+            "🧮 Memory usage after {}: {:.1} MiB",
+            stage,
+            bytes as f64 / (1024.0 * 1024.0)
+        ),
+        None => log::info!("🧮 Memory usage after {}: unavailable on this platform", stage),
+    }
+}
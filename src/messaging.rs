@@ -0,0 +1,248 @@
+use crate::browser;
+use crate::builtins::normalize;
+use crate::dialog::{Dialog, Turn};
+use crate::platform;
+use crate::privacy;
+use crate::secrets::{self, SecretsSettings};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{SmtpTransport, Transport};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use strsim::normalized_levenshtein;
+
+/// Minimum similarity between a spoken name and a contact name.
+const CONTACT_SIMILARITY_THRESHOLD: f64 = 0.6;
+
+/// Answers confirming a message.
+const CONFIRM_PHRASES: &[&str] = &["oui", "envoie", "envoie-le", "c'est bon", "yes", "send"];
+
+/// Answers rejecting a message.
+const REJECT_PHRASES: &[&str] = &["non", "no"];
+
+/// How confirmed messages are delivered.
+#[derive(Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MessagingBackend {
+    /// Opens a `mailto:` link in the default mail client, which sends it.
+    #[default]
+    Mailto,
+    /// Sends an email directly.
+    Smtp,
+    /// Runs `script` with `VOXAURORA_RECIPIENT` and `VOXAURORA_MESSAGE` set, e.g. to send an SMS.
+    Script,
+}
+
+/// SMTP server settings of the `smtp` backend.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct SmtpSettings {
+    pub server: String,
+    pub port: u16,
+    pub username: Option<String>,
+    /// May contain `{{secret:name}}` placeholders.
+    pub password: Option<String>,
+    pub from: String,
+}
+
+impl Default for SmtpSettings {
+    fn default() -> Self {
+        SmtpSettings {
+            server: String::new(),
+            port: 465,
+            username: None,
+            password: None,
+            from: String::new(),
+        }
+    }
+}
+
+/// Settings of the "send a message" built-in, read from the `messaging` section.
+#[derive(Deserialize, JsonSchema, Clone, Debug, Default)]
+#[serde(default)]
+pub struct MessagingSettings {
+    pub backend: MessagingBackend,
+    /// Spoken names and their address or phone number ("marie" -> "marie@example.com").
+    pub contacts: HashMap<String, String>,
+    pub smtp: SmtpSettings,
+    pub script: Option<String>,
+    pub subject: Option<String>,
+}
+
+/// A contact the message goes to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recipient {
+    pub name: String,
+    pub address: String,
+}
+
+/// Finds the recipient for a spoken name: the closest contact, or the text itself
+/// when it already is an address or phone number.
+pub fn resolve_recipient(spoken: &str, contacts: &HashMap<String, String>) -> Option<Recipient> {
+    let spoken = spoken.trim();
+    let looks_like_address = spoken.contains('@')
+        || spoken
+            .chars()
+            .all(|c| c.is_ascii_digit() || c == '+' || c == ' ');
+    if looks_like_address && !spoken.is_empty() {
+        return Some(Recipient {
+            name: spoken.to_string(),
+            address: spoken.replace(' ', ""),
+        });
+    }
+
+    let spoken = normalize(spoken);
+    contacts
+        .iter()
+        .map(|(name, address)| {
+            let score = normalized_levenshtein(&spoken, &name.to_lowercase());
+            (name, address, score)
+        })
+        .filter(|(_, _, score)| *score >= CONTACT_SIMILARITY_THRESHOLD)
+        .max_by(|a, b| a.2.total_cmp(&b.2))
+        .map(|(name, address, _)| Recipient {
+            name: name.clone(),
+            address: address.clone(),
+        })
+}
+
+#[derive(Debug)]
+enum Step {
+    Recipient,
+    Body,
+    Confirm,
+}
+
+/// "envoie un message": asks for the recipient, then the message, reads it back and
+/// sends it once confirmed.
+pub struct MessageDialog {
+    settings: MessagingSettings,
+    secrets: SecretsSettings,
+    step: Step,
+    recipient: Option<Recipient>,
+    body: String,
+}
+
+impl MessageDialog {
+    /// Starts the dialog, skipping the recipient question when `recipient` was already spoken.
+    /// Returns the dialog and its first question.
+    pub fn new(
+        settings: &MessagingSettings,
+        secrets: &SecretsSettings,
+        recipient: Option<&str>,
+    ) -> (Self, String) {
+        let mut dialog = MessageDialog {
+            settings: settings.clone(),
+            secrets: secrets.clone(),
+            step: Step::Recipient,
+            recipient: None,
+            body: String::new(),
+        };
+        let question = match recipient {
+            Some(spoken) => match dialog.answer(spoken) {
+                Turn::Continue(question) | Turn::Done(question) => question,
+            },
+            None => "Who should I send it to?".to_string(),
+        };
+        (dialog, question)
+    }
+}
+
+impl Dialog for MessageDialog {
+    fn answer(&mut self, text: &str) -> Turn {
+        let normalized = normalize(text);
+        match self.step {
+            Step::Recipient => match resolve_recipient(text, &self.settings.contacts) {
+                Some(recipient) => {
+                    let question = format!("What should I tell {}?", recipient.name);
+                    self.recipient = Some(recipient);
+                    self.step = Step::Body;
+                    Turn::Continue(question)
+                }
+                None => Turn::Continue(format!(
+                    "I don't know {}. Who should I send it to?",
+                    normalized
+                )),
+            },
+            Step::Body => {
+                self.body = text.trim().to_string();
+                self.step = Step::Confirm;
+                let name = self.recipient.as_ref().map(|r| r.name.as_str()).unwrap_or("");
+                Turn::Continue(format!("Send \"{}\" to {}? Say yes or no", self.body, name))
+            }
+            Step::Confirm if CONFIRM_PHRASES.contains(&normalized.as_str()) => {
+                let Some(recipient) = &self.recipient else {
+                    return Turn::Done("No recipient, message dropped".to_string());
+                };
+                match send(recipient, &self.body, &self.settings, &self.secrets) {
+                    Ok(()) => Turn::Done(format!("Message sent to {}", recipient.name)),
+                    Err(e) => Turn::Done(format!("Couldn't send the message: {}", e)),
+                }
+            }
+            Step::Confirm if REJECT_PHRASES.contains(&normalized.as_str()) => {
+                Turn::Done("Message cancelled".to_string())
+            }
+            Step::Confirm => Turn::Continue("Say yes to send the message, or no".to_string()),
+        }
+    }
+}
+
+/// Delivers `body` to `recipient` with the configured backend.
+pub fn send(
+    recipient: &Recipient,
+    body: &str,
+    settings: &MessagingSettings,
+    secrets_settings: &SecretsSettings,
+) -> Result<(), Box<dyn Error>> {
+    privacy::check("Sending messages")?;
+    log::info!("✉️ Sending a message to {}", recipient.name);
+    let subject = settings.subject.as_deref().unwrap_or("");
+
+    match settings.backend {
+        MessagingBackend::Mailto => {
+            let url = format!(
+                "mailto:{}?subject={}&body={}",
+                recipient.address,
+                urlencoding::encode(subject),
+                urlencoding::encode(body)
+            );
+            browser::open_default(&url)
+        }
+        MessagingBackend::Smtp => {
+            let smtp = &settings.smtp;
+            let email = lettre::Message::builder()
+                .from(smtp.from.parse()?)
+                .to(recipient.address.parse()?)
+                .subject(subject)
+                .body(body.to_string())?;
+            let mut transport = SmtpTransport::relay(&smtp.server)?.port(smtp.port);
+            if let Some(username) = &smtp.username {
+                let password = match &smtp.password {
+                    Some(password) => secrets::resolve_secrets(password, secrets_settings)?,
+                    None => String::new(),
+                };
+                transport = transport.credentials(Credentials::new(username.clone(), password));
+            }
+            transport.build().send(&email)?;
+            Ok(())
+        }
+        MessagingBackend::Script => {
+            let script = settings
+                .script
+                .as_deref()
+                .ok_or("messaging.script is required for the script backend")?;
+            // Passed through the environment so the message is never parsed by the shell
+            let status = platform::shell(script)
+                .env("VOXAURORA_RECIPIENT", &recipient.address)
+                .env("VOXAURORA_RECIPIENT_NAME", &recipient.name)
+                .env("VOXAURORA_MESSAGE", body)
+                .status()?;
+            if status.success() {
+                Ok(())
+            } else {
+                Err(format!("Message script exited with status: {}", status).into())
+            }
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use crate::privacy;
+use crate::secrets::{self, SecretsSettings};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::error::Error;
+use std::process::Command;
+
+const SPOTIFY_API: &str = "https://api.spotify.com/v1";
+
+/// Which media controller `music:` actions drive.
+#[derive(Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MusicBackend {
+    /// Any MPRIS player, through `playerctl`.
+    #[default]
+    Mpris,
+    /// The Spotify Web API, with a user token.
+    Spotify,
+}
+
+/// Media control settings, read from the `music` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct MusicSettings {
+    pub backend: MusicBackend,
+    /// MPRIS player to control (e.g. "spotify"); the active one when unset.
+    pub player: Option<String>,
+    /// Spotify Web API token; may contain `{{secret:name}}` placeholders.
+    pub spotify_token: Option<String>,
+    /// Handle "joue <artiste / titre>" as a built-in search.
+    pub voice_search: bool,
+}
+
+impl Default for MusicSettings {
+    fn default() -> Self {
+        MusicSettings {
+            backend: MusicBackend::Mpris,
+            player: None,
+            spotify_token: None,
+            voice_search: true,
+        }
+    }
+}
+
+/// A playback command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MusicCommand {
+    Play,
+    Pause,
+    Toggle,
+    Next,
+    Previous,
+    /// Search for an artist or track and play the best result.
+    Search(String),
+}
+
+impl MusicCommand {
+    /// Parses the part of a `music:` action after the prefix ("next", "search daft punk").
+    pub fn parse(spec: &str) -> Result<Self, Box<dyn Error>> {
+        let spec = spec.trim();
+        let (verb, rest) = spec.split_once(' ').unwrap_or((spec, ""));
+        match verb {
+            "play" => Ok(MusicCommand::Play),
+            "pause" => Ok(MusicCommand::Pause),
+            "toggle" => Ok(MusicCommand::Toggle),
+            "next" => Ok(MusicCommand::Next),
+            "previous" => Ok(MusicCommand::Previous),
+            "search" if !rest.trim().is_empty() => {
+                Ok(MusicCommand::Search(rest.trim().to_string()))
+            }
+            _ => Err(format!("Unknown music command: '{}'", spec).into()),
+        }
+    }
+}
+
+/// Something that can control media playback.
+pub trait MediaController {
+    fn send(&self, command: &MusicCommand) -> Result<(), Box<dyn Error>>;
+}
+
+/// Controls MPRIS players with `playerctl`.
+pub struct Mpris {
+    pub player: Option<String>,
+}
+
+impl MediaController for Mpris {
+    fn send(&self, command: &MusicCommand) -> Result<(), Box<dyn Error>> {
+        let mut playerctl = Command::new("playerctl");
+        if let Some(player) = &self.player {
+            playerctl.arg(format!("--player={}", player));
+        }
+        match command {
+            MusicCommand::Play => playerctl.arg("play"),
+            MusicCommand::Pause => playerctl.arg("pause"),
+            MusicCommand::Toggle => playerctl.arg("play-pause"),
+            MusicCommand::Next => playerctl.arg("next"),
+            MusicCommand::Previous => playerctl.arg("previous"),
+            // MPRIS has no search; Spotify's desktop client opens search URIs
+            MusicCommand::Search(query) => playerctl
+                .arg("open")
+                .arg(format!("spotify:search:{}", urlencoding::encode(query))),
+        };
+
+        let status = playerctl.status()?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(format!("playerctl exited with status: {}", status).into())
+        }
+    }
+}
+
+/// Controls the user's active Spotify device through the Web API.
+pub struct Spotify {
+    pub token: String,
+}
+
+impl Spotify {
+    /// Finds the best artist or track for `query`, as a `(context_uri, track_uri)` pair.
+    fn search(&self, query: &str) -> Result<(Option<String>, Option<String>), Box<dyn Error>> {
+        let url = format!(
+            "{}/search?type=artist,track&limit=1&q={}",
+            SPOTIFY_API,
+            urlencoding::encode(query)
+        );
+        let results: Value = ureq::get(&url)
+            .header("Authorization", self.bearer())
+            .call()?
+            .body_mut()
+            .read_json()?;
+        let uri = |kind: &str| results[kind]["items"][0]["uri"].as_str().map(str::to_string);
+        Ok((uri("artists"), uri("tracks")))
+    }
+
+    fn bearer(&self) -> String {
+        format!("Bearer {}", self.token)
+    }
+}
+
+impl MediaController for Spotify {
+    fn send(&self, command: &MusicCommand) -> Result<(), Box<dyn Error>> {
+        let player = format!("{}/me/player", SPOTIFY_API);
+        match command {
+            MusicCommand::Play => {
+                ureq::put(format!("{}/play", player))
+                    .header("Authorization", self.bearer())
+                    .send_empty()?;
+            }
+            MusicCommand::Pause => {
+                ureq::put(format!("{}/pause", player))
+                    .header("Authorization", self.bearer())
+                    .send_empty()?;
+            }
+            MusicCommand::Toggle => {
+                let state: Value = ureq::get(&player)
+                    .header("Authorization", self.bearer())
+                    .call()?
+                    .body_mut()
+                    .read_json()
+                    .unwrap_or(Value::Null);
+                let next = if state["is_playing"].as_bool() == Some(true) {
+                    MusicCommand::Pause
+                } else {
+                    MusicCommand::Play
+                };
+                return self.send(&next);
+            }
+            MusicCommand::Next => {
+                ureq::post(format!("{}/next", player))
+                    .header("Authorization", self.bearer())
+                    .send_empty()?;
+            }
+            MusicCommand::Previous => {
+                ureq::post(format!("{}/previous", player))
+                    .header("Authorization", self.bearer())
+                    .send_empty()?;
+            }
+            MusicCommand::Search(query) => {
+                let body = match self.search(query)? {
+                    (Some(artist), _) => json!({ "context_uri": artist }),
+                    (None, Some(track)) => json!({ "uris": [track] }),
+                    (None, None) => return Err(format!("Nothing found for '{}'", query).into()),
+                };
+                ureq::put(format!("{}/play", player))
+                    .header("Authorization", self.bearer())
+                    .send_json(body)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Builds the configured media controller.
+pub fn controller(
+    settings: &MusicSettings,
+    secrets_settings: &SecretsSettings,
+) -> Result<Box<dyn MediaController>, Box<dyn Error>> {
+    match settings.backend {
+        MusicBackend::Mpris => Ok(Box::new(Mpris {
+            player: settings.player.clone(),
+        })),
+        MusicBackend::Spotify => {
+            privacy::check("The Spotify backend")?;
+            let token = settings
+                .spotify_token
+                .as_deref()
+                .ok_or("music.spotify_token is required for the Spotify backend")?;
+            Ok(Box::new(Spotify {
+                token: secrets::resolve_secrets(token, secrets_settings)?,
+            }))
+        }
+    }
+}
+
+/// Runs a `music:` action.
+pub fn execute_music(
+    spec: &str,
+    settings: &MusicSettings,
+    secrets_settings: &SecretsSettings,
+) -> Result<(), Box<dyn Error>> {
+    let command = MusicCommand::parse(spec)?;
+    log::info!("🎵 Music: {:?}", command);
+    controller(settings, secrets_settings)?.send(&command)
+}
@@ -0,0 +1,135 @@
+use crate::whisper_integration;
+use once_cell::sync::OnceCell;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tokio::runtime::{Builder, Handle, Runtime, RuntimeFlavor};
+use whisper_rs::FullParams;
+
+/// Tokio runtime running the pipeline.
+#[derive(Deserialize, JsonSchema, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RuntimeKind {
+    /// Everything on the main thread; Whisper blocks the other tasks while it runs.
+    #[default]
+    CurrentThread,
+    /// A worker pool: the `--serve` connections and commands keep running while
+    /// Whisper decodes, which pays off on multi-core machines.
+    MultiThread,
+}
+
+/// CPU usage settings, read from the `performance` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug, Default)]
+#[serde(default)]
+pub struct PerformanceSettings {
+    /// Threads used by Whisper for each transcription. Whisper's default when unset.
+    pub whisper_threads: Option<i32>,
+    /// Intra-op threads used by the BERT embeddings model. Torch's default when unset.
+    pub bert_threads: Option<i32>,
+    /// Process niceness, from -20 (highest priority) to 19 (lowest).
+    /// Values below 0 usually require elevated privileges.
+    pub niceness: Option<i32>,
+    pub runtime: RuntimeKind,
+    /// Worker threads of the `multi_thread` runtime. One per core when unset.
+    pub worker_threads: Option<usize>,
+    /// Threads running blocking stages (matching, actions, fallback transcription).
+    /// They share the one BERT model of the `bert` thread. Tokio's default of 512 when
+    /// unset.
+    pub max_blocking_threads: Option<usize>,
+}
+
+/// Process-wide performance settings, set once after the config is loaded.
+static PERFORMANCE_SETTINGS: OnceCell<PerformanceSettings> = OnceCell::new();
+
+/// Installs the performance settings and applies the process-wide ones.
+/// Must be called before the models are loaded.
+pub fn init(settings: PerformanceSettings) {
+    if let Some(threads) = settings.bert_threads {
+        tch::set_num_threads(threads);
+        log::info!("BERT limited to {} threads", threads);
+    }
+    if let Some(niceness) = settings.niceness {
+        set_niceness(niceness);
+    }
+    if PERFORMANCE_SETTINGS.set(settings).is_err() {
+        log::warn!("Performance settings were already initialized, ignoring new values");
+    }
+}
+
+/// Returns the active performance settings, or the defaults if `init` was never called.
+pub fn settings() -> &'static PerformanceSettings {
+    PERFORMANCE_SETTINGS.get_or_init(PerformanceSettings::default)
+}
+
+/// Builds the runtime of the pipeline.
+pub fn build_runtime(settings: &PerformanceSettings) -> std::io::Result<Runtime> {
+    let mut builder = match settings.runtime {
+        RuntimeKind::CurrentThread => Builder::new_current_thread(),
+        RuntimeKind::MultiThread => Builder::new_multi_thread(),
+    };
+    if let (RuntimeKind::MultiThread, Some(threads)) = (settings.runtime, settings.worker_threads) {
+        builder.worker_threads(threads.max(1));
+    }
+    if let Some(threads) = settings.max_blocking_threads {
+        builder.max_blocking_threads(threads.max(1));
+    }
+    log::info!("Using the {:?} runtime", settings.runtime);
+    builder.enable_all().build()
+}
+
+/// Runs a CPU-bound stage from async code. On the multi-threaded runtime the worker
+/// hands its other tasks over first, so they aren't stalled while `f` runs.
+pub fn run_blocking<R>(f: impl FnOnce() -> R) -> R {
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(f)
+        }
+        _ => f(),
+    }
+}
+
+/// Threads for Whisper calls taking an explicit count: the configured number, or
+/// Whisper's own default of up to 4.
+pub fn whisper_thread_count() -> usize {
+    match whisper_threads() {
+        Some(threads) => threads.max(1) as usize,
+        None => std::thread::available_parallelism()
+            .map(|threads| threads.get().min(4))
+            .unwrap_or(4),
+    }
+}
+
+/// Applies the configured thread count to a Whisper run.
+pub fn apply_whisper_threads(params: &mut FullParams) {
+    if let Some(threads) = whisper_threads() {
+        params.set_n_threads(threads);
+    }
+}
+
+/// `whisper.n_threads`, or else `performance.whisper_threads`.
+fn whisper_threads() -> Option<i32> {
+    whisper_integration::whisper_settings()
+        .n_threads
+        .or(settings().whisper_threads)
+}
+
+#[cfg(unix)]
+fn set_niceness(niceness: i32) {
+    let pid = std::process::id().to_string();
+    let status = std::process::Command::new("renice")
+        .args(["-n", &niceness.to_string(), "-p", &pid])
+        .stdout(std::process::Stdio::null())
+        .status();
+    match status {
+        Ok(status) if status.success() => log::info!("Process niceness set to {}", niceness),
+        Ok(status) => log::warn!("renice exited with {}, niceness unchanged", status),
+        Err(e) => log::warn!("Failed to run renice: {}", e),
+    }
+}
+
+#[cfg(not(unix))]
+fn set_niceness(niceness: i32) {
+    log::warn!(
+        "Setting niceness ({}) is only supported on Unix systems",
+        niceness
+    );
+}
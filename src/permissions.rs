@@ -0,0 +1,177 @@
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::error::Error;
+use std::process::Command;
+
+/// macOS privacy permissions, read from the `permissions` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct PermissionSettings {
+    /// Open the System Settings pane of every missing permission at startup.
+    pub open_settings: bool,
+}
+
+impl Default for PermissionSettings {
+    fn default() -> Self {
+        PermissionSettings {
+            open_settings: true,
+        }
+    }
+}
+
+/// A permission macOS grants per application.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    /// Without it, the input stream only delivers silence.
+    Microphone,
+    /// Without it, typed text and shortcuts are silently dropped.
+    Accessibility,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 2] = [Permission::Microphone, Permission::Accessibility];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Permission::Microphone => "microphone",
+            Permission::Accessibility => "accessibility",
+        }
+    }
+
+    /// What breaks without the permission.
+    pub fn needed_for(self) -> &'static str {
+        match self {
+            Permission::Microphone => "hearing anything (the input only delivers silence)",
+            Permission::Accessibility => "typing text and sending shortcuts",
+        }
+    }
+
+    /// The System Settings pane granting it.
+    pub fn settings_url(self) -> &'static str {
+        match self {
+            Permission::Microphone => {
+                "x-apple.systempreferences:com.apple.preference.security?Privacy_Microphone"
+            }
+            Permission::Accessibility => {
+                "x-apple.systempreferences:com.apple.preference.security?Privacy_Accessibility"
+            }
+        }
+    }
+}
+
+/// Whether a permission is granted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Grant {
+    Granted,
+    /// Never asked yet; macOS asks on first use.
+    NotDetermined,
+    Denied,
+    /// Only macOS asks for these permissions.
+    NotApplicable,
+}
+
+#[cfg(target_os = "macos")]
+mod macos {
+    use std::ffi::c_void;
+    use std::os::raw::{c_char, c_long};
+
+    #[link(name = "ApplicationServices", kind = "framework")]
+    unsafe extern "C" {
+        fn AXIsProcessTrusted() -> u8;
+    }
+
+    #[link(name = "AVFoundation", kind = "framework")]
+    unsafe extern "C" {
+        static AVMediaTypeAudio: *const c_void;
+    }
+
+    #[link(name = "objc")]
+    unsafe extern "C" {
+        fn objc_getClass(name: *const c_char) -> *const c_void;
+        fn sel_registerName(name: *const c_char) -> *const c_void;
+        fn objc_msgSend();
+    }
+
+    pub fn accessibility_trusted() -> bool {
+        unsafe { AXIsProcessTrusted() != 0 }
+    }
+
+    /// `[AVCaptureDevice authorizationStatusForMediaType:AVMediaTypeAudio]`: 0 when not
+    /// determined, 1 restricted, 2 denied and 3 authorized.
+    pub fn microphone_status() -> c_long {
+        type MsgSend =
+            unsafe extern "C" fn(*const c_void, *const c_void, *const c_void) -> c_long;
+        unsafe {
+            let class = objc_getClass(c"AVCaptureDevice".as_ptr());
+            if class.is_null() {
+                return 0;
+            }
+            let selector = sel_registerName(c"authorizationStatusForMediaType:".as_ptr());
+            // objc_msgSend must be called through the exact signature of the method
+            let send =
+                std::mem::transmute::<unsafe extern "C" fn(), MsgSend>(objc_msgSend);
+            send(class, selector, AVMediaTypeAudio)
+        }
+    }
+}
+
+/// Asks macOS whether `permission` is granted to this process.
+#[cfg(target_os = "macos")]
+pub fn check(permission: Permission) -> Grant {
+    match permission {
+        Permission::Microphone => match macos::microphone_status() {
+            3 => Grant::Granted,
+            0 => Grant::NotDetermined,
+            _ => Grant::Denied,
+        },
+        Permission::Accessibility if macos::accessibility_trusted() => Grant::Granted,
+        Permission::Accessibility => Grant::Denied,
+    }
+}
+
+/// Asks macOS whether `permission` is granted to this process.
+#[cfg(not(target_os = "macos"))]
+pub fn check(_permission: Permission) -> Grant {
+    Grant::NotApplicable
+}
+
+/// Opens the System Settings pane granting `permission`.
+pub fn open_settings(permission: Permission) -> Result<(), Box<dyn Error>> {
+    Command::new("open").arg(permission.settings_url()).status()?;
+    Ok(())
+}
+
+/// The advice logged for a permission that isn't granted, if any.
+pub fn advice(permission: Permission, grant: Grant) -> Option<String> {
+    match grant {
+        Grant::Denied => Some(format!(
+            "🔐 The {} permission is missing, which is needed for {}. Grant it to your \
+             terminal (or VoxAurora) in System Settings > Privacy & Security, then restart.",
+            permission.name(),
+            permission.needed_for()
+        )),
+        Grant::NotDetermined => Some(format!(
+            "🔐 macOS will ask for the {} permission, needed for {}: accept it.",
+            permission.name(),
+            permission.needed_for()
+        )),
+        Grant::Granted | Grant::NotApplicable => None,
+    }
+}
+
+/// Logs every missing permission at startup and, with `open_settings`, opens the pane
+/// granting it.
+pub fn check_at_startup(settings: &PermissionSettings) {
+    for permission in Permission::ALL {
+        let grant = check(permission);
+        let Some(advice) = advice(permission, grant) else {
+            continue;
+        };
+        log::warn!("{}", advice);
+        if grant == Grant::Denied && settings.open_settings {
+            if let Err(e) = open_settings(permission) {
+                log::warn!("Failed to open System Settings: {}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use crate::echo::{EchoCancellationSettings, EchoCanceller};
+use realfft::num_complex::Complex;
+use realfft::{ComplexToReal, RealFftPlanner, RealToComplex};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// Target length of a spectral subtraction frame, rounded up to a power of two.
+const FRAME_SECONDS: f32 = 0.02;
+
+/// How fast the noise estimate rises towards louder frames. It drops to quieter ones
+/// at once, so it follows the quietest level of each frequency and speech barely
+/// moves it.
+const NOISE_RISE: f32 = 0.01;
+
+/// Transforms captured audio before it is evaluated for speech.
+pub trait AudioStep: Send {
+    /// Processes a chunk of mono samples. The output may be shorter or longer than the
+    /// input when the step buffers audio.
+    fn process(&mut self, chunk: &[f32]) -> Vec<f32>;
+
+    /// Drops the buffered audio after a gap in capture; what was learned is kept.
+    fn reset(&mut self) {}
+}
+
+/// One processing step, as listed in `input.pipeline`.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum PipelineStep {
+    /// Subtracts the estimated noise spectrum, e.g. the steady hum of a laptop fan.
+    SpectralSubtraction(SpectralSubtractionSettings),
+    /// Removes what the microphone hears of the machine's own playback, captured from a
+    /// loopback device.
+    EchoCancellation(EchoCancellationSettings),
+}
+
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct SpectralSubtractionSettings {
+    /// How many times the noise estimate is subtracted; higher removes more noise, and
+    /// more of the voice with it.
+    pub reduction: f32,
+    /// Lowest gain of a frequency, so fully removed noise doesn't leave "musical" chirps.
+    pub floor: f32,
+}
+
+impl Default for SpectralSubtractionSettings {
+    fn default() -> Self {
+        SpectralSubtractionSettings {
+            reduction: 1.5,
+            floor: 0.1,
+        }
+    }
+}
+
+/// The steps captured chunks go through, in order, before speech detection and
+/// transcription.
+pub struct AudioPipeline {
+    steps: Vec<Box<dyn AudioStep>>,
+}
+
+impl AudioPipeline {
+    /// Builds the configured steps for audio captured at `sample_rate`.
+    pub fn new(steps: &[PipelineStep], sample_rate: u32) -> Self {
+        AudioPipeline {
+            steps: steps
+                .iter()
+                .map(|step| -> Box<dyn AudioStep> {
+                    match step {
+                        PipelineStep::SpectralSubtraction(settings) => {
+                            Box::new(SpectralSubtraction::new(settings, sample_rate))
+                        }
+                        PipelineStep::EchoCancellation(settings) => {
+                            Box::new(EchoCanceller::new(settings, sample_rate))
+                        }
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    pub fn process(&mut self, chunk: Vec<f32>) -> Vec<f32> {
+        self.steps
+            .iter_mut()
+            .fold(chunk, |chunk, step| step.process(&chunk))
+    }
+
+    pub fn reset(&mut self) {
+        for step in &mut self.steps {
+            step.reset();
+        }
+    }
+}
+
+/// Spectral subtraction over half-overlapping frames: each frequency is attenuated by
+/// how much of its magnitude the noise estimate accounts for. Output lags the input by
+/// half a frame.
+pub struct SpectralSubtraction {
+    reduction: f32,
+    floor: f32,
+    forward: Arc<dyn RealToComplex<f32>>,
+    inverse: Arc<dyn ComplexToReal<f32>>,
+    /// Square root of a Hann window, applied before and after the transform so the
+    /// overlapping frames add back up to the input.
+    window: Vec<f32>,
+    /// Magnitude of the noise per frequency, unknown until the first frame.
+    noise: Option<Vec<f32>>,
+    /// Samples waiting for a full frame.
+    pending: Vec<f32>,
+    /// Second half of the previous frame, added to the first half of the next one.
+    overlap: Vec<f32>,
+}
+
+impl SpectralSubtraction {
+    pub fn new(settings: &SpectralSubtractionSettings, sample_rate: u32) -> Self {
+        let frame_len = ((sample_rate as f32 * FRAME_SECONDS) as usize)
+            .next_power_of_two()
+            .max(64);
+        let mut planner = RealFftPlanner::<f32>::new();
+        let window = (0..frame_len)
+            .map(|i| {
+                let hann =
+                    0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / frame_len as f32).cos();
+                hann.sqrt()
+            })
+            .collect();
+        SpectralSubtraction {
+            reduction: settings.reduction,
+            floor: settings.floor.clamp(0.0, 1.0),
+            forward: planner.plan_fft_forward(frame_len),
+            inverse: planner.plan_fft_inverse(frame_len),
+            window,
+            noise: None,
+            pending: vec![0.0; frame_len / 2],
+            overlap: vec![0.0; frame_len / 2],
+        }
+    }
+
+    fn frame_len(&self) -> usize {
+        self.window.len()
+    }
+
+    /// Denoises the frame at the start of `pending` and returns its first half, completed
+    /// by the previous frame.
+    fn process_frame(&mut self) -> Vec<f32> {
+        let frame_len = self.frame_len();
+        let hop = frame_len / 2;
+        let mut frame: Vec<f32> = self.pending[..frame_len]
+            .iter()
+            .zip(&self.window)
+            .map(|(sample, weight)| sample * weight)
+            .collect();
+        let mut spectrum = self.forward.make_output_vec();
+        if self.forward.process(&mut frame, &mut spectrum).is_err() {
+            return self.pending[..hop].to_vec();
+        }
+
+        let magnitudes: Vec<f32> = spectrum.iter().map(|bin| bin.norm()).collect();
+        let noise = self.noise.get_or_insert_with(|| magnitudes.clone());
+        for ((bin, magnitude), noise) in spectrum.iter_mut().zip(&magnitudes).zip(noise) {
+            *noise = if *magnitude < *noise {
+                *magnitude
+            } else {
+                *noise + (magnitude - *noise) * NOISE_RISE
+            };
+            let gain = if *magnitude > 0.0 {
+                (1.0 - self.reduction * *noise / magnitude).max(self.floor)
+            } else {
+                self.floor
+            };
+            *bin *= gain;
+        }
+        // The inverse transform of a real signal has no imaginary part at 0 and Nyquist
+        if let Some(first) = spectrum.first_mut() {
+            *first = Complex::new(first.re, 0.0);
+        }
+        if let Some(last) = spectrum.last_mut() {
+            *last = Complex::new(last.re, 0.0);
+        }
+        if self.inverse.process(&mut spectrum, &mut frame).is_err() {
+            return self.pending[..hop].to_vec();
+        }
+
+        let scale = 1.0 / frame_len as f32;
+        let frame: Vec<f32> = frame
+            .iter()
+            .zip(&self.window)
+            .map(|(sample, weight)| sample * weight * scale)
+            .collect();
+        let output = self
+            .overlap
+            .iter()
+            .zip(&frame[..hop])
+            .map(|(previous, current)| previous + current)
+            .collect();
+        self.overlap.copy_from_slice(&frame[hop..]);
+        output
+    }
+}
+
+impl AudioStep for SpectralSubtraction {
+    fn process(&mut self, chunk: &[f32]) -> Vec<f32> {
+        self.pending.extend_from_slice(chunk);
+        let hop = self.frame_len() / 2;
+        let mut output = Vec::with_capacity(self.pending.len());
+        while self.pending.len() >= self.frame_len() {
+            output.extend(self.process_frame());
+            self.pending.drain(..hop);
+        }
+        output
+    }
+
+    fn reset(&mut self) {
+        let hop = self.frame_len() / 2;
+        self.pending = vec![0.0; hop];
+        self.overlap = vec![0.0; hop];
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+
+/// A command running `script` through the platform's shell: `cmd /C` on Windows,
+/// `sh -c` elsewhere.
+#[cfg(windows)]
+pub fn shell(script: &str) -> Command {
+    use std::os::windows::process::CommandExt;
+    let mut command = Command::new("cmd");
+    // cmd parses its command line itself, so the script must reach it unquoted
+    command.arg("/C").raw_arg(script);
+    command
+}
+
+/// A command running `script` through the platform's shell: `cmd /C` on Windows,
+/// `sh -c` elsewhere.
+#[cfg(not(windows))]
+pub fn shell(script: &str) -> Command {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(script);
+    command
+}
+
+/// How a `shell` script reads the variable `name`.
+#[cfg(windows)]
+pub fn env_reference(name: &str) -> String {
+    format!("%{}%", name)
+}
+
+/// How a `shell` script reads the variable `name`.
+#[cfg(not(windows))]
+pub fn env_reference(name: &str) -> String {
+    format!("${{{}}}", name)
+}
+
+/// Like `shell`, as `user` through `sudo -n`, which fails instead of asking for a
+/// password. `env` is passed explicitly, as sudo resets the environment; the variables
+/// named in `preserved` are kept from the caller's through `--preserve-env` instead, so
+/// their value stays off the command line.
+#[cfg(not(windows))]
+pub fn shell_as(
+    user: &str,
+    script: &str,
+    env: &BTreeMap<String, String>,
+    preserved: &[&str],
+) -> Result<Command, Box<dyn Error>> {
+    let mut command = Command::new("sudo");
+    command.args(["-n", "-u", user]);
+    if !preserved.is_empty() {
+        command.arg(format!("--preserve-env={}", preserved.join(",")));
+    }
+    command.args(["--", "env"]);
+    command.args(env.iter().map(|(name, value)| format!("{}={}", name, value)));
+    command.arg("sh").arg("-c").arg(script);
+    Ok(command)
+}
+
+#[cfg(windows)]
+pub fn shell_as(
+    _user: &str,
+    _script: &str,
+    _env: &BTreeMap<String, String>,
+    _preserved: &[&str],
+) -> Result<Command, Box<dyn Error>> {
+    Err("Running commands as another user isn't supported on Windows".into())
+}
+
+/// Makes `command` lead a process group of its own, so `kill_tree` also stops what it
+/// spawns, e.g. the server behind `sh -c "npm run dev"`.
+#[cfg(windows)]
+pub fn own_process_group(command: &mut Command) {
+    use std::os::windows::process::CommandExt;
+    const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+    command.creation_flags(CREATE_NEW_PROCESS_GROUP);
+}
+
+/// Makes `command` lead a process group of its own, so `kill_tree` also stops what it
+/// spawns, e.g. the server behind `sh -c "npm run dev"`.
+#[cfg(not(windows))]
+pub fn own_process_group(command: &mut Command) {
+    use std::os::unix::process::CommandExt;
+    command.process_group(0);
+}
+
+/// Stops `child` and its descendants, then reaps it.
+#[cfg(windows)]
+pub fn kill_tree(child: &mut Child) -> Result<(), Box<dyn Error>> {
+    let pid = child.id().to_string();
+    let killed = Command::new("taskkill")
+        .args(["/T", "/F", "/PID", &pid])
+        .status()
+        .is_ok_and(|status| status.success());
+    if !killed {
+        child.kill()?;
+    }
+    child.wait()?;
+    Ok(())
+}
+
+/// Stops `child` and its descendants, then reaps it.
+#[cfg(not(windows))]
+pub fn kill_tree(child: &mut Child) -> Result<(), Box<dyn Error>> {
+    // A negative pid targets the whole group `own_process_group` created
+    let group = format!("-{}", child.id());
+    let killed = Command::new("kill")
+        .args(["-TERM", "--", &group])
+        .status()
+        .is_ok_and(|status| status.success());
+    if !killed {
+        child.kill()?;
+    }
+    child.wait()?;
+    Ok(())
+}
+
+/// `path` with a leading `~` replaced by the home directory.
+pub fn expand_home(path: &str) -> PathBuf {
+    let home = env::var_os(if cfg!(windows) { "USERPROFILE" } else { "HOME" });
+    match (path.strip_prefix('~'), home) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with(['/', '\\']) => {
+            Path::new(&home).join(rest.trim_start_matches(['/', '\\']))
+        }
+        _ => PathBuf::from(path),
+    }
+}
+
+/// The `java` of `JAVA_HOME` when it has one, the one on the `PATH` otherwise.
+pub fn java() -> PathBuf {
+    let executable = if cfg!(windows) { "java.exe" } else { "java" };
+    env::var_os("JAVA_HOME")
+        .map(|home| Path::new(&home).join("bin").join(executable))
+        .filter(|java| java.is_file())
+        .unwrap_or_else(|| PathBuf::from(executable))
+}
+
+/// Joins classpath entries with the platform's separator, `;` on Windows and `:`
+/// elsewhere.
+pub fn classpath(entries: &[PathBuf]) -> Result<OsString, Box<dyn Error>> {
+    Ok(env::join_paths(entries)?)
+}
+
+/// Finds a file shipped with VoxAurora, given with `/` separators (e.g. `tools`): in the
+/// working directory, next to the executable, then in the source tree it was built
+/// from. Falls back to `relative` itself when it is nowhere.
+pub fn resource(relative: &str) -> PathBuf {
+    let relative: PathBuf = relative.split('/').filter(|part| !part.is_empty()).collect();
+    let mut roots = Vec::new();
+    if let Ok(dir) = env::current_dir() {
+        roots.push(dir);
+    }
+    if let Some(dir) = env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf)) {
+        roots.push(dir);
+    }
+    roots.push(PathBuf::from(env!("CARGO_MANIFEST_DIR")));
+    roots
+        .iter()
+        .map(|root| root.join(&relative))
+        .find(|path| path.exists())
+        .unwrap_or(relative)
+}
@@ -0,0 +1,80 @@
+use once_cell::sync::{Lazy, OnceCell};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Audio played by the assistant itself, read from the `playback` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct PlaybackSettings {
+    /// Ignores the microphones while the assistant speaks, so it can't wake or command
+    /// itself. `echo_cancellation` in `input.pipeline` lets the user talk over it instead,
+    /// but needs a loopback device.
+    pub half_duplex: bool,
+    /// Echo of the room still heard once playback ends, in milliseconds.
+    pub tail_ms: u64,
+}
+
+impl Default for PlaybackSettings {
+    fn default() -> Self {
+        PlaybackSettings {
+            half_duplex: true,
+            tail_ms: 300,
+        }
+    }
+}
+
+/// Process-wide playback settings, set once after the config is loaded.
+static PLAYBACK_SETTINGS: OnceCell<PlaybackSettings> = OnceCell::new();
+
+/// Playbacks going on.
+static PLAYING: AtomicUsize = AtomicUsize::new(0);
+
+/// When the last playback ended.
+static LAST_END: Lazy<Mutex<Option<Instant>>> = Lazy::new(|| Mutex::new(None));
+
+pub fn init(settings: PlaybackSettings) {
+    if PLAYBACK_SETTINGS.set(settings).is_err() {
+        log::warn!("Playback settings were already initialized, ignoring new values");
+    }
+}
+
+/// Returns the active playback settings, or the defaults if `init` was never called.
+pub fn settings() -> &'static PlaybackSettings {
+    PLAYBACK_SETTINGS.get_or_init(PlaybackSettings::default)
+}
+
+/// Held while the assistant plays something.
+pub struct Playback(());
+
+impl Drop for Playback {
+    fn drop(&mut self) {
+        *LAST_END.lock().unwrap_or_else(|e| e.into_inner()) = Some(Instant::now());
+        PLAYING.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Marks the start of a playback, until the returned guard is dropped.
+pub fn start() -> Playback {
+    PLAYING.fetch_add(1, Ordering::Relaxed);
+    Playback(())
+}
+
+/// Whether captured audio is ignored: with `half_duplex`, while the assistant plays
+/// something and for `tail_ms` after.
+pub fn mutes_input() -> bool {
+    let settings = settings();
+    if !settings.half_duplex {
+        return false;
+    }
+    if PLAYING.load(Ordering::Relaxed) > 0 {
+        return true;
+    }
+    let tail = Duration::from_millis(settings.tail_ms);
+    LAST_END
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .is_some_and(|end| end.elapsed() < tail)
+}
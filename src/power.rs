@@ -0,0 +1,54 @@
+use crate::language;
+use crate::performance;
+use crate::wakeword;
+use crate::whisper_integration;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::error::Error;
+use whisper_rs::{FullParams, SamplingStrategy, WhisperContext};
+
+/// Power saving settings, read from the `power` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug, Default)]
+#[serde(default)]
+pub struct PowerSettings {
+    /// Park the heavy models after this many seconds without activity. Disabled when unset.
+    pub idle_after_secs: Option<u64>,
+    /// Small Whisper model (e.g. "./models/ggml-tiny.bin") listening for the wake word
+    /// while parked. Without it, the main Whisper model stays loaded and only BERT is parked.
+    pub keyword_model: Option<String>,
+}
+
+/// Loads the keyword spotting model, if one is configured.
+pub fn load_keyword_model(settings: &PowerSettings) -> Option<WhisperContext> {
+    let path = settings.keyword_model.clone()?;
+    match whisper_integration::init_model(path.clone()) {
+        Ok(model) => Some(model),
+        Err(e) => {
+            log::error!("Failed to load keyword model {}: {}", path, e);
+            None
+        }
+    }
+}
+
+/// Listens for the wake word with a quick greedy pass and plain-text matching,
+/// so neither BERT nor LanguageTool are needed while parked.
+pub fn spot_wake_word(model: &WhisperContext, audio: &[f32]) -> Result<bool, Box<dyn Error>> {
+    let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
+    params.set_print_special(false);
+    params.set_print_progress(false);
+    params.set_print_realtime(false);
+    params.set_single_segment(true);
+    params.set_no_context(true);
+    params.set_language(Some(language::undetected_language()));
+    performance::apply_whisper_threads(&mut params);
+
+    let mut state = model.create_state()?;
+    state.full(params, audio)?;
+
+    let mut text = String::new();
+    for segment in 0..state.full_n_segments()? {
+        text.push_str(&state.full_get_segment_text(segment)?);
+    }
+    log::debug!("Keyword spotter heard: {}", text);
+    Ok(wakeword::matches_wake_text(&text))
+}
@@ -0,0 +1,92 @@
+use crate::config::Config;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Privacy mode, read from the `privacy` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug, Default)]
+#[serde(default)]
+pub struct PrivacySettings {
+    /// Nothing said is stored and nothing leaves the machine: history, wake logs, saved
+    /// session audio, downloads and remote backends are all turned off. Backends on
+    /// localhost (LanguageTool, a local LLM...) keep working.
+    pub enabled: bool,
+}
+
+/// Set once a config with privacy mode is loaded, for the checks outside the config.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Whether privacy mode is on.
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Fails with a clear message when privacy mode forbids `feature`.
+pub fn check(feature: &str) -> Result<(), String> {
+    if enabled() {
+        Err(format!("{} is disabled in privacy mode", feature))
+    } else {
+        Ok(())
+    }
+}
+
+/// Whether `address` (a URL or `host:port`) points to this machine.
+pub fn is_loopback(address: &str) -> bool {
+    let rest = address.split_once("://").map_or(address, |(_, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or("");
+    let authority = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    let host = match authority.strip_prefix('[') {
+        Some(bracketed) => bracketed.split(']').next().unwrap_or(""),
+        None => authority.split(':').next().unwrap_or(""),
+    };
+    host.eq_ignore_ascii_case("localhost")
+        || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+/// Turns off every setting of `config` that stores what is said or reaches another
+/// machine, and enables the runtime checks. Called by `load_config`, so every mode is
+/// covered. Returns what was turned off.
+pub fn enforce(config: &mut Config) -> Vec<&'static str> {
+    ENABLED.store(true, Ordering::Relaxed);
+
+    let mut disabled = Vec::new();
+    if config.history.enabled {
+        config.history.enabled = false;
+        disabled.push("history");
+    }
+    if config.wake.log_file.take().is_some() {
+        disabled.push("wake.log_file");
+    }
+    if config.wake.audio_dir.take().is_some() {
+        disabled.push("wake.audio_dir");
+    }
+    if config.recording.dir.take().is_some() {
+        disabled.push("recording.dir");
+    }
+    if config.captioning.save_audio.take().is_some() {
+        disabled.push("captioning.save_audio");
+    }
+    if config.stt_fallback.url.take_if(|url| !is_loopback(url)).is_some() {
+        disabled.push("stt_fallback.url");
+    }
+    if config.summary.url.take_if(|url| !is_loopback(url)).is_some() {
+        disabled.push("summary.url");
+    }
+    if config.telemetry.otlp_endpoint.take_if(|endpoint| !is_loopback(endpoint)).is_some() {
+        disabled.push("telemetry.otlp_endpoint");
+    }
+    if config.control.listen.take_if(|address| !is_loopback(address)).is_some() {
+        disabled.push("control.listen");
+    }
+
+    log::info!(
+        "🔒 Privacy mode: nothing is stored or sent off this machine{}",
+        if disabled.is_empty() {
+            String::new()
+        } else {
+            format!(" (turned off: {})", disabled.join(", "))
+        }
+    );
+    disabled
+}
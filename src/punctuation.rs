@@ -0,0 +1,125 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+/// Marks that already end a sentence.
+const SENTENCE_END: [char; 4] = ['.', '!', '?', '…'];
+
+/// "peux-tu", "va-t-il", "avez-vous": a verb-subject inversion, which makes a question.
+static INVERSION_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^\w+-(?:t-)?(?:je|tu|il|elle|on|nous|vous|ils|elles)(?:\s|$)").unwrap()
+});
+
+/// Rule-based sentence punctuation, read from the `cleanup.punctuation` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct PunctuationSettings {
+    /// Ends sentences Whisper left open and capitalizes them. Works without LanguageTool.
+    pub enabled: bool,
+    /// Uppercase the first letter of every sentence.
+    pub capitalize: bool,
+    /// Utterances with fewer words are left as they are, so short commands stay bare.
+    pub min_words: usize,
+    /// An utterance starting with one of these words gets a question mark.
+    pub question_words: Vec<String>,
+    /// French typography: a space before the question mark ("Ça va ?").
+    pub french_spacing: bool,
+}
+
+impl Default for PunctuationSettings {
+    fn default() -> Self {
+        PunctuationSettings {
+            enabled: false,
+            capitalize: true,
+            min_words: 2,
+            question_words: [
+                "qui", "que", "quoi", "quand", "où", "comment", "pourquoi", "combien", "quel",
+                "quelle", "quels", "quelles", "lequel", "laquelle", "qu'est-ce", "est-ce",
+                "what", "who", "when", "where", "why", "how", "which", "is", "are", "do", "does",
+                "can", "could", "would", "should", "will",
+            ]
+            .iter()
+            .map(|word| word.to_string())
+            .collect(),
+            french_spacing: true,
+        }
+    }
+}
+
+/// Capitalizes the sentences of `text` and ends it with a period or a question mark
+/// when it has no final punctuation.
+pub fn restore(text: &str, settings: &PunctuationSettings) -> String {
+    let text = text.trim();
+    if !settings.enabled || text.is_empty() {
+        return text.to_string();
+    }
+
+    let mut result = if settings.capitalize {
+        capitalize_sentences(text)
+    } else {
+        text.to_string()
+    };
+    // A dangling comma is Whisper cutting the sentence, not a pause worth keeping
+    let kept = result.trim_end_matches([',', ';', ' ']).len();
+    result.truncate(kept);
+
+    if !result.ends_with(SENTENCE_END) && result.split_whitespace().count() >= settings.min_words {
+        if is_question(&result, &settings.question_words) {
+            if settings.french_spacing {
+                result.push(' ');
+            }
+            result.push('?');
+        } else {
+            result.push('.');
+        }
+    }
+    result
+}
+
+/// Uppercases the first letter of the text and of every sentence following `.`, `!`,
+/// `?` or `…` and a space.
+fn capitalize_sentences(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut capitalize = true;
+    let mut after_end = false;
+    for c in text.chars() {
+        if capitalize && c.is_alphabetic() {
+            result.extend(c.to_uppercase());
+            capitalize = false;
+            continue;
+        }
+        if SENTENCE_END.contains(&c) {
+            after_end = true;
+        } else if c.is_whitespace() {
+            capitalize |= after_end;
+        } else {
+            // Quotes and dashes may open a sentence; anything else means it started
+            if !matches!(c, '"' | '«' | '“' | '-' | '—' | '(') {
+                capitalize = false;
+            }
+            after_end = false;
+        }
+        result.push(c);
+    }
+    result
+}
+
+/// Whether `text` reads as a question: a question word first, "est-ce que" or an
+/// inversion ("peux-tu").
+fn is_question(text: &str, question_words: &[String]) -> bool {
+    let lower = text.to_lowercase().replace('’', "'");
+    let first = lower
+        .split_whitespace()
+        .next()
+        .unwrap_or("")
+        .trim_matches(|c: char| !c.is_alphanumeric() && c != '\'' && c != '-');
+    // "what's", "who's": the part before the apostrophe counts too
+    let before_apostrophe = first.split('\'').next().unwrap_or(first);
+    question_words
+        .iter()
+        .any(|word| word == first || word == before_apostrophe)
+        || lower.contains("est-ce que")
+        || lower.contains("est-ce qu'")
+        || INVERSION_RE.is_match(&lower)
+}
@@ -0,0 +1,301 @@
+use crate::audio::AudioProcessor;
+use crate::codec::{self, Codec};
+use std::error::Error;
+use std::io;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot};
+
+/// Largest segment accepted from a client: 30 seconds of 16kHz audio.
+const MAX_SEGMENT_SAMPLES: usize = 16_000 * 30;
+
+/// Segments shorter than this are too short to hold speech and are not sent.
+const MIN_SEGMENT_SAMPLES: usize = 1000;
+
+/// Sent by clients that compress their segments, followed by the codec id. Raw sample
+/// counts never get this large, so older clients are still understood.
+const CODEC_HANDSHAKE: u32 = u32::from_le_bytes(*b"VXA1");
+
+/// Sent first by clients with a token, followed by its byte count and the token.
+const TOKEN_HANDSHAKE: u32 = u32::from_le_bytes(*b"VXAT");
+
+/// Longest token accepted, so a client can't make the server allocate at will.
+const MAX_TOKEN_BYTES: usize = 1024;
+
+/// Variable holding the token shared by `--serve` and its clients.
+pub const TOKEN_ENV: &str = "VOXAURORA_SERVE_TOKEN";
+
+/// The token shared by `--serve` and its clients, from `TOKEN_ENV`, if set.
+pub fn token() -> Option<String> {
+    std::env::var(TOKEN_ENV).ok().filter(|token| !token.is_empty())
+}
+
+/// A speech segment received from a client, with the channel its transcription is sent back on.
+/// Dropping `reply` answers the client with an empty transcription.
+pub struct RemoteSegment {
+    pub samples: Vec<f32>,
+    pub reply: oneshot::Sender<String>,
+}
+
+/// Accepts speech segments from `--client` instances.
+///
+/// Wire format, in both directions a little-endian `u32` length followed by the payload:
+/// - client to server: the sample count, then 16kHz mono `f32` samples
+/// - server to client: the byte count, then the UTF-8 transcription
+///
+/// A client may instead open with `CODEC_HANDSHAKE` and a codec id byte, and then send
+/// each segment as its encoded byte count followed by the encoded audio.
+///
+/// When the server has a token, clients must open with `TOKEN_HANDSHAKE`, the token's byte
+/// count and the token, before anything else; the others are disconnected.
+pub struct SegmentServer {
+    receiver: mpsc::Receiver<RemoteSegment>,
+}
+
+impl SegmentServer {
+    /// Starts accepting clients on `address` in the background, only those sending `token`
+    /// if set.
+    pub async fn bind(address: &str, token: Option<String>) -> Result<Self, Box<dyn Error>> {
+        let listener = TcpListener::bind(address).await?;
+        log::info!("📡 Accepting audio segments on {}", address);
+        let token: Option<Arc<str>> = token.map(Into::into);
+
+        let (sender, receiver) = mpsc::channel(16);
+        tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, peer)) => {
+                        log::info!("🔌 Audio client connected: {}", peer);
+                        let sender = sender.clone();
+                        let token = token.clone();
+                        tokio::spawn(async move {
+                            match serve_client(stream, sender, token.as_deref()).await {
+                                Ok(()) => log::info!("Audio client disconnected: {}", peer),
+                                Err(e) => log::warn!("Audio client {} failed: {}", peer, e),
+                            }
+                        });
+                    }
+                    Err(e) => log::error!("Failed to accept audio client: {}", e),
+                }
+            }
+        });
+
+        Ok(SegmentServer { receiver })
+    }
+
+    /// Waits for the next segment sent by any client.
+    pub async fn next_segment(&mut self) -> Result<RemoteSegment, Box<dyn Error>> {
+        self.receiver
+            .recv()
+            .await
+            .ok_or_else(|| "Segment server stopped".into())
+    }
+}
+
+/// Forwards every segment of one client to the pipeline and sends back its transcription.
+async fn serve_client(
+    mut stream: TcpStream,
+    sender: mpsc::Sender<RemoteSegment>,
+    token: Option<&str>,
+) -> io::Result<()> {
+    if let Some(token) = token {
+        authenticate(&mut stream, token).await?;
+    }
+
+    let mut compressed = false;
+    let mut first = true;
+    loop {
+        let len = match stream.read_u32_le().await {
+            Ok(len) => len,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        if std::mem::take(&mut first) && len == CODEC_HANDSHAKE {
+            let codec = Codec::from_id(stream.read_u8().await?)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Unknown codec"))?;
+            log::info!("Audio client sends {:?} segments", codec);
+            compressed = true;
+            continue;
+        }
+        let samples = if compressed {
+            read_encoded(&mut stream, len as usize).await?
+        } else {
+            read_samples(&mut stream, len as usize).await?
+        };
+
+        let (reply, transcription) = oneshot::channel();
+        if sender.send(RemoteSegment { samples, reply }).await.is_err() {
+            return Ok(());
+        }
+        let text = transcription.await.unwrap_or_default();
+        write_reply(&mut stream, &text).await?;
+    }
+}
+
+/// Fails unless the client opens with `token`.
+pub async fn authenticate<R: AsyncRead + Unpin>(reader: &mut R, token: &str) -> io::Result<()> {
+    let denied = |reason: &str| io::Error::new(io::ErrorKind::PermissionDenied, reason);
+    if reader.read_u32_le().await? != TOKEN_HANDSHAKE {
+        return Err(denied("No token sent"));
+    }
+    let len = reader.read_u32_le().await? as usize;
+    if len > MAX_TOKEN_BYTES {
+        return Err(denied("Wrong token"));
+    }
+    let mut sent = vec![0u8; len];
+    reader.read_exact(&mut sent).await?;
+    // Compares every byte, so the time taken doesn't tell how much of the token matched
+    let expected = token.as_bytes();
+    let differences = sent.iter().zip(expected).fold(0u8, |acc, (a, b)| acc | (a ^ b));
+    if sent.len() != expected.len() || differences != 0 {
+        return Err(denied("Wrong token"));
+    }
+    Ok(())
+}
+
+/// Captures and segments speech locally, sending each segment to the server at `address`,
+/// compressed with `codec` if set. Reconnects on the next segment whenever the connection is lost.
+pub async fn run_client(
+    address: &str,
+    processor: &mut AudioProcessor,
+    codec: Option<Codec>,
+    token: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
+    let mut connection: Option<TcpStream> = None;
+
+    loop {
+        // The server transcribes each segment on its own, continued or not
+        let segment = processor.get_next_speech_segment().await?.samples;
+        if segment.len() < MIN_SEGMENT_SAMPLES {
+            continue;
+        }
+
+        if connection.is_none() {
+            match connect(address, codec, token).await {
+                Ok(stream) => {
+                    log::info!("🔌 Connected to VoxAurora server at {}", address);
+                    connection = Some(stream);
+                }
+                Err(e) => log::warn!("Failed to connect to {}: {}", address, e),
+            }
+        }
+        let Some(stream) = connection.as_mut() else {
+            log::warn!("Server unavailable, dropping speech segment");
+            continue;
+        };
+
+        let encoded = match codec {
+            Some(codec) => match codec::encode(&segment, codec) {
+                Ok(bytes) => Some(bytes),
+                Err(e) => {
+                    log::error!("Failed to encode speech segment: {}", e);
+                    continue;
+                }
+            },
+            None => None,
+        };
+        let exchange = async {
+            match &encoded {
+                Some(bytes) => write_encoded(stream, bytes).await?,
+                None => write_segment(stream, &segment).await?,
+            }
+            read_reply(stream).await
+        };
+        match exchange.await {
+            Ok(text) if !text.is_empty() => log::info!("📝 Server transcription: {}", text),
+            Ok(_) => {}
+            Err(e) => {
+                log::warn!("Lost connection to {}: {}", address, e);
+                connection = None;
+            }
+        }
+    }
+}
+
+/// Connects to the server, sending `token` if set and announcing `codec` if segments are
+/// compressed.
+async fn connect(
+    address: &str,
+    codec: Option<Codec>,
+    token: Option<&str>,
+) -> io::Result<TcpStream> {
+    let mut stream = TcpStream::connect(address).await?;
+    if let Some(token) = token {
+        write_token(&mut stream, token).await?;
+    }
+    if let Some(codec) = codec {
+        stream.write_u32_le(CODEC_HANDSHAKE).await?;
+        stream.write_u8(codec.id()).await?;
+    }
+    Ok(stream)
+}
+
+pub async fn write_token<W: AsyncWrite + Unpin>(writer: &mut W, token: &str) -> io::Result<()> {
+    writer.write_u32_le(TOKEN_HANDSHAKE).await?;
+    writer.write_u32_le(token.len() as u32).await?;
+    writer.write_all(token.as_bytes()).await
+}
+
+pub async fn write_segment<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    samples: &[f32],
+) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(4 + samples.len() * 4);
+    frame.extend_from_slice(&(samples.len() as u32).to_le_bytes());
+    for sample in samples {
+        frame.extend_from_slice(&sample.to_le_bytes());
+    }
+    writer.write_all(&frame).await
+}
+
+pub async fn read_segment<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<Vec<f32>> {
+    let len = reader.read_u32_le().await? as usize;
+    read_samples(reader, len).await
+}
+
+async fn read_samples<R: AsyncRead + Unpin>(reader: &mut R, len: usize) -> io::Result<Vec<f32>> {
+    if len > MAX_SEGMENT_SAMPLES {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Segment of {} samples exceeds the limit", len),
+        ));
+    }
+    let mut bytes = vec![0u8; len * 4];
+    reader.read_exact(&mut bytes).await?;
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect())
+}
+
+pub async fn write_encoded<W: AsyncWrite + Unpin>(writer: &mut W, bytes: &[u8]) -> io::Result<()> {
+    writer.write_u32_le(bytes.len() as u32).await?;
+    writer.write_all(bytes).await
+}
+
+/// Reads `len` bytes of encoded audio and decodes them.
+async fn read_encoded<R: AsyncRead + Unpin>(reader: &mut R, len: usize) -> io::Result<Vec<f32>> {
+    // Even WAV, the largest encoding, takes 2 bytes per sample
+    if len > MAX_SEGMENT_SAMPLES * 2 + 44 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Encoded segment of {} bytes exceeds the limit", len),
+        ));
+    }
+    let mut bytes = vec![0u8; len];
+    reader.read_exact(&mut bytes).await?;
+    codec::decode(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+async fn write_reply<W: AsyncWrite + Unpin>(writer: &mut W, text: &str) -> io::Result<()> {
+    writer.write_u32_le(text.len() as u32).await?;
+    writer.write_all(text.as_bytes()).await
+}
+
+async fn read_reply<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<String> {
+    let len = reader.read_u32_le().await? as usize;
+    let mut bytes = vec![0u8; len];
+    reader.read_exact(&mut bytes).await?;
+    String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
@@ -0,0 +1,354 @@
+use crate::bert;
+use crate::codec::{self, Codec};
+use crate::command_state::CommandState;
+use crate::config::{Config, Handled};
+use crate::decoding;
+use crate::explain;
+use crate::whisper_integration;
+use chrono::Local;
+use once_cell::sync::OnceCell;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use whisper_rs::WhisperContext;
+
+/// Name of the file listing the segments of a recorded session.
+const MANIFEST: &str = "session.jsonl";
+
+/// Name of the file recording what the transcripts of a session were produced with.
+const VERSIONS: &str = "versions.json";
+
+/// Session recording, read from the `recording` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct RecordingSettings {
+    /// Every run records the utterances it transcribed, with what they matched, into a
+    /// new session folder here, for `--replay`. Disabled when unset.
+    pub dir: Option<String>,
+    /// Codec of the recorded segments. FLAC keeps them lossless.
+    pub codec: Codec,
+}
+
+impl Default for RecordingSettings {
+    fn default() -> Self {
+        RecordingSettings {
+            dir: None,
+            codec: Codec::Flac,
+        }
+    }
+}
+
+/// One line of `session.jsonl`: a transcribed utterance and what the pipeline did.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RecordedSegment {
+    /// Audio file, relative to the session folder.
+    pub audio: String,
+    pub transcript: String,
+    /// Primary trigger of the command that matched; `None` for dictation.
+    pub trigger: Option<String>,
+    pub score: Option<f32>,
+    /// Handled by a built-in command; `--replay` doesn't compare its match, as built-ins
+    /// have side effects.
+    #[serde(default)]
+    pub builtin: bool,
+}
+
+/// Everything a transcript depends on besides the audio and the configs, so a replay
+/// that differs can tell a regression from a model update.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ComponentVersions {
+    pub voxaurora: String,
+    /// File name, size and CRC-32 of the Whisper model.
+    pub whisper_model: String,
+    pub embeddings_model: String,
+    /// LanguageTool snapshot, when the cleanup uses it.
+    pub languagetool: Option<String>,
+    pub reproducible: bool,
+}
+
+impl ComponentVersions {
+    /// The components of this run, with the Whisper model at `model_path`.
+    pub fn current(model_path: &str) -> Self {
+        ComponentVersions {
+            voxaurora: env!("CARGO_PKG_VERSION").to_string(),
+            whisper_model: model_fingerprint(Path::new(model_path)),
+            embeddings_model: bert::MODEL_ID.to_string(),
+            languagetool: whisper_integration::languagetool_version(),
+            reproducible: decoding::reproducible(),
+        }
+    }
+
+    /// One line per component that isn't the same in `other`.
+    pub fn differences(&self, other: &ComponentVersions) -> Vec<String> {
+        let line = |name: &str, old: &str, new: &str| {
+            (old != new).then(|| format!("{}: {} => {}", name, old, new))
+        };
+        let languagetool =
+            |version: &Option<String>| version.as_deref().unwrap_or("off").to_string();
+        [
+            line("voxaurora", &self.voxaurora, &other.voxaurora),
+            line("whisper model", &self.whisper_model, &other.whisper_model),
+            line(
+                "embeddings model",
+                &self.embeddings_model,
+                &other.embeddings_model,
+            ),
+            line(
+                "languagetool",
+                &languagetool(&self.languagetool),
+                &languagetool(&other.languagetool),
+            ),
+            line(
+                "reproducible",
+                &self.reproducible.to_string(),
+                &other.reproducible.to_string(),
+            ),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+/// Name, size and CRC-32 of the file at `path`; only its name if it can't be read.
+fn model_fingerprint(path: &Path) -> String {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string());
+    let checksum = || -> std::io::Result<(u64, u32)> {
+        let mut file = fs::File::open(path)?;
+        let size = file.metadata()?.len();
+        let mut crc = flate2::Crc::new();
+        let mut buffer = vec![0; 1 << 20];
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            crc.update(&buffer[..read]);
+        }
+        Ok((size, crc.sum()))
+    };
+    match checksum() {
+        Ok((size, crc)) => format!("{} ({} bytes, crc32 {:08x})", name, size, crc),
+        Err(e) => format!("{} (unreadable: {})", name, e),
+    }
+}
+
+/// Appends the segments of the running session to its folder.
+pub struct SessionRecording {
+    dir: PathBuf,
+    codec: Codec,
+    count: usize,
+}
+
+impl SessionRecording {
+    /// Creates a new session folder, named after the current time, in `settings.dir`.
+    pub fn create(settings: &RecordingSettings) -> Result<Option<Self>, Box<dyn Error>> {
+        let Some(root) = &settings.dir else {
+            return Ok(None);
+        };
+        let dir = Path::new(root).join(Local::now().format("%Y-%m-%d_%H-%M-%S").to_string());
+        fs::create_dir_all(&dir)?;
+        Ok(Some(SessionRecording {
+            dir,
+            codec: settings.codec,
+            count: 0,
+        }))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Saves `audio` and adds its line to the manifest.
+    pub fn record(
+        &mut self,
+        audio: &[f32],
+        transcript: &str,
+        handled: &Handled,
+    ) -> Result<(), Box<dyn Error>> {
+        self.count += 1;
+        let file = format!("{:04}.{}", self.count, self.codec.extension());
+        fs::write(self.dir.join(&file), codec::encode(audio, self.codec)?)?;
+
+        let (trigger, score) = match handled {
+            Handled::Command { trigger, score } => (Some(trigger.clone()), Some(*score)),
+            Handled::Builtin | Handled::Dictation | Handled::TimedOut => (None, None),
+        };
+        let segment = RecordedSegment {
+            audio: file,
+            transcript: transcript.to_string(),
+            trigger,
+            score,
+            builtin: *handled == Handled::Builtin,
+        };
+        let mut manifest = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(MANIFEST))?;
+        writeln!(manifest, "{}", serde_json::to_string(&segment)?)?;
+        Ok(())
+    }
+}
+
+static RECORDER: OnceCell<Mutex<SessionRecording>> = OnceCell::new();
+
+/// Starts recording the session when `recording.dir` is set.
+pub fn init(settings: &RecordingSettings) -> Result<(), Box<dyn Error>> {
+    let Some(recorder) = SessionRecording::create(settings)? else {
+        return Ok(());
+    };
+    log::info!("⏺️ Recording this session into {}", recorder.dir().display());
+    if RECORDER.set(Mutex::new(recorder)).is_err() {
+        log::warn!("Session recording was already initialized");
+    }
+    Ok(())
+}
+
+/// Writes the components of this run next to the recorded segments, and logs them in
+/// reproducible mode. Called once the Whisper model at `model_path` is loaded.
+pub fn record_versions(model_path: &str) {
+    let recorder = RECORDER.get();
+    if recorder.is_none() && !decoding::reproducible() {
+        return;
+    }
+    let versions = ComponentVersions::current(model_path);
+    log::info!(
+        "🔁 VoxAurora {}, Whisper model {}, embeddings model {}, LanguageTool {}",
+        versions.voxaurora,
+        versions.whisper_model,
+        versions.embeddings_model,
+        versions.languagetool.as_deref().unwrap_or("off")
+    );
+    let Some(recorder) = recorder else {
+        return;
+    };
+    let dir = recorder.lock().unwrap_or_else(|e| e.into_inner()).dir().to_path_buf();
+    let written = serde_json::to_string_pretty(&versions)
+        .map_err(|e| e.to_string())
+        .and_then(|json| fs::write(dir.join(VERSIONS), json).map_err(|e| e.to_string()));
+    if let Err(e) = written {
+        log::error!("Failed to record component versions: {}", e);
+    }
+}
+
+/// The components a recorded session was produced with, if it recorded them.
+pub fn load_versions(dir: &Path) -> Option<ComponentVersions> {
+    let content = fs::read_to_string(dir.join(VERSIONS)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// Whether this session is recorded.
+pub fn enabled() -> bool {
+    RECORDER.get().is_some()
+}
+
+/// Records a transcribed utterance, when the session is recorded.
+pub fn record(audio: &[f32], transcript: &str, handled: &Handled) {
+    let Some(recorder) = RECORDER.get() else {
+        return;
+    };
+    let mut recorder = recorder.lock().unwrap_or_else(|e| e.into_inner());
+    if let Err(e) = recorder.record(audio, transcript, handled) {
+        log::error!("Failed to record segment: {}", e);
+    }
+}
+
+/// Reads the manifest of a recorded session.
+pub fn load(dir: &Path) -> Result<Vec<RecordedSegment>, Box<dyn Error>> {
+    let content = fs::read_to_string(dir.join(MANIFEST))
+        .map_err(|e| format!("{} has no readable {}: {}", dir.display(), MANIFEST, e))?;
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(index, line)| {
+            serde_json::from_str::<RecordedSegment>(line).map_err(|e| -> Box<dyn Error> {
+                format!("{} line {}: {}", MANIFEST, index + 1, e).into()
+            })
+        })
+        .collect()
+}
+
+/// A recorded segment next to what the current code makes of it.
+#[derive(Serialize, Debug, Clone)]
+pub struct ReplayedSegment {
+    pub recorded: RecordedSegment,
+    pub transcript: String,
+    pub trigger: Option<String>,
+    pub score: Option<f32>,
+}
+
+impl ReplayedSegment {
+    pub fn transcript_changed(&self) -> bool {
+        self.transcript != self.recorded.transcript
+    }
+
+    pub fn match_changed(&self) -> bool {
+        !self.recorded.builtin && self.trigger != self.recorded.trigger
+    }
+
+    pub fn changed(&self) -> bool {
+        self.transcript_changed() || self.match_changed()
+    }
+}
+
+/// Transcribes and matches every recorded segment of `dir` again, without running any
+/// action. Commands are matched with all of them enabled, like `--test-config`.
+pub fn replay(
+    dir: &Path,
+    model: &WhisperContext,
+    config: &Config,
+) -> Result<Vec<ReplayedSegment>, Box<dyn Error>> {
+    let state = CommandState::default();
+    let mut replayed = Vec::new();
+    for recorded in load(dir)? {
+        let audio = codec::decode(&fs::read(dir.join(&recorded.audio))?)?;
+        let transcription = whisper_integration::transcribe_sync(model, &audio, "fr", None)?;
+        let matched = transcription.text(whisper_integration::cleanup_settings().consumers.matcher);
+        let explanation =
+            explain::explain(matched, config, &state, 1).map_err(|e| e.to_string())?;
+        let score = explanation
+            .winner
+            .as_ref()
+            .and_then(|_| explanation.candidates.first())
+            .map(|candidate| candidate.score);
+        replayed.push(ReplayedSegment {
+            recorded,
+            transcript: transcription.corrected,
+            trigger: explanation.winner,
+            score,
+        });
+    }
+    Ok(replayed)
+}
+
+/// The report of one segment: a line when it is unchanged, the differences otherwise.
+pub fn render(segment: &ReplayedSegment) -> String {
+    let trigger = |trigger: &Option<String>| trigger.clone().unwrap_or_else(|| "none".into());
+    let audio = &segment.recorded.audio;
+    if !segment.changed() {
+        return format!("✅ {} '{}'", audio, segment.transcript);
+    }
+    let mut out = format!("❌ {}", audio);
+    if segment.transcript_changed() {
+        out.push_str(&format!(
+            "\n   - text: {}\n   + text: {}",
+            segment.recorded.transcript, segment.transcript
+        ));
+    }
+    if segment.match_changed() {
+        out.push_str(&format!(
+            "\n   - match: {}\n   + match: {}",
+            trigger(&segment.recorded.trigger),
+            trigger(&segment.trigger)
+        ));
+    }
+    out
+}
@@ -0,0 +1,148 @@
+use std::error::Error;
+use std::io::{self, BufRead, Write};
+use std::process::Command;
+use VoxAurora::history::History;
+use VoxAurora::wake_log::{self, Review, WakeDecision};
+use VoxAurora::{audio, codec, config};
+
+/// `--review-wakes`: walks through the logged wake decisions not reviewed yet so false
+/// wakes can be marked. They become negative phrases on the next start, and the final
+/// statistics suggest a similarity threshold.
+pub fn run(config_paths: Vec<String>) -> Result<(), Box<dyn Error>> {
+    let config = config::load_config(crate::cli::config_paths(config_paths))?;
+    // The history, when enabled, holds the decisions instead of the log file
+    let history = config
+        .history
+        .enabled
+        .then(|| History::open(&config.history.path))
+        .transpose()?;
+    let (path, ids, mut decisions) = match &history {
+        Some(history) => {
+            let (ids, decisions): (Vec<i64>, Vec<WakeDecision>) =
+                history.wake_decisions()?.into_iter().unzip();
+            (config.history.path.clone(), ids, decisions)
+        }
+        None => {
+            let path = config
+                .wake
+                .log_file
+                .clone()
+                .ok_or("wake.log_file is not set, so no wake decision was logged")?;
+            let decisions = wake_log::load(&path)?;
+            (path, Vec::new(), decisions)
+        }
+    };
+
+    let pending: Vec<usize> = (0..decisions.len())
+        .filter(|index| decisions[*index].review.is_none())
+        .collect();
+    println!("{} wake decisions in {}, {} to review", decisions.len(), path, pending.len());
+    println!("[y] real wake  [n] false wake  [p] play  [s] skip  [q] quit");
+
+    let mut lines = io::stdin().lock().lines();
+    let mut reviewed = Vec::new();
+    'review: for index in pending {
+        print_decision(&decisions[index]);
+        loop {
+            print!("> ");
+            io::stdout().flush()?;
+            let Some(line) = lines.next() else {
+                break 'review;
+            };
+            match line?.trim() {
+                "y" => {
+                    decisions[index].review = Some(Review::Correct);
+                    reviewed.push(index);
+                }
+                "n" => {
+                    decisions[index].review = Some(Review::FalseWake);
+                    reviewed.push(index);
+                }
+                "p" => {
+                    if let Err(e) = play(&decisions[index]) {
+                        println!("Can't play this decision: {}", e);
+                    }
+                    continue;
+                }
+                "s" | "" => {}
+                "q" => break 'review,
+                _ => {
+                    println!("[y] real wake  [n] false wake  [p] play  [s] skip  [q] quit");
+                    continue;
+                }
+            }
+            break;
+        }
+    }
+    match &history {
+        Some(history) => {
+            for index in reviewed {
+                history.update_wake(ids[index], &decisions[index])?;
+            }
+        }
+        None => wake_log::save(&path, &decisions)?,
+    }
+
+    let stats = wake_log::stats(&decisions);
+    println!(
+        "{} decisions, {} wakes, {} reviewed, {} false wakes",
+        stats.total, stats.woke, stats.reviewed, stats.false_wakes
+    );
+    if stats.false_wakes > 0 {
+        println!("False wake phrases are ignored from the next start on.");
+    }
+    if let Some(threshold) = stats.suggested_threshold {
+        println!(
+            "Suggested wake.similarity_threshold: {:.3} (currently {:.3})",
+            threshold, config.wake.similarity_threshold
+        );
+    }
+    Ok(())
+}
+
+fn print_decision(decision: &WakeDecision) {
+    let confidence = decision
+        .token_confidence
+        .map(|confidence| format!("{:.3}", confidence))
+        .unwrap_or_else(|| "-".to_string());
+    let verdict = if decision.woke {
+        "woke"
+    } else if decision.negative {
+        "rejected (negative phrase)"
+    } else {
+        "rejected"
+    };
+    println!();
+    println!("{}  {}", decision.timestamp, verdict);
+    println!(
+        "  '{}' ~ '{}' (similarity {:.3}, token confidence {})",
+        decision.text,
+        decision.variant.as_deref().unwrap_or("-"),
+        decision.similarity,
+        confidence
+    );
+}
+
+/// Plays the decision's audio through the system player, converted to WAV.
+fn play(decision: &WakeDecision) -> Result<(), Box<dyn Error>> {
+    let audio_path = decision.audio.as_ref().ok_or("no audio was saved (wake.audio_dir)")?;
+    let samples = codec::decode(&std::fs::read(audio_path)?)?;
+    let wav = std::env::temp_dir().join("voxaurora-wake-review.wav");
+    std::fs::write(&wav, audio::encode_wav(&samples))?;
+
+    let status = if cfg!(target_os = "macos") {
+        Command::new("afplay").arg(&wav).status()?
+    } else if cfg!(target_os = "windows") {
+        let script = format!(
+            "(New-Object Media.SoundPlayer '{}').PlaySync()",
+            wav.display()
+        );
+        Command::new("powershell").args(["-NoProfile", "-Command", &script]).status()?
+    } else {
+        Command::new("aplay").arg("-q").arg(&wav).status()?
+    };
+    if !status.success() {
+        return Err(format!("the player exited with {}", status).into());
+    }
+    Ok(())
+}
@@ -0,0 +1,31 @@
+use crate::actions::TextSink;
+use crate::window::{self, FocusedWindow};
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+/// Decides where dictated text goes while a given application has the focus.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+pub struct RoutingRule {
+    /// Matched case-insensitively against the focused app name or window title.
+    pub app: String,
+    pub sink: TextSink,
+}
+
+/// Returns the sink of the first rule matching `window`.
+pub fn route(rules: &[RoutingRule], window: &FocusedWindow) -> Option<TextSink> {
+    rules
+        .iter()
+        .find(|rule| window.matches(&rule.app))
+        .map(|rule| {
+            log::info!("🧭 '{}' has the focus, routing text to {:?}", window.app, rule.sink);
+            rule.sink
+        })
+}
+
+/// Looks up the focused window and applies the rules, if there are any.
+pub fn route_focused(rules: &[RoutingRule]) -> Option<TextSink> {
+    if rules.is_empty() {
+        return None;
+    }
+    route(rules, &window::focused_window()?)
+}
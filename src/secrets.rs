@@ -0,0 +1,242 @@
+use crate::platform;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs;
+
+static SECRET_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\{\{secret:([A-Za-z0-9_.\-]+)\}\}").unwrap());
+
+/// Where secrets can be looked up, tried in the configured order.
+#[derive(Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    /// `VOXAURORA_SECRET_<NAME>` environment variables.
+    Env,
+    /// The OS keyring (Keychain, Credential Manager, Secret Service).
+    Keyring,
+    /// A ChaCha20-Poly1305 encrypted JSON file.
+    File,
+}
+
+/// Secrets settings, read from the `secrets` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct SecretsSettings {
+    pub providers: Vec<ProviderKind>,
+    /// Keyring service name under which secrets are stored.
+    pub keyring_service: String,
+    /// Path to the encrypted secrets file.
+    pub encrypted_file: Option<String>,
+    /// Environment variable holding the hex-encoded 32-byte key of the encrypted file.
+    pub key_env: String,
+}
+
+impl Default for SecretsSettings {
+    fn default() -> Self {
+        SecretsSettings {
+            providers: vec![ProviderKind::Env, ProviderKind::Keyring, ProviderKind::File],
+            keyring_service: "voxaurora".to_string(),
+            encrypted_file: None,
+            key_env: "VOXAURORA_SECRETS_KEY".to_string(),
+        }
+    }
+}
+
+/// A source of named secrets.
+pub trait SecretProvider {
+    /// Returns the secret called `name`, or `None` if this provider doesn't know it.
+    fn get(&self, name: &str) -> Result<Option<String>, Box<dyn Error>>;
+}
+
+/// The `VOXAURORA_SECRET_<NAME>` variable holding secret `name`.
+pub fn env_name(name: &str) -> String {
+    format!("VOXAURORA_SECRET_{}", name.to_uppercase().replace(['-', '.'], "_"))
+}
+
+/// Reads secrets from `VOXAURORA_SECRET_<NAME>` environment variables.
+pub struct EnvProvider;
+
+impl SecretProvider for EnvProvider {
+    fn get(&self, name: &str) -> Result<Option<String>, Box<dyn Error>> {
+        Ok(std::env::var(env_name(name)).ok())
+    }
+}
+
+/// Reads secrets from the OS keyring.
+pub struct KeyringProvider {
+    pub service: String,
+}
+
+impl SecretProvider for KeyringProvider {
+    fn get(&self, name: &str) -> Result<Option<String>, Box<dyn Error>> {
+        let entry = keyring::Entry::new(&self.service, name)?;
+        match entry.get_password() {
+            Ok(password) => Ok(Some(password)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Reads secrets from a file containing a 12-byte nonce followed by the
+/// ChaCha20-Poly1305 encrypted JSON object `{ "name": "value", ... }`.
+pub struct EncryptedFileProvider {
+    pub path: String,
+    pub key_env: String,
+}
+
+impl EncryptedFileProvider {
+    fn key(&self) -> Result<Key, Box<dyn Error>> {
+        let hex = std::env::var(&self.key_env)
+            .map_err(|_| format!("Secrets key variable {} is not set", self.key_env))?;
+        let bytes = decode_hex(hex.trim())?;
+        if bytes.len() != 32 {
+            return Err("Secrets key must be 32 bytes (64 hex characters)".into());
+        }
+        Ok(Key::clone_from_slice(&bytes))
+    }
+}
+
+impl SecretProvider for EncryptedFileProvider {
+    fn get(&self, name: &str) -> Result<Option<String>, Box<dyn Error>> {
+        let data = fs::read(&self.path)?;
+        if data.len() < 12 {
+            return Err(format!("Secrets file {} is truncated", self.path).into());
+        }
+        let (nonce, ciphertext) = data.split_at(12);
+        let cipher = ChaCha20Poly1305::new(&self.key()?);
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| format!("Failed to decrypt secrets file {}", self.path))?;
+        let secrets: HashMap<String, String> = serde_json::from_slice(&plaintext)?;
+        Ok(secrets.get(name).cloned())
+    }
+}
+
+/// Encrypts `secrets` with the hex-encoded `key` into the format read by `EncryptedFileProvider`.
+pub fn encrypt_secrets(
+    secrets: &HashMap<String, String>,
+    key: &str,
+    nonce: [u8; 12],
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let key_bytes = decode_hex(key.trim())?;
+    if key_bytes.len() != 32 {
+        return Err("Secrets key must be 32 bytes (64 hex characters)".into());
+    }
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key_bytes));
+    let plaintext = serde_json::to_vec(secrets)?;
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), plaintext.as_slice())
+        .map_err(|_| "Failed to encrypt secrets")?;
+    Ok([nonce.as_slice(), ciphertext.as_slice()].concat())
+}
+
+/// Encrypts the plain JSON object of secrets in `plain_path` into `encrypted_file`,
+/// with the key in the `key_env` variable and a fresh nonce. Returns the file written.
+pub fn encrypt_file(plain_path: &str, settings: &SecretsSettings) -> Result<String, Box<dyn Error>> {
+    let path = settings
+        .encrypted_file
+        .clone()
+        .ok_or("secrets.encrypted_file must be set to know where to write the secrets")?;
+    let key = std::env::var(&settings.key_env)
+        .map_err(|_| format!("Secrets key variable {} is not set", settings.key_env))?;
+    let secrets: HashMap<String, String> = serde_json::from_str(&fs::read_to_string(plain_path)?)
+        .map_err(|e| format!("{} must be a JSON object of strings: {}", plain_path, e))?;
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    fs::write(&path, encrypt_secrets(&secrets, &key, nonce.into())?)?;
+    Ok(path)
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    if !hex.is_ascii() || hex.len() % 2 != 0 {
+        return Err("Invalid hex string".into());
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex[i..i + 2], 16).map_err(|e| Box::new(e) as Box<dyn Error>)
+        })
+        .collect()
+}
+
+/// Builds the providers in the configured lookup order.
+fn providers(settings: &SecretsSettings) -> Vec<Box<dyn SecretProvider>> {
+    settings
+        .providers
+        .iter()
+        .filter_map(|kind| -> Option<Box<dyn SecretProvider>> {
+            match kind {
+                ProviderKind::Env => Some(Box::new(EnvProvider)),
+                ProviderKind::Keyring => Some(Box::new(KeyringProvider {
+                    service: settings.keyring_service.clone(),
+                })),
+                ProviderKind::File => settings.encrypted_file.as_ref().map(|path| {
+                    Box::new(EncryptedFileProvider {
+                        path: path.clone(),
+                        key_env: settings.key_env.clone(),
+                    }) as Box<dyn SecretProvider>
+                }),
+            }
+        })
+        .collect()
+}
+
+/// Looks up a secret in every configured provider, in order.
+pub fn get_secret(name: &str, settings: &SecretsSettings) -> Result<String, Box<dyn Error>> {
+    for provider in providers(settings) {
+        match provider.get(name) {
+            Ok(Some(value)) => return Ok(value),
+            Ok(None) => {}
+            Err(e) => log::warn!("Secret provider failed for '{}': {}", name, e),
+        }
+    }
+    Err(format!("Secret '{}' not found in any provider", name).into())
+}
+
+/// Replaces every `{{secret:name}}` placeholder in `input` with its value.
+/// Fails if any referenced secret can't be resolved, so actions never run with a blank credential.
+pub fn resolve_secrets(input: &str, settings: &SecretsSettings) -> Result<String, Box<dyn Error>> {
+    replace_secrets(input, |name| get_secret(name, settings))
+}
+
+/// Replaces every `{{secret:name}}` placeholder in the shell script `input` with a reference to
+/// its `VOXAURORA_SECRET_<NAME>` variable, returned with the values to set. The shell never
+/// parses the secret and it stays off the command line `ps` shows.
+pub fn export_secrets(
+    input: &str,
+    settings: &SecretsSettings,
+) -> Result<(String, BTreeMap<String, String>), Box<dyn Error>> {
+    let mut env = BTreeMap::new();
+    let script = replace_secrets(input, |name| {
+        let var = env_name(name);
+        env.insert(var.clone(), get_secret(name, settings)?);
+        Ok(platform::env_reference(&var))
+    })?;
+    Ok((script, env))
+}
+
+fn replace_secrets(
+    input: &str,
+    mut replacement: impl FnMut(&str) -> Result<String, Box<dyn Error>>,
+) -> Result<String, Box<dyn Error>> {
+    if !SECRET_RE.is_match(input) {
+        return Ok(input.to_string());
+    }
+
+    let mut resolved = String::with_capacity(input.len());
+    let mut last_end = 0;
+    for caps in SECRET_RE.captures_iter(input) {
+        let placeholder = caps.get(0).unwrap();
+        resolved.push_str(&input[last_end..placeholder.start()]);
+        resolved.push_str(&replacement(&caps[1])?);
+        last_end = placeholder.end();
+    }
+    resolved.push_str(&input[last_end..]);
+    Ok(resolved)
+}
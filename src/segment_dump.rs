@@ -0,0 +1,196 @@
+use crate::audio;
+use crate::config::Handled;
+use crate::wakeword::WakeCheck;
+use chrono::Local;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Folder every finalized segment is written to, set by `--record-segments`.
+static DIR: OnceCell<PathBuf> = OnceCell::new();
+
+/// What decided whether a segment carried the wake word.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WakeSource {
+    /// The wake pass of Whisper over the whole segment.
+    Whisper,
+    /// The wake stream, listening on sliding windows while the segment was recorded.
+    Stream,
+    /// The keyword spotter of parked models.
+    Keyword,
+}
+
+/// The wake-word decision of a segment.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct WakeOutcome {
+    pub source: WakeSource,
+    pub woke: bool,
+    /// What the wake pass heard and how close it was; only known to the Whisper pass.
+    pub text: Option<String>,
+    pub variant: Option<String>,
+    pub similarity: Option<f32>,
+    /// Rejected as a known false wake.
+    #[serde(default)]
+    pub negative: bool,
+}
+
+impl WakeOutcome {
+    pub fn from_check(check: &WakeCheck) -> Self {
+        WakeOutcome {
+            source: WakeSource::Whisper,
+            woke: check.woke,
+            text: Some(check.text.clone()),
+            variant: check.variant.map(str::to_string),
+            similarity: Some(check.similarity),
+            negative: check.negative,
+        }
+    }
+
+    /// A decision that only tells whether the wake word was heard.
+    pub fn heard(source: WakeSource, woke: bool) -> Self {
+        WakeOutcome {
+            source,
+            woke,
+            text: None,
+            variant: None,
+            similarity: None,
+            negative: false,
+        }
+    }
+}
+
+/// One utterance of a segment and what the pipeline did with it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DumpedUtterance {
+    pub transcript: String,
+    /// Primary trigger of the command that matched.
+    pub trigger: Option<String>,
+    pub score: Option<f32>,
+    /// "command", "builtin", "dictation", "timed_out", "continues" for an utterance cut
+    /// at the maximum duration, or "failed" when its command returned an error.
+    pub outcome: String,
+}
+
+/// The sidecar JSON written next to a segment's WAV.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SegmentSidecar {
+    /// WAV file, relative to the folder.
+    pub audio: String,
+    pub timestamp: String,
+    pub duration_secs: f32,
+    /// `None` when the wake word wasn't listened for: push-to-talk, the rest of an
+    /// utterance cut mid-speech, or a segment set aside before the check.
+    pub wake: Option<WakeOutcome>,
+    pub utterances: Vec<DumpedUtterance>,
+}
+
+/// A segment being processed. Its WAV is written when it starts and its sidecar once it
+/// is dropped, whichever way the pipeline was left.
+pub struct DumpedSegment {
+    sidecar_path: PathBuf,
+    sidecar: SegmentSidecar,
+}
+
+impl DumpedSegment {
+    /// Writes `samples` (16 kHz mono) to a WAV named after the current time in `dir`.
+    pub fn create(dir: &Path, samples: &[f32]) -> io::Result<Self> {
+        let now = Local::now();
+        let stem = now.format("%Y%m%d-%H%M%S%.3f").to_string();
+        let file = format!("{}.wav", stem);
+        fs::write(dir.join(&file), audio::encode_wav(samples))?;
+        Ok(DumpedSegment {
+            sidecar_path: dir.join(format!("{}.json", stem)),
+            sidecar: SegmentSidecar {
+                audio: file,
+                timestamp: now.to_rfc3339(),
+                duration_secs: samples.len() as f32 / audio::WHISPER_SAMPLE_RATE as f32,
+                wake: None,
+                utterances: Vec::new(),
+            },
+        })
+    }
+
+    pub fn sidecar_path(&self) -> &Path {
+        &self.sidecar_path
+    }
+
+    pub fn set_wake(&mut self, wake: WakeOutcome) {
+        self.sidecar.wake = Some(wake);
+    }
+
+    /// Adds an utterance that ran; `handled` is `None` when its command failed.
+    pub fn add_utterance(&mut self, transcript: &str, handled: Option<&Handled>) {
+        let (trigger, score, outcome) = match handled {
+            Some(Handled::Command { trigger, score }) => {
+                (Some(trigger.clone()), Some(*score), "command")
+            }
+            Some(Handled::Builtin) => (None, None, "builtin"),
+            Some(Handled::Dictation) => (None, None, "dictation"),
+            Some(Handled::TimedOut) => (None, None, "timed_out"),
+            None => (None, None, "failed"),
+        };
+        self.push(transcript, trigger, score, outcome);
+    }
+
+    /// Adds an utterance cut at the maximum duration, waiting for its end.
+    pub fn add_partial(&mut self, transcript: &str) {
+        self.push(transcript, None, None, "continues");
+    }
+
+    fn push(
+        &mut self,
+        transcript: &str,
+        trigger: Option<String>,
+        score: Option<f32>,
+        outcome: &str,
+    ) {
+        self.sidecar.utterances.push(DumpedUtterance {
+            transcript: transcript.to_string(),
+            trigger,
+            score,
+            outcome: outcome.to_string(),
+        });
+    }
+}
+
+impl Drop for DumpedSegment {
+    fn drop(&mut self) {
+        let written = serde_json::to_string_pretty(&self.sidecar)
+            .map_err(|e| e.to_string())
+            .and_then(|json| fs::write(&self.sidecar_path, json).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            log::warn!("Failed to write {}: {}", self.sidecar_path.display(), e);
+        }
+    }
+}
+
+/// Writes every finalized segment into `dir` from now on.
+pub fn init(dir: &str) -> io::Result<()> {
+    let dir = PathBuf::from(dir);
+    fs::create_dir_all(&dir)?;
+    log::info!("🧪 Writing every speech segment into {}", dir.display());
+    if DIR.set(dir).is_err() {
+        log::warn!("Segment recording was already initialized");
+    }
+    Ok(())
+}
+
+/// Starts dumping a finalized segment, when `--record-segments` is set.
+pub fn begin(samples: &[f32]) -> Option<DumpedSegment> {
+    let dir = DIR.get()?;
+    match DumpedSegment::create(dir, samples) {
+        Ok(segment) => Some(segment),
+        Err(e) => {
+            log::warn!("Failed to write segment into {}: {}", dir.display(), e);
+            None
+        }
+    }
+}
+
+/// Reads the sidecar of a dumped segment.
+pub fn load(path: &Path) -> Result<SegmentSidecar, Box<dyn std::error::Error>> {
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
@@ -0,0 +1,103 @@
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::error::Error;
+use std::ops::Range;
+use whisper_rs::WhisperState;
+
+/// Whisper timestamps are in centiseconds; audio is 16kHz.
+const SAMPLES_PER_TIMESTAMP: usize = 160;
+
+/// Utterance splitting on Whisper's segment timestamps, read from the `segmentation`
+/// section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct SegmentationSettings {
+    /// Split captured segments into the utterances Whisper found in them, so commands
+    /// spoken back to back without a long pause run one by one.
+    pub enabled: bool,
+    /// Whisper segments closer than this are kept in the same utterance.
+    pub min_gap_ms: u64,
+    /// Audio kept around each utterance, as Whisper's boundaries are a bit tight.
+    pub padding_ms: u64,
+}
+
+impl Default for SegmentationSettings {
+    fn default() -> Self {
+        SegmentationSettings {
+            enabled: false,
+            min_gap_ms: 250,
+            padding_ms: 150,
+        }
+    }
+}
+
+/// Sample ranges of the segments Whisper decoded in `state`.
+pub fn segment_bounds(state: &WhisperState) -> Result<Vec<Range<usize>>, Box<dyn Error>> {
+    let mut bounds = Vec::new();
+    for segment in 0..state.full_n_segments()? {
+        let start = state.full_get_segment_t0(segment)?.max(0) as usize * SAMPLES_PER_TIMESTAMP;
+        let end = state.full_get_segment_t1(segment)?.max(0) as usize * SAMPLES_PER_TIMESTAMP;
+        if end > start {
+            bounds.push(start..end);
+        }
+    }
+    Ok(bounds)
+}
+
+/// Groups Whisper segments into utterances, merging gaps shorter than `min_gap_ms` and
+/// padding each utterance, clamped to `len` samples. Without segments the whole audio
+/// is one utterance.
+pub fn utterance_ranges(
+    bounds: &[Range<usize>],
+    len: usize,
+    settings: &SegmentationSettings,
+) -> Vec<Range<usize>> {
+    let min_gap = settings.min_gap_ms as usize * 16;
+    let padding = settings.padding_ms as usize * 16;
+
+    let mut merged: Vec<Range<usize>> = Vec::new();
+    for bound in bounds {
+        match merged.last_mut() {
+            Some(last) if bound.start < last.end + min_gap => last.end = last.end.max(bound.end),
+            _ => merged.push(bound.clone()),
+        }
+    }
+    if merged.is_empty() {
+        return vec![0..len];
+    }
+
+    // Padding never reaches into the previous utterance
+    let mut utterances: Vec<Range<usize>> = Vec::with_capacity(merged.len());
+    for range in merged {
+        let previous_end = utterances.last().map(|last| last.end).unwrap_or(0);
+        let start = range.start.saturating_sub(padding).max(previous_end);
+        let end = (range.end + padding).min(len);
+        if start < end {
+            utterances.push(start..end);
+        }
+    }
+    utterances
+}
+
+/// Splits `audio` into the utterances Whisper found in it.
+pub fn split_utterances(
+    audio: &[f32],
+    state: &WhisperState,
+    settings: &SegmentationSettings,
+) -> Vec<Vec<f32>> {
+    let bounds = match segment_bounds(state) {
+        Ok(bounds) => bounds,
+        Err(e) => {
+            log::warn!("Failed to read Whisper timestamps, keeping the whole segment: {}", e);
+            Vec::new()
+        }
+    };
+    let utterances: Vec<Vec<f32>> = utterance_ranges(&bounds, audio.len(), settings)
+        .into_iter()
+        .map(|range| audio[range].to_vec())
+        .collect();
+    if utterances.len() > 1 {
+        log::info!("✂️ Split segment into {} utterances", utterances.len());
+    }
+    utterances
+}
@@ -0,0 +1,195 @@
+use crate::actions::ShellOptions;
+use crate::platform;
+use once_cell::sync::Lazy;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::OpenOptions;
+use std::process::{Child, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A long-running process `service:` actions manage, read from the `services` section of
+/// the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+pub struct ServiceSpec {
+    /// Shell command starting the service, e.g. "npm run dev".
+    pub cmd: String,
+    /// `cwd`, `env` and `user`, as for `cmd:` actions.
+    #[serde(flatten)]
+    pub shell: ShellOptions,
+    /// File the service's output is appended to; it is discarded when unset.
+    #[serde(default)]
+    pub log: Option<String>,
+}
+
+/// A service started by VoxAurora.
+struct Job {
+    child: Child,
+    started: Instant,
+}
+
+/// Services started since launch, by name. Exited ones stay until started again, so
+/// their exit code can be reported.
+static JOBS: Lazy<Mutex<HashMap<String, Job>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// What the job table knows about a service.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServiceStatus {
+    Running { pid: u32, uptime: Duration },
+    /// Exited by itself, with its exit code when it has one.
+    Exited(Option<i32>),
+    NotStarted,
+}
+
+impl ServiceStatus {
+    pub fn is_running(&self) -> bool {
+        matches!(self, ServiceStatus::Running { .. })
+    }
+
+    /// A short sentence about the service named `name`.
+    pub fn describe(&self, name: &str) -> String {
+        match self {
+            ServiceStatus::Running { pid, uptime } => format!(
+                "{} is running (pid {}, for {})",
+                name,
+                pid,
+                format_uptime(*uptime)
+            ),
+            ServiceStatus::Exited(Some(code)) => format!("{} exited with code {}", name, code),
+            ServiceStatus::Exited(None) => format!("{} was killed", name),
+            ServiceStatus::NotStarted => format!("{} isn't running", name),
+        }
+    }
+}
+
+fn format_uptime(uptime: Duration) -> String {
+    let seconds = uptime.as_secs();
+    match seconds {
+        0..60 => format!("{} s", seconds),
+        60..3600 => format!("{} min", seconds / 60),
+        _ => format!("{} h {} min", seconds / 3600, seconds % 3600 / 60),
+    }
+}
+
+/// Starts the service named `name`, unless it is already running. Returns its pid.
+pub fn start(name: &str, services: &HashMap<String, ServiceSpec>) -> Result<u32, Box<dyn Error>> {
+    let spec = services
+        .get(name)
+        .ok_or_else(|| format!("No service named '{}' in the config", name))?;
+    let mut jobs = JOBS.lock().unwrap_or_else(|e| e.into_inner());
+    if let ServiceStatus::Running { pid, .. } = job_status(jobs.get_mut(name)) {
+        return Err(format!("{} is already running (pid {})", name, pid).into());
+    }
+
+    let mut command = match &spec.shell.user {
+        Some(user) => platform::shell_as(user, &spec.cmd, &spec.shell.env, &[])?,
+        None => platform::shell(&spec.cmd),
+    };
+    if let Some(cwd) = &spec.shell.cwd {
+        command.current_dir(platform::expand_home(cwd));
+    }
+    // Its output would mix with VoxAurora's, and break `--output json`
+    let (stdout, stderr) = match &spec.log {
+        Some(path) => {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            (Stdio::from(file.try_clone()?), Stdio::from(file))
+        }
+        None => (Stdio::null(), Stdio::null()),
+    };
+    command
+        .envs(&spec.shell.env)
+        .stdin(Stdio::null())
+        .stdout(stdout)
+        .stderr(stderr);
+    platform::own_process_group(&mut command);
+
+    let child = command.spawn()?;
+    let pid = child.id();
+    log::info!("🚀 Started service {} (pid {})", name, pid);
+    jobs.insert(
+        name.to_string(),
+        Job {
+            child,
+            started: Instant::now(),
+        },
+    );
+    Ok(pid)
+}
+
+/// Stops the service named `name` and whatever it spawned.
+pub fn stop(name: &str) -> Result<(), Box<dyn Error>> {
+    let mut jobs = JOBS.lock().unwrap_or_else(|e| e.into_inner());
+    let Some(mut job) = jobs.remove(name) else {
+        return Err(format!("{} isn't running", name).into());
+    };
+    if job.child.try_wait()?.is_none() {
+        platform::kill_tree(&mut job.child)?;
+    }
+    log::info!("🛑 Stopped service {}", name);
+    Ok(())
+}
+
+/// What the job table knows about the service named `name`.
+pub fn status(name: &str) -> ServiceStatus {
+    let mut jobs = JOBS.lock().unwrap_or_else(|e| e.into_inner());
+    job_status(jobs.get_mut(name))
+}
+
+/// Every service started since launch, by name.
+pub fn statuses() -> Vec<(String, ServiceStatus)> {
+    let mut jobs = JOBS.lock().unwrap_or_else(|e| e.into_inner());
+    let mut statuses: Vec<(String, ServiceStatus)> = jobs
+        .iter_mut()
+        .map(|(name, job)| (name.clone(), job_status(Some(job))))
+        .collect();
+    statuses.sort_by(|a, b| a.0.cmp(&b.0));
+    statuses
+}
+
+fn job_status(job: Option<&mut Job>) -> ServiceStatus {
+    let Some(job) = job else {
+        return ServiceStatus::NotStarted;
+    };
+    match job.child.try_wait() {
+        Ok(Some(exit)) => ServiceStatus::Exited(exit.code()),
+        Ok(None) => ServiceStatus::Running {
+            pid: job.child.id(),
+            uptime: job.started.elapsed(),
+        },
+        Err(e) => {
+            log::warn!("Failed to check service process {}: {}", job.child.id(), e);
+            ServiceStatus::Exited(None)
+        }
+    }
+}
+
+/// Runs a `service:` action: `start <name>`, `stop <name>` or `status <name>`.
+pub fn execute_service(
+    spec: &str,
+    services: &HashMap<String, ServiceSpec>,
+) -> Result<(), Box<dyn Error>> {
+    let (verb, name) = spec
+        .trim()
+        .split_once(char::is_whitespace)
+        .map(|(verb, name)| (verb, name.trim()))
+        .ok_or_else(|| format!("Service action '{}' needs a service name", spec))?;
+    match verb {
+        "start" => start(name, services).map(|_| ()),
+        "stop" => stop(name),
+        "status" => {
+            let status = status(name).describe(name);
+            log::info!("🛰️ {}", status);
+            if let Err(e) = notify_rust::Notification::new()
+                .summary("VoxAurora")
+                .body(&status)
+                .show()
+            {
+                log::warn!("Failed to show service notification: {}", e);
+            }
+            Ok(())
+        }
+        _ => Err(format!("Unknown service action '{}' (start, stop or status)", verb).into()),
+    }
+}
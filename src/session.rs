@@ -0,0 +1,217 @@
+use crate::codec::{self, Codec};
+use crate::secrets::SecretsSettings;
+use crate::summary::{self, SummarySettings};
+use chrono::{DateTime, Local};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Layout of a written transcript, chosen from the file extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TranscriptFormat {
+    Markdown,
+    Html,
+}
+
+impl TranscriptFormat {
+    /// HTML for `.html`/`.htm` files, Markdown otherwise.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("html") | Some("htm") => TranscriptFormat::Html,
+            _ => TranscriptFormat::Markdown,
+        }
+    }
+}
+
+/// What one speaker said without being interrupted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpeakerTurn {
+    /// When the turn started, from the beginning of the session.
+    pub offset: Duration,
+    /// Speaker number, from 1; 0 when speakers aren't labeled.
+    pub speaker: usize,
+    pub text: String,
+}
+
+/// Records a captioning session and keeps its transcript file up to date, so an
+/// interrupted session still leaves everything said so far on disk.
+pub struct SessionRecorder {
+    path: PathBuf,
+    format: TranscriptFormat,
+    started_at: DateTime<Local>,
+    started: Instant,
+    /// Where the current segment ends in a recorded file, counted instead of `started`.
+    position: Option<Duration>,
+    turns: Vec<SpeakerTurn>,
+}
+
+impl SessionRecorder {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        SessionRecorder {
+            format: TranscriptFormat::from_path(&path),
+            path,
+            started_at: Local::now(),
+            started: Instant::now(),
+            position: None,
+            turns: Vec::new(),
+        }
+    }
+
+    /// Counts offsets in the time of a recorded file instead of the wall clock, from
+    /// `position`, where the next segment recorded ends in it.
+    pub fn set_position(&mut self, position: Duration) {
+        self.position = Some(position);
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.position.unwrap_or_else(|| self.started.elapsed())
+    }
+
+    /// Adds a segment that just ended and lasted `duration`. Consecutive segments of the
+    /// same speaker are merged into one turn.
+    pub fn record(&mut self, speaker: usize, text: &str, duration: Duration) -> io::Result<()> {
+        let offset = self.elapsed().saturating_sub(duration);
+        match self.turns.last_mut() {
+            Some(turn) if turn.speaker == speaker => {
+                turn.text.push(' ');
+                turn.text.push_str(text);
+            }
+            _ => self.turns.push(SpeakerTurn {
+                offset,
+                speaker,
+                text: text.to_string(),
+            }),
+        }
+        self.write()
+    }
+
+    /// Saves the audio of a segment that just ended and lasted `duration` next to the
+    /// transcript (`standup.md` -> `standup_audio/00-01-05.flac`).
+    pub fn save_audio(
+        &self,
+        samples: &[f32],
+        duration: Duration,
+        codec: Codec,
+    ) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let stem = self
+            .path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "session".to_string());
+        let dir = self.path.with_file_name(format!("{}_audio", stem));
+        fs::create_dir_all(&dir)?;
+
+        let offset = format_offset(self.elapsed().saturating_sub(duration));
+        let path = dir.join(format!("{}.{}", offset.replace(':', "-"), codec.extension()));
+        fs::write(&path, codec::encode(samples, codec)?)?;
+        Ok(path)
+    }
+
+    pub fn turns(&self) -> &[SpeakerTurn] {
+        &self.turns
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes the final transcript and, when `summary.url` is set, a summary next to it.
+    /// Returns the transcript path; a failed summary is logged but doesn't fail the session.
+    pub fn finish(
+        self,
+        summary: &SummarySettings,
+        secrets: &SecretsSettings,
+    ) -> io::Result<PathBuf> {
+        self.write()?;
+        log::info!("📝 Transcript saved to {}", self.path.display());
+
+        if summary.url.is_some() && !self.turns.is_empty() {
+            log::info!("🧾 Summarizing the session...");
+            let transcript = render_markdown(&self.title(), &self.turns);
+            match summary::summarize(&transcript, summary, secrets) {
+                Ok(text) => {
+                    let path = summary::summary_path(&self.path);
+                    let title = self.title().replacen("Transcript", "Summary", 1);
+                    fs::write(&path, format!("# {}\n\n{}\n", title, text))?;
+                    log::info!("🧾 Summary saved to {}", path.display());
+                }
+                Err(e) => log::error!("Failed to summarize {}: {}", self.path.display(), e),
+            }
+        }
+        Ok(self.path)
+    }
+
+    fn title(&self) -> String {
+        format!("Transcript - {}", self.started_at.format("%Y-%m-%d %H:%M"))
+    }
+
+    fn write(&self) -> io::Result<()> {
+        if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let content = match self.format {
+            TranscriptFormat::Markdown => render_markdown(&self.title(), &self.turns),
+            TranscriptFormat::Html => render_html(&self.title(), &self.turns),
+        };
+        fs::write(&self.path, content)
+    }
+}
+
+/// "00:01:05" from the start of the session.
+pub fn format_offset(offset: Duration) -> String {
+    let secs = offset.as_secs();
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+fn speaker_label(speaker: usize) -> Option<String> {
+    (speaker > 0).then(|| format!("Speaker {}", speaker))
+}
+
+pub fn render_markdown(title: &str, turns: &[SpeakerTurn]) -> String {
+    let mut out = format!("# {}\n\n", title);
+    for turn in turns {
+        match speaker_label(turn.speaker) {
+            Some(label) => out.push_str(&format!(
+                "**[{}] {}:** {}\n\n",
+                format_offset(turn.offset),
+                label,
+                turn.text
+            )),
+            None => out.push_str(&format!(
+                "**[{}]** {}\n\n",
+                format_offset(turn.offset),
+                turn.text
+            )),
+        }
+    }
+    out
+}
+
+pub fn render_html(title: &str, turns: &[SpeakerTurn]) -> String {
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{0}</title></head>\n<body>\n<h1>{0}</h1>\n",
+        escape_html(title)
+    );
+    for turn in turns {
+        let label = speaker_label(turn.speaker)
+            .map(|label| format!(" <strong>{}</strong>", label))
+            .unwrap_or_default();
+        out.push_str(&format!(
+            "<p><time>{}</time>{}: {}</p>\n",
+            format_offset(turn.offset),
+            label,
+            escape_html(&turn.text)
+        ));
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,147 @@
+use realfft::RealFftPlanner;
+
+/// Analysis frame: 32 ms at 16kHz.
+const FRAME_LEN: usize = 512;
+const HOP_LEN: usize = 256;
+const SAMPLE_RATE: f32 = 16_000.0;
+
+/// Log-spaced frequency bands covering the voice range.
+const BAND_COUNT: usize = 24;
+const MIN_FREQ: f32 = 80.0;
+const MAX_FREQ: f32 = 4_000.0;
+
+/// Segments shorter than this (in samples) don't carry enough voice to identify anyone.
+const MIN_VOICEPRINT_SAMPLES: usize = FRAME_LEN * 8;
+
+/// A spectral signature of a voice: the average log energy per frequency band over the
+/// voiced frames, mean-removed and normalized so loudness and distance to the mic cancel out.
+pub type Voiceprint = Vec<f32>;
+
+/// Computes the voiceprint of a 16kHz mono segment, or `None` if it is too short.
+pub fn voiceprint(samples: &[f32]) -> Option<Voiceprint> {
+    if samples.len() < MIN_VOICEPRINT_SAMPLES {
+        return None;
+    }
+
+    let fft = RealFftPlanner::<f32>::new().plan_fft_forward(FRAME_LEN);
+    let mut input = fft.make_input_vec();
+    let mut spectrum = fft.make_output_vec();
+    let window: Vec<f32> = (0..FRAME_LEN)
+        .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / FRAME_LEN as f32).cos())
+        .collect();
+    let bin_hz = SAMPLE_RATE / FRAME_LEN as f32;
+    let band_edges: Vec<usize> = (0..=BAND_COUNT)
+        .map(|b| {
+            let freq = MIN_FREQ * (MAX_FREQ / MIN_FREQ).powf(b as f32 / BAND_COUNT as f32);
+            (freq / bin_hz).round() as usize
+        })
+        .collect();
+
+    let mut frames: Vec<(f32, Vec<f32>)> = Vec::new();
+    for start in (0..samples.len() - FRAME_LEN).step_by(HOP_LEN) {
+        let frame = &samples[start..start + FRAME_LEN];
+        for ((slot, sample), weight) in input.iter_mut().zip(frame).zip(&window) {
+            *slot = sample * weight;
+        }
+        if fft.process(&mut input, &mut spectrum).is_err() {
+            continue;
+        }
+        let power: Vec<f32> = spectrum.iter().map(|c| c.norm_sqr()).collect();
+        let bands: Vec<f32> = band_edges
+            .windows(2)
+            .map(|edge| {
+                let end = edge[1].max(edge[0] + 1).min(power.len());
+                let energy: f32 = power[edge[0].min(end - 1)..end].iter().sum();
+                (energy + 1e-10).ln()
+            })
+            .collect();
+        let frame_energy = frame.iter().map(|s| s * s).sum::<f32>();
+        frames.push((frame_energy, bands));
+    }
+
+    // Only the louder half of the frames is kept, so pauses don't dilute the voice
+    let mean_energy = frames.iter().map(|(e, _)| e).sum::<f32>() / frames.len() as f32;
+    let voiced: Vec<&Vec<f32>> = frames
+        .iter()
+        .filter(|(energy, _)| *energy >= mean_energy)
+        .map(|(_, bands)| bands)
+        .collect();
+    if voiced.is_empty() {
+        return None;
+    }
+
+    let mut print = vec![0.0f32; BAND_COUNT];
+    for bands in &voiced {
+        for (total, band) in print.iter_mut().zip(bands.iter()) {
+            *total += band / voiced.len() as f32;
+        }
+    }
+    let mean = print.iter().sum::<f32>() / BAND_COUNT as f32;
+    print.iter_mut().for_each(|band| *band -= mean);
+    let norm = print.iter().map(|b| b * b).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        print.iter_mut().for_each(|band| *band /= norm);
+    }
+    Some(print)
+}
+
+/// Cosine similarity of two voiceprints.
+pub fn similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms > 0.0 { dot / norms } else { 0.0 }
+}
+
+/// Assigns speaker numbers to segments by clustering their voiceprints online.
+/// Speakers are numbered from 1 in order of first appearance.
+pub struct SpeakerTracker {
+    /// Running mean voiceprint and segment count of each speaker.
+    speakers: Vec<(Voiceprint, usize)>,
+    threshold: f32,
+    max_speakers: usize,
+    last: Option<usize>,
+}
+
+impl SpeakerTracker {
+    /// `threshold` is the similarity above which a segment is attributed to a known speaker.
+    pub fn new(threshold: f32, max_speakers: usize) -> Self {
+        SpeakerTracker {
+            speakers: Vec::new(),
+            threshold,
+            max_speakers: max_speakers.max(1),
+            last: None,
+        }
+    }
+
+    /// Returns the speaker of `samples`. Segments too short to identify are given
+    /// to the previous speaker.
+    pub fn identify(&mut self, samples: &[f32]) -> usize {
+        let Some(print) = voiceprint(samples) else {
+            return self.last.unwrap_or(1);
+        };
+
+        let best = self
+            .speakers
+            .iter()
+            .enumerate()
+            .map(|(index, (centroid, _))| (index, similarity(&print, centroid)))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        let index = match best {
+            Some((index, score)) if score >= self.threshold => index,
+            Some((index, _)) if self.speakers.len() >= self.max_speakers => index,
+            _ => {
+                self.speakers.push((print.clone(), 0));
+                self.speakers.len() - 1
+            }
+        };
+
+        let (centroid, count) = &mut self.speakers[index];
+        *count += 1;
+        for (value, sample) in centroid.iter_mut().zip(&print) {
+            *value += (sample - *value) / *count as f32;
+        }
+        self.last = Some(index + 1);
+        index + 1
+    }
+}
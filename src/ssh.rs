@@ -0,0 +1,159 @@
+use crate::platform;
+use crate::secrets::{self, SecretsSettings};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use ssh2::{CheckResult, KnownHostFileKind, Session};
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Read;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// A machine `ssh:` actions run commands on, read from the `ssh` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+pub struct SshHost {
+    /// Address or hostname to connect to.
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    pub user: String,
+    /// Private key authenticating `user`; a leading `~` is the home directory.
+    pub key: String,
+    /// Passphrase of the key, usually a `{{secret:name}}` placeholder.
+    #[serde(default)]
+    pub passphrase: Option<String>,
+    /// OpenSSH known_hosts file the host key must be listed in.
+    #[serde(default = "default_known_hosts")]
+    pub known_hosts: String,
+    /// Seconds allowed to connect and for each read of the output.
+    #[serde(default = "default_timeout")]
+    pub timeout_secs: u64,
+}
+
+fn default_port() -> u16 {
+    22
+}
+
+fn default_known_hosts() -> String {
+    "~/.ssh/known_hosts".to_string()
+}
+
+fn default_timeout() -> u64 {
+    10
+}
+
+/// Splits an `ssh:` action into the host name and the command, whose surrounding double
+/// quotes are optional: `web "systemctl restart nginx"`.
+pub fn parse_action(spec: &str) -> Result<(&str, &str), Box<dyn Error>> {
+    let (host, command) = spec
+        .trim()
+        .split_once(char::is_whitespace)
+        .ok_or_else(|| format!("SSH action '{}' needs a host and a command", spec))?;
+    let command = command.trim();
+    let command = command
+        .strip_prefix('"')
+        .and_then(|c| c.strip_suffix('"'))
+        .unwrap_or(command);
+    if command.is_empty() {
+        return Err(format!("SSH action '{}' needs a command", spec).into());
+    }
+    Ok((host, command))
+}
+
+/// Connects to `host`, checking its key against the known_hosts file, and authenticates
+/// with the configured private key.
+fn connect(host: &SshHost, secrets_settings: &SecretsSettings) -> Result<Session, Box<dyn Error>> {
+    let timeout = Duration::from_secs(host.timeout_secs);
+    let address = (host.host.as_str(), host.port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| format!("Couldn't resolve {}", host.host))?;
+    let tcp = TcpStream::connect_timeout(&address, timeout)?;
+
+    let mut session = Session::new()?;
+    session.set_tcp_stream(tcp);
+    session.set_timeout(timeout.as_millis() as u32);
+    session.handshake()?;
+
+    let mut known_hosts = session.known_hosts()?;
+    let known_hosts_file = platform::expand_home(&host.known_hosts);
+    known_hosts
+        .read_file(&known_hosts_file, KnownHostFileKind::OpenSSH)
+        .map_err(|e| format!("Failed to read {}: {}", known_hosts_file.display(), e))?;
+    let (key, _) = session
+        .host_key()
+        .ok_or_else(|| format!("{} sent no host key", host.host))?;
+    match known_hosts.check_port(&host.host, host.port, key) {
+        CheckResult::Match => {}
+        CheckResult::NotFound => {
+            return Err(format!(
+                "{} isn't in {}: connect once with ssh to accept its key",
+                host.host,
+                known_hosts_file.display()
+            )
+            .into());
+        }
+        CheckResult::Mismatch => {
+            return Err(
+                format!("The host key of {} changed, refusing to connect", host.host).into(),
+            );
+        }
+        CheckResult::Failure => {
+            return Err(format!("Failed to check the host key of {}", host.host).into());
+        }
+    }
+
+    // Resolved here, like action secrets, so the passphrase never sits in the parsed config
+    let passphrase = host
+        .passphrase
+        .as_deref()
+        .map(|passphrase| secrets::resolve_secrets(passphrase, secrets_settings))
+        .transpose()?;
+    session.userauth_pubkey_file(
+        &host.user,
+        None,
+        &platform::expand_home(&host.key),
+        passphrase.as_deref(),
+    )?;
+    Ok(session)
+}
+
+/// Runs an `ssh:` action: `<host> "<command>"`, with `host` a name from the `ssh`
+/// section. Fails when the command exits with a non-zero code.
+pub fn execute_ssh(
+    spec: &str,
+    hosts: &HashMap<String, SshHost>,
+    secrets_settings: &SecretsSettings,
+) -> Result<(), Box<dyn Error>> {
+    let (name, command) = parse_action(spec)?;
+    let host = hosts
+        .get(name)
+        .ok_or_else(|| format!("No SSH host named '{}' in the config", name))?;
+    let session = connect(host, secrets_settings)?;
+
+    let mut channel = session.channel_session()?;
+    channel.exec(command)?;
+    let mut stdout = String::new();
+    channel.read_to_string(&mut stdout)?;
+    let mut stderr = String::new();
+    channel.stderr().read_to_string(&mut stderr)?;
+    channel.wait_close()?;
+    let code = channel.exit_status()?;
+
+    if !stdout.trim().is_empty() {
+        log::info!("🖧 {}: {}", name, stdout.trim_end());
+    }
+    if code == 0 {
+        log::info!("🖧 Ran '{}' on {}", command, name);
+        Ok(())
+    } else {
+        Err(format!(
+            "'{}' on {} exited with code {}: {}",
+            command,
+            name,
+            code,
+            stderr.trim()
+        )
+        .into())
+    }
+}
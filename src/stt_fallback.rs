@@ -0,0 +1,151 @@
+use crate::audio;
+use crate::decoding;
+use crate::secrets::{self, SecretsSettings};
+use crate::whisper_integration::{self, Transcription};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::error::Error;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use whisper_rs::WhisperContext;
+
+const MULTIPART_BOUNDARY: &str = "voxaurora-segment";
+
+/// Remote transcription fallback, read from the `stt_fallback` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct FallbackSettings {
+    /// OpenAI-compatible transcription endpoint (e.g. a self-hosted whisper server,
+    /// or another VoxAurora's `/v1/audio/transcriptions`). Disabled when unset.
+    pub url: Option<String>,
+    /// How long local Whisper may run before the segment is also sent to `url`.
+    pub budget_ms: u64,
+    /// Sent as a bearer token; may contain `{{secret:name}}` placeholders.
+    pub api_key: Option<String>,
+    /// Model name sent with the request.
+    pub model: String,
+}
+
+impl Default for FallbackSettings {
+    fn default() -> Self {
+        FallbackSettings {
+            url: None,
+            budget_ms: 3000,
+            api_key: None,
+            model: "whisper-1".to_string(),
+        }
+    }
+}
+
+/// Transcribes locally, but if Whisper hasn't finished within the budget, also sends the
+/// segment to the remote backend and keeps whichever answer comes first.
+/// Local transcription is aborted when the remote one wins.
+pub async fn transcribe_with_fallback(
+    model: Arc<WhisperContext>,
+    audio: Vec<f32>,
+    lang: &str,
+    settings: &FallbackSettings,
+    secrets: &SecretsSettings,
+) -> Result<Transcription, Box<dyn Error>> {
+    // Which backend answers first depends on timing, so reproducible runs stay local
+    let Some(url) = settings.url.clone().filter(|_| !decoding::reproducible()) else {
+        return whisper_integration::transcribe(&model, &audio, lang).await;
+    };
+
+    let audio = Arc::new(audio);
+    let abort = Arc::new(AtomicBool::new(false));
+    let mut local = tokio::task::spawn_blocking({
+        let (audio, abort, lang) = (audio.clone(), abort.clone(), lang.to_string());
+        move || {
+            whisper_integration::transcribe_sync(&model, &audio, &lang, Some(abort))
+                .map_err(|e| e.to_string())
+        }
+    });
+
+    tokio::select! {
+        result = &mut local => return Ok(result??),
+        _ = tokio::time::sleep(Duration::from_millis(settings.budget_ms)) => {}
+    }
+
+    log::warn!(
+        "⏱️ Local transcription exceeded {} ms, also trying {}",
+        settings.budget_ms,
+        url
+    );
+    let api_key = match &settings.api_key {
+        Some(key) => Some(secrets::resolve_secrets(key, secrets)?),
+        None => None,
+    };
+    let mut remote = tokio::task::spawn_blocking({
+        let (model_name, lang) = (settings.model.clone(), lang.to_string());
+        move || {
+            remote_transcribe(&url, &audio, &lang, &model_name, api_key.as_deref())
+                .map(|text| whisper_integration::clean_transcription_in(&text, &lang))
+                .map_err(|e| e.to_string())
+        }
+    });
+
+    tokio::select! {
+        result = &mut local => Ok(result??),
+        result = &mut remote => match result? {
+            Ok(text) => {
+                log::info!("☁️ Remote transcription answered first");
+                abort.store(true, Ordering::Relaxed);
+                Ok(text)
+            }
+            Err(e) => {
+                log::warn!("Remote transcription failed, waiting for Whisper: {}", e);
+                Ok(local.await??)
+            }
+        },
+    }
+}
+
+/// Sends a segment to an OpenAI-compatible transcription endpoint.
+fn remote_transcribe(
+    url: &str,
+    audio: &[f32],
+    lang: &str,
+    model: &str,
+    api_key: Option<&str>,
+) -> Result<String, Box<dyn Error>> {
+    let mut body = Vec::new();
+    for (name, value) in [("model", model), ("language", lang)] {
+        body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                MULTIPART_BOUNDARY, name, value
+            )
+            .as_bytes(),
+        );
+    }
+    body.extend_from_slice(
+        format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"segment.wav\"\r\nContent-Type: audio/wav\r\n\r\n",
+            MULTIPART_BOUNDARY
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(&audio::encode_wav(audio));
+    body.extend_from_slice(format!("\r\n--{}--\r\n", MULTIPART_BOUNDARY).as_bytes());
+
+    let mut request = ureq::post(url).header(
+        "Content-Type",
+        format!("multipart/form-data; boundary={}", MULTIPART_BOUNDARY),
+    );
+    if let Some(key) = api_key {
+        request = request.header("Authorization", format!("Bearer {}", key));
+    }
+    let response: serde_json::Value = serde_json::from_str(
+        &request
+            .send(&body[..])?
+            .body_mut()
+            .read_to_string()?,
+    )?;
+
+    response["text"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| "Remote response has no 'text' field".into())
+}
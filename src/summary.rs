@@ -0,0 +1,72 @@
+use crate::secrets::{self, SecretsSettings};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::json;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+/// End-of-session summarization, read from the `summary` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct SummarySettings {
+    /// OpenAI-compatible chat completions endpoint of a local LLM
+    /// (e.g. `http://localhost:11434/v1/chat/completions` for Ollama). Disabled when unset.
+    pub url: Option<String>,
+    pub model: String,
+    /// Sent as a bearer token; may contain `{{secret:name}}` placeholders.
+    pub api_key: Option<String>,
+    /// Instructions sent before the transcript.
+    pub prompt: String,
+}
+
+impl Default for SummarySettings {
+    fn default() -> Self {
+        SummarySettings {
+            url: None,
+            model: "llama3.2".to_string(),
+            api_key: None,
+            prompt: concat!(
+                "Summarize this transcript in the language it is written in: ",
+                "a short overview, then the decisions taken and the action items with their owners."
+            )
+            .to_string(),
+        }
+    }
+}
+
+/// Where the summary of `transcript` is saved: `standup.md` -> `standup.summary.md`.
+pub fn summary_path(transcript: &Path) -> PathBuf {
+    let stem = transcript
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "transcript".to_string());
+    transcript.with_file_name(format!("{}.summary.md", stem))
+}
+
+/// Asks the configured LLM for a summary of `transcript`.
+pub fn summarize(
+    transcript: &str,
+    settings: &SummarySettings,
+    secrets_settings: &SecretsSettings,
+) -> Result<String, Box<dyn Error>> {
+    let url = settings.url.as_deref().ok_or("summary.url is not set")?;
+    let body = json!({
+        "model": settings.model,
+        "messages": [
+            { "role": "system", "content": settings.prompt },
+            { "role": "user", "content": transcript },
+        ],
+    });
+
+    let mut request = ureq::post(url);
+    if let Some(key) = &settings.api_key {
+        let key = secrets::resolve_secrets(key, secrets_settings)?;
+        request = request.header("Authorization", format!("Bearer {}", key));
+    }
+    let response: serde_json::Value = request.send_json(body)?.body_mut().read_json()?;
+
+    response["choices"][0]["message"]["content"]
+        .as_str()
+        .map(|summary| summary.trim().to_string())
+        .ok_or_else(|| "Summary response has no message content".into())
+}
@@ -0,0 +1,175 @@
+use crate::bert;
+use crate::events::{self, Event};
+use crate::whisper_integration;
+use once_cell::sync::Lazy;
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Panics in a row after which a subsystem is taken out of the pipeline for a while.
+const MAX_FAILURES: u32 = 3;
+
+/// How long a degraded subsystem is skipped before it is tried again.
+const DEGRADED_FOR: Duration = Duration::from_secs(60);
+
+/// A part of the pipeline whose failures must not take the process down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subsystem {
+    Whisper,
+    Bert,
+    LanguageTool,
+}
+
+impl Subsystem {
+    pub fn name(self) -> &'static str {
+        match self {
+            Subsystem::Whisper => "whisper",
+            Subsystem::Bert => "bert",
+            Subsystem::LanguageTool => "languagetool",
+        }
+    }
+
+    /// Prepares the next call after a panic. Whisper needs nothing, as every call
+    /// creates its own state.
+    fn restart(self) {
+        match self {
+            Subsystem::Whisper => {}
+            // The model may be half-initialized; the next call loads a fresh one
+            Subsystem::Bert => bert::unload_model(),
+            Subsystem::LanguageTool => {}
+        }
+    }
+
+    /// Whether a degraded subsystem can be used again, once its cooldown is over.
+    fn recovered(self) -> bool {
+        match self {
+            Subsystem::LanguageTool => whisper_integration::ping_languagetool().is_ok(),
+            Subsystem::Whisper | Subsystem::Bert => true,
+        }
+    }
+}
+
+/// What happened to a subsystem after a panic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Restarted and used again on the next call.
+    Restarted,
+    /// Failed too often; skipped until the cooldown is over.
+    Degraded,
+}
+
+/// Failure tracking of one subsystem.
+#[derive(Debug, Default, Clone)]
+pub struct SubsystemState {
+    consecutive_failures: u32,
+    degraded_until: Option<Instant>,
+}
+
+impl SubsystemState {
+    /// Whether the subsystem may be called at `now`.
+    pub fn available(&self, now: Instant) -> bool {
+        self.degraded_until.is_none_or(|until| now >= until)
+    }
+
+    pub fn degraded(&self) -> bool {
+        self.degraded_until.is_some()
+    }
+
+    /// Records a panic at `now`.
+    pub fn failed(&mut self, now: Instant) -> Outcome {
+        self.consecutive_failures += 1;
+        if self.consecutive_failures >= MAX_FAILURES {
+            self.degraded_until = Some(now + DEGRADED_FOR);
+            Outcome::Degraded
+        } else {
+            Outcome::Restarted
+        }
+    }
+
+    /// Records a successful call; returns whether the subsystem was degraded.
+    pub fn succeeded(&mut self) -> bool {
+        self.consecutive_failures = 0;
+        self.degraded_until.take().is_some()
+    }
+
+    /// Starts another cooldown for a subsystem that isn't back yet.
+    fn still_down(&mut self, now: Instant) {
+        self.degraded_until = Some(now + DEGRADED_FOR);
+    }
+}
+
+/// Indexed by `Subsystem as usize`.
+static STATES: Lazy<Mutex<[SubsystemState; 3]>> = Lazy::new(Mutex::default);
+
+fn with_state<R>(subsystem: Subsystem, f: impl FnOnce(&mut SubsystemState) -> R) -> R {
+    // The supervisor must survive the panics it catches, so poisoning is ignored
+    let mut states = STATES.lock().unwrap_or_else(|e| e.into_inner());
+    f(&mut states[subsystem as usize])
+}
+
+/// Runs `f`, catching its panics so the rest of the pipeline (audio capture first of
+/// all) keeps running. A subsystem that panics is restarted; after `MAX_FAILURES`
+/// panics in a row it is degraded: every call fails fast until `DEGRADED_FOR` is over
+/// and the subsystem answers again.
+pub fn run<R>(subsystem: Subsystem, f: impl FnOnce() -> R) -> Result<R, String> {
+    let now = Instant::now();
+    let available = with_state(subsystem, |state| state.available(now));
+    if !available {
+        return Err(format!("{} is degraded after repeated failures", subsystem.name()));
+    }
+    let retrying = with_state(subsystem, |state| state.degraded());
+    if retrying && !subsystem.recovered() {
+        with_state(subsystem, |state| state.still_down(now));
+        return Err(format!("{} is still unavailable", subsystem.name()));
+    }
+
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(result) => {
+            if with_state(subsystem, SubsystemState::succeeded) {
+                log::info!("🩹 {} recovered", subsystem.name());
+                report(subsystem, "recovered", "");
+            }
+            Ok(result)
+        }
+        Err(payload) => {
+            let message = panic_message(payload.as_ref());
+            subsystem.restart();
+            let outcome = with_state(subsystem, |state| state.failed(Instant::now()));
+            match outcome {
+                Outcome::Restarted => {
+                    log::error!("💥 {} panicked, restarting it: {}", subsystem.name(), message);
+                    report(subsystem, "restarted", &message);
+                }
+                Outcome::Degraded => {
+                    log::error!(
+                        "💥 {} keeps panicking, skipping it for {} s: {}",
+                        subsystem.name(),
+                        DEGRADED_FOR.as_secs(),
+                        message
+                    );
+                    report(subsystem, "degraded", &message);
+                }
+            }
+            Err(format!("{} panicked: {}", subsystem.name(), message))
+        }
+    }
+}
+
+fn report(subsystem: Subsystem, state: &str, error: &str) {
+    events::emit(Event::Subsystem {
+        name: subsystem.name(),
+        state,
+        error,
+    });
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
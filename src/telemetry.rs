@@ -0,0 +1,246 @@
+use once_cell::sync::{Lazy, OnceCell};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// OpenTelemetry trace export, read from the `telemetry` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct TelemetrySettings {
+    /// OTLP/HTTP traces endpoint of a collector, Jaeger or Grafana Tempo
+    /// (e.g. `http://localhost:4318/v1/traces`). Disabled when unset.
+    pub otlp_endpoint: Option<String>,
+    /// `service.name` of the exported spans.
+    pub service_name: String,
+    /// Extra headers sent with every export, e.g. an `Authorization` token.
+    pub headers: HashMap<String, String>,
+}
+
+impl Default for TelemetrySettings {
+    fn default() -> Self {
+        TelemetrySettings {
+            otlp_endpoint: None,
+            service_name: "voxaurora".to_string(),
+            headers: HashMap::new(),
+        }
+    }
+}
+
+/// One stage of an utterance: capture, stt, cleanup, match or action.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub name: &'static str,
+    pub id: u64,
+    pub start: SystemTime,
+    pub end: SystemTime,
+    pub attributes: Vec<(&'static str, String)>,
+    pub error: Option<String>,
+}
+
+/// The spans of one utterance, children of a root `utterance` span.
+#[derive(Debug, Clone)]
+pub struct UtteranceTrace {
+    pub trace_id: u128,
+    pub root: Span,
+    pub spans: Vec<Span>,
+}
+
+impl UtteranceTrace {
+    /// Starts a trace whose root span begins at `start`.
+    pub fn new(start: SystemTime) -> Self {
+        UtteranceTrace {
+            trace_id: (u128::from(random_id()) << 64) | u128::from(random_id()),
+            root: Span {
+                name: "utterance",
+                id: random_id(),
+                start,
+                end: start,
+                attributes: Vec::new(),
+                error: None,
+            },
+            spans: Vec::new(),
+        }
+    }
+
+    /// Adds a child span ending now, which started `elapsed` ago.
+    pub fn record(&mut self, name: &'static str, elapsed: Duration) -> &mut Span {
+        let end = SystemTime::now();
+        self.spans.push(Span {
+            name,
+            id: random_id(),
+            start: end.checked_sub(elapsed).unwrap_or(end),
+            end,
+            attributes: Vec::new(),
+            error: None,
+        });
+        self.spans.last_mut().expect("a span was just pushed")
+    }
+
+    /// The trace as an OTLP/HTTP JSON export request.
+    pub fn to_otlp(&self, service_name: &str) -> Value {
+        let trace_id = format!("{:032x}", self.trace_id);
+        let mut spans = vec![otlp_span(&self.root, &trace_id, None)];
+        spans.extend(
+            self.spans
+                .iter()
+                .map(|span| otlp_span(span, &trace_id, Some(self.root.id))),
+        );
+        json!({
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": [otlp_attribute("service.name", service_name)],
+                },
+                "scopeSpans": [{
+                    "scope": { "name": "voxaurora", "version": env!("CARGO_PKG_VERSION") },
+                    "spans": spans,
+                }],
+            }],
+        })
+    }
+}
+
+fn otlp_span(span: &Span, trace_id: &str, parent: Option<u64>) -> Value {
+    let mut value = json!({
+        "traceId": trace_id,
+        "spanId": format!("{:016x}", span.id),
+        "name": span.name,
+        // SPAN_KIND_INTERNAL
+        "kind": 1,
+        // 64-bit integers are strings in the OTLP JSON encoding
+        "startTimeUnixNano": unix_nanos(span.start).to_string(),
+        "endTimeUnixNano": unix_nanos(span.end).to_string(),
+        "attributes": span
+            .attributes
+            .iter()
+            .map(|(key, value)| otlp_attribute(key, value))
+            .collect::<Vec<_>>(),
+        // STATUS_CODE_OK or STATUS_CODE_ERROR
+        "status": match &span.error {
+            Some(message) => json!({ "code": 2, "message": message }),
+            None => json!({ "code": 1 }),
+        },
+    });
+    if let Some(parent) = parent {
+        value["parentSpanId"] = json!(format!("{:016x}", parent));
+    }
+    value
+}
+
+fn otlp_attribute(key: &str, value: &str) -> Value {
+    json!({ "key": key, "value": { "stringValue": value } })
+}
+
+fn unix_nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_nanos())
+}
+
+/// A non-zero identifier, random enough to tell traces apart without a `rand` dependency.
+fn random_id() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    static SEED: Lazy<RandomState> = Lazy::new(RandomState::new);
+    let mut hasher = SEED.build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.write_u128(unix_nanos(SystemTime::now()));
+    hasher.finish().max(1)
+}
+
+/// Process-wide telemetry settings, set once after the config is loaded.
+static TELEMETRY_SETTINGS: OnceCell<TelemetrySettings> = OnceCell::new();
+
+/// The trace of the utterance being processed. Utterances go through the pipeline one
+/// at a time, so the stages deep in the call stack add their span here.
+static CURRENT: Lazy<Mutex<Option<UtteranceTrace>>> = Lazy::new(|| Mutex::new(None));
+
+/// Traces are sent by one background thread, so a slow collector never delays a command.
+static EXPORTER: Lazy<Mutex<Sender<UtteranceTrace>>> = Lazy::new(|| {
+    let (sender, receiver) = mpsc::channel::<UtteranceTrace>();
+    std::thread::spawn(move || {
+        let settings = settings();
+        let Some(endpoint) = &settings.otlp_endpoint else {
+            return;
+        };
+        for trace in receiver {
+            let mut request = ureq::post(endpoint);
+            for (name, value) in &settings.headers {
+                request = request.header(name, value);
+            }
+            if let Err(e) = request.send_json(trace.to_otlp(&settings.service_name)) {
+                log::warn!("Failed to export trace to {}: {}", endpoint, e);
+            }
+        }
+    });
+    Mutex::new(sender)
+});
+
+fn current() -> std::sync::MutexGuard<'static, Option<UtteranceTrace>> {
+    CURRENT.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Installs the telemetry settings.
+pub fn init(settings: TelemetrySettings) {
+    if let Some(endpoint) = &settings.otlp_endpoint {
+        log::info!("📈 Exporting utterance traces to {}", endpoint);
+    }
+    if TELEMETRY_SETTINGS.set(settings).is_err() {
+        log::warn!("Telemetry settings were already initialized, ignoring new values");
+    }
+}
+
+/// Returns the active telemetry settings, or the defaults if `init` was never called.
+pub fn settings() -> &'static TelemetrySettings {
+    TELEMETRY_SETTINGS.get_or_init(TelemetrySettings::default)
+}
+
+/// Whether traces are exported.
+pub fn enabled() -> bool {
+    settings().otlp_endpoint.is_some()
+}
+
+/// Starts the trace of an utterance captured over the last `captured` duration, with
+/// its `capture` span.
+pub fn begin_utterance(captured: Duration) {
+    if !enabled() {
+        return;
+    }
+    let now = SystemTime::now();
+    let mut trace = UtteranceTrace::new(now.checked_sub(captured).unwrap_or(now));
+    trace.record("capture", captured);
+    *current() = Some(trace);
+}
+
+/// Adds a span for a stage of the current utterance which started at `started`.
+/// Does nothing when no utterance is traced.
+pub fn record(
+    name: &'static str,
+    started: Instant,
+    attributes: Vec<(&'static str, String)>,
+    error: Option<String>,
+) {
+    if let Some(trace) = current().as_mut() {
+        let span = trace.record(name, started.elapsed());
+        span.attributes = attributes;
+        span.error = error;
+    }
+}
+
+/// Ends the current utterance and hands its trace to the exporter.
+pub fn end_utterance() {
+    let Some(mut trace) = current().take() else {
+        return;
+    };
+    trace.root.end = SystemTime::now();
+    let sent = EXPORTER
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .send(trace);
+    if sent.is_err() {
+        log::warn!("Trace exporter stopped, dropping trace");
+    }
+}
@@ -0,0 +1,134 @@
+use crate::decoding;
+use once_cell::sync::OnceCell;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+use tokio::task::JoinError;
+
+/// What a stage that took too long is replaced with.
+#[derive(Deserialize, JsonSchema, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeoutFallback {
+    /// Goes on without the stage: the text isn't corrected by LanguageTool, and an
+    /// utterance that can't be matched is dropped.
+    #[default]
+    Skip,
+    /// Goes on with the text as it is: uncorrected, without the other cleanup stages
+    /// either, and typed as dictation when it can't be matched.
+    RawText,
+}
+
+/// How long one stage of the pipeline may run.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct StageTimeout {
+    /// In milliseconds; 0 waits as long as the stage takes.
+    pub timeout_ms: u64,
+    pub fallback: TimeoutFallback,
+}
+
+impl Default for StageTimeout {
+    fn default() -> Self {
+        StageTimeout {
+            timeout_ms: 5000,
+            fallback: TimeoutFallback::Skip,
+        }
+    }
+}
+
+impl StageTimeout {
+    /// `None` when unlimited, as in reproducible runs, whose results can't depend on
+    /// how fast the machine is.
+    fn limit(&self) -> Option<Duration> {
+        let limited = self.timeout_ms > 0 && !decoding::reproducible();
+        limited.then(|| Duration::from_millis(self.timeout_ms))
+    }
+}
+
+/// Stage timeouts, read from the `timeouts` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug, Default)]
+#[serde(default)]
+pub struct TimeoutSettings {
+    /// LanguageTool correction of each transcription.
+    pub correction: StageTimeout,
+    /// BERT embedding of the utterance.
+    pub embedding: StageTimeout,
+    /// Comparison of the utterance with every trigger phrase.
+    pub matching: StageTimeout,
+}
+
+/// Process-wide timeouts, set once after the config is loaded, for the cleanup that runs
+/// without the config at hand.
+static TIMEOUT_SETTINGS: OnceCell<TimeoutSettings> = OnceCell::new();
+
+pub fn init(settings: TimeoutSettings) {
+    if TIMEOUT_SETTINGS.set(settings).is_err() {
+        log::warn!("Timeout settings were already initialized, ignoring new values");
+    }
+}
+
+/// Returns the active timeouts, or the defaults if `init` was never called.
+pub fn settings() -> &'static TimeoutSettings {
+    TIMEOUT_SETTINGS.get_or_init(TimeoutSettings::default)
+}
+
+fn log_timeout(name: &str, stage: &StageTimeout) {
+    log::warn!(
+        "⏱️ {} took longer than {} ms, {}",
+        name,
+        stage.timeout_ms,
+        match stage.fallback {
+            TimeoutFallback::Skip => "skipping it",
+            TimeoutFallback::RawText => "using the raw text",
+        }
+    );
+}
+
+/// Runs `f` on the blocking pool, waiting at most the stage's timeout. Returns `None`
+/// when it took longer: the task can't be interrupted, so it finishes in the background
+/// and its result is dropped.
+pub async fn run_blocking<R: Send + 'static>(
+    name: &str,
+    stage: &StageTimeout,
+    f: impl FnOnce() -> R + Send + 'static,
+) -> Result<Option<R>, JoinError> {
+    let task = tokio::task::spawn_blocking(f);
+    let Some(limit) = stage.limit() else {
+        return task.await.map(Some);
+    };
+    match tokio::time::timeout(limit, task).await {
+        Ok(result) => result.map(Some),
+        Err(_) => {
+            log_timeout(name, stage);
+            Ok(None)
+        }
+    }
+}
+
+/// `run_blocking` for synchronous callers, which may run outside of the runtime: `f`
+/// runs on a thread of its own, left behind when it takes too long.
+pub fn run_on_thread<R: Send + 'static>(
+    name: &str,
+    stage: &StageTimeout,
+    f: impl FnOnce() -> R + Send + 'static,
+) -> Option<R> {
+    let Some(limit) = stage.limit() else {
+        return Some(f());
+    };
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = sender.send(f());
+    });
+    match receiver.recv_timeout(limit) {
+        Ok(result) => Some(result),
+        Err(RecvTimeoutError::Timeout) => {
+            log_timeout(name, stage);
+            None
+        }
+        Err(RecvTimeoutError::Disconnected) => {
+            log::error!("{} stopped without an answer", name);
+            None
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use crate::codec;
+use crate::whisper_integration;
+use serde_json::json;
+use std::error::Error;
+use std::io::Read;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tiny_http::{Header, Method, Request, Response};
+use whisper_rs::WhisperContext;
+
+/// Route of the OpenAI-compatible transcription endpoint.
+pub const TRANSCRIPTIONS_PATH: &str = "/v1/audio/transcriptions";
+
+/// Largest accepted upload, the same limit as the OpenAI API.
+const MAX_UPLOAD_BYTES: u64 = 25 * 1024 * 1024;
+
+/// Transcriptions running at the same time. More are refused rather than queued, as each
+/// holds an upload of up to `MAX_UPLOAD_BYTES` and a Whisper state.
+pub const MAX_CONCURRENT_TRANSCRIPTIONS: usize = 2;
+
+/// Transcriptions currently holding a `TranscriptionSlot`.
+static RUNNING: AtomicUsize = AtomicUsize::new(0);
+
+/// One of the `MAX_CONCURRENT_TRANSCRIPTIONS` transcriptions, released when dropped.
+pub struct TranscriptionSlot(());
+
+impl TranscriptionSlot {
+    /// Takes a free slot, or `None` when every one is in use.
+    pub fn acquire() -> Option<Self> {
+        RUNNING
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |running| {
+                (running < MAX_CONCURRENT_TRANSCRIPTIONS).then_some(running + 1)
+            })
+            .ok()
+            .map(|_| TranscriptionSlot(()))
+    }
+}
+
+impl Drop for TranscriptionSlot {
+    fn drop(&mut self) {
+        RUNNING.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// One field of a `multipart/form-data` body.
+pub struct FormPart {
+    pub name: String,
+    pub filename: Option<String>,
+    pub data: Vec<u8>,
+}
+
+/// Whether `request` targets the transcription endpoint.
+pub fn is_transcription_request(request: &Request) -> bool {
+    request.method() == &Method::Post
+        && request.url().split('?').next() == Some(TRANSCRIPTIONS_PATH)
+}
+
+/// Handles `POST /v1/audio/transcriptions` like the OpenAI API: a multipart form with a
+/// WAV `file`, and optional `language` (`default_language` when unset) and
+/// `response_format` (`json` or `text`) fields. The audio goes through the same Whisper
+/// and cleanup pipeline as spoken commands. Answers 503 while the model is parked.
+pub fn handle_transcription(
+    mut request: Request,
+    model: Option<&WhisperContext>,
+    default_language: &str,
+) {
+    let Some(model) = model else {
+        return respond_error(request, 503, "The Whisper model is parked until the next wake word");
+    };
+    let form = match read_form(&mut request) {
+        Ok(form) => form,
+        Err(e) => return respond_error(request, 400, &e.to_string()),
+    };
+    let field = |name: &str| {
+        form.iter()
+            .find(|part| part.name == name)
+            .map(|part| String::from_utf8_lossy(&part.data).trim().to_string())
+    };
+
+    let Some(file) = form.iter().find(|part| part.name == "file") else {
+        return respond_error(request, 400, "Missing 'file' field");
+    };
+    let audio = match codec::decode(&file.data) {
+        Ok(audio) => audio,
+        Err(e) => return respond_error(request, 400, &format!("Unreadable audio: {}", e)),
+    };
+    let language = field("language").unwrap_or_else(|| default_language.to_string());
+
+    log::info!(
+        "🌐 Transcription request: {} ({} samples, {})",
+        file.filename.as_deref().unwrap_or("unnamed"),
+        audio.len(),
+        language
+    );
+    let text = match whisper_integration::transcribe_sync(model, &audio, &language, None) {
+        Ok(transcription) => transcription.corrected,
+        Err(e) => return respond_error(request, 500, &format!("Transcription failed: {}", e)),
+    };
+
+    if field("response_format").as_deref() == Some("text") {
+        respond(request, 200, text, "text/plain; charset=utf-8");
+    } else {
+        let body = json!({ "text": text }).to_string();
+        respond(request, 200, body, "application/json");
+    }
+}
+
+fn read_form(request: &mut Request) -> Result<Vec<FormPart>, Box<dyn Error>> {
+    let content_type = request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Content-Type"))
+        .map(|header| header.value.as_str().to_string())
+        .ok_or("Missing Content-Type header")?;
+    if !content_type.starts_with("multipart/form-data") {
+        return Err("Expected a multipart/form-data body".into());
+    }
+    let boundary = header_param(&content_type, "boundary").ok_or("Missing multipart boundary")?;
+
+    let mut body = Vec::new();
+    request
+        .as_reader()
+        .take(MAX_UPLOAD_BYTES + 1)
+        .read_to_end(&mut body)?;
+    if body.len() as u64 > MAX_UPLOAD_BYTES {
+        return Err("Upload exceeds 25 MB".into());
+    }
+
+    Ok(parse_multipart(&body, &boundary))
+}
+
+/// Splits a `multipart/form-data` body into its fields.
+pub fn parse_multipart(body: &[u8], boundary: &str) -> Vec<FormPart> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let mut sections = Vec::new();
+    let mut rest = body;
+    while let Some(position) = find(rest, &delimiter) {
+        sections.push(&rest[..position]);
+        rest = &rest[position + delimiter.len()..];
+    }
+    sections.push(rest);
+
+    let mut parts = Vec::new();
+    // The first section is the preamble; "--" after a delimiter closes the body
+    for section in sections.into_iter().skip(1) {
+        if section.starts_with(b"--") {
+            break;
+        }
+        let section = section.strip_prefix(b"\r\n").unwrap_or(section);
+        let Some(header_end) = find(section, b"\r\n\r\n") else {
+            continue;
+        };
+        let headers = String::from_utf8_lossy(&section[..header_end]);
+        let data = &section[header_end + 4..];
+        let data = data.strip_suffix(b"\r\n").unwrap_or(data);
+
+        let Some(disposition) = headers
+            .lines()
+            .find(|line| line.to_ascii_lowercase().starts_with("content-disposition"))
+        else {
+            continue;
+        };
+        if let Some(name) = header_param(disposition, "name") {
+            parts.push(FormPart {
+                name,
+                filename: header_param(disposition, "filename"),
+                data: data.to_vec(),
+            });
+        }
+    }
+    parts
+}
+
+/// Reads `key=value` (optionally quoted) from a `;`-separated header value.
+fn header_param(header: &str, key: &str) -> Option<String> {
+    header.split(';').map(str::trim).find_map(|param| {
+        let value = param.strip_prefix(key)?.strip_prefix('=')?;
+        Some(value.trim_matches('"').to_string())
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Answers 429 without reading the upload, when no `TranscriptionSlot` is free.
+pub fn respond_busy(request: Request) {
+    respond_error(request, 429, "Too many transcriptions in progress, retry later");
+}
+
+fn respond_error(request: Request, status: u16, message: &str) {
+    log::warn!("Transcription request rejected: {}", message);
+    let kind = if status >= 500 { "server_error" } else { "invalid_request_error" };
+    let body = json!({ "error": { "message": message, "type": kind } });
+    respond(request, status, body.to_string(), "application/json");
+}
+
+fn respond(request: Request, status: u16, body: String, content_type: &str) {
+    let header = Header::from_bytes(&b"Content-Type"[..], content_type.as_bytes())
+        .expect("Static header is valid");
+    let response = Response::from_string(body)
+        .with_status_code(status)
+        .with_header(header);
+    if let Err(e) = request.respond(response) {
+        log::warn!("Failed to send transcription response: {}", e);
+    }
+}
@@ -0,0 +1,367 @@
+use crate::audio;
+use ort::session::Session;
+use ort::value::Tensor;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Mean absolute amplitude above which the energy detector hears speech.
+pub const DEFAULT_ENERGY_THRESHOLD: f32 = 0.01;
+
+/// Samples per Silero window at 16kHz.
+const SILERO_WINDOW: usize = 512;
+
+/// Samples of the previous window Silero v5 expects in front of each one.
+const SILERO_CONTEXT: usize = 64;
+
+/// Size of Silero's recurrent state, `[2, 1, 128]`.
+const SILERO_STATE: usize = 2 * 128;
+
+/// Decides whether chunks of captured audio contain speech.
+pub trait VoiceActivityDetector: Send {
+    /// Whether `chunk`, mono audio at the capture rate, contains speech.
+    fn is_speech(&mut self, chunk: &[f32]) -> bool;
+
+    /// Whether `chunk` carries on speech already heard, judged against `offset_ratio`
+    /// times the usual threshold. Detectors without a threshold use `is_speech`.
+    fn continues_speech(&mut self, chunk: &[f32], _offset_ratio: f32) -> bool {
+        self.is_speech(chunk)
+    }
+
+    /// Forgets what was heard, once an utterance is complete.
+    fn reset(&mut self) {}
+
+    /// Replaces the energy threshold with one calibrated on the ambient noise; detectors
+    /// that don't use one ignore it.
+    fn set_energy_threshold(&mut self, _threshold: f32) {}
+}
+
+/// Which voice activity detector segments speech.
+#[derive(Deserialize, JsonSchema, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum VadBackend {
+    /// Mean amplitude over a threshold: cheap, but noise counts as speech.
+    #[default]
+    Energy,
+    /// The Silero neural network, which tells speech from noise far better.
+    Silero,
+}
+
+/// Voice activity detection, read from the `input.vad` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct VadSettings {
+    pub backend: VadBackend,
+    /// Mean amplitude above which the `energy` backend hears speech.
+    pub energy_threshold: f32,
+    /// Silero VAD ONNX model (v5), e.g. from the snakers4/silero-vad repository.
+    pub silero_model: String,
+    /// Speech probability above which the `silero` backend hears speech.
+    pub silero_threshold: f32,
+    /// Speech in progress only ends below this fraction of the threshold it started
+    /// above, so a softer word mid-sentence doesn't end it. 1 disables the hysteresis.
+    pub offset_ratio: f32,
+    /// Speech still heard after the level fell below the offset threshold, bridging the
+    /// dips between words. Counted before `input.timing.silence_secs`, so it only matters
+    /// when that is short.
+    pub hangover_secs: f32,
+    pub calibration: CalibrationSettings,
+}
+
+impl Default for VadSettings {
+    fn default() -> Self {
+        VadSettings {
+            backend: VadBackend::Energy,
+            energy_threshold: DEFAULT_ENERGY_THRESHOLD,
+            silero_model: "./models/silero_vad.onnx".to_string(),
+            silero_threshold: 0.5,
+            offset_ratio: 0.7,
+            hangover_secs: 0.0,
+            calibration: CalibrationSettings::default(),
+        }
+    }
+}
+
+/// Ambient noise calibration of the `energy` backend, read from the
+/// `input.vad.calibration` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct CalibrationSettings {
+    /// Listens to the room when capture starts and derives the threshold from its noise;
+    /// `energy_threshold` is used until then, and for good when disabled.
+    pub enabled: bool,
+    /// Ambient audio listened to.
+    pub duration_secs: f32,
+    /// The threshold is the noise floor times this.
+    pub multiplier: f32,
+    /// Bounds of the calibrated threshold, so that neither a silent room nor a noisy
+    /// one ends up with a detector hearing everything or nothing.
+    pub min_threshold: f32,
+    pub max_threshold: f32,
+}
+
+impl Default for CalibrationSettings {
+    fn default() -> Self {
+        CalibrationSettings {
+            enabled: true,
+            duration_secs: 2.5,
+            multiplier: 3.0,
+            min_threshold: 0.003,
+            max_threshold: 0.05,
+        }
+    }
+}
+
+impl CalibrationSettings {
+    /// The threshold for a room whose noise floor is `noise_floor`.
+    pub fn threshold(&self, noise_floor: f32) -> f32 {
+        let max = self.max_threshold.max(self.min_threshold);
+        (noise_floor * self.multiplier).clamp(self.min_threshold, max)
+    }
+}
+
+/// Energies of the chunks heard while calibrating one device.
+pub struct Calibration {
+    energies: Vec<f32>,
+    heard: usize,
+    needed: usize,
+}
+
+impl Calibration {
+    pub fn new(settings: &CalibrationSettings, sample_rate: u32) -> Self {
+        Calibration {
+            energies: Vec::new(),
+            heard: 0,
+            needed: (settings.duration_secs.max(0.0) * sample_rate as f32) as usize,
+        }
+    }
+
+    /// Adds a chunk; returns the noise floor once enough audio was heard. The median
+    /// chunk energy is taken, so a word or a knock during calibration doesn't count.
+    pub fn add(&mut self, chunk: &[f32]) -> Option<f32> {
+        if !chunk.is_empty() {
+            self.energies.push(energy(chunk));
+            self.heard += chunk.len();
+        }
+        if self.heard < self.needed || self.energies.is_empty() {
+            return None;
+        }
+        self.energies.sort_unstable_by(f32::total_cmp);
+        Some(self.energies[self.energies.len() / 2])
+    }
+}
+
+/// Set by the "recalibrate" built-in, for the audio processor to pick up.
+static RECALIBRATION_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Asks the live audio processor to measure the ambient noise again.
+pub fn request_recalibration() {
+    RECALIBRATION_REQUESTED.store(true, Ordering::Relaxed);
+}
+
+/// Whether a recalibration was requested since the last call.
+pub fn take_recalibration_request() -> bool {
+    RECALIBRATION_REQUESTED.swap(false, Ordering::Relaxed)
+}
+
+/// Mean absolute amplitude of `chunk`.
+pub fn energy(chunk: &[f32]) -> f32 {
+    if chunk.is_empty() {
+        return 0.0;
+    }
+    chunk.iter().map(|sample| sample.abs()).sum::<f32>() / chunk.len() as f32
+}
+
+/// The original heuristic: any chunk loud enough is speech.
+pub struct EnergyVad {
+    pub threshold: f32,
+}
+
+impl VoiceActivityDetector for EnergyVad {
+    fn is_speech(&mut self, chunk: &[f32]) -> bool {
+        energy(chunk) > self.threshold
+    }
+
+    fn continues_speech(&mut self, chunk: &[f32], offset_ratio: f32) -> bool {
+        energy(chunk) > self.threshold * offset_ratio
+    }
+
+    fn set_energy_threshold(&mut self, threshold: f32) {
+        self.threshold = threshold;
+    }
+}
+
+/// Silero VAD through ONNX Runtime. Chunks are resampled to 16kHz and cut into
+/// 512-sample windows; a chunk is speech when one of its windows is.
+pub struct SileroVad {
+    session: Session,
+    threshold: f32,
+    state: Vec<f32>,
+    context: Vec<f32>,
+    /// Samples waiting for a full window.
+    pending: Vec<f32>,
+    /// Fed every chunk, so the capture is resampled as one stream.
+    resampler: audio::StreamResampler,
+}
+
+impl SileroVad {
+    pub fn new(model_path: &str, sample_rate: u32, threshold: f32) -> Result<Self, Box<dyn Error>> {
+        let session = Session::builder()?
+            .with_intra_threads(1)?
+            .commit_from_file(model_path)?;
+        Ok(SileroVad {
+            session,
+            threshold,
+            state: vec![0.0; SILERO_STATE],
+            context: vec![0.0; SILERO_CONTEXT],
+            pending: Vec::new(),
+            resampler: audio::StreamResampler::new(sample_rate),
+        })
+    }
+
+    /// Speech probability of one 16kHz window.
+    fn probability(&mut self, window: &[f32]) -> Result<f32, Box<dyn Error>> {
+        let mut input = Vec::with_capacity(SILERO_CONTEXT + window.len());
+        input.extend_from_slice(&self.context);
+        input.extend_from_slice(window);
+        self.context.copy_from_slice(&window[window.len() - SILERO_CONTEXT..]);
+
+        let input = Tensor::from_array(([1usize, input.len()], input))?;
+        let state = Tensor::from_array(([2usize, 1, 128], self.state.clone()))?;
+        let sample_rate = Tensor::from_array(([1usize], vec![16_000i64]))?;
+        let outputs = self.session.run(ort::inputs![
+            "input" => input,
+            "state" => state,
+            "sr" => sample_rate,
+        ]?)?;
+
+        let (_, state) = outputs["stateN"].try_extract_raw_tensor::<f32>()?;
+        self.state.copy_from_slice(state);
+        let (_, probability) = outputs["output"].try_extract_raw_tensor::<f32>()?;
+        Ok(probability.first().copied().unwrap_or(0.0))
+    }
+
+    /// Whether one of the windows completed by `chunk` is speech above `threshold`.
+    fn speech_above(&mut self, chunk: &[f32], threshold: f32) -> bool {
+        self.pending.extend(self.resampler.process(chunk));
+        let mut speech = false;
+        while self.pending.len() >= SILERO_WINDOW {
+            let window: Vec<f32> = self.pending.drain(..SILERO_WINDOW).collect();
+            match self.probability(&window) {
+                Ok(probability) => speech |= probability > threshold,
+                Err(e) => {
+                    log::warn!("Silero VAD failed, treating the chunk as silence: {}", e);
+                    return false;
+                }
+            }
+        }
+        speech
+    }
+}
+
+impl VoiceActivityDetector for SileroVad {
+    fn is_speech(&mut self, chunk: &[f32]) -> bool {
+        self.speech_above(chunk, self.threshold)
+    }
+
+    fn continues_speech(&mut self, chunk: &[f32], offset_ratio: f32) -> bool {
+        self.speech_above(chunk, self.threshold * offset_ratio)
+    }
+
+    fn reset(&mut self) {
+        self.state.fill(0.0);
+        self.context.fill(0.0);
+        self.pending.clear();
+        self.resampler.reset();
+    }
+}
+
+/// Onset/offset hysteresis and hangover around a detector: speech starts above its
+/// threshold, goes on above `offset_ratio` times it, and ends `hangover_secs` after the
+/// last chunk that did.
+pub struct Hysteresis {
+    detector: Box<dyn VoiceActivityDetector>,
+    offset_ratio: f32,
+    /// Hangover, in samples at the capture rate.
+    hangover: usize,
+    /// Samples of hangover left since the level fell below the offset threshold.
+    remaining: usize,
+    speaking: bool,
+}
+
+impl Hysteresis {
+    pub fn new(
+        detector: Box<dyn VoiceActivityDetector>,
+        settings: &VadSettings,
+        sample_rate: u32,
+    ) -> Self {
+        Hysteresis {
+            detector,
+            offset_ratio: settings.offset_ratio.clamp(0.0, 1.0),
+            hangover: (settings.hangover_secs.max(0.0) * sample_rate as f32) as usize,
+            remaining: 0,
+            speaking: false,
+        }
+    }
+}
+
+impl VoiceActivityDetector for Hysteresis {
+    fn is_speech(&mut self, chunk: &[f32]) -> bool {
+        let heard = if self.speaking {
+            self.detector.continues_speech(chunk, self.offset_ratio)
+        } else {
+            self.detector.is_speech(chunk)
+        };
+        if heard {
+            self.speaking = true;
+            self.remaining = self.hangover;
+        } else if self.speaking {
+            self.speaking = self.remaining > 0;
+            self.remaining = self.remaining.saturating_sub(chunk.len());
+        }
+        self.speaking
+    }
+
+    fn reset(&mut self) {
+        self.speaking = false;
+        self.remaining = 0;
+        self.detector.reset();
+    }
+
+    fn set_energy_threshold(&mut self, threshold: f32) {
+        self.detector.set_energy_threshold(threshold);
+    }
+}
+
+/// Builds the configured detector for audio captured at `sample_rate`, with its
+/// hysteresis. A Silero model that can't be loaded falls back to the energy detector.
+pub fn create(settings: &VadSettings, sample_rate: u32) -> Box<dyn VoiceActivityDetector> {
+    Box::new(Hysteresis::new(
+        create_detector(settings, sample_rate),
+        settings,
+        sample_rate,
+    ))
+}
+
+fn create_detector(settings: &VadSettings, sample_rate: u32) -> Box<dyn VoiceActivityDetector> {
+    let energy = EnergyVad {
+        threshold: settings.energy_threshold,
+    };
+    match settings.backend {
+        VadBackend::Energy => Box::new(energy),
+        VadBackend::Silero => {
+            match SileroVad::new(&settings.silero_model, sample_rate, settings.silero_threshold) {
+                Ok(silero) => Box::new(silero),
+                Err(e) => {
+                    log::error!(
+                        "Failed to load Silero VAD from {}, using the energy detector: {}",
+                        settings.silero_model,
+                        e
+                    );
+                    Box::new(energy)
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use crate::codec;
+use crate::history;
+use crate::wakeword::{WakeCheck, WakeSettings};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Margin kept between a suggested threshold and the scores it separates.
+const THRESHOLD_MARGIN: f32 = 0.01;
+
+/// The user's verdict on a logged wake decision.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Review {
+    Correct,
+    FalseWake,
+}
+
+/// One line of the wake log.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WakeDecision {
+    pub timestamp: String,
+    pub text: String,
+    pub variant: Option<String>,
+    pub similarity: f32,
+    pub token_confidence: Option<f32>,
+    pub woke: bool,
+    /// Rejected as a known false wake.
+    #[serde(default)]
+    pub negative: bool,
+    /// The segment audio, when `wake.audio_dir` is set.
+    pub audio: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub review: Option<Review>,
+}
+
+/// Summary printed after a review.
+#[derive(Debug, Default, PartialEq)]
+pub struct WakeStats {
+    pub total: usize,
+    pub woke: usize,
+    pub reviewed: usize,
+    pub false_wakes: usize,
+    /// `similarity_threshold` separating the reviewed false wakes from the real ones.
+    pub suggested_threshold: Option<f32>,
+}
+
+/// Appends `check` to `wake.log_file`, or to the history when it is enabled, with the
+/// segment audio when `wake.audio_dir` is set.
+/// Only segments that looked like the wake word are logged, not every utterance.
+pub fn record(check: &WakeCheck, audio: &[f32], settings: &WakeSettings) {
+    if settings.log_file.is_none() && !history::enabled() {
+        return;
+    }
+    if !check.woke && !check.is_candidate(settings) {
+        return;
+    }
+
+    let now = Local::now();
+    let audio = settings.audio_dir.as_ref().and_then(|dir| {
+        let name = format!(
+            "{}.{}",
+            now.format("%Y%m%d-%H%M%S%.3f"),
+            settings.audio_codec.extension()
+        );
+        let path = Path::new(dir).join(name);
+        match save_audio(&path, audio, settings) {
+            Ok(()) => Some(path.to_string_lossy().into_owned()),
+            Err(e) => {
+                log::warn!("Failed to save wake audio {}: {}", path.display(), e);
+                None
+            }
+        }
+    });
+
+    let decision = WakeDecision {
+        timestamp: now.to_rfc3339(),
+        text: check.text.clone(),
+        variant: check.variant.map(str::to_string),
+        similarity: check.similarity,
+        token_confidence: check.token_confidence,
+        woke: check.woke,
+        negative: check.negative,
+        audio,
+        review: None,
+    };
+    if history::enabled() {
+        history::with_history(|history| history.record_wake(&decision));
+    } else if let Some(log_file) = &settings.log_file {
+        if let Err(e) = append(Path::new(log_file), &decision) {
+            log::warn!("Failed to write wake log {}: {}", log_file, e);
+        }
+    }
+}
+
+fn save_audio(
+    path: &Path,
+    audio: &[f32],
+    settings: &WakeSettings,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, codec::encode(audio, settings.audio_codec)?)?;
+    Ok(())
+}
+
+fn append(path: &Path, decision: &WakeDecision) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(decision)?)
+}
+
+/// Reads the wake log; a missing file is an empty log. Unreadable lines are skipped.
+pub fn load(path: impl AsRef<Path>) -> io::Result<Vec<WakeDecision>> {
+    let content = match fs::read_to_string(path.as_ref()) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    Ok(content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(decision) => Some(decision),
+            Err(e) => {
+                log::warn!("Skipping invalid wake log line: {}", e);
+                None
+            }
+        })
+        .collect())
+}
+
+/// Rewrites the wake log, e.g. after a review.
+pub fn save(path: impl AsRef<Path>, decisions: &[WakeDecision]) -> io::Result<()> {
+    let mut content = String::new();
+    for decision in decisions {
+        content.push_str(&serde_json::to_string(decision)?);
+        content.push('\n');
+    }
+    fs::write(path.as_ref(), content)
+}
+
+/// Texts of the decisions reviewed as false wakes.
+pub fn false_wake_phrases(decisions: &[WakeDecision]) -> Vec<String> {
+    let mut phrases: Vec<String> = Vec::new();
+    for decision in decisions {
+        if decision.review == Some(Review::FalseWake) && !phrases.contains(&decision.text) {
+            phrases.push(decision.text.clone());
+        }
+    }
+    phrases
+}
+
+/// `settings` with the false wakes reviewed in its log, or in the history, added to
+/// the negative phrases.
+pub fn with_reviewed_negatives(settings: &WakeSettings) -> WakeSettings {
+    let mut settings = settings.clone();
+    let decisions: Option<Vec<WakeDecision>> = if history::enabled() {
+        history::with_history(|history| history.wake_decisions())
+            .map(|decisions| decisions.into_iter().map(|(_, decision)| decision).collect())
+    } else if let Some(log_file) = &settings.log_file {
+        load(log_file)
+            .map_err(|e| log::warn!("Failed to read wake log {}: {}", log_file, e))
+            .ok()
+    } else {
+        None
+    };
+    if let Some(decisions) = decisions {
+        let phrases = false_wake_phrases(&decisions);
+        if !phrases.is_empty() {
+            log::info!("Ignoring {} reviewed false wake phrases", phrases.len());
+        }
+        settings.negative_phrases.extend(phrases);
+    }
+    settings
+}
+
+pub fn stats(decisions: &[WakeDecision]) -> WakeStats {
+    let reviewed = |review: Review| {
+        decisions
+            .iter()
+            .filter(move |decision| decision.review == Some(review))
+            .map(|decision| decision.similarity)
+    };
+    let false_max = reviewed(Review::FalseWake).reduce(f32::max);
+    let correct_min = reviewed(Review::Correct).reduce(f32::min);
+
+    // Only suggested when a threshold can reject every false wake
+    let suggested_threshold = match (false_max, correct_min) {
+        (Some(false_max), Some(correct_min)) if false_max < correct_min => {
+            Some((false_max + correct_min) / 2.0)
+        }
+        (Some(false_max), None) => Some((false_max + THRESHOLD_MARGIN).min(1.0)),
+        _ => None,
+    };
+
+    WakeStats {
+        total: decisions.len(),
+        woke: decisions.iter().filter(|decision| decision.woke).count(),
+        reviewed: decisions.iter().filter(|decision| decision.review.is_some()).count(),
+        false_wakes: reviewed(Review::FalseWake).count(),
+        suggested_threshold,
+    }
+}
+
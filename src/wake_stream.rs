@@ -0,0 +1,238 @@
+use crate::audio::{self, WHISPER_SAMPLE_RATE};
+use crate::supervisor::{self, Subsystem};
+use crate::wake_log;
+use crate::wakeword::{self, WakeSettings};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use tokio::sync::watch;
+use whisper_rs::WhisperContext;
+
+/// Windows waiting for the wake check; more are dropped rather than delaying it.
+const MAX_PENDING_WINDOWS: usize = 4;
+
+/// Longest wait at the end of a segment for the windows still being checked.
+const SYNC_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Wake word stream, read from the `wake.stream` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct WakeStreamSettings {
+    /// Listens for the wake word on short sliding windows while speech goes on, so it is
+    /// known once the segment ends, instead of running a wake pass over the whole
+    /// segment then. Utterances aren't split by `segmentation` in this mode.
+    pub enabled: bool,
+    /// Audio in each window checked.
+    pub window_secs: f32,
+    /// Time between the starts of two windows.
+    pub hop_secs: f32,
+}
+
+impl Default for WakeStreamSettings {
+    fn default() -> Self {
+        WakeStreamSettings {
+            enabled: false,
+            window_secs: 2.0,
+            hop_secs: 1.0,
+        }
+    }
+}
+
+/// State shared by the feed, the checking thread and the listener.
+struct Shared {
+    model: Mutex<Weak<WhisperContext>>,
+    /// Set when a window of the current segment held the wake word.
+    woke: AtomicBool,
+    /// Windows sent by the feed.
+    sent: AtomicU64,
+    /// Windows up to this one belong to a segment dropped as too short.
+    discarded: AtomicU64,
+    /// Windows checked, or skipped, by the thread.
+    checked: watch::Sender<u64>,
+}
+
+/// The capture side of the stream: cuts the 16 kHz audio of the first device into
+/// windows while speech is heard.
+pub struct WakeFeed {
+    shared: Arc<Shared>,
+    sender: SyncSender<Vec<f32>>,
+    window: usize,
+    hop: usize,
+    buffer: Vec<f32>,
+    since_window: usize,
+    /// Speech was heard since the last window sent.
+    pending: bool,
+    /// Fed every chunk, so the capture is resampled as one stream.
+    resampler: Option<audio::StreamResampler>,
+}
+
+impl WakeFeed {
+    /// Adds a chunk captured at `sample_rate`; `speech` tells whether a segment is being
+    /// recorded. A window is sent every hop while it is.
+    pub fn push(&mut self, chunk: &[f32], sample_rate: u32, speech: bool) {
+        if self.resampler.as_ref().is_some_and(|r| r.sample_rate() != sample_rate) {
+            self.resampler = None;
+        }
+        let chunk = self
+            .resampler
+            .get_or_insert_with(|| audio::StreamResampler::new(sample_rate))
+            .process(chunk);
+        self.since_window += chunk.len();
+        self.buffer.extend(chunk);
+        let excess = self.buffer.len().saturating_sub(self.window);
+        self.buffer.drain(..excess);
+        self.pending |= speech;
+        if speech && self.since_window >= self.hop {
+            self.send_window();
+        }
+    }
+
+    /// Checks what was said since the last window, at the end of a segment.
+    pub fn finish(&mut self) {
+        if self.pending {
+            self.send_window();
+        }
+    }
+
+    /// Forgets the wake word if it was heard in a segment dropped as too short.
+    pub fn discard(&mut self) {
+        self.pending = false;
+        let sent = self.shared.sent.load(Ordering::Relaxed);
+        self.shared.discarded.store(sent, Ordering::Relaxed);
+        self.shared.woke.store(false, Ordering::Relaxed);
+    }
+
+    fn send_window(&mut self) {
+        self.since_window = 0;
+        self.pending = false;
+        match self.sender.try_send(self.buffer.clone()) {
+            Ok(()) => {
+                self.shared.sent.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(TrySendError::Full(_)) => {
+                log::warn!("Wake word checks lag behind, dropping a window")
+            }
+            Err(TrySendError::Disconnected(_)) => log::error!("The wake word thread stopped"),
+        }
+    }
+}
+
+/// The pipeline side of the stream: tells, once a segment is complete, whether the
+/// wake word was heard in it.
+pub struct WakeListener {
+    shared: Arc<Shared>,
+}
+
+impl WakeListener {
+    /// The model the windows are decoded with, e.g. once reloaded after parking. Windows
+    /// are skipped while it is unloaded.
+    pub fn set_model(&self, model: &Arc<WhisperContext>) {
+        *self.shared.model.lock().unwrap_or_else(|e| e.into_inner()) = Arc::downgrade(model);
+    }
+
+    /// Whether a window of the segment just completed held the wake word. Waits for the
+    /// windows still being checked, up to `SYNC_TIMEOUT`, and starts the next segment
+    /// afresh.
+    pub async fn woke_during_segment(&self) -> bool {
+        let sent = self.shared.sent.load(Ordering::Relaxed);
+        let mut checked = self.shared.checked.subscribe();
+        let caught_up = checked.wait_for(|checked| *checked >= sent);
+        if tokio::time::timeout(SYNC_TIMEOUT, caught_up).await.is_err() {
+            log::warn!("Wake word checks still running after {:?}", SYNC_TIMEOUT);
+        }
+        self.shared.woke.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// Starts the thread checking the windows for the wake word with `model`.
+pub fn start(
+    settings: &WakeStreamSettings,
+    wake: WakeSettings,
+    model: &Arc<WhisperContext>,
+) -> (WakeFeed, WakeListener) {
+    let rate = WHISPER_SAMPLE_RATE as f32;
+    let window = ((settings.window_secs.max(0.5) * rate) as usize).max(1);
+    let hop = ((settings.hop_secs.max(0.1) * rate) as usize).clamp(1, window);
+    let (checked, _) = watch::channel(0);
+    let shared = Arc::new(Shared {
+        model: Mutex::new(Arc::downgrade(model)),
+        woke: AtomicBool::new(false),
+        sent: AtomicU64::new(0),
+        discarded: AtomicU64::new(0),
+        checked,
+    });
+    let (sender, receiver) = mpsc::sync_channel(MAX_PENDING_WINDOWS);
+    let thread_shared = shared.clone();
+    std::thread::spawn(move || listen(receiver, &thread_shared, &wake));
+    log::info!(
+        "👂 Checking for the wake word every {:.1} s over the last {:.1} s",
+        hop as f32 / rate,
+        window as f32 / rate
+    );
+    let feed = WakeFeed {
+        shared: shared.clone(),
+        sender,
+        window,
+        hop,
+        buffer: Vec::with_capacity(window),
+        since_window: 0,
+        pending: false,
+        resampler: None,
+    };
+    (feed, WakeListener { shared })
+}
+
+fn listen(windows: Receiver<Vec<f32>>, shared: &Shared, settings: &WakeSettings) {
+    for window in windows {
+        let index = *shared.checked.borrow() + 1;
+        let model = shared
+            .model
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .upgrade();
+        // One wake per segment: the windows overlap, and would hear it twice
+        if let Some(model) = model.filter(|_| !shared.woke.load(Ordering::Relaxed)) {
+            let woke = check_window(&model, &window, settings);
+            if woke && index > shared.discarded.load(Ordering::Relaxed) {
+                shared.woke.store(true, Ordering::Relaxed);
+            }
+        }
+        shared.checked.send_modify(|checked| *checked += 1);
+    }
+}
+
+/// Runs the wake pass over one window, logging the decision like a whole segment's.
+fn check_window(model: &WhisperContext, window: &[f32], settings: &WakeSettings) -> bool {
+    let decoded = supervisor::run(Subsystem::Whisper, || {
+        let mut state = model.create_state()?;
+        state.full(wakeword::wake_params(), window)?;
+        Ok::<_, whisper_rs::WhisperError>(state)
+    });
+    let state = match decoded {
+        Ok(Ok(state)) => state,
+        Ok(Err(e)) => {
+            log::error!("Error processing a wake window: {}", e);
+            return false;
+        }
+        Err(e) => {
+            log::error!("Wake word check skipped: {}", e);
+            return false;
+        }
+    };
+    if state.full_n_segments().unwrap_or(0) == 0 {
+        return false;
+    }
+    match wakeword::is_wake_word_present_sync(&state, 0, settings) {
+        Ok(check) => {
+            wake_log::record(&check, window, settings);
+            check.woke
+        }
+        Err(e) => {
+            log::error!("Error during wake word detection: {}", e);
+            false
+        }
+    }
+}
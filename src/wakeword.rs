@@ -62,11 +62,16 @@
 //     Ok(false)
 // }
 
-use crate::bert::encode_sentence;
+use crate::bert;
+use crate::codec::Codec;
+use crate::language;
+use crate::performance;
+use crate::wake_stream::WakeStreamSettings;
 use crate::whisper_integration;
-use once_cell::sync::Lazy;
+use schemars::JsonSchema;
+use serde::Deserialize;
 use std::error::Error;
-use whisper_rs::WhisperState;
+use whisper_rs::{FullParams, SamplingStrategy, WhisperState};
 
 /// The wake words to detect
 const WAKE_VARIANTS: &[&str; 12] = &[
@@ -84,56 +89,238 @@ const WAKE_VARIANTS: &[&str; 12] = &[
     "vox ouroho.",
 ];
 
-/// The minimum cosine similarity threshold to consider a match
+/// The default minimum cosine similarity threshold to consider a match
 const EMBEDDING_SIMILARITY_THRESHOLD: f32 = 0.7;
 
-/// Pre-calculated embeddings for each wake word
-static WAKE_VARIANTS_EMBEDDINGS: Lazy<Vec<Vec<f32>>> = Lazy::new(|| {
+/// The default minimum confidence of the wake word tokens (between 0.0 and 1.0)
+const TOKEN_PROB_THRESHOLD: f32 = 0.3;
+
+/// Wake word detection settings, read from the `wake` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct WakeSettings {
+    /// Minimum cosine similarity between the segment and a wake variant.
+    pub similarity_threshold: f32,
+    /// Minimum mean probability Whisper gave the tokens forming the wake word, so a
+    /// low-confidence hallucination can't wake the assistant. 0 disables the check.
+    pub min_token_prob: f32,
+    /// Phrases that must never wake the assistant, e.g. a colleague called "Laura".
+    /// False wakes marked with `--review-wakes` are added to them.
+    pub negative_phrases: Vec<String>,
+    /// JSON Lines file every wake decision is appended to, for `--review-wakes`.
+    pub log_file: Option<String>,
+    /// Folder keeping the audio of each logged decision, in this codec.
+    pub audio_dir: Option<String>,
+    pub audio_codec: Codec,
+    pub stream: WakeStreamSettings,
+}
+
+impl Default for WakeSettings {
+    fn default() -> Self {
+        WakeSettings {
+            similarity_threshold: EMBEDDING_SIMILARITY_THRESHOLD,
+            min_token_prob: TOKEN_PROB_THRESHOLD,
+            negative_phrases: Vec::new(),
+            log_file: None,
+            audio_dir: None,
+            audio_codec: Codec::Wav,
+            stream: WakeStreamSettings::default(),
+        }
+    }
+}
+
+/// The minimum normalized Levenshtein similarity for plain-text matching
+const TEXT_SIMILARITY_THRESHOLD: f64 = 0.8;
+
+/// How close a segment must be to a negative phrase to be rejected
+const NEGATIVE_SIMILARITY_THRESHOLD: f64 = 0.85;
+
+/// Returns the wake word variants, e.g. to pre-encode them at startup.
+pub fn wake_variants() -> &'static [&'static str] {
     WAKE_VARIANTS
+}
+
+/// Words said before the wake word that still count as addressing the assistant.
+const WAKE_PREFIXES: &[&str] = &["ok", "okay", "hey", "hé", "eh", "dis"];
+
+/// Lowercased words of `text`, split on whitespace and hyphens, without punctuation.
+fn wake_words(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| c.is_whitespace() || c == '-')
+        .map(|word| word.trim_matches(|c: char| c.is_ascii_punctuation()))
+        .filter(|word| !word.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Lightweight wake word check on plain text, without BERT or LanguageTool.
+/// Used while the heavy models are parked. The utterance must open with the wake word,
+/// as whole words and optionally after "ok" or "hey", so a sentence that merely
+/// mentions "Laura" doesn't wake everything up.
+pub fn matches_wake_text(text: &str) -> bool {
+    let words = wake_words(text);
+    let start = usize::from(words.first().is_some_and(|w| WAKE_PREFIXES.contains(&w.as_str())));
+    let words = &words[start..];
+    WAKE_VARIANTS.iter().any(|variant| {
+        let variant = wake_words(variant);
+        if words.len() < variant.len() {
+            return false;
+        }
+        let opening = words[..variant.len()].join(" ");
+        strsim::normalized_levenshtein(&opening, &variant.join(" ")) >= TEXT_SIMILARITY_THRESHOLD
+    })
+}
+
+/// Text and probability of the text tokens of a segment; special tokens are skipped.
+fn segment_tokens(
+    state: &WhisperState,
+    segment_index: i32,
+) -> Result<Vec<(String, f32)>, Box<dyn Error + Send + Sync>> {
+    let mut tokens = Vec::new();
+    for token_index in 0..state.full_n_tokens(segment_index)? {
+        let text = state.full_get_token_text(segment_index, token_index)?;
+        if text.trim_start().starts_with("[_") {
+            continue;
+        }
+        let prob = state.full_get_token_prob(segment_index, token_index)?;
+        tokens.push((text, prob));
+    }
+    Ok(tokens)
+}
+
+/// Mean probability of the tokens spelling part of a wake variant ("Vox", " Aur", "ora"),
+/// or of every word token when none does. `None` without any word token.
+pub fn wake_token_confidence(tokens: &[(String, f32)]) -> Option<f32> {
+    let words: Vec<(String, f32)> = tokens
         .iter()
-        .map(|&word| {
-            encode_sentence(word).unwrap_or_else(|_| {
-                log::error!("Failed to encode wake word: {}", word);
-                vec![]
-            })
+        .map(|(text, prob)| {
+            let clean = text.to_lowercase();
+            let clean = clean.trim_matches(|c: char| !c.is_alphanumeric());
+            (clean.to_string(), *prob)
         })
-        .collect()
-});
+        .filter(|(clean, _)| !clean.is_empty())
+        .collect();
+    let wake_tokens: Vec<f32> = words
+        .iter()
+        .filter(|(clean, _)| WAKE_VARIANTS.iter().any(|variant| variant.contains(clean.as_str())))
+        .map(|(_, prob)| *prob)
+        .collect();
+    let probs = if wake_tokens.is_empty() {
+        words.iter().map(|(_, prob)| *prob).collect()
+    } else {
+        wake_tokens
+    };
+    if probs.is_empty() {
+        return None;
+    }
+    Some(probs.iter().sum::<f32>() / probs.len() as f32)
+}
+
+/// Outcome of a wake word check, with the signals it was based on.
+#[derive(Debug, Clone, Default)]
+pub struct WakeCheck {
+    pub woke: bool,
+    /// The cleaned segment text.
+    pub text: String,
+    /// The closest wake variant and its embedding similarity.
+    pub variant: Option<&'static str>,
+    pub similarity: f32,
+    /// Set once the similarity passed, see `wake_token_confidence`.
+    pub token_confidence: Option<f32>,
+    /// Rejected because the text is a known false wake.
+    pub negative: bool,
+}
+
+impl WakeCheck {
+    /// Whether the text looked like the wake word, even if another check rejected it.
+    pub fn is_candidate(&self, settings: &WakeSettings) -> bool {
+        self.similarity > settings.similarity_threshold
+    }
+}
+
+/// Whether `text` is one of the phrases known to cause false wakes.
+pub fn is_negative_phrase(text: &str, negatives: &[String]) -> bool {
+    let text = text
+        .trim_matches(|c: char| c.is_ascii_punctuation() || c.is_whitespace())
+        .to_lowercase();
+    negatives.iter().any(|negative| {
+        let negative = negative
+            .trim_matches(|c: char| c.is_ascii_punctuation() || c.is_whitespace())
+            .to_lowercase();
+        strsim::normalized_levenshtein(&text, &negative) >= NEGATIVE_SIMILARITY_THRESHOLD
+    })
+}
+
+/// Whisper parameters of the wake pass.
+pub fn wake_params() -> FullParams<'static, 'static> {
+    let mut params = FullParams::new(SamplingStrategy::default());
+    params.set_print_special(false);
+    params.set_print_progress(false);
+    params.set_print_realtime(false);
+    params.set_token_timestamps(false);
+    params.set_language(Some(language::undetected_language()));
+    performance::apply_whisper_threads(&mut params);
+    params
+}
 
 /// Synchronous function that performs actual wake word detection.
-fn is_wake_word_present_sync(
+pub fn is_wake_word_present_sync(
     state: &WhisperState,
     segment_index: i32,
-) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    settings: &WakeSettings,
+) -> Result<WakeCheck, Box<dyn Error + Send + Sync>> {
     // Retrieve the raw text of the segment
     let raw_segment_text = state.full_get_segment_text(segment_index)?;
-    // Clean the text using a shared function
-    let segment_text = whisper_integration::clean_whisper_text(&raw_segment_text);
+    // A one-word phrase doesn't need the LanguageTool round-trip, see `cleanup.wake`
+    let segment_text = whisper_integration::clean_wake_text(&raw_segment_text);
 
     // Generate the embedding from the cleaned text
-    let segment_embedding = crate::bert::encode_sentence(&segment_text)?;
-
-    for (i, &wake_word) in WAKE_VARIANTS.iter().enumerate() {
-        let candidate_embedding = &WAKE_VARIANTS_EMBEDDINGS[i];
-        if candidate_embedding.is_empty() {
-            continue;
-        }
-        let similarity = crate::bert::cosine_similarity(&segment_embedding, candidate_embedding);
+    let segment_embedding = bert::encode_sentence(&segment_text)?;
 
+    let mut check = WakeCheck {
+        text: segment_text,
+        ..Default::default()
+    };
+    let scored = bert::nearest(&segment_embedding, WAKE_VARIANTS.iter().copied(), 1)?;
+    if let Some((phrase, similarity)) = scored.into_iter().next() {
         log::info!(
-            "Comparing cleaned segment '{}' with '{}': similarity = {:.3}",
-            segment_text,
-            wake_word,
+            "Closest wake variant to cleaned segment '{}': '{}', similarity = {:.3}",
+            check.text,
+            phrase,
             similarity
         );
+        check.variant = WAKE_VARIANTS.iter().copied().find(|&variant| variant == phrase);
+        check.similarity = similarity;
+    }
+    if !check.is_candidate(settings) {
+        return Ok(check);
+    }
 
-        if similarity > EMBEDDING_SIMILARITY_THRESHOLD {
-            log::info!("Wake word detected!");
-            return Ok(true);
-        }
+    if is_negative_phrase(&check.text, &settings.negative_phrases) {
+        log::info!("Wake word rejected: '{}' is a known false wake", check.text);
+        check.negative = true;
+        return Ok(check);
     }
 
-    Ok(false)
+    // Second signal: Whisper must have been confident about the tokens, as
+    // hallucinations on noise tend to have low probabilities
+    let tokens = segment_tokens(state, segment_index)?;
+    for (text, prob) in &tokens {
+        log::debug!("Wake token '{}' (p = {:.3})", text.trim(), prob);
+    }
+    let confidence = wake_token_confidence(&tokens).unwrap_or(0.0);
+    check.token_confidence = Some(confidence);
+    if confidence < settings.min_token_prob {
+        log::info!(
+            "Wake word rejected: token confidence {:.3} < {:.3}",
+            confidence,
+            settings.min_token_prob
+        );
+        return Ok(check);
+    }
+    log::info!("Wake word detected! (token confidence {:.3})", confidence);
+    check.woke = true;
+    Ok(check)
 }
 
 use std::sync::Arc;
@@ -142,12 +329,12 @@ use std::sync::Arc;
 pub async fn is_wake_word_present(
     state: Arc<WhisperState>,
     segment_index: i32,
-) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    settings: WakeSettings,
+) -> Result<WakeCheck, Box<dyn Error + Send + Sync>> {
     // Move the Arc into the blocking task
     let result = tokio::task::spawn_blocking(move || {
-        is_wake_word_present_sync(&state, segment_index)
+        is_wake_word_present_sync(&state, segment_index, &settings)
     })
     .await??;
     Ok(result)
 }
-
@@ -0,0 +1,151 @@
+use crate::events::{self, Event};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+
+/// Samples below this are digital zeros rather than a quiet room.
+const DIGITAL_SILENCE: f32 = 1e-6;
+
+/// Input monitoring, read from the `input.watchdog` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct WatchdogSettings {
+    pub enabled: bool,
+    /// Audio made only of zeros for this long is reported (muted mic, wrong source).
+    pub silence_secs: u64,
+    /// A device sending nothing for this long is reported (unplugged, taken by another app).
+    pub stall_secs: u64,
+    /// Also show a desktop notification, not only a log line and an event.
+    pub notify: bool,
+}
+
+impl Default for WatchdogSettings {
+    fn default() -> Self {
+        WatchdogSettings {
+            enabled: true,
+            silence_secs: 10,
+            stall_secs: 5,
+            notify: true,
+        }
+    }
+}
+
+/// What is wrong with an input device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Problem {
+    Silence,
+    Stall,
+}
+
+impl Problem {
+    pub fn name(self) -> &'static str {
+        match self {
+            Problem::Silence => "silence",
+            Problem::Stall => "stall",
+        }
+    }
+
+    /// What the user should check.
+    pub fn hint(self) -> &'static str {
+        match self {
+            Problem::Silence => concat!(
+                "the microphone only delivers silence: check that it isn't muted ",
+                "and that the right input is selected (input.devices)"
+            ),
+            Problem::Stall => concat!(
+                "the microphone stopped sending audio: it may have been unplugged ",
+                "or taken by another application"
+            ),
+        }
+    }
+}
+
+/// Watches the chunks of one device for digital silence and stalls.
+pub struct InputWatchdog {
+    settings: WatchdogSettings,
+    sample_rate: u32,
+    last_chunk: Instant,
+    /// Consecutive samples of digital silence.
+    silent_samples: usize,
+    /// The problem already reported, so it is reported once.
+    reported: Option<Problem>,
+}
+
+impl InputWatchdog {
+    /// Watches a device capturing at `sample_rate`.
+    pub fn new(settings: WatchdogSettings, sample_rate: u32) -> Self {
+        InputWatchdog {
+            settings,
+            sample_rate,
+            last_chunk: Instant::now(),
+            silent_samples: 0,
+            reported: None,
+        }
+    }
+
+    /// Restarts the stall timer when chunks are read again after a pause, e.g. after a
+    /// long transcription during which they simply queued up.
+    pub fn resume(&mut self, now: Instant) {
+        self.last_chunk = now;
+    }
+
+    /// Checks a chunk of mono samples; returns the problem the first time it is seen.
+    pub fn chunk(&mut self, chunk: &[f32], now: Instant) -> Option<Problem> {
+        self.last_chunk = now;
+        if !self.settings.enabled {
+            return None;
+        }
+
+        if chunk.iter().all(|sample| sample.abs() < DIGITAL_SILENCE) {
+            self.silent_samples += chunk.len();
+        } else {
+            self.silent_samples = 0;
+        }
+
+        let silence_limit = self.settings.silence_secs as usize * self.sample_rate as usize;
+        if self.silent_samples >= silence_limit {
+            return self.report(Problem::Silence);
+        }
+        if self.silent_samples == 0 && self.reported.take().is_some() {
+            log::info!("🎙️ Audio input is back");
+        }
+        None
+    }
+
+    /// Returns `Problem::Stall` the first time no chunk came for `stall_secs`.
+    pub fn check_stall(&mut self, now: Instant) -> Option<Problem> {
+        let stall = Duration::from_secs(self.settings.stall_secs);
+        if self.settings.enabled && now.duration_since(self.last_chunk) >= stall {
+            return self.report(Problem::Stall);
+        }
+        None
+    }
+
+    fn report(&mut self, problem: Problem) -> Option<Problem> {
+        if self.reported == Some(problem) {
+            return None;
+        }
+        self.reported = Some(problem);
+        Some(problem)
+    }
+}
+
+/// Logs `problem`, emits it as an event and, when enabled, shows a notification.
+pub fn alert(device: &str, problem: Problem, settings: &WatchdogSettings) {
+    log::warn!("⚠️ {}: {}", device, problem.hint());
+    events::emit(Event::InputAlert {
+        device,
+        problem: problem.name(),
+        hint: problem.hint(),
+    });
+
+    if settings.notify {
+        if let Err(e) = notify_rust::Notification::new()
+            .summary(&format!("VoxAurora: no audio from {}", device))
+            .body(problem.hint())
+            .show()
+        {
+            log::warn!("Failed to show input notification: {}", e);
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use crate::privacy;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::error::Error;
+
+/// Weather answers settings, read from the `weather` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct WeatherSettings {
+    /// Weather provider; only "open-meteo" is built in.
+    pub provider: String,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// Name of the place, used in answers.
+    pub place: Option<String>,
+}
+
+impl Default for WeatherSettings {
+    fn default() -> Self {
+        WeatherSettings {
+            provider: "open-meteo".to_string(),
+            latitude: None,
+            longitude: None,
+            place: None,
+        }
+    }
+}
+
+/// Current conditions at a location.
+#[derive(Debug, Clone)]
+pub struct WeatherReport {
+    pub temperature_c: f64,
+    pub wind_kmh: f64,
+    pub description: String,
+}
+
+/// A source of current weather conditions.
+pub trait WeatherProvider {
+    fn current(&self, latitude: f64, longitude: f64) -> Result<WeatherReport, Box<dyn Error>>;
+}
+
+/// The free, keyless Open-Meteo API.
+pub struct OpenMeteo;
+
+#[derive(Deserialize)]
+struct OpenMeteoResponse {
+    current: OpenMeteoCurrent,
+}
+
+#[derive(Deserialize)]
+struct OpenMeteoCurrent {
+    temperature_2m: f64,
+    wind_speed_10m: f64,
+    weather_code: u32,
+}
+
+impl WeatherProvider for OpenMeteo {
+    fn current(&self, latitude: f64, longitude: f64) -> Result<WeatherReport, Box<dyn Error>> {
+        let url = format!(
+            "https://api.open-meteo.com/v1/forecast?latitude={}&longitude={}&current=temperature_2m,wind_speed_10m,weather_code",
+            latitude, longitude
+        );
+        let body = ureq::get(&url)
+            .header("Accept", "application/json")
+            .call()?
+            .body_mut()
+            .read_to_string()?;
+        let response: OpenMeteoResponse = serde_json::from_str(&body)?;
+        Ok(WeatherReport {
+            temperature_c: response.current.temperature_2m,
+            wind_kmh: response.current.wind_speed_10m,
+            description: describe_wmo_code(response.current.weather_code).to_string(),
+        })
+    }
+}
+
+/// Builds the configured provider.
+pub fn provider(settings: &WeatherSettings) -> Result<Box<dyn WeatherProvider>, Box<dyn Error>> {
+    match settings.provider.as_str() {
+        "open-meteo" => Ok(Box::new(OpenMeteo)),
+        other => Err(format!("Unknown weather provider: '{}'", other).into()),
+    }
+}
+
+/// Answers "what's the weather" for the configured location.
+pub fn answer(settings: &WeatherSettings) -> String {
+    if let Err(e) = privacy::check("Weather") {
+        return e;
+    }
+    let (Some(latitude), Some(longitude)) = (settings.latitude, settings.longitude) else {
+        return "Set weather.latitude and weather.longitude to get weather answers".to_string();
+    };
+    let report = provider(settings).and_then(|provider| provider.current(latitude, longitude));
+    match report {
+        Ok(report) => format!(
+            "{}{}, {:.0}°C, wind {:.0} km/h",
+            report.description,
+            settings
+                .place
+                .as_ref()
+                .map(|place| format!(" in {}", place))
+                .unwrap_or_default(),
+            report.temperature_c,
+            report.wind_kmh
+        ),
+        Err(e) => {
+            log::error!("Failed to get the weather: {}", e);
+            "The weather is unavailable right now".to_string()
+        }
+    }
+}
+
+/// Describes a WMO weather interpretation code, as returned by Open-Meteo.
+fn describe_wmo_code(code: u32) -> &'static str {
+    match code {
+        0 => "Clear sky",
+        1 | 2 => "Partly cloudy",
+        3 => "Overcast",
+        45 | 48 => "Fog",
+        51..=57 => "Drizzle",
+        61..=67 => "Rain",
+        71..=77 => "Snow",
+        80..=82 => "Rain showers",
+        85 | 86 => "Snow showers",
+        95..=99 => "Thunderstorm",
+        _ => "Unknown conditions",
+    }
+}
@@ -1,33 +1,286 @@
+use crate::accents::{self, AccentSettings};
+use crate::audio::{self, WHISPER_SAMPLE_RATE};
+use crate::casing::{self, CasingSettings};
+use crate::content_filter::{self, FilterSettings};
 use crate::dawg_loader;
+use crate::decoding::{self, DecodeQuality};
+use crate::history;
+use crate::language;
+use crate::performance;
+use crate::platform;
+use crate::punctuation::{self, PunctuationSettings};
+use crate::supervisor::{self, Subsystem};
+use crate::telemetry;
+use crate::timeouts::{self, TimeoutFallback};
 use crate::bert;
-use once_cell::sync::Lazy;
+use once_cell::sync::{Lazy, OnceCell};
 use regex::Regex;
+use schemars::JsonSchema;
 use serde::Deserialize;
 use std::collections::HashMap;
 use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
 use std::process::{Child, Command};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak, mpsc};
 use std::thread;
 use std::time::Duration;
 use unicode_normalization::UnicodeNormalization;
 use ureq;
-use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters};
+use whisper_rs::{
+    FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters, WhisperState,
+};
 
-/// Global DAWGS: a tuple of (AhoCorasick for each language, word lists).
+/// Global DAWGS: a tuple of (AhoCorasick for each language, word lists, accent indexes).
 pub static DAWGS: Lazy<(
     HashMap<&'static str, daachorse::DoubleArrayAhoCorasick<u32>>,
-    HashMap<&'static str, Vec<String>>,
-)> = Lazy::new(|| dawg_loader::load_dawgs());
+    HashMap<&'static str, dawg_loader::WordList>,
+    HashMap<&'static str, accents::AccentIndex>,
+)> = Lazy::new(|| dawg_loader::load_dawgs(cleanup_settings().accents.enabled));
+
+/// Text cleanup settings, read from the `cleanup` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct CleanupSettings {
+    pub merge: MergeOptions,
+    pub accents: AccentSettings,
+    pub casing: CasingSettings,
+    pub filter: FilterSettings,
+    /// How the wake pass cleans its text before looking for the wake word.
+    pub wake: WakeCleaning,
+    /// Correct transcriptions with LanguageTool; when disabled its server isn't started.
+    pub languagetool: bool,
+    pub punctuation: PunctuationSettings,
+    /// Which stage of the cleanup each consumer of a transcription reads.
+    pub consumers: TextConsumers,
+}
+
+impl Default for CleanupSettings {
+    fn default() -> Self {
+        CleanupSettings {
+            merge: MergeOptions::default(),
+            accents: AccentSettings::default(),
+            casing: CasingSettings::default(),
+            filter: FilterSettings::default(),
+            wake: WakeCleaning::default(),
+            languagetool: true,
+            punctuation: PunctuationSettings::default(),
+            consumers: TextConsumers::default(),
+        }
+    }
+}
+
+/// A stage of the cleanup of a transcription.
+#[derive(Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TextStage {
+    /// Whisper tags stripped, sensitive words filtered and accents restored: what was
+    /// said, without LanguageTool rewording it.
+    RawCleaned,
+    /// The whole cleanup: LanguageTool, word merging, casing and punctuation.
+    Corrected,
+}
+
+/// Which stage each consumer reads, from the `cleanup.consumers` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct TextConsumers {
+    /// The wake word search, when `cleanup.wake` is `"full"`.
+    pub wakeword: TextStage,
+    /// Built-in and configured command matching.
+    pub matcher: TextStage,
+    /// Dictated text, typed or added to the dictation buffer.
+    pub typing: TextStage,
+    /// Transcripts written by `--caption`.
+    pub captions: TextStage,
+}
+
+impl Default for TextConsumers {
+    fn default() -> Self {
+        TextConsumers {
+            wakeword: TextStage::Corrected,
+            matcher: TextStage::RawCleaned,
+            typing: TextStage::Corrected,
+            captions: TextStage::Corrected,
+        }
+    }
+}
+
+/// A transcription at both stages of its cleanup.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Transcription {
+    pub raw_cleaned: String,
+    pub corrected: String,
+    /// How sure Whisper was of the decode, from 0 to 1; unknown for remote backends.
+    pub confidence: Option<f32>,
+}
+
+impl Transcription {
+    pub fn text(&self, stage: TextStage) -> &str {
+        match stage {
+            TextStage::RawCleaned => &self.raw_cleaned,
+            TextStage::Corrected => &self.corrected,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.raw_cleaned.is_empty() && self.corrected.is_empty()
+    }
+
+    /// Appends the transcription of the segment carrying on from this one. The
+    /// confidence is the lowest of the two.
+    pub fn stitch(self, next: Transcription) -> Transcription {
+        Transcription {
+            raw_cleaned: stitch_text(&self.raw_cleaned, &next.raw_cleaned),
+            corrected: stitch_text(&self.corrected, &next.corrected),
+            confidence: match (self.confidence, next.confidence) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            },
+        }
+    }
+}
+
+/// Most words two consecutive segments are expected to share, from their overlap.
+const MAX_STITCHED_WORDS: usize = 8;
+
+/// A word as compared across segments: lowercase, without punctuation.
+fn stitch_key(word: &str) -> String {
+    word.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Joins the transcripts of two overlapping segments, dropping the words of `previous`
+/// that `next` starts with again. The last word of `previous` may have been cut in the
+/// middle, so it is dropped too when the words before it are found.
+pub fn stitch_text(previous: &str, next: &str) -> String {
+    let before: Vec<&str> = previous.split_whitespace().collect();
+    let after: Vec<&str> = next.split_whitespace().collect();
+    let before_keys: Vec<String> = before.iter().map(|word| stitch_key(word)).collect();
+    let after_keys: Vec<String> = after.iter().map(|word| stitch_key(word)).collect();
+
+    // The longest run of shared words wins
+    let shared_run = (1..=MAX_STITCHED_WORDS.min(after.len())).rev().find_map(|shared| {
+        (0..=1).find_map(|cut| {
+            let end = before.len().checked_sub(cut)?;
+            let start = end.checked_sub(shared)?;
+            let tail = &before_keys[start..end];
+            (tail.iter().all(|key| !key.is_empty()) && *tail == after_keys[..shared])
+                .then_some(start)
+        })
+    });
+    let kept = shared_run.unwrap_or(before.len());
+    before[..kept]
+        .iter()
+        .chain(&after)
+        .copied()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Cleaning applied to the wake pass transcription.
+#[derive(Deserialize, JsonSchema, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WakeCleaning {
+    /// Tag stripping and lowercasing only: no LanguageTool round-trip for a one-word phrase.
+    #[default]
+    Light,
+    /// The full `clean_whisper_text` pipeline, as for commands.
+    Full,
+}
+
+/// Controls how aggressively separated tokens are merged back into single words.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct MergeOptions {
+    /// Maximum number of consecutive tokens considered for a single merge.
+    pub max_merge: usize,
+    /// Minimum merge score when merging 2 tokens whose spaced form is also a word.
+    pub threshold_two: f32,
+    /// Same as `threshold_two`, for 3 tokens.
+    pub threshold_three: f32,
+    /// Same as `threshold_two`, for 4 tokens or more.
+    pub threshold_more: f32,
+    /// Enables the special handling of short 2-token merges (e.g. "bon jour").
+    pub short_word_special_case: bool,
+    /// Merged words shorter than this (in bytes) count as "short".
+    pub short_word_max_len: usize,
+    /// Below this BERT plausibility score, a short merge is skipped if its spaced form exists.
+    pub short_word_min_bert_score: f32,
+    /// Use BERT to arbitrate when both the merged and spaced forms are dictionary words.
+    /// When disabled, only the dictionary evidence (exact vs. fuzzy match) is used.
+    pub use_bert: bool,
+}
+
+impl Default for MergeOptions {
+    fn default() -> Self {
+        MergeOptions {
+            max_merge: 2,
+            threshold_two: 0.70,
+            threshold_three: 0.75,
+            threshold_more: 0.80,
+            short_word_special_case: true,
+            short_word_max_len: 10,
+            short_word_min_bert_score: 0.1,
+            use_bert: true,
+        }
+    }
+}
+
+/// Process-wide cleanup settings, set once after the config is loaded.
+static CLEANUP_SETTINGS: OnceCell<CleanupSettings> = OnceCell::new();
+
+/// Installs the cleanup settings used by `clean_whisper_text`.
+pub fn init_cleanup(settings: CleanupSettings) {
+    if CLEANUP_SETTINGS.set(settings).is_err() {
+        log::warn!("Cleanup settings were already initialized, ignoring new values");
+    }
+}
+
+/// Returns the active cleanup settings, or the defaults if `init_cleanup` was never called.
+pub fn cleanup_settings() -> &'static CleanupSettings {
+    CLEANUP_SETTINGS.get_or_init(CleanupSettings::default)
+}
+
+/// The LanguageTool snapshot the cleanup uses, from the name of its folder, when
+/// `cleanup.languagetool` is on.
+pub fn languagetool_version() -> Option<String> {
+    if !cleanup_settings().languagetool {
+        return None;
+    }
+    let jar = languagetool_jar();
+    Some(jar.parent()?.file_name()?.to_string_lossy().into_owned())
+}
+
+/// The LanguageTool server jar the build script extracted into `tools/`.
+fn languagetool_jar() -> PathBuf {
+    let tools = platform::resource("tools");
+    // The snapshot's folder is named after its version; the latest one wins
+    fs::read_dir(&tools)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path().join("languagetool-server.jar"))
+        .filter(|jar| jar.is_file())
+        .max()
+        .unwrap_or_else(|| {
+            tools
+                .join("LanguageTool-6.6-SNAPSHOT")
+                .join("languagetool-server.jar")
+        })
+}
 
 /// Starts the LanguageTool server in the background and waits until it's ready.
 pub fn start_languagetool_server() -> Child {
-    let child = Command::new("java")
-        .args([
-            "-cp",
-            "tools/LanguageTool-6.6-SNAPSHOT/languagetool-server.jar",
-            "org.languagetool.server.HTTPServer",
-            "--port",
-            "8081",
-        ])
+    let jar = languagetool_jar();
+    let classpath = platform::classpath(&[jar]).expect("Invalid LanguageTool jar path");
+    let child = Command::new(platform::java())
+        .arg("-cp")
+        .arg(classpath)
+        .args(["org.languagetool.server.HTTPServer", "--port", "8081"])
         .spawn()
         .expect("Failed to start LanguageTool");
 
@@ -76,40 +329,584 @@ fn wait_for_languagetool_server() -> Result<(), Box<dyn Error>> {
     Err("LanguageTool server did not start in time".into())
 }
 
-/// Initializes the Whisper model with default parameters.
+/// Sends a short check to the LanguageTool server, failing unless it answers.
+pub fn ping_languagetool() -> Result<(), Box<dyn Error>> {
+    let request_url = format!(
+        "http://localhost:8081/v2/check?language=fr-FR&text={}",
+        urlencoding::encode("Bonjour")
+    );
+    ureq::get(&request_url)
+        .header("Accept", "application/json")
+        .call()?;
+    Ok(())
+}
+
+/// Whisper model settings, read from the `whisper` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct WhisperSettings {
+    /// Runs Whisper on the GPU backend VoxAurora was built with (the `cuda`, `metal` or
+    /// `vulkan` feature); CPU-only builds ignore it.
+    pub use_gpu: bool,
+    /// Index of the GPU to use, when there are several.
+    pub gpu_device: i32,
+    /// Threads of each transcription, overriding `performance.whisper_threads`.
+    pub n_threads: Option<i32>,
+    /// Inference states created with the model and reused by its transcriptions, so
+    /// each segment doesn't allocate its own. One per concurrent transcription is enough.
+    pub pooled_states: usize,
+}
+
+impl Default for WhisperSettings {
+    fn default() -> Self {
+        WhisperSettings {
+            use_gpu: true,
+            gpu_device: 0,
+            n_threads: None,
+            pooled_states: 2,
+        }
+    }
+}
+
+/// Process-wide Whisper settings, set once after the config is loaded.
+static WHISPER_SETTINGS: OnceCell<WhisperSettings> = OnceCell::new();
+
+/// Installs the Whisper settings used by `init_model`.
+pub fn init_whisper(settings: WhisperSettings) {
+    if WHISPER_SETTINGS.set(settings).is_err() {
+        log::warn!("Whisper settings were already initialized, ignoring new values");
+    }
+}
+
+/// Returns the active Whisper settings, or the defaults if `init_whisper` was never called.
+pub fn whisper_settings() -> &'static WhisperSettings {
+    WHISPER_SETTINGS.get_or_init(WhisperSettings::default)
+}
+
+/// The GPU backend whisper.cpp was built with, if any.
+pub fn gpu_backend() -> Option<&'static str> {
+    if cfg!(feature = "cuda") {
+        Some("CUDA")
+    } else if cfg!(feature = "metal") {
+        Some("Metal")
+    } else if cfg!(feature = "vulkan") {
+        Some("Vulkan")
+    } else {
+        None
+    }
+}
+
+/// Initializes the Whisper model, on the GPU when `whisper.use_gpu` allows it.
 pub fn init_model(path_to_model: String) -> Result<WhisperContext, Box<dyn Error>> {
-    let ctx = WhisperContext::new_with_params(&path_to_model, WhisperContextParameters::default())?;
+    let settings = whisper_settings();
+    let mut params = WhisperContextParameters::default();
+    match gpu_backend() {
+        Some(backend) if settings.use_gpu => {
+            log::info!("🚀 Running Whisper on {} device {}", backend, settings.gpu_device);
+            params.use_gpu(true).gpu_device(settings.gpu_device);
+        }
+        Some(_) => {
+            log::info!("Running Whisper on the CPU, whisper.use_gpu is off");
+            params.use_gpu(false);
+        }
+        None => {
+            params.use_gpu(false);
+        }
+    }
+    let ctx = WhisperContext::new_with_params(&path_to_model, params)?;
     Ok(ctx)
 }
 
+/// Idle states of a model, taken by its inferences and put back once they are done.
+struct StatePool {
+    model: Weak<WhisperContext>,
+    idle: Vec<WhisperState>,
+}
+
+/// Pools of the models registered with `pool_states`.
+static STATE_POOLS: Lazy<Mutex<Vec<StatePool>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// Creates `whisper.pooled_states` states for `model` up front, reused by every
+/// transcription it runs. Models that aren't registered create a state per inference.
+pub fn pool_states(model: &Arc<WhisperContext>) -> Result<(), Box<dyn Error>> {
+    let idle = (0..whisper_settings().pooled_states)
+        .map(|_| model.create_state())
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut pools = STATE_POOLS.lock().unwrap_or_else(|e| e.into_inner());
+    pools.retain(|pool| pool.model.strong_count() > 0);
+    pools.push(StatePool {
+        model: Arc::downgrade(model),
+        idle,
+    });
+    Ok(())
+}
+
+/// Frees the states of the models that were dropped, e.g. when parking them, as each
+/// state keeps its model in memory.
+pub fn release_states() {
+    let mut pools = STATE_POOLS.lock().unwrap_or_else(|e| e.into_inner());
+    pools.retain(|pool| pool.model.strong_count() > 0);
+}
+
+/// The pool of `model`. Pools of dropped models are skipped: another context may have
+/// been allocated where theirs was, and their states belong to the old one.
+fn pool_of<'a>(pools: &'a mut [StatePool], model: &WhisperContext) -> Option<&'a mut StatePool> {
+    pools
+        .iter_mut()
+        .find(|pool| pool.model.strong_count() > 0 && std::ptr::eq(pool.model.as_ptr(), model))
+}
+
+/// An idle state of `model` from its pool, or a new one.
+fn take_state(model: &WhisperContext) -> Result<WhisperState, Box<dyn Error>> {
+    let pooled = {
+        let mut pools = STATE_POOLS.lock().unwrap_or_else(|e| e.into_inner());
+        pool_of(&mut pools, model).and_then(|pool| pool.idle.pop())
+    };
+    match pooled {
+        Some(state) => Ok(state),
+        None => Ok(model.create_state()?),
+    }
+}
+
+/// Puts `state` back in the pool of `model`, unless it is full or there is none.
+fn put_back_state(model: &WhisperContext, state: WhisperState) {
+    let mut pools = STATE_POOLS.lock().unwrap_or_else(|e| e.into_inner());
+    let capacity = whisper_settings().pooled_states;
+    if let Some(pool) = pool_of(&mut pools, model).filter(|pool| pool.idle.len() < capacity) {
+        pool.idle.push(state);
+    }
+}
+
 /// Transcribes an audio segment asynchronously using Whisper.
 pub async fn transcribe(
     model: &WhisperContext,
     audio: &[f32],
     lang: &str,
+) -> Result<Transcription, Box<dyn Error>> {
+    performance::run_blocking(|| transcribe_sync(model, audio, lang, None))
+}
+
+/// Blocking version of `transcribe`. Whisper gives up early once `abort` is set,
+/// e.g. when another backend already answered.
+pub fn transcribe_sync(
+    model: &WhisperContext,
+    audio: &[f32],
+    lang: &str,
+    abort: Option<Arc<AtomicBool>>,
+) -> Result<Transcription, Box<dyn Error>> {
+    let (result, quality) =
+        supervisor::run(Subsystem::Whisper, || run_whisper(model, audio, lang, abort))??;
+    Ok(cleaned(&result, quality, lang))
+}
+
+/// `transcribe_sync` reusing `state`, for workers transcribing many segments in a row.
+pub fn transcribe_with_state(
+    state: &mut WhisperState,
+    audio: &[f32],
+    lang: &str,
+) -> Result<Transcription, Box<dyn Error>> {
+    let (result, quality) =
+        supervisor::run(Subsystem::Whisper, || decode(state, audio, lang, None))??;
+    Ok(cleaned(&result, quality, lang))
+}
+
+/// The cleaned up transcription of Whisper's raw text in `lang`.
+fn cleaned(result: &str, quality: Option<DecodeQuality>, lang: &str) -> Transcription {
+    let started = std::time::Instant::now();
+    let mut cleaned_result = clean_transcription_in(result, lang);
+    telemetry::record("cleanup", started, Vec::new(), None);
+    cleaned_result.confidence = quality.map(|quality| quality.confidence());
+    cleaned_result
+}
+
+/// Transcribes speech in another language than French: only Whisper tags are stripped
+/// and sensitive words filtered, as the correction stages are French-only.
+pub fn transcribe_foreign(
+    model: &WhisperContext,
+    audio: &[f32],
+    lang: &str,
 ) -> Result<String, Box<dyn Error>> {
-    let mut params = FullParams::new(SamplingStrategy::default());
-    params.set_print_special(false);
-    params.set_print_progress(false);
-    params.set_print_realtime(false);
-    params.set_token_timestamps(false);
-    params.set_language(Some(lang));
+    let (result, _) =
+        supervisor::run(Subsystem::Whisper, || run_whisper(model, audio, lang, None))??;
+    let clean = strip_whisper_tags(&result);
+    Ok(content_filter::apply_filter(clean.trim(), &cleanup_settings().filter))
+}
+
+/// Streaming transcription, read from the `streaming` section of the config.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct StreamingSettings {
+    /// Transcribes the speech of the first microphone on a sliding window while it is
+    /// recorded, so a command runs as soon as its trigger phrase is stable instead of
+    /// once the segment ends.
+    pub enabled: bool,
+    /// Most recent audio of the segment each pass decodes.
+    pub window_secs: f32,
+    /// Time between two passes.
+    pub hop_secs: f32,
+    /// Consecutive passes that must agree on a word before it is stable.
+    pub stable_passes: usize,
+}
+
+impl Default for StreamingSettings {
+    fn default() -> Self {
+        StreamingSettings {
+            enabled: false,
+            window_secs: 8.0,
+            hop_secs: 0.5,
+            stable_passes: 2,
+        }
+    }
+}
+
+/// Passes waiting to be decoded; later windows are dropped, the next one covering them.
+const MAX_PENDING_PASSES: usize = 2;
+
+/// What was heard of the segment being recorded.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PartialTranscript {
+    /// Number of the segment, counting the ones dropped as too short too.
+    pub segment: u64,
+    pub text: String,
+    /// The leading words of `text` the last `stable_passes` passes agree on.
+    pub stable: String,
+    /// Decoded from the last window once the segment ended. There is one per segment
+    /// returned by the capture, in order.
+    pub is_final: bool,
+}
+
+enum StreamMessage {
+    Window(Vec<f32>),
+    /// The segment ended; its last window is decoded anyway.
+    End(Vec<f32>),
+    Discard,
+}
+
+/// The capture side of a transcription stream: keeps the last window of the segment
+/// being recorded and has it decoded every hop.
+pub struct SpeechFeed {
+    sender: mpsc::Sender<StreamMessage>,
+    pending: Arc<AtomicUsize>,
+    window: usize,
+    hop: usize,
+    buffer: Vec<f32>,
+    since_pass: usize,
+    /// Fed every chunk of a segment, so it is resampled as one stream.
+    resampler: Option<audio::StreamResampler>,
+}
+
+impl SpeechFeed {
+    /// Adds a chunk of the segment being recorded, captured at `sample_rate`.
+    pub fn push(&mut self, chunk: &[f32], sample_rate: u32) {
+        if self.resampler.as_ref().is_some_and(|r| r.sample_rate() != sample_rate) {
+            self.resampler = None;
+        }
+        let chunk = self
+            .resampler
+            .get_or_insert_with(|| audio::StreamResampler::new(sample_rate))
+            .process(chunk);
+        self.since_pass += chunk.len();
+        self.buffer.extend(chunk);
+        let excess = self.buffer.len().saturating_sub(self.window);
+        self.buffer.drain(..excess);
+        if self.since_pass < self.hop {
+            return;
+        }
+        self.since_pass = 0;
+        if self.pending.load(Ordering::Relaxed) >= MAX_PENDING_PASSES {
+            log::debug!("Streaming transcription lags behind, skipping a pass");
+            return;
+        }
+        self.pending.fetch_add(1, Ordering::Relaxed);
+        self.send(StreamMessage::Window(self.buffer.clone()));
+    }
+
+    /// Ends the segment, which gets its final transcript.
+    pub fn finish(&mut self) {
+        self.since_pass = 0;
+        self.reset_resampler();
+        let window = std::mem::take(&mut self.buffer);
+        self.send(StreamMessage::End(window));
+    }
+
+    /// Forgets the segment being recorded, dropped as too short.
+    pub fn discard(&mut self) {
+        self.since_pass = 0;
+        self.buffer.clear();
+        self.reset_resampler();
+        self.send(StreamMessage::Discard);
+    }
+
+    /// Starts the next segment as a new stream.
+    fn reset_resampler(&mut self) {
+        if let Some(resampler) = &mut self.resampler {
+            resampler.reset();
+        }
+    }
+
+    fn send(&self, message: StreamMessage) {
+        if self.sender.send(message).is_err() {
+            log::error!("The streaming transcription thread stopped");
+        }
+    }
+}
+
+/// The model a transcription stream decodes with, e.g. once reloaded after parking.
+/// Passes are skipped while it is unloaded.
+#[derive(Clone)]
+pub struct StreamModel(Arc<Mutex<Weak<WhisperContext>>>);
+
+impl StreamModel {
+    pub fn set(&self, model: &Arc<WhisperContext>) {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner()) = Arc::downgrade(model);
+    }
 
-    // Create a new state for this inference
-    let mut state = model.create_state()?;
+    fn get(&self) -> Option<Arc<WhisperContext>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).upgrade()
+    }
+}
 
-    // Process the audio data
-    state.full(params, audio)?;
+/// The pipeline side of a transcription stream.
+pub struct SpeechStream {
+    pub partials: tokio::sync::mpsc::UnboundedReceiver<PartialTranscript>,
+    pub model: StreamModel,
+}
+
+/// Starts the thread decoding the windows of a `SpeechFeed` with `model`.
+pub fn start_streaming(
+    settings: &StreamingSettings,
+    model: &Arc<WhisperContext>,
+) -> (SpeechFeed, SpeechStream) {
+    let rate = WHISPER_SAMPLE_RATE as f32;
+    let window = ((settings.window_secs.max(1.0) * rate) as usize).max(1);
+    let hop = ((settings.hop_secs.max(0.1) * rate) as usize).clamp(1, window);
+    let (sender, receiver) = mpsc::channel();
+    let (partials, partials_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let pending = Arc::new(AtomicUsize::new(0));
+    let model = StreamModel(Arc::new(Mutex::new(Arc::downgrade(model))));
+    let stable_passes = settings.stable_passes.max(1);
+    thread::spawn({
+        let (pending, model) = (pending.clone(), model.clone());
+        move || stream_transcripts(receiver, &pending, &model, stable_passes, partials)
+    });
+    log::info!(
+        "🌊 Transcribing the last {:.1} s of speech every {:.1} s",
+        window as f32 / rate,
+        hop as f32 / rate
+    );
+    let feed = SpeechFeed {
+        sender,
+        pending,
+        window,
+        hop,
+        buffer: Vec::with_capacity(window),
+        since_pass: 0,
+        resampler: None,
+    };
+    let stream = SpeechStream {
+        partials: partials_receiver,
+        model,
+    };
+    (feed, stream)
+}
 
-    // Concatenate all segments
+fn stream_transcripts(
+    messages: mpsc::Receiver<StreamMessage>,
+    pending: &AtomicUsize,
+    model: &StreamModel,
+    stable_passes: usize,
+    partials: tokio::sync::mpsc::UnboundedSender<PartialTranscript>,
+) {
+    let mut segment = 0;
+    // The latest passes of the segment, oldest first
+    let mut passes: Vec<String> = Vec::new();
+    for message in messages {
+        let (window, is_final) = match message {
+            StreamMessage::Window(window) => {
+                pending.fetch_sub(1, Ordering::Relaxed);
+                (window, false)
+            }
+            StreamMessage::End(window) => (window, true),
+            StreamMessage::Discard => {
+                segment += 1;
+                passes.clear();
+                continue;
+            }
+        };
+        let text = match model.get() {
+            Some(model) if !window.is_empty() => decode_window(&model, &window),
+            _ => String::new(),
+        };
+        let stable = if is_final {
+            text.clone()
+        } else {
+            passes.push(text.clone());
+            let excess = passes.len().saturating_sub(stable_passes);
+            passes.drain(..excess);
+            if passes.len() < stable_passes {
+                String::new()
+            } else {
+                stable_prefix(&passes)
+            }
+        };
+        let partial = PartialTranscript {
+            segment,
+            text,
+            stable,
+            is_final,
+        };
+        if partials.send(partial).is_err() {
+            return;
+        }
+        if is_final {
+            segment += 1;
+            passes.clear();
+        }
+    }
+}
+
+/// Raw text of one window of a stream, with Whisper's tags stripped and sensitive words
+/// filtered; the slower cleanup stages are left to whoever acts on it.
+fn decode_window(model: &WhisperContext, window: &[f32]) -> String {
+    let lang = language::undetected_language();
+    match supervisor::run(Subsystem::Whisper, || run_whisper(model, window, lang, None)) {
+        Ok(Ok((text, _))) => {
+            let clean = strip_whisper_tags(&text);
+            content_filter::apply_filter(clean.trim(), &cleanup_settings().filter)
+        }
+        Ok(Err(e)) => {
+            log::error!("Error transcribing a streamed window: {}", e);
+            String::new()
+        }
+        Err(e) => {
+            log::error!("Streamed window skipped: {}", e);
+            String::new()
+        }
+    }
+}
+
+/// The leading words of the last of `passes` that every pass starts with, compared
+/// without case or punctuation.
+pub fn stable_prefix(passes: &[String]) -> String {
+    let Some(last) = passes.last() else {
+        return String::new();
+    };
+    let words: Vec<&str> = last.split_whitespace().collect();
+    let agreed = passes.iter().fold(words.len(), |agreed, pass| {
+        let shared = pass
+            .split_whitespace()
+            .zip(&words)
+            .take_while(|(word, last)| stitch_key(word) == stitch_key(last))
+            .count();
+        agreed.min(shared)
+    });
+    words[..agreed].join(" ")
+}
+
+/// Runs Whisper on `audio`, returning the concatenated raw text of its segments and how
+/// good the decode looks, unless nothing was decoded. A decode that repeats itself or
+/// has a low log-probability is retried at the next `decoding.temperatures`; when none
+/// looks right, the most probable one is kept.
+fn run_whisper(
+    model: &WhisperContext,
+    audio: &[f32],
+    lang: &str,
+    abort: Option<Arc<AtomicBool>>,
+) -> Result<(String, Option<DecodeQuality>), Box<dyn Error>> {
+    // The state is reused by the retries, then by the next inference
+    let mut state = take_state(model)?;
+    let decoded = decode(&mut state, audio, lang, abort);
+    if decoded.is_ok() {
+        put_back_state(model, state);
+    }
+    decoded
+}
+
+/// `run_whisper` with a state already created.
+fn decode(
+    state: &mut WhisperState,
+    audio: &[f32],
+    lang: &str,
+    abort: Option<Arc<AtomicBool>>,
+) -> Result<(String, Option<DecodeQuality>), Box<dyn Error>> {
+    let settings = decoding::settings();
+    let mut best: Option<(String, DecodeQuality)> = None;
+    let temperatures: &[f32] = if settings.temperatures.is_empty() || settings.reproducible {
+        &[0.0]
+    } else {
+        &settings.temperatures
+    };
+
+    for &temperature in temperatures {
+        let strategy = if settings.reproducible {
+            SamplingStrategy::Greedy { best_of: 1 }
+        } else {
+            SamplingStrategy::default()
+        };
+        let mut params = FullParams::new(strategy);
+        params.set_print_special(false);
+        params.set_print_progress(false);
+        params.set_print_realtime(false);
+        params.set_token_timestamps(false);
+        params.set_language(Some(lang));
+        // Reused states must not prompt the decoder with the previous utterance
+        params.set_no_context(true);
+        // The fallback is driven from here, so whisper.cpp's own one is turned off
+        params.set_temperature(temperature);
+        params.set_temperature_inc(0.0);
+        performance::apply_whisper_threads(&mut params);
+        if settings.reproducible {
+            params.set_n_threads(decoding::REPRODUCIBLE_THREADS);
+        }
+        if let Some(abort) = abort.clone() {
+            params.set_abort_callback_safe(move || abort.load(Ordering::Relaxed));
+        }
+
+        // Process the audio data
+        state.full(params, audio)?;
+        let (text, token_probs) = segments_text(state)?;
+        let quality = DecodeQuality::measure(text.trim(), &token_probs);
+        if quality.acceptable(settings) {
+            return Ok((text, Some(quality)));
+        }
+        log::info!(
+            "🌡️ Poor decode at temperature {:.1} (compression ratio {:.2}, log-prob {:.2})",
+            temperature,
+            quality.compression_ratio,
+            quality.avg_logprob
+        );
+        if best
+            .as_ref()
+            .is_none_or(|(_, best)| quality.avg_logprob > best.avg_logprob)
+        {
+            best = Some((text, quality));
+        }
+        if abort.as_ref().is_some_and(|abort| abort.load(Ordering::Relaxed)) {
+            break;
+        }
+    }
+    Ok(match best {
+        Some((text, quality)) => (text, Some(quality)),
+        None => (String::new(), None),
+    })
+}
+
+/// Concatenates the text of every segment of `state`, with the probabilities of its
+/// text tokens. Segments are separated by a single space and nothing follows the last
+/// one: what is typed after the text is up to the `output` format.
+fn segments_text(state: &WhisperState) -> Result<(String, Vec<f32>), Box<dyn Error>> {
     let num_segments = state.full_n_segments()?;
-    let mut result = String::new();
+    let mut segments = Vec::new();
+    let mut token_probs = Vec::new();
     for seg in 0..num_segments {
         let num_tokens = state.full_n_tokens(seg)?;
         let mut segment_text = String::new();
         for token in 0..num_tokens {
             let token_text = state.full_get_token_text(seg, token)?;
             let token_text = token_text.trim();
+            if !token_text.starts_with("[_") {
+                token_probs.push(state.full_get_token_prob(seg, token)?);
+            }
             // Add a space if needed, except for punctuation or special markers
             if !segment_text.is_empty()
                 && !token_text.starts_with(|c: char| c.is_ascii_punctuation())
@@ -119,38 +916,118 @@ pub async fn transcribe(
             }
             segment_text.push_str(token_text);
         }
-        result.push_str(segment_text.trim());
-        result.push(' ');
+        let segment_text = segment_text.trim();
+        if !segment_text.is_empty() {
+            segments.push(segment_text.to_string());
+        }
     }
-
-    // Call our cleaning function
-    let cleaned_result = clean_whisper_text(&result);
-    Ok(cleaned_result)
+    Ok((segments.join(" "), token_probs))
 }
 
 /// Cleans up Whisper text by removing special tags, normalizing whitespace,
-/// and calling LanguageTool for correction.
+/// restoring missing accents, calling LanguageTool for correction and
+/// re-applying the original casing.
 pub fn clean_whisper_text(original: &str) -> String {
-    // Remove special tags like [_BEG_] or [_TT_...]
-    let re_beg = Regex::new(r"\[_BEG_\]").unwrap();
-    let re_tt = Regex::new(r"\[_TT_\d+\]").unwrap();
-    let mut clean = re_beg.replace_all(original, "").to_string();
-    clean = re_tt.replace_all(&clean, "").to_string();
+    clean_transcription(original).corrected
+}
 
-    // Remove multiple spaces
-    let re_spaces = Regex::new(r"\s+").unwrap();
-    clean = re_spaces.replace_all(&clean, " ").to_string();
+/// `clean_whisper_text`, keeping the text as it was before LanguageTool too.
+pub fn clean_transcription(original: &str) -> Transcription {
+    clean_with(original, "fr", None)
+}
+
+/// `clean_transcription` of text in `lang`: LanguageTool corrects it in that language.
+/// When utterances are detected, only the dictionary of `lang` restores accents and
+/// merges words; otherwise every dictionary does, for mixed dictation.
+pub fn clean_transcription_in(original: &str, lang: &str) -> Transcription {
+    clean_with(original, lang, language::dictionary_language(lang, language::settings()))
+}
+
+/// `clean_transcription` with LanguageTool in `lang` and the dictionary of `dictionary`,
+/// or all of them when `None`.
+fn clean_with(original: &str, lang: &str, dictionary: Option<&str>) -> Transcription {
+    // Filter sensitive words before anything is logged or sent to LanguageTool
+    let clean = strip_whisper_tags(original);
+    let clean = content_filter::apply_filter(&clean, &cleanup_settings().filter);
 
     log::info!("Text before correction: {}", clean);
 
+    // Restore accents Whisper dropped, so LanguageTool and the DAWG see real words
+    let accented =
+        accents::restore_accents_in(clean.trim(), &cleanup_settings().accents, dictionary);
+
     // Call LanguageTool
-    let lang_tooled = burt_correct_text(clean.trim());
+    // A LanguageTool failure only costs its corrections, not the utterance
+    let lang_tooled = if cleanup_settings().languagetool {
+        let stage = &timeouts::settings().correction;
+        let corrected = timeouts::run_on_thread("LanguageTool", stage, {
+            let (accented, lang) = (accented.clone(), lang.to_string());
+            move || {
+                supervisor::run(Subsystem::LanguageTool, || burt_correct_text(&accented, &lang))
+            }
+        });
+        match corrected {
+            Some(Ok(corrected)) => corrected,
+            Some(Err(e)) => {
+                log::warn!("Skipping LanguageTool: {}", e);
+                accented.clone()
+            }
+            None if stage.fallback == TimeoutFallback::RawText => {
+                return Transcription {
+                    corrected: accented.clone(),
+                    raw_cleaned: accented,
+                    confidence: None,
+                };
+            }
+            None => accented.clone(),
+        }
+    } else {
+        accented.clone()
+    };
 
     // Then merge separated words using DAWG
-    let corrected = merge_separated_words_dawg_regex(&lang_tooled, 2);
+    let merged = merge_separated_words_in(&lang_tooled, &cleanup_settings().merge, dictionary);
+
+    // Finally restore casing the correction stages may have lost
+    let cased = casing::restore_casing(&accented, &merged, &cleanup_settings().casing);
+
+    // Close the sentences Whisper left open on short segments
+    let punctuated = punctuation::restore(&cased, &cleanup_settings().punctuation);
+
+    // Corrections and merges can form new sensitive words, so filter again
+    let corrected = content_filter::apply_filter(&punctuated, &cleanup_settings().filter);
     log::info!("Text after correction: {}", corrected);
+    history::record_correction(&clean, &corrected);
 
-    corrected
+    Transcription {
+        raw_cleaned: accented,
+        corrected,
+        confidence: None,
+    }
+}
+
+/// Cleaning for the wake pass: Whisper tags stripped and lowercased, without accents,
+/// LanguageTool or merging. Switch `cleanup.wake` to `"full"` for the whole pipeline.
+pub fn clean_wake_text(original: &str) -> String {
+    if cleanup_settings().wake == WakeCleaning::Full {
+        let transcription = clean_transcription(original);
+        return transcription.text(cleanup_settings().consumers.wakeword).to_string();
+    }
+    let clean = strip_whisper_tags(original).trim().to_lowercase();
+    // Still filtered, as the wake text is logged
+    content_filter::apply_filter(&clean, &cleanup_settings().filter)
+}
+
+/// Removes special tags like [_BEG_] or [_TT_...] and collapses whitespace.
+fn strip_whisper_tags(original: &str) -> String {
+    let re_beg = Regex::new(r"\[_BEG_\]").unwrap();
+    let re_tt = Regex::new(r"\[_TT_\d+\]").unwrap();
+    let mut clean = re_beg.replace_all(original, "").to_string();
+    clean = re_tt.replace_all(&clean, "").to_string();
+
+    // Remove multiple spaces
+    let re_spaces = Regex::new(r"\s+").unwrap();
+    re_spaces.replace_all(&clean, " ").to_string()
 }
 
 /// Data structure for the LanguageTool JSON response
@@ -175,13 +1052,24 @@ struct LTResponse {
     matches: Vec<Match>,
 }
 
-/// Calls LanguageTool to correct the text using the server at port 8081.
-pub fn burt_correct_text(text: &str) -> String {
+/// LanguageTool's code for the Whisper language `lang`: a variant is needed for the
+/// languages whose spelling differs between countries.
+fn languagetool_language(lang: &str) -> &str {
+    match lang {
+        "en" => "en-US",
+        "de" => "de-DE",
+        "pt" => "pt-PT",
+        other => other,
+    }
+}
+
+/// Calls LanguageTool to correct the text in `lang` using the server at port 8081.
+pub fn burt_correct_text(text: &str, lang: &str) -> String {
     let base_url = "http://localhost:8081/v2/check";
     let request_url = format!(
         "{}?language={}&text={}",
         base_url,
-        "fr",
+        languagetool_language(lang),
         urlencoding::encode(text)
     );
 
@@ -223,13 +1111,25 @@ pub fn burt_correct_text(text: &str) -> String {
     corrected
 }
 
-/// Checks whether a word is "reasonable": length <= 20, only alphabetic or apostrophes
+/// Checks whether a word is "reasonable": length <= 20, only alphabetic, apostrophes or hyphens
 fn is_reasonable_word(word: &str) -> bool {
-    word.len() <= 20 && word.chars().all(|c| c.is_alphabetic() || c == '\'')
+    word.len() <= 20
+        && word
+            .chars()
+            .all(|c| c.is_alphabetic() || c == '\'' || c == '’' || c == '-')
 }
 
+/// Separators Whisper tends to drop between tokens ("aujourd hui", "peut être").
+const JOINERS: &[&str] = &["'", "’", "-"];
+
 /// Main entry point for merging separated tokens if they appear in the DAWG
-pub fn merge_separated_words_dawg_regex(text: &str, max_merge: usize) -> String {
+pub fn merge_separated_words_dawg_regex(text: &str, options: &MergeOptions) -> String {
+    merge_separated_words_in(text, options, None)
+}
+
+/// `merge_separated_words_dawg_regex` with the dictionary of `lang` only, or all of them
+/// when `None`.
+pub fn merge_separated_words_in(text: &str, options: &MergeOptions, lang: Option<&str>) -> String {
     let token_matches = get_token_matches(text);
 
     log::info!(
@@ -244,7 +1144,7 @@ pub fn merge_separated_words_dawg_regex(text: &str, max_merge: usize) -> String
     while i < token_matches.len() {
         // Attempt to merge several consecutive tokens if possible
         if let Some((merged_word, merged_count)) =
-            try_merge_tokens(text, &token_matches, i, max_merge)
+            try_merge_tokens(text, &token_matches, i, options, lang)
         {
             // If merge succeeds
             let token_start = token_matches[i].start();
@@ -278,15 +1178,16 @@ fn get_token_matches(text: &str) -> Vec<regex::Match<'_>> {
     re.find_iter(text).collect()
 }
 
-/// Attempts to merge multiple consecutive tokens, starting from max_merge down to 2.
+/// Attempts to merge multiple consecutive tokens, starting from `options.max_merge` down to 2.
 /// Returns Some((merged_word, number_of_tokens_merged)) if successful, None otherwise.
 fn try_merge_tokens(
     text: &str,
     token_matches: &[regex::Match<'_>],
     start_index: usize,
-    max_merge: usize,
+    options: &MergeOptions,
+    lang: Option<&str>,
 ) -> Option<(String, usize)> {
-    for merge_len in (2..=max_merge).rev() {
+    for merge_len in (2..=options.max_merge).rev() {
         if start_index + merge_len <= token_matches.len() {
             // Verify that tokens are adjacent and only separated by whitespace
             if !are_tokens_adjacent(text, token_matches, start_index, merge_len) {
@@ -294,8 +1195,12 @@ fn try_merge_tokens(
             }
 
             // Build both the merged and spaced versions
-            let (candidate, candidate_with_space, candidate_lower, candidate_with_space_lower) =
-                build_candidates(text, token_matches, start_index, merge_len);
+            let (
+                mut candidate,
+                candidate_with_space,
+                mut candidate_lower,
+                candidate_with_space_lower,
+            ) = build_candidates(text, token_matches, start_index, merge_len);
 
             log::info!(
                 "Checking candidate: '{}' (from '{}')",
@@ -309,21 +1214,38 @@ fn try_merge_tokens(
             }
 
             // Check if the candidate exists in any DAWG
-            let (in_dawg, spaced_in_dawg) =
-                check_in_dawg(&candidate_lower, &candidate_with_space_lower);
+            let (mut in_dawg, mut exact_in_dawg, spaced_in_dawg) =
+                check_in_dawg(&candidate_lower, &candidate_with_space_lower, lang);
+
+            // The plain concatenation isn't a word: try restoring a lost apostrophe or hyphen
+            if !exact_in_dawg {
+                let tokens: Vec<&str> = token_matches[start_index..start_index + merge_len]
+                    .iter()
+                    .map(|m| m.as_str())
+                    .collect();
+                if let Some((variant, variant_lower)) = find_punctuated_variant(&tokens, lang) {
+                    log::info!(
+                        "Found punctuated variant '{}' for '{}'",
+                        variant_lower,
+                        candidate_with_space_lower
+                    );
+                    candidate = variant;
+                    candidate_lower = variant_lower;
+                    in_dawg = true;
+                    exact_in_dawg = true;
+                }
+            }
 
             if in_dawg {
-                let merge_score = compute_merge_score(&candidate_lower, merge_len);
-                log::info!("Merge score for '{}': {:.2}", candidate_lower, merge_score);
-
                 // Decide whether to merge or not
                 if let Some((word, count)) = handle_merge_decision(
                     &candidate,
                     &candidate_lower,
                     &candidate_with_space,
+                    exact_in_dawg,
                     spaced_in_dawg,
                     merge_len,
-                    merge_score,
+                    options,
                 ) {
                     return Some((word, count));
                 }
@@ -381,15 +1303,58 @@ fn build_candidates(
     )
 }
 
-/// Checks whether the merged word (and its spaced variant) is present in any DAWG.
-fn check_in_dawg(candidate_lower: &str, candidate_with_space_lower: &str) -> (bool, bool) {
-    let mut in_dawg = false;
+/// Joins `tokens` with every combination of apostrophes, hyphens or nothing at the
+/// boundaries, and returns the first variant that is an exact dictionary word,
+/// both as written and lowercased. The plain concatenation is not tried.
+fn find_punctuated_variant(tokens: &[&str], lang: Option<&str>) -> Option<(String, String)> {
+    let choices = JOINERS.len() + 1;
+    let combinations = choices.pow(tokens.len().saturating_sub(1) as u32);
+
+    // Combination 0 means "no separator anywhere", i.e. the plain concatenation
+    for combination in 1..combinations {
+        let mut remaining = combination;
+        let mut variant = tokens[0].to_string();
+        for token in &tokens[1..] {
+            let choice = remaining % choices;
+            remaining /= choices;
+            if choice > 0 {
+                variant.push_str(JOINERS[choice - 1]);
+            }
+            variant.push_str(token);
+        }
+
+        let variant_lower = variant.nfkc().collect::<String>().to_lowercase();
+        if DAWGS
+            .0
+            .iter()
+            .filter(|(code, _)| dawg_loader::in_language(code, lang))
+            .any(|(_, dawg)| dawg_loader::contains_exact(dawg, &variant_lower))
+        {
+            return Some((variant, variant_lower));
+        }
+    }
+    None
+}
+
+/// Checks whether the merged word (and its spaced variant) is present in any DAWG of `lang`.
+/// Returns `(in_dawg, exact_in_dawg, spaced_in_dawg)`, where `in_dawg` also accepts fuzzy matches.
+fn check_in_dawg(
+    candidate_lower: &str,
+    candidate_with_space_lower: &str,
+    lang: Option<&str>,
+) -> (bool, bool, bool) {
+    let mut exact_in_dawg = false;
+    let mut similar_in_dawg = false;
     let mut spaced_in_dawg = false;
 
-    for (lang, dawg) in DAWGS.0.iter() {
+    let dictionaries = DAWGS
+        .0
+        .iter()
+        .filter(|(code, _)| dawg_loader::in_language(code, lang));
+    for (lang, dawg) in dictionaries {
         if dawg_loader::contains_exact(dawg, candidate_lower) {
             log::debug!("Found '{}' in {} DAWG", candidate_lower, lang);
-            in_dawg = true;
+            exact_in_dawg = true;
         }
         if dawg_loader::contains_exact(dawg, candidate_with_space_lower) {
             log::debug!(
@@ -400,15 +1365,18 @@ fn check_in_dawg(candidate_lower: &str, candidate_with_space_lower: &str) -> (bo
             spaced_in_dawg = true;
         }
 
+        if exact_in_dawg {
+            continue;
+        }
         if let Some(word_list) = DAWGS.1.get(lang) {
-            if dawg_loader::is_most_similar(word_list, candidate_lower, 1) {
+            if dawg_loader::is_most_similar_bucketed(word_list, candidate_lower, 1) {
                 log::debug!("Found '{}' as similar in {} DAWG", candidate_lower, lang);
-                in_dawg = true;
+                similar_in_dawg = true;
             }
         }
     }
 
-    (in_dawg, spaced_in_dawg)
+    (exact_in_dawg || similar_in_dawg, exact_in_dawg, spaced_in_dawg)
 }
 
 /// Decides whether to merge tokens, based on various conditions (score, spaced variant existence, etc.).
@@ -416,21 +1384,34 @@ fn handle_merge_decision(
     candidate: &str,
     candidate_lower: &str,
     candidate_with_space: &str,
+    exact_in_dawg: bool,
     spaced_in_dawg: bool,
     merge_len: usize,
-    merge_score: f32,
+    options: &MergeOptions,
 ) -> Option<(String, usize)> {
-    // Special case: merging 2 short words (< 10 letters)
-    let short_common_word = (merge_len == 2) && (candidate_lower.len() < 10);
+    // Only the merged form is a word: nothing to arbitrate
+    if !spaced_in_dawg {
+        log::info!("Merging: '{}' [spaced version not in dictionary]", candidate);
+        return Some((candidate.to_string(), merge_len));
+    }
+
+    // Both forms are words: plausibility evidence is the tie-breaker
+    let plausibility = merge_plausibility(candidate_lower, exact_in_dawg, options);
+    let merge_score = compute_merge_score(candidate_lower, merge_len, plausibility);
+    log::info!("Merge score for '{}': {:.2}", candidate_lower, merge_score);
+
+    // Special case: merging 2 short words (< short_word_max_len letters)
+    let short_common_word = options.short_word_special_case
+        && (merge_len == 2)
+        && (candidate_lower.len() < options.short_word_max_len);
 
     if short_common_word {
-        let bert_score = check_word_with_bert(candidate_lower).unwrap_or(0.0);
-        if spaced_in_dawg && bert_score < 0.1 {
+        if plausibility < options.short_word_min_bert_score {
             log::info!(
-                "Not merging common short expression: '{}' (keeping '{}') [BERT score: {:.2}]",
+                "Not merging common short expression: '{}' (keeping '{}') [plausibility: {:.2}]",
                 candidate,
                 candidate_with_space,
-                bert_score
+                plausibility
             );
             None
         } else {
@@ -443,12 +1424,12 @@ fn handle_merge_decision(
         }
     } else {
         let threshold = match merge_len {
-            2 => 0.70,
-            3 => 0.75,
-            _ => 0.80,
+            2 => options.threshold_two,
+            3 => options.threshold_three,
+            _ => options.threshold_more,
         };
 
-        if !spaced_in_dawg || merge_score >= threshold {
+        if merge_score >= threshold {
             log::info!(
                 "Merging: '{}' [score: {:.2} >= {:.2}]",
                 candidate,
@@ -467,8 +1448,24 @@ fn handle_merge_decision(
     }
 }
 
-/// Computes a merge score in [0..1].
-fn compute_merge_score(word: &str, merge_len: usize) -> f32 {
+/// Scores how plausible a merged word is, in [0..1].
+/// Uses BERT when enabled, otherwise only the dictionary evidence (exact vs. fuzzy match).
+fn merge_plausibility(word: &str, exact_in_dawg: bool, options: &MergeOptions) -> f32 {
+    if !options.use_bert {
+        return if exact_in_dawg { 1.0 } else { 0.0 };
+    }
+
+    match check_word_with_bert(word) {
+        Ok(score) => score,
+        Err(_) => {
+            log::warn!("BERT check failed for '{}'", word);
+            0.0
+        }
+    }
+}
+
+/// Computes a merge score in [0..1] from the word shape and its plausibility.
+fn compute_merge_score(word: &str, merge_len: usize, plausibility: f32) -> f32 {
     let len = word.len();
 
     // If the length is outside [3..20], return 0
@@ -486,16 +1483,10 @@ fn compute_merge_score(word: &str, merge_len: usize) -> f32 {
     // Slight penalty for very short words
     let length_penalty = if len < 5 { -0.05 } else { 0.0 };
 
-    // BERT score (approx. [0..1])
-    let bert_score = match check_word_with_bert(word) {
-        Ok(s) => s * 0.10, // Weighted to avoid an all-or-nothing effect
-        Err(_) => {
-            log::warn!("BERT check failed for '{}'", word);
-            0.0
-        }
-    };
+    // Weighted to avoid an all-or-nothing effect
+    let plausibility_score = plausibility * 0.10;
 
-    let total = base_score + length_penalty + bert_score;
+    let total = base_score + length_penalty + plausibility_score;
     total.clamp(0.0, 1.0)
 }
 
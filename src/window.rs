@@ -0,0 +1,94 @@
+use std::process::Command;
+
+/// The window that currently has the keyboard focus.
+#[derive(Clone, Debug, Default)]
+pub struct FocusedWindow {
+    /// Application name or window class (e.g. "firefox", "KeePassXC").
+    pub app: String,
+    /// Window title, when the platform exposes it.
+    pub title: String,
+}
+
+impl FocusedWindow {
+    /// Whether `pattern` appears in the app name or title, ignoring case.
+    pub fn matches(&self, pattern: &str) -> bool {
+        let pattern = pattern.to_lowercase();
+        self.app.to_lowercase().contains(&pattern) || self.title.to_lowercase().contains(&pattern)
+    }
+}
+
+/// Returns the focused window, or `None` if it can't be detected on this system.
+pub fn focused_window() -> Option<FocusedWindow> {
+    let window = detect();
+    if window.is_none() {
+        log::debug!("Focused window could not be detected");
+    }
+    window
+}
+
+#[cfg(target_os = "linux")]
+fn detect() -> Option<FocusedWindow> {
+    // X11 only; Wayland compositors don't expose the focused window to other clients
+    let app = run("xdotool", &["getactivewindow", "getwindowclassname"])?;
+    let title = run("xdotool", &["getactivewindow", "getwindowname"]).unwrap_or_default();
+    Some(FocusedWindow { app, title })
+}
+
+#[cfg(target_os = "macos")]
+fn detect() -> Option<FocusedWindow> {
+    let app = run(
+        "osascript",
+        &[
+            "-e",
+            "tell application \"System Events\" to get name of first application process whose frontmost is true",
+        ],
+    )?;
+    Some(FocusedWindow {
+        app,
+        title: String::new(),
+    })
+}
+
+#[cfg(target_os = "windows")]
+fn detect() -> Option<FocusedWindow> {
+    const SCRIPT: &str = r#"
+Add-Type @"
+using System;
+using System.Runtime.InteropServices;
+using System.Text;
+public static class Focus {
+  [DllImport("user32.dll")] public static extern IntPtr GetForegroundWindow();
+  [DllImport("user32.dll")] public static extern int GetWindowText(IntPtr h, StringBuilder s, int n);
+  [DllImport("user32.dll")] public static extern uint GetWindowThreadProcessId(IntPtr h, out uint p);
+}
+"@
+$h = [Focus]::GetForegroundWindow()
+$p = 0
+[Focus]::GetWindowThreadProcessId($h, [ref]$p) | Out-Null
+$t = New-Object System.Text.StringBuilder 512
+[Focus]::GetWindowText($h, $t, 512) | Out-Null
+(Get-Process -Id $p).ProcessName
+$t.ToString()
+"#;
+    let output = run("powershell", &["-NoProfile", "-Command", SCRIPT])?;
+    let mut lines = output.lines();
+    Some(FocusedWindow {
+        app: lines.next()?.trim().to_string(),
+        title: lines.next().unwrap_or("").trim().to_string(),
+    })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+fn detect() -> Option<FocusedWindow> {
+    None
+}
+
+/// Runs a helper program and returns its trimmed stdout if it succeeded.
+fn run(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let text = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!text.is_empty()).then_some(text)
+}